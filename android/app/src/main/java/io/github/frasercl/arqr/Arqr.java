@@ -0,0 +1,21 @@
+package io.github.frasercl.arqr;
+
+import java.nio.ByteBuffer;
+
+/** The native scanner, from libarqr.so (see src/jni.rs in the crate). */
+public final class Arqr {
+    static {
+        System.loadLibrary("arqr");
+    }
+
+    private Arqr() {}
+
+    /**
+     * Scans the Y plane of a YUV_420_888 image. The plane has to be a direct
+     * buffer, as camera2 and CameraX hand out.
+     *
+     * @return the code found, with its payload if it could be read, or null
+     *     if no code was found
+     */
+    public static native ScanResult scanYuvFrame(ByteBuffer y, int width, int height, int rowStride, int pixelStride);
+}
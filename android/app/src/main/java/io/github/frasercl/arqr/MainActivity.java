@@ -0,0 +1,110 @@
+package io.github.frasercl.arqr;
+
+import android.Manifest;
+import android.content.pm.PackageManager;
+import android.os.Bundle;
+import android.os.SystemClock;
+import android.widget.TextView;
+
+import androidx.activity.ComponentActivity;
+import androidx.camera.core.CameraSelector;
+import androidx.camera.core.ImageAnalysis;
+import androidx.camera.core.ImageProxy;
+import androidx.camera.core.Preview;
+import androidx.camera.lifecycle.ProcessCameraProvider;
+import androidx.camera.view.PreviewView;
+import androidx.core.content.ContextCompat;
+
+import com.google.common.util.concurrent.ListenableFuture;
+
+import java.nio.charset.StandardCharsets;
+import java.util.Locale;
+import java.util.concurrent.ExecutorService;
+import java.util.concurrent.Executors;
+
+/** Shows the camera, and what arqr finds in each frame along the bottom. */
+public class MainActivity extends ComponentActivity {
+    private static final int CAMERA_REQUEST = 1;
+
+    private final ExecutorService analysis = Executors.newSingleThreadExecutor();
+    private TextView status;
+
+    @Override
+    protected void onCreate(Bundle savedInstanceState) {
+        super.onCreate(savedInstanceState);
+        setContentView(R.layout.activity_main);
+        status = findViewById(R.id.status);
+        if (checkSelfPermission(Manifest.permission.CAMERA) == PackageManager.PERMISSION_GRANTED) {
+            startCamera();
+        } else {
+            requestPermissions(new String[] {Manifest.permission.CAMERA}, CAMERA_REQUEST);
+        }
+    }
+
+    @Override
+    public void onRequestPermissionsResult(int requestCode, String[] permissions, int[] grantResults) {
+        super.onRequestPermissionsResult(requestCode, permissions, grantResults);
+        if (requestCode == CAMERA_REQUEST && grantResults.length > 0
+                && grantResults[0] == PackageManager.PERMISSION_GRANTED) {
+            startCamera();
+        } else {
+            status.setText("arqr needs the camera");
+        }
+    }
+
+    @Override
+    protected void onDestroy() {
+        super.onDestroy();
+        analysis.shutdown();
+    }
+
+    private void startCamera() {
+        ListenableFuture<ProcessCameraProvider> future = ProcessCameraProvider.getInstance(this);
+        future.addListener(() -> {
+            ProcessCameraProvider provider;
+            try {
+                provider = future.get();
+            } catch (Exception e) {
+                status.setText("couldn't open the camera: " + e);
+                return;
+            }
+            Preview preview = new Preview.Builder().build();
+            PreviewView view = findViewById(R.id.preview);
+            preview.setSurfaceProvider(view.getSurfaceProvider());
+
+            // YUV_420_888 is the default format, and only the latest frame is
+            // kept while a scan is running
+            ImageAnalysis scan = new ImageAnalysis.Builder()
+                    .setBackpressureStrategy(ImageAnalysis.STRATEGY_KEEP_ONLY_LATEST)
+                    .build();
+            scan.setAnalyzer(analysis, this::analyze);
+
+            provider.unbindAll();
+            provider.bindToLifecycle(this, CameraSelector.DEFAULT_BACK_CAMERA, preview, scan);
+        }, ContextCompat.getMainExecutor(this));
+    }
+
+    private void analyze(ImageProxy image) {
+        ImageProxy.PlaneProxy y = image.getPlanes()[0];
+        long start = SystemClock.elapsedRealtimeNanos();
+        ScanResult result = Arqr.scanYuvFrame(
+                y.getBuffer(), image.getWidth(), image.getHeight(), y.getRowStride(), y.getPixelStride());
+        double ms = (SystemClock.elapsedRealtimeNanos() - start) / 1e6;
+        image.close();
+
+        String text;
+        if (result == null) {
+            text = String.format(Locale.ROOT, "no code (%.1f ms)", ms);
+        } else {
+            float[] corners = result.corners;
+            String read = result.payload == null
+                    ? "couldn't be read"
+                    : "reads \"" + new String(result.payload, StandardCharsets.UTF_8) + "\"";
+            text = String.format(Locale.ROOT, "version %d code at (%.0f, %.0f) (%.0f, %.0f) (%.0f, %.0f) (%.0f, %.0f), %s (%.1f ms)",
+                    result.version, corners[0], corners[1], corners[2], corners[3],
+                    corners[4], corners[5], corners[6], corners[7], read, ms);
+        }
+        // Corners are in the sensor's orientation, not the screen's
+        runOnUiThread(() -> status.setText(text));
+    }
+}
@@ -0,0 +1,32 @@
+package io.github.frasercl.arqr;
+
+/** A code found by {@link Arqr#scanYuvFrame}. */
+public final class ScanResult {
+    /** {@link #ecLevel} for a code whose format information couldn't be read */
+    public static final int EC_UNKNOWN = -1;
+    /** {@link #ecLevel} for each error correction level, from least to most redundant */
+    public static final int EC_L = 0;
+    public static final int EC_M = 1;
+    public static final int EC_Q = 2;
+    public static final int EC_H = 3;
+
+    /**
+     * The code's corners as x, y pairs (top-left, top-right, bottom-right,
+     * bottom-left) in pixels, in the sensor's orientation
+     */
+    public final float[] corners;
+    /** The bytes the code holds, or null if it couldn't be read */
+    public final byte[] payload;
+    /** The code's version, from 1 to 40, or 0 if it wasn't sampled */
+    public final int version;
+    /** One of the {@code EC_*} levels */
+    public final int ecLevel;
+
+    // Made by the native code
+    ScanResult(float[] corners, byte[] payload, int version, int ecLevel) {
+        this.corners = corners;
+        this.payload = payload;
+        this.version = version;
+        this.ecLevel = ecLevel;
+    }
+}
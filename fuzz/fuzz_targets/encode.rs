@@ -0,0 +1,29 @@
+//! Arbitrary data through the QR encoder, at every kind of version and
+//! level, including ones that don't exist. The first two bytes pick them;
+//! the rest is the data.
+
+#![no_main]
+
+use arqr::encode::{EcLevel, QrCode, Version};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let [v, level, payload @ ..] = data else { return };
+    let ec_level = [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H][(level % 4) as usize];
+    let version = if level & 4 == 0 {
+        Version::Normal(*v as u32 % 42)
+    } else {
+        Version::Micro(*v as u32 % 6)
+    };
+
+    let codes = [
+        QrCode::with_version(payload, version, ec_level),
+        QrCode::encode(payload, ec_level),
+        QrCode::encode_micro(payload, ec_level),
+    ];
+    for code in codes.into_iter().flatten() {
+        assert_eq!(code.size(), code.version().size());
+        code.is_dark(code.size(), 0);
+        code.to_bitmap(1, 0);
+    }
+});
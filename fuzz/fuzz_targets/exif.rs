@@ -0,0 +1,22 @@
+//! Arbitrary bytes as a JPEG or WebP file for the EXIF orientation reader.
+//! The first byte picks which header to put in front of them, so the fuzzer
+//! doesn't have to find the magic numbers itself.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let [kind, rest @ ..] = data else { return };
+    let mut file = match kind % 3 {
+        0 => vec![0xff, 0xd8],
+        1 => {
+            let mut header = b"RIFF\0\0\0\0WEBP".to_vec();
+            header[4..8].copy_from_slice(&(rest.len() as u32 + 4).to_le_bytes());
+            header
+        }
+        _ => Vec::new(),
+    };
+    file.extend_from_slice(rest);
+    arqr::exif::orientation(&file);
+});
@@ -0,0 +1,33 @@
+//! Arbitrary target triples, NaNs and infinities included, through corner
+//! picking and the geometry that follows it
+
+#![no_main]
+
+use arqr::{
+    Point,
+    homography::Homography,
+    target::{Target, complete_quad, pick_corners, to_affine_transform, to_side_len},
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // 6 coordinates for each of 3 targets
+    if data.len() < 3 * 6 * 4 {
+        return;
+    }
+    let mut values = data.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap()));
+    let mut next = || values.next().unwrap();
+    let targets: [Target<f32>; 3] = std::array::from_fn(|_| {
+        Target::new(next(), next(), next(), next(), next(), next())
+    });
+
+    let Some(corners) = pick_corners(&targets) else { return };
+    let quad = complete_quad(corners);
+    let side = to_side_len(corners);
+    to_affine_transform(corners, side);
+    let unit = [Point::new(0.0, 0.0), Point::new(1.0, 0.0), Point::new(1.0, 1.0), Point::new(0.0, 1.0)];
+    if let Some(h) = Homography::from_points(&unit, &quad) {
+        h.apply(Point::new(0.5, 0.5));
+        h.inverse();
+    }
+});
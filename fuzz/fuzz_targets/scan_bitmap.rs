@@ -0,0 +1,21 @@
+//! Arbitrary bits as an already binarized image. The first byte picks the
+//! width; every bit after it is a pixel, as many whole rows as there are.
+
+#![no_main]
+
+use arqr::bitmap::Bitmap;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let [w, bytes @ ..] = data else { return };
+    let width = *w as u32 + 1;
+    let height = (bytes.len() as u32 * 8) / width;
+    let mut bmp = Bitmap::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let i = (y * width + x) as usize;
+            *bmp.get_pixel_mut(x, y) = (bytes[i / 8] >> (i % 8)) & 1 == 1;
+        }
+    }
+    arqr::scan_bitmap(&bmp);
+});
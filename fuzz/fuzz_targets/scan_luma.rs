@@ -0,0 +1,26 @@
+//! Arbitrary bytes as a greyscale image, through both binarizers and the
+//! whole scan. The first two bytes pick the width and the binarizer; the
+//! rest are pixels, as many whole rows as there are.
+
+#![no_main]
+
+use arqr::{Scanner, bitmap::Binarizer};
+use image::GrayImage;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let [w, mode, pixels @ ..] = data else { return };
+    let width = *w as u32 + 1;
+    let height = (pixels.len() as u32) / width;
+    let img = GrayImage::from_raw(width, height, pixels[..(width * height) as usize].to_vec()).unwrap();
+
+    let mut scanner = Scanner::new();
+    scanner.binarizer = match mode % 3 {
+        0 => Binarizer::Global,
+        // Radii bigger than the image included
+        _ => Binarizer::Adaptive { radius: (*mode as u32) >> 2, offset: mode % 16 },
+    };
+    scanner.scan(&img);
+    // A second frame goes down the path that reuses the first one's regions
+    scanner.scan(&img);
+});
@@ -0,0 +1,117 @@
+#ifndef ARQR_H
+#define ARQR_H
+
+/* Generated by cbindgen from src/ffi.rs. Don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Returned by the `arqr_scan_*` functions when the frame was scanned
+#define ARQR_OK 0
+
+// Returned by the `arqr_scan_*` functions for a null pointer, an empty
+// frame or a stride shorter than a row
+#define ARQR_BAD_ARGUMENT -1
+
+// `ArqrResult::ec_level` for a code whose error correction level couldn't
+// be read
+#define ARQR_EC_UNKNOWN -1
+
+// `ArqrResult::ec_level` for each error correction level, from least to
+// most redundant
+#define ARQR_EC_L 0
+
+#define ARQR_EC_M 1
+
+#define ARQR_EC_Q 2
+
+#define ARQR_EC_H 3
+
+// A scanner, along with the payload of its last scan for `ArqrResult` to
+// point into
+typedef struct ArqrScanner ArqrScanner;
+
+// What `arqr_scan_gray` or `arqr_scan_bgra` found in a frame
+typedef struct ArqrResult {
+  // 1 if a code was found, 0 if not
+  uint32_t found;
+  // The code's corners in pixels, as x, y pairs: top-left, top-right,
+  // bottom-right, bottom-left. Zero if no code was found.
+  double corners[8];
+  // Number of position targets found, whether or not they made a code
+  uint32_t targets;
+  // Time taken to scan, in milliseconds
+  double scan_ms;
+  // The code's message, `payload_len` bytes long, or null if it couldn't
+  // be read. It's the bytes that were encoded, so it's only text if text
+  // was encoded, and it isn't NUL-terminated. Owned by the scanner: valid
+  // until its next scan or until it's freed.
+  const uint8_t *payload;
+  uintptr_t payload_len;
+  // The code's version, from 1 to 40, or 0 if it wasn't sampled
+  uint32_t version;
+  // One of the `ARQR_EC_*` levels, or `ARQR_EC_UNKNOWN` if the code's
+  // format information couldn't be read
+  int32_t ec_level;
+} ArqrResult;
+
+
+
+
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Makes a scanner with the default settings. Free it with
+// `arqr_scanner_free`.
+struct ArqrScanner *arqr_scanner_new(void);
+
+// Frees a scanner from `arqr_scanner_new`. Null is ignored.
+//
+// # Safety
+//
+// `scanner` has to have come from `arqr_scanner_new`, and not been freed
+// already.
+void arqr_scanner_free(struct ArqrScanner *scanner);
+
+// Scans a `width` by `height` greyscale frame, one byte per pixel, with rows
+// `stride` bytes apart. Fills in `out` and returns `ARQR_OK`, or returns
+// `ARQR_BAD_ARGUMENT` without touching `out`.
+//
+// # Safety
+//
+// `scanner` has to be a live scanner from `arqr_scanner_new`, not in use on
+// another thread. `pixels` has to point to `stride * (height - 1) + width`
+// readable bytes, and `out` to an `ArqrResult`.
+int32_t arqr_scan_gray(struct ArqrScanner *scanner,
+                       const uint8_t *pixels,
+                       uint32_t width,
+                       uint32_t height,
+                       uint32_t stride,
+                       struct ArqrResult *out);
+
+// Scans a `width` by `height` BGRA frame, four bytes per pixel (blue, green,
+// red, then alpha, which is ignored), with rows `stride` bytes apart. Fills
+// in `out` and returns `ARQR_OK`, or returns `ARQR_BAD_ARGUMENT` without
+// touching `out`.
+//
+// # Safety
+//
+// `scanner` has to be a live scanner from `arqr_scanner_new`, not in use on
+// another thread. `pixels` has to point to `stride * (height - 1) + width *
+// 4` readable bytes, and `out` to an `ArqrResult`.
+int32_t arqr_scan_bgra(struct ArqrScanner *scanner,
+                       const uint8_t *pixels,
+                       uint32_t width,
+                       uint32_t height,
+                       uint32_t stride,
+                       struct ArqrResult *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ARQR_H */
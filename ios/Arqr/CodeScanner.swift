@@ -0,0 +1,102 @@
+// A Swift wrapper around arqr's C interface (include/arqr.h; see src/ffi.rs
+// in the crate), for scanning AVFoundation frames in place. Build
+// libarqr.a for each target the app ships, e.g.
+//
+//     cargo rustc --lib --release --target aarch64-apple-ios \
+//         --no-default-features --features ffi --crate-type staticlib
+//
+// link it, and add this directory to the target's Swift import paths, so
+// that `import CArqr` finds module.modulemap.
+
+import CArqr
+import CoreGraphics
+import CoreVideo
+import Foundation
+
+/// A code found by `CodeScanner.scan`
+public struct ScannedCode {
+    /// How much of the code is given over to error correction, from about
+    /// 7% of it recoverable at `l` to 30% at `h`
+    public enum EcLevel {
+        case l, m, q, h
+
+        init?(_ level: Int32) {
+            switch level {
+            case ARQR_EC_L: self = .l
+            case ARQR_EC_M: self = .m
+            case ARQR_EC_Q: self = .q
+            case ARQR_EC_H: self = .h
+            default: return nil
+            }
+        }
+    }
+
+    /// The code's corners in pixels: top-left, top-right, bottom-right,
+    /// bottom-left
+    public let corners: [CGPoint]
+    /// The bytes the code holds, or nil if it couldn't be read
+    public let payload: Data?
+    /// The code's version, from 1 to 40, or nil if it wasn't sampled
+    public let version: Int?
+    /// Nil if the code's format information couldn't be read
+    public let ecLevel: EcLevel?
+    /// Time taken to scan, in milliseconds
+    public let scanMs: Double
+
+    /// The payload as text, if it's UTF-8
+    public var text: String? {
+        payload.flatMap { String(data: $0, encoding: .utf8) }
+    }
+
+    init(_ result: ArqrResult) {
+        let xys = withUnsafeBytes(of: result.corners) { Array($0.bindMemory(to: Double.self)) }
+        corners = stride(from: 0, to: xys.count, by: 2).map { CGPoint(x: xys[$0], y: xys[$0 + 1]) }
+        // The scanner owns the payload until its next scan, so it's copied out
+        payload = result.payload.map { Data(bytes: $0, count: Int(result.payload_len)) }
+        version = result.version == 0 ? nil : Int(result.version)
+        ecLevel = EcLevel(result.ec_level)
+        scanMs = result.scan_ms
+    }
+}
+
+/// A scanner, for one thread at a time. It keeps its buffers between frames,
+/// so use the same one for every frame of a capture session.
+public final class CodeScanner {
+    private let scanner: OpaquePointer
+
+    public init() {
+        scanner = arqr_scanner_new()
+    }
+
+    deinit {
+        arqr_scanner_free(scanner)
+    }
+
+    /// Scans a camera frame without repacking it: 32BGRA frames whole, and
+    /// bi-planar 4:2:0 (NV12) frames by their luma plane. Nil if no code was
+    /// found, or the frame is in some other format.
+    public func scan(_ buffer: CVPixelBuffer) -> ScannedCode? {
+        CVPixelBufferLockBaseAddress(buffer, .readOnly)
+        defer { CVPixelBufferUnlockBaseAddress(buffer, .readOnly) }
+        var result = ArqrResult()
+        let status: Int32
+        switch CVPixelBufferGetPixelFormatType(buffer) {
+        case kCVPixelFormatType_32BGRA:
+            guard let pixels = CVPixelBufferGetBaseAddress(buffer) else { return nil }
+            status = arqr_scan_bgra(
+                scanner, pixels.assumingMemoryBound(to: UInt8.self),
+                UInt32(CVPixelBufferGetWidth(buffer)), UInt32(CVPixelBufferGetHeight(buffer)),
+                UInt32(CVPixelBufferGetBytesPerRow(buffer)), &result)
+        case kCVPixelFormatType_420YpCbCr8BiPlanarVideoRange, kCVPixelFormatType_420YpCbCr8BiPlanarFullRange:
+            guard let lumas = CVPixelBufferGetBaseAddressOfPlane(buffer, 0) else { return nil }
+            status = arqr_scan_gray(
+                scanner, lumas.assumingMemoryBound(to: UInt8.self),
+                UInt32(CVPixelBufferGetWidthOfPlane(buffer, 0)), UInt32(CVPixelBufferGetHeightOfPlane(buffer, 0)),
+                UInt32(CVPixelBufferGetBytesPerRowOfPlane(buffer, 0)), &result)
+        default:
+            return nil
+        }
+        guard status == ARQR_OK, result.found != 0 else { return nil }
+        return ScannedCode(result)
+    }
+}
@@ -0,0 +1,88 @@
+//! Scanning `ndarray` arrays, for frames that come out of numerical code
+//! (e.g. a camera pipeline in Python, handed over through numpy) rather than
+//! as `ImageBuffer`s.
+//!
+//! Arrays are indexed `[row, column]`, or `[row, column, channel]` for colour,
+//! the same layout as numpy images. Views are read in place through their
+//! strides, so slices, crops, transposes and flipped views scan without being
+//! copied into a standard layout first.
+
+use image::{Pixel, Rgb};
+use ndarray::{ArrayView2, ArrayView3, Axis};
+use crate::{Scanner, ScanResult};
+
+impl Scanner {
+    /// Binarizes and scans a greyscale frame, one byte per pixel
+    pub fn scan_array(&mut self, frame: ArrayView2<u8>) -> ScanResult {
+        let (height, width) = frame.dim();
+        self.scan_lumas(frame.iter().copied(), width as u32, height as u32)
+    }
+
+    /// Binarizes and scans a frame with its channels along the last axis:
+    /// grey, grey and alpha, RGB or RGBA. Alpha is ignored.
+    ///
+    /// Panics if the frame has more than four channels, or none.
+    pub fn scan_array_channels(&mut self, frame: ArrayView3<u8>) -> ScanResult {
+        let (height, width, channels) = frame.dim();
+        match channels {
+            1 | 2 => self.scan_array(frame.index_axis_move(Axis(2), 0)),
+            3 | 4 => {
+                let lumas = (0..height).flat_map(move |y| {
+                    (0..width).map(move |x| {
+                        Rgb([frame[[y, x, 0]], frame[[y, x, 1]], frame[[y, x, 2]]]).to_luma().0[0]
+                    })
+                });
+                self.scan_lumas(lumas, width as u32, height as u32)
+            }
+            _ => panic!("can't scan a frame with {} channels", channels),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array2, Array3, s};
+    use crate::{
+        Scanner,
+        encode::{EcLevel, QrCode, Version},
+        testgen::{Distortion, generate},
+    };
+
+    fn sample() -> Array2<u8> {
+        let code = QrCode::with_version(b"ndarray", Version::Normal(2), EcLevel::M).unwrap();
+        let distortion = Distortion { rotation: 0.2, ..Distortion::default() };
+        let image = generate(&code, &distortion).image;
+        let (width, height) = image.dimensions();
+        Array2::from_shape_vec((height as usize, width as usize), image.into_raw()).unwrap()
+    }
+
+    #[test]
+    fn scans_strided_views() {
+        let frame = sample();
+        let mut scanner = Scanner::new();
+        assert_eq!(scanner.scan_array(frame.view()).payload.as_deref(), Some(&b"ndarray"[..]));
+        // Every other column of a frame twice as wide
+        let mut wide = Array2::zeros((frame.nrows(), frame.ncols() * 2));
+        wide.slice_mut(s![.., ..;2]).assign(&frame);
+        let result = scanner.scan_array(wide.slice(s![.., ..;2]));
+        assert_eq!(result.payload.as_deref(), Some(&b"ndarray"[..]));
+        // Stored column-major
+        let transposed = frame.t().to_owned();
+        let result = scanner.scan_array(transposed.t());
+        assert_eq!(result.payload.as_deref(), Some(&b"ndarray"[..]));
+    }
+
+    #[test]
+    fn scans_colour_frames() {
+        let frame = sample();
+        let mut scanner = Scanner::new();
+        for channels in 1..=4 {
+            let colour = Array3::from_shape_fn((frame.nrows(), frame.ncols(), channels), |(y, x, c)| {
+                // Alpha is left transparent, to check it's ignored
+                if c == 3 || (channels == 2 && c == 1) { 0 } else { frame[[y, x]] }
+            });
+            let result = scanner.scan_array_channels(colour.view());
+            assert_eq!(result.payload.as_deref(), Some(&b"ndarray"[..]), "{} channels", channels);
+        }
+    }
+}
@@ -0,0 +1,902 @@
+//! Finds and decodes Aztec codes, as printed on boarding passes and train
+//! tickets. Where a QR code has three position targets in its corners, an
+//! Aztec code has one "bullseye" in its middle: a dark centre module in
+//! rings of alternating colour, one module wide, out to 4 (compact symbols)
+//! or 6 (full-size ones). Just outside that, a ring of orientation marks and
+//! a mode message give the symbol's rotation, how many layers of data wrap
+//! around the core, and how many of their codewords are data. The data
+//! spirals outwards-in, two modules deep, and is protected by Reed-Solomon
+//! check words.
+//!
+//! The bullseye is found much like a position target, by runs of equal size
+//! along a row and then down a column. The corners of its rings are then
+//! found by casting rays out from the centre, and everything else is sampled
+//! through the homography they give, refined on the reference grid that
+//! runs through full-size symbols every 16 modules. Mirrored symbols are
+//! read too.
+
+use std::f64::consts::TAU;
+use crate::{
+    Point,
+    bitmap::Bitmap,
+    homography::Homography,
+    reed_solomon::Field,
+};
+
+/// The centre of a possible bullseye
+#[derive(Clone, Copy, Debug)]
+pub struct Bullseye {
+    pub center: Point<f64>,
+    /// Rough size of a module, in pixels
+    pub module: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct AztecCode {
+    /// Outer corners of the symbol, in pixels, starting at its top-left and
+    /// going clockwise
+    pub corners: [Point<f64>; 4],
+    pub compact: bool,
+    /// Layers of data around the core, 1 to 4 for compact symbols or 1 to 32
+    /// for full-size ones
+    pub layers: u32,
+    pub mirrored: bool,
+    /// The decoded message. Text comes out as ASCII, and bytes from binary
+    /// runs as they are.
+    pub payload: Vec<u8>,
+    /// How many codewords Reed-Solomon had to fix
+    pub corrected: usize,
+}
+
+/// Finds and decodes every Aztec code in `img`
+pub fn scan(img: &Bitmap) -> Vec<AztecCode> {
+    let mut codes: Vec<AztecCode> = Vec::new();
+    for b in find_bullseyes(img) {
+        // Several bullseyes are usually found around each one's centre
+        if codes.iter().any(|c| inside(&c.corners, b.center)) {
+            continue;
+        }
+        codes.extend(decode(img, &b));
+    }
+    codes
+}
+
+/// Whether `p` is inside the convex quadrilateral `corners`
+fn inside(corners: &[Point<f64>; 4], p: Point<f64>) -> bool {
+    let sides: Vec<bool> = (0..4)
+        .map(|i| {
+            let (a, b) = (corners[i], corners[(i + 1) % 4]);
+            (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x) > 0.0
+        })
+        .collect();
+    sides.iter().all(|&s| s) || sides.iter().all(|&s| !s)
+}
+
+/// Lengths of `n + 1` runs of pixels, starting at (`x`, `y`) and walking by
+/// (`dx`, `dy`): the rest of the run (`x`, `y`) is in, then the `n` after
+/// it. The last run is only followed as far as `limit` pixels. None if the
+/// image ends first.
+fn walk(img: &Bitmap, x: u32, y: u32, (dx, dy): (i32, i32), n: usize, limit: u32) -> Option<Vec<u32>> {
+    let mut runs = vec![0];
+    let color = *img.get_pixel_checked(x, y)?;
+    let (mut x, mut y, mut color) = (x as i64, y as i64, color);
+    loop {
+        let Some(&px) = img.get_pixel_checked(x as u32, y as u32) else {
+            // Running off the edge is fine in the last run
+            return (runs.len() == n + 1).then_some(runs);
+        };
+        if px != color {
+            if runs.len() == n + 1 {
+                return Some(runs);
+            }
+            color = px;
+            runs.push(0);
+        }
+        let last = runs.len() - 1;
+        runs[last] += 1;
+        if last == n && runs[last] >= limit {
+            return Some(runs);
+        }
+        x += dx as i64;
+        y += dy as i64;
+    }
+}
+
+/// If `inner` (the 7 runs from one side of ring 3 to the other) are about
+/// the same size, and `outer` (ring 4 on each side) aren't much smaller,
+/// their mean size
+fn bullseye_runs(inner: &[u32], outer: [u32; 2]) -> Option<f64> {
+    let mean = inner.iter().sum::<u32>() as f64 / inner.len() as f64;
+    let close = |len: u32| (len as f64 - mean).abs() <= mean * 0.5 + 0.5;
+    (inner.iter().all(|&len| close(len)) && outer.iter().all(|&len| len as f64 >= mean * 0.5)).then_some(mean)
+}
+
+/// Checks for a bullseye through (`x`, `y`) along one axis. Returns the
+/// middle of its centre module along that axis, and its module size.
+fn confirm(img: &Bitmap, x: u32, y: u32, vertical: bool, module: f64) -> Option<(f64, f64)> {
+    let (back, fwd) = if vertical { ((0, -1), (0, 1)) } else { ((-1, 0), (1, 0)) };
+    let limit = (module * 2.0).ceil() as u32 + 1;
+    let back = walk(img, x, y, back, 4, limit)?;
+    let fwd = walk(img, x, y, fwd, 4, limit)?;
+    let center = back[0] + fwd[0] - 1;
+    let inner = [back[3], back[2], back[1], center, fwd[1], fwd[2], fwd[3]];
+    let mean = bullseye_runs(&inner, [back[4], fwd[4]])?;
+    let start = if vertical { y } else { x } + 1 - back[0];
+    Some((start as f64 + center as f64 / 2.0, mean))
+}
+
+/// Finds the centres of bullseyes in `img`. Some may be other things with
+/// the same rings of runs, or a little off the centre of a real one;
+/// `decode` weeds them out.
+pub fn find_bullseyes(img: &Bitmap) -> Vec<Bullseye> {
+    let mut found: Vec<Bullseye> = Vec::new();
+    for (y, row) in img.rows().enumerate().step_by(2) {
+        let row = row.as_slice();
+        // Start and length of each run of one colour
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        let mut start = 0;
+        for x in 1..=row.len() {
+            if x == row.len() || row[x] != row[start] {
+                runs.push((start as u32, (x - start) as u32));
+                start = x;
+            }
+        }
+
+        for w in runs.windows(9) {
+            // Ring 4, then rings 3 to 0 and back out, then ring 4 again;
+            // true is white
+            if row[w[0].0 as usize] {
+                continue;
+            }
+            let inner: Vec<u32> = w[1..8].iter().map(|r| r.1).collect();
+            let Some(module) = bullseye_runs(&inner, [w[0].1, w[8].1]) else { continue };
+            let x = w[4].0 + w[4].1 / 2;
+            let Some((cy, v_module)) = confirm(img, x, y as u32, true, module) else { continue };
+            let Some((cx, h_module)) = confirm(img, x, cy as u32, false, module) else { continue };
+            let center = Point::new(cx, cy);
+            // Rows and columns a little off centre can pass too, more so the
+            // more the symbol's turned, so near misses are all kept for
+            // `decode` to try
+            if found.iter().any(|b| b.center.dist_to(center) < 1.0) {
+                continue;
+            }
+            found.push(Bullseye { center, module: (v_module + h_module) / 2.0 });
+        }
+    }
+    found
+}
+
+/// Rays cast from a bullseye's centre to find its corners
+const RAYS: usize = 256;
+
+/// The corners of the bullseye square whose edge is the `edge`th crossing
+/// out from the centre, clockwise, by casting rays out to it and fitting a
+/// line to each side. Edge `n` is `n - 0.5` modules out.
+fn find_corners(img: &Bitmap, b: &Bullseye, edge: usize) -> Option<[Point<f64>; 4]> {
+    let is_dark = |p: Point<f64>| {
+        p.x >= 0.0 && p.y >= 0.0 && img.get_pixel_checked(p.x as u32, p.y as u32).is_some_and(|&px| !px)
+    };
+    let step = 0.25;
+    let max_dist = b.module * (edge as f64 + 2.0) * 1.5;
+    let edges: Vec<Option<Point<f64>>> = (0..RAYS)
+        .map(|i| {
+            let (s, c) = (i as f64 * TAU / RAYS as f64).sin_cos();
+            // A crossing only counts if the new colour lasts a third of a
+            // module, so a ray grazing the corner of a pixel doesn't count it
+            let at = |t: f64| Point::new(b.center.x + c * t, b.center.y + s * t);
+            let min_run = (b.module / 3.0).max(step);
+            let mut dark = true;
+            let mut crossed = 0;
+            let mut t = 0.0;
+            while t < max_dist {
+                if is_dark(at(t)) != dark {
+                    let mut u = t;
+                    while u < t + min_run && is_dark(at(u)) != dark {
+                        u += step;
+                    }
+                    if u >= t + min_run {
+                        dark = !dark;
+                        crossed += 1;
+                        if crossed == edge {
+                            return Some(at(t - step / 2.0));
+                        }
+                    }
+                    t = u;
+                    continue;
+                }
+                t += step;
+            }
+            None
+        })
+        .collect();
+    let dist = |i: usize| edges[i % RAYS].map_or(0.0, |p| p.dist_to(b.center));
+
+    // The corners stick out furthest; look for one in each quarter turn from
+    // the furthest point of all
+    let first = (0..RAYS).max_by(|&i, &j| dist(i).total_cmp(&dist(j)))?;
+    let rough: [usize; 4] = [0, 1, 2, 3].map(|k| {
+        let mid = first + k * RAYS / 4 + RAYS;
+        (mid - RAYS / 8..=mid + RAYS / 8).max_by(|&i, &j| dist(i).total_cmp(&dist(j))).unwrap() % RAYS
+    });
+    if rough.iter().any(|&i| edges[i].is_none()) {
+        return None;
+    }
+
+    // Fit a line to the middle of each side, then meet them up
+    let lines: Vec<Option<(Point<f64>, Point<f64>)>> = (0..4)
+        .map(|k| {
+            let (from, to) = (rough[k], rough[(k + 1) % 4]);
+            let span = (to + RAYS - from) % RAYS;
+            let skip = span / 8 + 1;
+            let points: Vec<Point<f64>> = (from + skip..from + span.saturating_sub(skip).max(skip))
+                .filter_map(|i| edges[i % RAYS])
+                .collect();
+            // Fit again without whatever strays far from the first fit
+            let (p, d) = fit_line(&points)?;
+            let near: Vec<Point<f64>> = points.into_iter()
+                .filter(|q| ((q.x - p.x) * d.y - (q.y - p.y) * d.x).abs() < b.module / 4.0 + 0.5)
+                .collect();
+            fit_line(&near)
+        })
+        .collect();
+    let mut corners = rough.map(|i| edges[i].unwrap());
+    for (k, corner) in corners.iter_mut().enumerate() {
+        if let (Some(a), Some(b)) = (lines[(k + 3) % 4], lines[k]) {
+            if let Some(p) = intersect(a, b) {
+                *corner = p;
+            }
+        }
+    }
+    Some(corners)
+}
+
+/// Least-squares line through `points`, as a point on it and its direction
+fn fit_line(points: &[Point<f64>]) -> Option<(Point<f64>, Point<f64>)> {
+    if points.len() < 3 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mx = points.iter().map(|p| p.x).sum::<f64>() / n;
+    let my = points.iter().map(|p| p.y).sum::<f64>() / n;
+    let (mut sxx, mut sxy, mut syy) = (0.0, 0.0, 0.0);
+    for p in points {
+        let (dx, dy) = (p.x - mx, p.y - my);
+        sxx += dx * dx;
+        sxy += dx * dy;
+        syy += dy * dy;
+    }
+    let angle = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+    Some((Point::new(mx, my), Point::new(angle.cos(), angle.sin())))
+}
+
+fn intersect((p, d): (Point<f64>, Point<f64>), (q, e): (Point<f64>, Point<f64>)) -> Option<Point<f64>> {
+    let cross = d.x * e.y - d.y * e.x;
+    if cross.abs() < 1e-9 {
+        return None;
+    }
+    let t = ((q.x - p.x) * e.y - (q.y - p.y) * e.x) / cross;
+    Some(Point::new(p.x + d.x * t, p.y + d.y * t))
+}
+
+/// Samples modules, by their position in the symbol relative to its centre
+/// module, as it should be read: upright and unmirrored
+struct Sampler<'a> {
+    img: &'a Bitmap,
+    /// From the bullseye's own module grid, as found, to pixels
+    to_image: Homography,
+    /// Quarter turns clockwise from how the symbol should be read to how it
+    /// was found
+    turns: u32,
+    mirrored: bool,
+}
+
+impl Sampler<'_> {
+    fn to_image(&self, x: f64, y: f64) -> Point<f64> {
+        let (mut x, mut y) = if self.mirrored { (-x, y) } else { (x, y) };
+        for _ in 0..self.turns {
+            (x, y) = (-y, x);
+        }
+        self.to_image.apply(Point::new(x, y))
+    }
+
+    fn is_dark(&self, x: i32, y: i32) -> bool {
+        let p = self.to_image(x as f64, y as f64);
+        p.x >= 0.0 && p.y >= 0.0 && self.img.get_pixel_checked(p.x as u32, p.y as u32).is_some_and(|&px| !px)
+    }
+}
+
+/// Positions on the mode message ring of radius `r`, from its top-left
+/// corner clockwise, paired with where each is along its side
+fn ring(r: i32) -> impl Iterator<Item = ((i32, i32), i32)> {
+    (0..4).flat_map(move |side| (0..2 * r).map(move |i| {
+        let pos = match side {
+            0 => (-r + i, -r),
+            1 => (r, -r + i),
+            2 => (r - i, r),
+            _ => (-r, r - i),
+        };
+        (pos, i)
+    }))
+}
+
+/// Whether the module `i` along `side` of the mode message ring (as walked
+/// by `ring`) is an orientation mark that should be dark, or None if it
+/// isn't a mark. Each corner has a mark either side of it: the top-left has
+/// all three dark, the top-right the corner and the one below it, the
+/// bottom-right the one above it, and the bottom-left none.
+fn orientation_mark(side: usize, i: i32, r: i32) -> Option<bool> {
+    match i {
+        0 | 1 => Some(side < 2),
+        _ if i == 2 * r - 1 => Some(side % 2 == 1),
+        _ => None,
+    }
+}
+
+/// Points on a symbol's module grid, and where each was found in the image
+type Matches = (Vec<Point<f64>>, Vec<Point<f64>>);
+
+/// Corners of every square of the bullseye from the second to the `outer`th
+/// edge out, as positions on its module grid and the pixels they were found
+/// at. Each set of corners starts from whichever, so they're turned to line
+/// up with the outermost's.
+fn bullseye_corners(img: &Bitmap, b: &Bullseye, outer: usize) -> Option<Matches> {
+    let (mut grid, mut found): (Vec<Point<f64>>, Vec<Point<f64>>) = (Vec::new(), Vec::new());
+    for edge in (2..=outer).rev() {
+        let mut corners = find_corners(img, b, edge)?;
+        if let Some(&first) = found.first() {
+            let along = |p: &Point<f64>| {
+                ((p.x - b.center.x) * (first.x - b.center.x) + (p.y - b.center.y) * (first.y - b.center.y)) / p.dist_to(b.center)
+            };
+            let start = (0..4).max_by(|&i, &j| along(&corners[i]).total_cmp(&along(&corners[j])))?;
+            corners.rotate_left(start);
+        }
+        let half = edge as f64 - 0.5;
+        grid.extend([(-half, -half), (half, -half), (half, half), (-half, half)].map(|(x, y)| Point::new(x, y)));
+        found.extend(corners);
+    }
+    Some((grid, found))
+}
+
+/// Follows the reference grid of a full-size symbol `size` modules across
+/// out from the bullseye, refitting `to_image` to where each of its crossings
+/// is found (on top of `grid` and `found`, the bullseye's corners) one ring
+/// of them at a time. Fitting to the bullseye alone can be a module or more
+/// out by the edge of a big symbol.
+fn follow_grid(img: &Bitmap, mut to_image: Homography, mut grid: Vec<Point<f64>>, mut found: Vec<Point<f64>>, size: i32) -> Option<Homography> {
+    let half = size / 2;
+    let is_dark = |h: &Homography, x: f64, y: f64| {
+        let p = h.apply(Point::new(x, y));
+        p.x >= 0.0 && p.y >= 0.0 && img.get_pixel_checked(p.x as u32, p.y as u32).is_some_and(|&px| !px)
+    };
+    // Along each line of the grid, modules an even number away from the
+    // centre are dark and the rest light
+    const ARM: i32 = 5;
+    for ring in 1..=half / 16 {
+        for (i, j) in (-ring..=ring).flat_map(|i| (-ring..=ring).map(move |j| (i, j))) {
+            if i.abs() != ring && j.abs() != ring {
+                continue;
+            }
+            let (cx, cy) = (16 * i, 16 * j);
+            let arm: Vec<(i32, i32)> = (-ARM..=ARM)
+                .filter(|&k| k != 0)
+                .flat_map(|k| [(cx + k, cy), (cx, cy + k)])
+                .filter(|&(x, y)| x.abs() <= half && y.abs() <= half)
+                .collect();
+            // Try the crossing a little way each way, and take the middle
+            // of the offsets that fit best
+            let mut best = (0, Vec::new());
+            for dy in -5..=5 {
+                for dx in -5..=5 {
+                    let (ox, oy) = (dx as f64 * 0.15, dy as f64 * 0.15);
+                    let score = arm.iter()
+                        .filter(|&&(x, y)| is_dark(&to_image, x as f64 + ox, y as f64 + oy) == ((x + y) % 2 == 0))
+                        .count();
+                    if score > best.0 {
+                        best = (score, vec![(ox, oy)]);
+                    } else if score == best.0 {
+                        best.1.push((ox, oy));
+                    }
+                }
+            }
+            let (score, offsets) = best;
+            if score * 10 < arm.len() * 9 {
+                continue;
+            }
+            let n = offsets.len() as f64;
+            let (ox, oy) = offsets.iter().fold((0.0, 0.0), |(sx, sy), &(x, y)| (sx + x / n, sy + y / n));
+            grid.push(Point::new(cx as f64, cy as f64));
+            found.push(to_image.apply(Point::new(cx as f64 + ox, cy as f64 + oy)));
+        }
+        to_image = Homography::from_points(&grid, &found)?;
+    }
+    Some(to_image)
+}
+
+/// Decodes the Aztec code around a bullseye, if there is one
+pub fn decode(img: &Bitmap, bullseye: &Bullseye) -> Option<AztecCode> {
+    let (grid, found) = bullseye_corners(img, bullseye, 4)?;
+    let to_image = Homography::from_points(&grid, &found)?;
+    let mut sampler = Sampler { img, to_image, turns: 0, mirrored: false };
+
+    // Full-size symbols have two more rings, a white one and a dark one;
+    // in compact ones the orientation marks and mode message are there
+    let ring_fraction = |r: i32, dark: bool| {
+        ring(r).filter(|&((x, y), _)| sampler.is_dark(x, y) == dark).count() as f64 / (8 * r) as f64
+    };
+    let compact = !(ring_fraction(5, false) > 0.85 && ring_fraction(6, true) > 0.85);
+    let (grid, found) = if compact {
+        (grid, found)
+    } else {
+        // Their two extra rings make for a better fit
+        let (grid, found) = bullseye_corners(img, bullseye, 6)?;
+        sampler.to_image = Homography::from_points(&grid, &found)?;
+        (grid, found)
+    };
+    let r = if compact { 5 } else { 7 };
+
+    // Find the turn and mirroring that put the orientation marks where
+    // they go
+    let mut best = (usize::MAX, 0, false);
+    for mirrored in [false, true] {
+        for turns in 0..4 {
+            sampler.turns = turns;
+            sampler.mirrored = mirrored;
+            let wrong = ring(r).enumerate()
+                .filter_map(|(n, ((x, y), i))| orientation_mark(n / (2 * r as usize), i, r).map(|m| m != sampler.is_dark(x, y)))
+                .filter(|&wrong| wrong)
+                .count();
+            if wrong < best.0 {
+                best = (wrong, turns, mirrored);
+            }
+        }
+    }
+    let (wrong, turns, mirrored) = best;
+    if wrong > 2 {
+        return None;
+    }
+    sampler.turns = turns;
+    sampler.mirrored = mirrored;
+
+    let (layers, data_words) = read_mode_message(&sampler, compact)?;
+    if !compact {
+        sampler.to_image = follow_grid(img, sampler.to_image, grid, found, full_size(layers))?;
+    }
+    let (size, bits) = read_data(&sampler, compact, layers);
+    let (mut words, word_size) = codewords(&bits, layers);
+    let ecc_len = words.len().checked_sub(data_words)?;
+    let corrected = data_field(word_size).correct(&mut words, ecc_len, 1)?;
+    let message = unstuff(&words[..data_words], word_size)?;
+    let payload = decode_message(&message)?;
+
+    let half = size as f64 / 2.0;
+    let corners = [(-half, -half), (half, -half), (half, half), (-half, half)].map(|(x, y)| sampler.to_image(x, y));
+    Some(AztecCode { corners, compact, layers, mirrored, payload, corrected })
+}
+
+/// Reads the mode message off the ring of radius `r`, giving the number of
+/// layers and of data codewords
+fn read_mode_message(sampler: &Sampler, compact: bool) -> Option<(u32, usize)> {
+    let r = if compact { 5 } else { 7 };
+    // Along each side, everything but the two modules at each end (and the
+    // reference grid, in the middle of full-size symbols)
+    let words: Vec<u16> = ring(r)
+        .filter(|&(_, i)| (2..2 * r - 1).contains(&i) && (compact || i != r))
+        .map(|((x, y), _)| sampler.is_dark(x, y))
+        .collect::<Vec<bool>>()
+        .chunks(4)
+        .map(|bits| bits.iter().fold(0, |acc, &b| acc << 1 | b as u16))
+        .collect();
+    let mut words = words;
+    let ecc_len = if compact { 5 } else { 6 };
+    Field::new(4, 0x13).correct(&mut words, ecc_len, 1)?;
+    let data = words[..words.len() - ecc_len].iter().fold(0u32, |acc, &w| acc << 4 | w as u32);
+    Some(if compact {
+        ((data >> 6) + 1, (data & 0x3f) as usize + 1)
+    } else {
+        ((data >> 11) + 1, (data & 0x7ff) as usize + 1)
+    })
+}
+
+/// Size in modules of a full-size symbol with `layers` layers, reference
+/// grid included
+fn full_size(layers: u32) -> i32 {
+    let base = 14 + layers as i32 * 4;
+    base + 1 + 2 * ((base / 2 - 1) / 15)
+}
+
+/// Reads the data layers, outermost first, giving the symbol's size in
+/// modules and its bits
+fn read_data(sampler: &Sampler, compact: bool, layers: u32) -> (u32, Vec<bool>) {
+    let layers = layers as i32;
+    let base = if compact { 11 } else { 14 } + layers * 4;
+    // Full-size symbols have a line of the reference grid every 16 modules
+    // out from the centre, which the data skips over
+    let (size, map): (i32, Vec<i32>) = if compact {
+        (base, (0..base).collect())
+    } else {
+        let size = full_size(layers as u32);
+        let (orig_center, center) = (base / 2, size / 2);
+        let mut map = vec![0; base as usize];
+        for i in 0..orig_center {
+            let offset = i + i / 15;
+            map[(orig_center - i - 1) as usize] = center - offset - 1;
+            map[(orig_center + i) as usize] = center + offset + 1;
+        }
+        (size, map)
+    };
+    let center = size / 2;
+    let at = |x: i32, y: i32| sampler.is_dark(map[x as usize] - center, map[y as usize] - center);
+
+    let mut bits = Vec::new();
+    for layer in 0..layers {
+        let len = (layers - layer) * 4 + if compact { 9 } else { 12 };
+        let (low, high) = (layer * 2, base - 1 - layer * 2);
+        // Down the left, along the bottom, up the right and back along the
+        // top, two modules deep, outer module first
+        let mut sides = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        for j in 0..len {
+            for k in 0..2 {
+                sides[0].push(at(low + k, low + j));
+                sides[1].push(at(low + j, high - k));
+                sides[2].push(at(high - k, high - j));
+                sides[3].push(at(high - j, low + k));
+            }
+        }
+        bits.extend(sides.concat());
+    }
+    (size as u32, bits)
+}
+
+/// The field data codewords are in, for a symbol with `word_size`-bit
+/// codewords
+fn data_field(word_size: usize) -> Field {
+    match word_size {
+        6 => Field::new(6, 0x43),
+        8 => Field::new(8, 0x12d),
+        10 => Field::new(10, 0x409),
+        _ => Field::new(12, 0x1069),
+    }
+}
+
+/// Splits the data layers' bits into codewords, dropping the padding at the
+/// start. Returns the codewords and their size in bits.
+fn codewords(bits: &[bool], layers: u32) -> (Vec<u16>, usize) {
+    let word_size = match layers {
+        1..=2 => 6,
+        3..=8 => 8,
+        9..=22 => 10,
+        _ => 12,
+    };
+    let words = bits[bits.len() % word_size..]
+        .chunks(word_size)
+        .map(|w| w.iter().fold(0, |acc, &b| acc << 1 | b as u16))
+        .collect();
+    (words, word_size)
+}
+
+/// The message bits in the data codewords. Since codewords of all 0s or all
+/// 1s aren't allowed, a codeword whose first bits would all be the same
+/// has the opposite bit "stuffed" after them, which comes out again here.
+fn unstuff(words: &[u16], word_size: usize) -> Option<Vec<bool>> {
+    let all = (1 << word_size) - 1;
+    let mut bits = Vec::with_capacity(words.len() * word_size);
+    for &w in words {
+        if w == 0 || w == all {
+            return None;
+        }
+        if w == 1 || w == all - 1 {
+            bits.extend(std::iter::repeat_n(w > 1, word_size - 1));
+        } else {
+            bits.extend((0..word_size).rev().map(|bit| w >> bit & 1 == 1));
+        }
+    }
+    Some(bits)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Upper,
+    Lower,
+    Mixed,
+    Punct,
+    Digit,
+    Binary,
+}
+
+enum Code {
+    Byte(u8),
+    Text(&'static [u8]),
+    /// Switch to a mode until told otherwise
+    Latch(Mode),
+    /// Switch to a mode for the next character only
+    Shift(Mode),
+    /// FNC1, or an ECI
+    Flag,
+}
+
+/// What `value` means in `mode`
+fn lookup(mode: Mode, value: u16) -> Code {
+    let v = value as u8;
+    match (mode, v) {
+        (Mode::Punct, 0) => Code::Flag,
+        (_, 0) => Code::Shift(Mode::Punct),
+        (Mode::Punct, 1..=5) => Code::Text([&b"\r"[..], b"\r\n", b". ", b", ", b": "][v as usize - 1]),
+        (Mode::Punct, 6..=30) => Code::Byte(b"!\"#$%&'()*+,-./:;<=>?[]{}"[v as usize - 6]),
+        (Mode::Punct, _) => Code::Latch(Mode::Upper),
+        (_, 1) => Code::Byte(b' '),
+        (Mode::Digit, 2..=11) => Code::Byte(b'0' + v - 2),
+        (Mode::Digit, 12) => Code::Byte(b','),
+        (Mode::Digit, 13) => Code::Byte(b'.'),
+        (Mode::Digit, 14) => Code::Latch(Mode::Upper),
+        (Mode::Digit, _) => Code::Shift(Mode::Upper),
+        (Mode::Upper, 2..=27) => Code::Byte(b'A' + v - 2),
+        (Mode::Lower, 2..=27) => Code::Byte(b'a' + v - 2),
+        (Mode::Upper, 28) => Code::Latch(Mode::Lower),
+        (Mode::Lower, 28) => Code::Shift(Mode::Upper),
+        (Mode::Upper | Mode::Lower, 29) => Code::Latch(Mode::Mixed),
+        (Mode::Upper | Mode::Lower, 30) => Code::Latch(Mode::Digit),
+        // Control characters 1 to 13, then 27 to 31
+        (Mode::Mixed, 2..=14) => Code::Byte(v - 1),
+        (Mode::Mixed, 15..=19) => Code::Byte(v + 12),
+        (Mode::Mixed, 20..=27) => Code::Byte(b"@\\^_`|~\x7f"[v as usize - 20]),
+        (Mode::Mixed, 28) => Code::Latch(Mode::Lower),
+        (Mode::Mixed, 29) => Code::Latch(Mode::Upper),
+        (Mode::Mixed, 30) => Code::Latch(Mode::Punct),
+        _ => Code::Shift(Mode::Binary),
+    }
+}
+
+/// Turns the message bits into bytes
+fn decode_message(bits: &[bool]) -> Option<Vec<u8>> {
+    let mut pos = 0;
+    let mut read = |n: usize| -> Option<u16> {
+        let value = bits.get(pos..pos + n)?.iter().fold(0, |acc, &b| acc << 1 | b as u16);
+        pos += n;
+        Some(value)
+    };
+
+    let mut out = Vec::new();
+    // The mode to go back to after a shift
+    let mut latched = Mode::Upper;
+    let mut mode = Mode::Upper;
+    // The message is padded out with 1s, which can leave a partial code, or
+    // a binary run too short for its length, at the end
+    loop {
+        if mode == Mode::Binary {
+            let Some(mut len) = read(5) else { break };
+            if len == 0 {
+                let Some(long) = read(11) else { break };
+                len = long + 31;
+            }
+            for _ in 0..len {
+                let Some(byte) = read(8) else { return Some(out) };
+                out.push(byte as u8);
+            }
+            mode = latched;
+            continue;
+        }
+        let Some(value) = read(if mode == Mode::Digit { 4 } else { 5 }) else { break };
+        match lookup(mode, value) {
+            Code::Byte(b) => {
+                out.push(b);
+                mode = latched;
+            }
+            Code::Text(t) => {
+                out.extend_from_slice(t);
+                mode = latched;
+            }
+            Code::Latch(m) => {
+                latched = m;
+                mode = m;
+            }
+            Code::Shift(m) => {
+                latched = mode;
+                mode = m;
+            }
+            Code::Flag => {
+                match read(3) {
+                    // FNC1, which GS1 data marks with a group separator
+                    Some(0) => out.push(0x1d),
+                    Some(7) => return None,
+                    // An ECI number, of that many digits; the bytes are
+                    // passed on as they are
+                    Some(digits) => {
+                        for _ in 0..digits {
+                            read(4)?;
+                        }
+                    }
+                    None => break,
+                }
+                mode = latched;
+            }
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Message bits from (value, width) pairs
+    fn bits(codes: &[(u16, usize)]) -> Vec<bool> {
+        codes.iter().flat_map(|&(value, width)| (0..width).rev().map(move |bit| value >> bit & 1 == 1)).collect()
+    }
+
+    /// `bits` as codewords, stuffed, and padded out with 1s
+    fn stuff(bits: &[bool], word_size: usize) -> Vec<u16> {
+        let bit = |i: usize| bits.get(i).copied().unwrap_or(true);
+        let mut words = Vec::new();
+        let mut i = 0;
+        while i < bits.len() {
+            let first = (i..i + word_size - 1).fold(0, |acc, i| acc << 1 | bit(i) as u16);
+            let all = (1 << (word_size - 1)) - 1;
+            if first == 0 || first == all {
+                words.push(first << 1 | (first == 0) as u16);
+                i += word_size - 1;
+            } else {
+                words.push(first << 1 | bit(i + word_size - 1) as u16);
+                i += word_size;
+            }
+        }
+        words
+    }
+
+    /// An Aztec symbol with `layers` layers holding `message`, drawn at 4
+    /// pixels a module, mirrored if asked. Returns it and the number of
+    /// pixels in from its edge the centre module's middle is.
+    fn draw(compact: bool, layers: u32, message: &[bool], mirrored: bool) -> (Bitmap, f64) {
+        let capacity: usize = (0..layers)
+            .map(|layer| 8 * ((layers - layer) * 4 + if compact { 9 } else { 12 }) as usize)
+            .sum();
+        let word_size = codewords(&vec![false; capacity], layers).1;
+        let data = stuff(message, word_size);
+        let total = capacity / word_size;
+        let mut words = data.clone();
+        words.extend(data_field(word_size).encode(&data, total - data.len(), 1));
+        let mut data_bits = vec![false; capacity % word_size];
+        data_bits.extend(words.iter().flat_map(|&w| (0..word_size).rev().map(move |bit| w >> bit & 1 == 1)));
+
+        let (r, base): (i32, i32) = if compact { (5, 11) } else { (7, 14) };
+        let base = base + 4 * layers as i32;
+        let size = if compact { base } else { full_size(layers) };
+        let center = size / 2;
+        let mut dark = vec![false; (size * size) as usize];
+        let mut set = |x: i32, y: i32, d: bool| dark[((y + center) * size + x + center) as usize] = d;
+
+        // Reference grid, then the bullseye and mode message over it
+        if !compact {
+            for y in -center..=center {
+                for x in -center..=center {
+                    if x % 16 == 0 || y % 16 == 0 {
+                        set(x, y, (x + y) % 2 == 0);
+                    }
+                }
+            }
+        }
+        for y in -r..=r {
+            for x in -r..=r {
+                let ring = x.abs().max(y.abs());
+                if ring < r {
+                    set(x, y, ring % 2 == 0);
+                }
+            }
+        }
+        let mode = if compact { (layers - 1) << 6 | (data.len() as u32 - 1) } else { (layers - 1) << 11 | (data.len() as u32 - 1) };
+        let mode_words: Vec<u16> = (0..if compact { 2 } else { 4 }).rev().map(|i| (mode >> (4 * i) & 0xf) as u16).collect();
+        let mut mode_bits = mode_words.clone();
+        mode_bits.extend(Field::new(4, 0x13).encode(&mode_words, if compact { 5 } else { 6 }, 1));
+        let mut mode_bits = mode_bits.iter().flat_map(|&w| (0..4).rev().map(move |bit| w >> bit & 1 == 1));
+        for (n, ((x, y), i)) in ring(r).enumerate() {
+            if let Some(mark) = orientation_mark(n / (2 * r as usize), i, r) {
+                set(x, y, mark);
+            } else if (2..2 * r - 1).contains(&i) && (compact || i != r) {
+                set(x, y, mode_bits.next().unwrap());
+            }
+        }
+
+        // The data layers, laid down in the order `read_data` reads them
+        let map: Vec<i32> = if compact {
+            (0..base).collect()
+        } else {
+            let orig_center = base / 2;
+            let mut map = vec![0; base as usize];
+            for i in 0..orig_center {
+                let offset = i + i / 15;
+                map[(orig_center - i - 1) as usize] = center - offset - 1;
+                map[(orig_center + i) as usize] = center + offset + 1;
+            }
+            map
+        };
+        let mut data_bits = data_bits.into_iter();
+        for layer in 0..layers as i32 {
+            let len = (layers as i32 - layer) * 4 + if compact { 9 } else { 12 };
+            let (low, high) = (layer * 2, base - 1 - layer * 2);
+            let mut sides: [Vec<(i32, i32)>; 4] = Default::default();
+            for j in 0..len {
+                for k in 0..2 {
+                    sides[0].push((low + k, low + j));
+                    sides[1].push((low + j, high - k));
+                    sides[2].push((high - k, high - j));
+                    sides[3].push((high - j, low + k));
+                }
+            }
+            for (x, y) in sides.concat() {
+                set(map[x as usize] - center, map[y as usize] - center, data_bits.next().unwrap());
+            }
+        }
+
+        let (scale, margin) = (4, 24);
+        let side = size as u32 * scale + 2 * margin;
+        let mut img = Bitmap::new(side, side);
+        for y in 0..size as u32 * scale {
+            for x in 0..size as u32 * scale {
+                let module_x = if mirrored { size - 1 - (x / scale) as i32 } else { (x / scale) as i32 };
+                *img.get_pixel_mut(margin + x, margin + y) = !dark[((y / scale) as i32 * size + module_x) as usize];
+            }
+        }
+        (img, (margin + center as u32 * scale) as f64 + scale as f64 / 2.0)
+    }
+
+    /// "Hello 42": H, latch to lower case, ello and a space, latch to
+    /// digits, then 4 and 2
+    fn hello() -> Vec<bool> {
+        bits(&[(9, 5), (28, 5), (6, 5), (13, 5), (13, 5), (16, 5), (1, 5), (30, 5), (6, 4), (4, 4)])
+    }
+
+    #[test]
+    fn decodes_messages() {
+        assert_eq!(decode_message(&hello()).as_deref(), Some(&b"Hello 42"[..]));
+        // A binary shift of two bytes, then punctuation, FNC1 and an ECI
+        let message = bits(&[(31, 5), (2, 5), (0xff, 8), (0x00, 8), (0, 5), (0, 5), (0, 3), (0, 5), (0, 5), (1, 3), (3, 4)]);
+        assert_eq!(decode_message(&message).as_deref(), Some(&[0xff, 0x00, 0x1d][..]));
+        // A flag of 7 is reserved
+        assert_eq!(decode_message(&bits(&[(0, 5), (0, 5), (7, 3)])), None);
+        // Padding 1s that don't make a whole code are dropped
+        assert_eq!(decode_message(&bits(&[(2, 5), (0x7, 3)])).as_deref(), Some(&b"A"[..]));
+    }
+
+    #[test]
+    fn unstuffs_codewords() {
+        let message = bits(&[(0, 5), (0x1f, 5), (0b10110, 5)]);
+        let words = stuff(&message, 6);
+        assert_eq!(words[..2], [0b000001, 0b111110]);
+        let unstuffed = unstuff(&words, 6).unwrap();
+        assert_eq!(unstuffed[..message.len()], message[..]);
+        // All 0s and all 1s aren't allowed
+        assert_eq!(unstuff(&[0], 6), None);
+        assert_eq!(unstuff(&[0x3f], 6), None);
+    }
+
+    #[test]
+    fn finds_bullseyes_and_reads_mode_messages() {
+        for (compact, layers) in [(true, 1), (true, 4), (false, 5)] {
+            let (img, center) = draw(compact, layers, &hello(), false);
+            let b = find_bullseyes(&img).into_iter()
+                .find(|b| b.center.dist_to(Point::new(center, center)) < 2.0)
+                .unwrap();
+            assert!((b.module - 4.0).abs() < 1.0);
+
+            // Sampled where it was drawn, since the bullseye's corners don't
+            // say which way up it is
+            let to_image = Homography([[4.0, 0.0, center], [0.0, 4.0, center], [0.0, 0.0, 1.0]]);
+            let sampler = Sampler { img: &img, to_image, turns: 0, mirrored: false };
+            let (read_layers, data_words) = read_mode_message(&sampler, compact).unwrap();
+            assert_eq!(read_layers, layers);
+            assert_eq!(data_words, stuff(&hello(), codewords(&[], layers).1).len());
+        }
+    }
+
+    #[test]
+    fn decodes_compact_and_full_size_symbols() {
+        for (compact, layers, mirrored) in [(true, 1, false), (true, 3, true), (false, 5, false)] {
+            let (img, _) = draw(compact, layers, &hello(), mirrored);
+            let codes = scan(&img);
+            assert_eq!(codes.len(), 1, "{compact} {layers}");
+            let code = &codes[0];
+            assert_eq!((code.compact, code.layers, code.mirrored), (compact, layers, mirrored));
+            assert_eq!(code.payload, b"Hello 42");
+            assert_eq!(code.corrected, 0);
+        }
+    }
+}
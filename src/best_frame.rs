@@ -0,0 +1,125 @@
+//! Picks the best of several recent frames of each tracked code to decode,
+//! so the expensive decoding step runs once on a good view instead of on
+//! every frame, blurry and tiny ones included.
+//!
+//! Frames are scored on how big the code is, how sharp it is, and how still
+//! it's holding (fast-moving codes smear).
+
+use std::{collections::HashMap, ops::Deref};
+use image::{GrayImage, ImageBuffer, Luma, Pixel};
+use crate::{
+    Point,
+    Rect,
+    feedback::FrameStats,
+    target::complete_quad,
+    tracker::{Track, TrackId, Tracker},
+};
+
+/// The best view of a code from a window of frames, cropped out of its frame
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub track: TrackId,
+    pub score: f64,
+    /// The code and some of its surroundings, in greyscale
+    pub image: GrayImage,
+    /// Where the code's corners are in `image`, in the same order as
+    /// `ScanResult::bbox`
+    pub corners: [Point<f64>; 3],
+}
+
+#[derive(Clone, Debug)]
+struct Pending {
+    best: Candidate,
+    frames: u32,
+}
+
+/// Collects candidate frames for each tracked code and hands back the best
+/// one every `window` frames
+#[derive(Clone, Debug)]
+pub struct BestFrameSelector {
+    pending: HashMap<TrackId, Pending>,
+    /// Number of frames of a code to choose between
+    pub window: u32,
+    /// Code side length, in pixels, past which being bigger doesn't improve
+    /// the score
+    pub target_side: f64,
+    /// Surroundings to crop along with the code, as a fraction of its size
+    pub margin: f64,
+}
+
+impl Default for BestFrameSelector {
+    fn default() -> Self {
+        Self { pending: HashMap::new(), window: 5, target_side: 150.0, margin: 0.5 }
+    }
+}
+
+impl BestFrameSelector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scores a frame of a code, from 0 to about 1
+    pub fn score<Px, C>(&self, img: &ImageBuffer<Px, C>, track: &Track) -> f64
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        let side = track.side_len();
+        let size = (side / self.target_side).min(1.0);
+        let region = Rect::bounding(&complete_quad(track.raw_corners)).to_pixels();
+        let sharpness = FrameStats::measure(img, Some(region), 1).sharpness.min(1.0);
+        // Moving a whole code width per second counts as half as stable
+        let stability = 1.0 / (1.0 + track.speed() / side.max(1.0));
+        size * sharpness * stability
+    }
+
+    /// Considers the current frame for every confirmed code detected in it,
+    /// and returns the best frame of each code whose window just filled up.
+    /// Codes the tracker has forgotten are dropped without being returned.
+    pub fn update<Px, C>(&mut self, img: &ImageBuffer<Px, C>, tracker: &Tracker) -> Vec<Candidate>
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        self.pending.retain(|id, _| tracker.tracks().iter().any(|t| t.id == *id));
+
+        let mut ready = Vec::new();
+        for track in tracker.confirmed().filter(|t| t.misses == 0) {
+            let score = self.score(img, track);
+            let pending = self.pending.get(&track.id);
+            let better = pending.is_none_or(|p| score > p.best.score);
+            let frames = pending.map_or(0, |p| p.frames) + 1;
+
+            if better {
+                let best = self.crop(img, track, score);
+                self.pending.insert(track.id, Pending { best, frames });
+            } else if let Some(p) = self.pending.get_mut(&track.id) {
+                p.frames = frames;
+            }
+
+            if frames >= self.window {
+                if let Some(p) = self.pending.remove(&track.id) {
+                    ready.push(p.best);
+                }
+            }
+        }
+        ready
+    }
+
+    fn crop<Px, C>(&self, img: &ImageBuffer<Px, C>, track: &Track, score: f64) -> Candidate
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        let (width, height) = img.dimensions();
+        let quad = complete_quad(track.raw_corners);
+        let rect = Rect::bounding(&quad).inflate(track.side_len() * self.margin).to_pixels();
+        let (x0, y0) = (rect.min.x.min(width), rect.min.y.min(height));
+        let (x1, y1) = (rect.max.x.min(width), rect.max.y.min(height));
+        let image = GrayImage::from_fn(x1 - x0, y1 - y0, |x, y| {
+            Luma([img.get_pixel(x0 + x, y0 + y).to_luma().0[0]])
+        });
+        let corners = track.raw_corners.map(|c| Point::new(c.x - x0 as f64, c.y - y0 as f64));
+        Candidate { track: track.id, score, image, corners }
+    }
+}
@@ -0,0 +1,704 @@
+//! Scans image files from the command line and prints what was found in each.
+//!
+//! Usage: `cargo run --bin arqr-cli -- [--format text|json|csv] [--jobs N] <image or dir>...`
+//!
+//! or, to scan raw frames piped in from something like `ffmpeg` or
+//! `libcamera-vid`: `cargo run --bin arqr-cli -- --stdin WxH [--pix-fmt gray|nv12]`
+//!
+//! Scanner settings are read from `arqr.toml` if there is one, or the file
+//! given with `--config` (see `arqr::config`). `--binarizer global|adaptive`
+//! overrides the file.
+//!
+//! Directories are searched recursively for images, in any format `image`
+//! reads, WebP included, and HEIF and AVIF photos when built with the `heif`
+//! feature (which needs libheif). Animated GIFs, PNGs and WebPs and
+//! multi-page TIFFs are scanned frame by frame, as are the pages of PDFs
+//! when built with the `pdf` feature (which needs poppler's `pdftoppm`), and
+//! the images in a HEIF file. Frames and pages are numbered from 0, as in
+//! `scan.tif[3]`. Each code found is given as its four corners (top-left,
+//! top-right, bottom-right, bottom-left) in pixels, and its payload if it
+//! could be read. Files are scanned in parallel, but always reported in
+//! order.
+//!
+//! With `--stdin`, frames of the given size are read back to back from
+//! standard input until it closes, and each gets a line of JSON as soon as
+//! it's scanned:
+//!
+//! ```text
+//! {"schema":1,"sequence":0,"stage":"complete","targets":3,"codes":[...],"timings_ms":{...}}
+//! ```
+//!
+//! laid out as described in `arqr::json`, with `sequence` counting frames
+//! from 0. For NV12 only the luma plane is looked at.
+//!
+//! `--best-frame` (with `--stdin`) follows codes from frame to frame and
+//! reads each one only once every few frames, from whichever of them showed
+//! it biggest, sharpest and stillest (see `arqr::best_frame`). The frame
+//! that finishes each lot carries what was read; the rest come out with a
+//! null `payload` and `decode_error`.
+//!
+//! `--super-res` (with `--stdin`) also follows codes, and builds up a
+//! sharper view of each one too small to read from the frames it's in (see
+//! `arqr::superres`). When that view is read, the frame that finished it
+//! carries what was read, if the frame itself wasn't.
+//!
+//! For scripts, the exit status says how scanning files went:
+//!
+//! - 0: at least one code was read
+//! - 1: no codes were read (though some may have been found)
+//! - 2: the command line was wrong
+//! - 3: something couldn't be read, and no codes were read
+//!
+//! `--corpus DIR` saves every frame where a code was found but couldn't be
+//! read, or position targets were found but no code was, along with what
+//! the scanner made of it (see `arqr::corpus`).
+//!
+//! `--annotate OUT.png` scans a single image and saves a copy of it with what
+//! was found drawn on (see `arqr::draw`). For animations, that's the first
+//! frame. If `OUT` ends in `.svg`, only the overlay is saved, as an SVG to
+//! lay over the original.
+//!
+//! `--format json` prints an array with an object for each frame, holding the
+//! scanner's result in the same layout:
+//!
+//! ```text
+//! {"file":"a.gif","frame":2,"load_ms":3.456,"result":{"schema":1,...}}
+//! ```
+//!
+//! and `{"file":"b.png","error":"..."}` for each file that couldn't be read.
+//!
+//! `--quiet` prints only the payloads of the codes read, one per line, and
+//! read errors. In every format, bytes of a payload that aren't UTF-8 are
+//! replaced with U+FFFD.
+//!
+//! `--copy` puts the last payload read on the clipboard, or with `--stdin`,
+//! each new payload as it's read. It needs the `clipboard` feature.
+//!
+//! `--zbar` behaves like `zbarimg`, for scripts written around it: a
+//! `QR-Code:<payload>` line for each code read, then `scanned N barcode
+//! symbols from M images in S seconds` on standard error, left out if
+//! `--quiet` is given too, as with `zbarimg -q`. Each frame counts as an
+//! image, and only codes that were read count as symbols, as `zbarimg` only
+//! reports what it decodes. The exit status is `zbarimg`'s too: 0 if every
+//! frame had a code read, 1 if something couldn't be read, and 4 if some
+//! frame didn't.
+
+use std::{
+    env,
+    fs,
+    io::{self, Read, Write as _},
+    path::{Path, PathBuf},
+    process,
+    sync::{Mutex, atomic::{AtomicUsize, Ordering}},
+    thread,
+    time::Instant,
+};
+use image::{GrayImage, ImageBuffer, ImageFormat, Luma, imageops};
+use arqr::{
+    FrameMeta, Point, ScanResult, Scanner,
+    best_frame::BestFrameSelector,
+    corpus::Corpus,
+    draw::Overlay,
+    frames::open_frames,
+    json,
+    superres::SuperResolver,
+    tracker::Tracker,
+};
+#[cfg(feature = "clipboard")]
+use arqr::clipboard::Clipboard;
+#[cfg(feature = "config")]
+use arqr::config::{BinarizerKind, CONFIG_FILE, Config};
+#[cfg(feature = "heif")]
+use arqr::heif::is_heif;
+#[cfg(feature = "pdf")]
+use arqr::pdf::is_pdf;
+
+const USAGE: &str = if cfg!(feature = "config") {
+    "usage: arqr-cli [--config FILE] [--binarizer global|adaptive] [--format text|json|csv | --zbar | --quiet] [--jobs N] [--corpus DIR] [--copy] <image or dir>...\n       arqr-cli [--config FILE] [--binarizer global|adaptive] --annotate OUT.png <image>\n       arqr-cli [--config FILE] [--binarizer global|adaptive] [--corpus DIR] [--copy] --stdin WxH [--pix-fmt gray|nv12] [--best-frame] [--super-res]"
+} else {
+    "usage: arqr-cli [--format text|json|csv | --zbar | --quiet] [--jobs N] [--corpus DIR] [--copy] <image or dir>...\n       arqr-cli --annotate OUT.png <image>\n       arqr-cli [--corpus DIR] [--copy] --stdin WxH [--pix-fmt gray|nv12] [--best-frame] [--super-res]"
+};
+
+/// Exit status when no codes were read
+const EXIT_NOT_FOUND: i32 = 1;
+/// Exit status when something couldn't be read (and no codes were read)
+const EXIT_READ_ERROR: i32 = 3;
+/// `zbarimg`'s exit status when something couldn't be read
+const EXIT_ZBAR_ERROR: i32 = 1;
+/// `zbarimg`'s exit status when some image had no code in it
+const EXIT_ZBAR_NOT_FOUND: i32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    Csv,
+}
+
+/// Layout of raw frames read with `--stdin`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PixelFormat {
+    /// One byte per pixel
+    Gray,
+    /// A full-size luma plane, then a half-size interleaved chroma plane
+    Nv12,
+}
+
+impl PixelFormat {
+    fn frame_len(self, width: u32, height: u32) -> usize {
+        let luma = width as usize * height as usize;
+        match self {
+            PixelFormat::Gray => luma,
+            PixelFormat::Nv12 => luma + 2 * (width as usize).div_ceil(2) * (height as usize).div_ceil(2),
+        }
+    }
+}
+
+/// What was found in one frame of a file
+#[derive(Debug)]
+struct FrameReport {
+    index: usize,
+    quad: Option<[Point<f64>; 4]>,
+    /// The code's version, if it was sampled
+    version: Option<u32>,
+    /// The code's message, if it could be read
+    payload: Option<String>,
+    /// Why the code's message couldn't be read
+    decode_error: Option<arqr::decode::DecodeError>,
+    /// Time spent decoding the frame from the file, in milliseconds
+    load_ms: f64,
+    /// Time spent scanning the frame, in milliseconds
+    scan_ms: f64,
+    /// The result, from `ScanResult::to_json`
+    json: String,
+}
+
+/// Everything found in one file, or why it couldn't be read. Frames read
+/// before an error are kept.
+#[derive(Debug)]
+struct FileReport {
+    path: PathBuf,
+    frames: Vec<FrameReport>,
+    error: Option<String>,
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+#[cfg(not(feature = "pdf"))]
+fn is_pdf(_: &Path) -> bool {
+    false
+}
+
+#[cfg(not(feature = "heif"))]
+fn is_heif(_: &Path) -> bool {
+    false
+}
+
+/// Adds every image under `dir` to `files`. Anything that doesn't look like an
+/// image is skipped.
+fn collect_images(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_images(&path, files)?;
+        } else if ImageFormat::from_path(&path).is_ok() || is_pdf(&path) || is_heif(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Saves a failed frame into the corpus, if there is one. Failing to save
+/// is reported, but doesn't stop the scan.
+fn offer_to_corpus(corpus: Option<&Mutex<Corpus>>, img: &GrayImage, result: &ScanResult, source: &str) {
+    let Some(corpus) = corpus else { return };
+    if let Err(e) = corpus.lock().unwrap().offer(img, result, source) {
+        eprintln!("couldn't save {} to the corpus: {}", source, e);
+    }
+}
+
+fn scan_file(scanner: &mut Scanner, path: &Path, corpus: Option<&Mutex<Corpus>>) -> FileReport {
+    let mut report = FileReport { path: path.to_owned(), frames: Vec::new(), error: None };
+    let mut load_start = Instant::now();
+    let frames = match open_frames(path) {
+        Ok(frames) => frames,
+        Err(e) => {
+            report.error = Some(e.to_string());
+            return report;
+        }
+    };
+    for frame in frames {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                report.error = Some(e.to_string());
+                break;
+            }
+        };
+        let load_ms = load_start.elapsed().as_secs_f64() * 1000.0;
+
+        let scan_start = Instant::now();
+        scanner.set_frame_meta(FrameMeta { sequence: frame.index as u64, timestamp: None });
+        let result = scanner.scan(&frame.image);
+        let scan_ms = scan_start.elapsed().as_secs_f64() * 1000.0;
+        if corpus.is_some() && Corpus::is_failure(&result) {
+            let gray = imageops::grayscale(&frame.image);
+            offer_to_corpus(corpus, &gray, &result, &format!("{}[{}]", path.display(), frame.index));
+        }
+
+        let quad = result.quad();
+        let payload = result.payload.as_deref().map(|p| String::from_utf8_lossy(p).into_owned());
+        report.frames.push(FrameReport {
+            index: frame.index,
+            quad,
+            version: result.version,
+            payload,
+            decode_error: result.decode_error,
+            load_ms,
+            scan_ms,
+            json: result.to_json(),
+        });
+        load_start = Instant::now();
+    }
+    report
+}
+
+/// Parses a frame size like `640x480`
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (w, h) = size.split_once('x')?;
+    Some((w.parse().ok().filter(|&w| w > 0)?, h.parse().ok().filter(|&h| h > 0)?))
+}
+
+/// Scans raw frames from standard input until it closes, printing a line of
+/// JSON for each. `on_read` is given each payload read that differs from the
+/// one before.
+/// What `--stdin` does with codes it follows from frame to frame
+#[derive(Clone, Copy, Debug, Default)]
+struct Following {
+    /// `--best-frame`: only decode the best of every few frames
+    best_frame: bool,
+    /// `--super-res`: read codes too small to read from several frames at
+    /// once
+    super_res: bool,
+}
+
+fn scan_stdin(
+    width: u32,
+    height: u32,
+    pix_fmt: PixelFormat,
+    configure: impl Fn(&mut Scanner),
+    following: Following,
+    corpus: Option<&Mutex<Corpus>>,
+    mut on_read: impl FnMut(&[u8]),
+) -> io::Result<()> {
+    let mut scanner = Scanner::new();
+    configure(&mut scanner);
+    scanner.decode = !following.best_frame;
+    let mut tracker = (following.best_frame || following.super_res).then(Tracker::new);
+    let mut selector = following.best_frame.then(BestFrameSelector::new);
+    let mut resolver = following.super_res.then(SuperResolver::new);
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    let mut frame = vec![0; pix_fmt.frame_len(width, height)];
+    let mut last_payload = None;
+    for index in 0.. {
+        // Stopping between frames is the normal way to finish
+        let mut filled = 0;
+        while filled < frame.len() {
+            match input.read(&mut frame[filled..])? {
+                0 if filled == 0 => return Ok(()),
+                0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "input ended partway through a frame")),
+                n => filled += n,
+            }
+        }
+
+        // Both formats start with the luma plane, which is all that's scanned
+        let luma = &frame[..width as usize * height as usize];
+        let img = ImageBuffer::<Luma<u8>, _>::from_raw(width, height, luma).unwrap();
+        scanner.set_frame_meta(FrameMeta { sequence: index, timestamp: None });
+        let mut result = scanner.scan(&img);
+        if let Some(tracker) = &mut tracker {
+            tracker.update(&result);
+            for candidate in selector.as_mut().map(|s| s.update(&img, tracker)).unwrap_or_default() {
+                let read = scanner.decode_candidate(&candidate);
+                let done = read.payload.is_some();
+                take_read(&mut result, read);
+                if done {
+                    break;
+                }
+            }
+            // Composites keep building whether or not this frame was read
+            let composite_reads = resolver.as_mut().map(|r| r.update(&img, tracker)).unwrap_or_default();
+            if let Some((_, read)) = composite_reads.into_iter().next().filter(|_| result.payload.is_none()) {
+                take_read(&mut result, read);
+            }
+        }
+        if corpus.is_some() && Corpus::is_failure(&result) {
+            let img = ImageBuffer::from_raw(width, height, luma.to_vec()).unwrap();
+            offer_to_corpus(corpus, &img, &result, &format!("stdin[{}]", index));
+        }
+
+        writeln!(output, "{}", result.to_json())?;
+        // Whatever's reading wants each result as it happens
+        output.flush()?;
+        if result.payload.is_some() && result.payload != last_payload {
+            on_read(result.payload.as_deref().unwrap());
+            last_payload = result.payload;
+        }
+    }
+    Ok(())
+}
+
+/// Gives a frame what was read from the best of the frames up to it, or
+/// from a composite of them, and the time reading it took
+fn take_read(frame: &mut ScanResult, read: ScanResult) {
+    frame.timings.decode += read.timings.total();
+    frame.modules = read.modules;
+    frame.version = read.version;
+    frame.format = read.format;
+    frame.payload = read.payload;
+    frame.decode_error = read.decode_error;
+    frame.codeword_errors = read.codeword_errors;
+    frame.mirrored = read.mirrored;
+}
+
+/// Outlines and labels drawn by `--annotate`
+const ANNOTATE_COLOR: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+
+/// Scans the first frame of `path` and saves it to `out` with the overlay
+/// drawn on, or just the overlay if `out` is an SVG. Returns whether a code
+/// was read.
+fn annotate(path: &Path, out: &Path, configure: impl Fn(&mut Scanner)) -> Result<bool, String> {
+    let mut frames = open_frames(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+    let frame = frames.next()
+        .ok_or_else(|| format!("{} has no frames", path.display()))?
+        .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+    let mut scanner = Scanner::new();
+    configure(&mut scanner);
+    let result = scanner.scan(&frame.image);
+    let overlay = Overlay::from_result(&result, ANNOTATE_COLOR);
+    let saved = if out.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("svg")) {
+        let (width, height) = frame.image.dimensions();
+        fs::write(out, overlay.to_svg(width, height)).map_err(|e| e.to_string())
+    } else {
+        let mut img = frame.image;
+        overlay.draw_on(&mut img);
+        img.save(out).map_err(|e| e.to_string())
+    };
+    saved.map_err(|e| format!("couldn't save {}: {}", out.display(), e))?;
+    Ok(result.payload.is_some())
+}
+
+/// Scans every file on `jobs` threads, returning the reports in the same
+/// order as `files`. Each thread's scanner is set up with `configure`.
+fn scan_all(
+    files: &[PathBuf],
+    jobs: usize,
+    configure: impl Fn(&mut Scanner) + Sync,
+    corpus: Option<&Mutex<Corpus>>,
+) -> Vec<FileReport> {
+    let next = AtomicUsize::new(0);
+    let reports: Mutex<Vec<Option<FileReport>>> = Mutex::new((0..files.len()).map(|_| None).collect());
+    thread::scope(|s| {
+        for _ in 0..jobs.min(files.len()) {
+            s.spawn(|| {
+                let mut scanner = Scanner::new();
+                configure(&mut scanner);
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = files.get(i) else { break };
+                    let report = scan_file(&mut scanner, path, corpus);
+                    reports.lock().unwrap()[i] = Some(report);
+                }
+            });
+        }
+    });
+    reports.into_inner().unwrap().into_iter().flatten().collect()
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+fn print_text(reports: &[FileReport]) {
+    for report in reports {
+        let path = report.path.display();
+        for frame in &report.frames {
+            // Only number frames in files that have more than one
+            let name = if report.frames.len() > 1 {
+                format!("{}[{}]", path, frame.index)
+            } else {
+                path.to_string()
+            };
+            match frame.quad {
+                Some(quad) => {
+                    let corners: Vec<String> = quad.iter().map(|p| format!("({:.1}, {:.1})", p.x, p.y)).collect();
+                    match &frame.payload {
+                        Some(payload) => println!("{}: code at {} reads {:?}", name, corners.join(" "), payload),
+                        None => match frame.decode_error {
+                            Some(e) => println!("{}: code at {}, but {}", name, corners.join(" "), e),
+                            None => println!("{}: code at {}", name, corners.join(" ")),
+                        },
+                    }
+                }
+                None => println!("{}: no code found", name),
+            }
+        }
+        if let Some(error) = &report.error {
+            eprintln!("couldn't read {}: {}", path, error);
+        }
+    }
+}
+
+/// One object per frame, plus one per file that couldn't be read
+fn print_json(reports: &[FileReport]) {
+    let mut entries = Vec::new();
+    for report in reports {
+        let file = json::string(&report.path.display().to_string());
+        for frame in &report.frames {
+            entries.push(format!(
+                "{{\"file\":{},\"frame\":{},\"load_ms\":{:.3},\"result\":{}}}",
+                file, frame.index, frame.load_ms, frame.json,
+            ));
+        }
+        if let Some(error) = &report.error {
+            entries.push(format!("{{\"file\":{},\"error\":{}}}", file, json::string(error)));
+        }
+    }
+    println!("[");
+    for (i, entry) in entries.iter().enumerate() {
+        println!("  {}{}", entry, if i + 1 < entries.len() { "," } else { "" });
+    }
+    println!("]");
+}
+
+/// One row per frame, plus one per file that couldn't be read. Corner columns
+/// are empty when no code was found.
+fn print_csv(reports: &[FileReport]) {
+    println!("file,frame,tl_x,tl_y,tr_x,tr_y,br_x,br_y,bl_x,bl_y,version,payload,load_ms,scan_ms,error");
+    for report in reports {
+        let file = csv_field(&report.path.display().to_string());
+        for frame in &report.frames {
+            let corners = match frame.quad {
+                Some(quad) => quad.iter().map(|p| format!("{:.2},{:.2}", p.x, p.y)).collect::<Vec<_>>().join(","),
+                None => ",".repeat(7),
+            };
+            let version = frame.version.map_or(String::new(), |v| v.to_string());
+            let payload = frame.payload.as_deref().map_or(String::new(), csv_field);
+            println!("{},{},{},{},{},{:.3},{:.3},", file, frame.index, corners, version, payload, frame.load_ms, frame.scan_ms);
+        }
+        if let Some(error) = &report.error {
+            println!("{},{}{}", file, ",".repeat(13), csv_field(error));
+        }
+    }
+}
+
+/// Mimics `zbarimg`'s output (see the `--zbar` docs above), and returns the
+/// exit status it would have given
+fn print_zbar(reports: &[FileReport], seconds: f64, quiet: bool) -> i32 {
+    let images: usize = reports.iter().map(|r| r.frames.len()).sum();
+    let mut symbols = 0;
+    for payload in reports.iter().flat_map(|r| &r.frames).filter_map(|frame| frame.payload.as_ref()) {
+        println!("QR-Code:{}", payload);
+        symbols += 1;
+    }
+    for report in reports {
+        if let Some(error) = &report.error {
+            eprintln!("couldn't read {}: {}", report.path.display(), error);
+        }
+    }
+    if !quiet && images > 0 {
+        eprintln!("scanned {} barcode symbols from {} images in {:.2} seconds", symbols, images, seconds);
+        if symbols < images {
+            eprintln!("\nWARNING: barcode data was not detected in some image(s)");
+        }
+    }
+    if reports.iter().any(|r| r.error.is_some()) {
+        EXIT_ZBAR_ERROR
+    } else if symbols < images {
+        EXIT_ZBAR_NOT_FOUND
+    } else {
+        0
+    }
+}
+
+fn main() {
+    let mut format = Format::Text;
+    let mut quiet = false;
+    let mut zbar = false;
+    let mut jobs = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut inputs = Vec::new();
+    let mut stdin_size = None;
+    let mut pix_fmt = PixelFormat::Gray;
+    let mut corpus_dir = None;
+    let mut annotate_out = None;
+    let mut copy = false;
+    let mut following = Following::default();
+    #[cfg(feature = "config")]
+    let (mut config_path, mut binarizer) = (None, None);
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = match args.next().as_deref() {
+                Some("text") => Format::Text,
+                Some("json") => Format::Json,
+                Some("csv") => Format::Csv,
+                _ => usage(),
+            },
+            "--quiet" => quiet = true,
+            "--zbar" => zbar = true,
+            "--jobs" => jobs = match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => n,
+                _ => usage(),
+            },
+            #[cfg(feature = "config")]
+            "--config" => config_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            #[cfg(feature = "config")]
+            "--binarizer" => binarizer = match args.next().as_deref() {
+                Some("global") => Some(BinarizerKind::Global),
+                Some("adaptive") => Some(BinarizerKind::Adaptive),
+                _ => usage(),
+            },
+            "--stdin" => stdin_size = Some(args.next().as_deref().and_then(parse_size).unwrap_or_else(|| usage())),
+            "--pix-fmt" => pix_fmt = match args.next().as_deref() {
+                Some("gray") => PixelFormat::Gray,
+                Some("nv12") => PixelFormat::Nv12,
+                _ => usage(),
+            },
+            "--corpus" => corpus_dir = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--annotate" => annotate_out = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--copy" => copy = true,
+            "--best-frame" => following.best_frame = true,
+            "--super-res" => following.super_res = true,
+            "-h" | "--help" => usage(),
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+    // Files and standard input don't mix
+    if inputs.is_empty() == stdin_size.is_none() || ((following.best_frame || following.super_res) && stdin_size.is_none()) {
+        usage();
+    }
+    if copy && !cfg!(feature = "clipboard") {
+        eprintln!("--copy needs arqr-cli built with the clipboard feature");
+        process::exit(2);
+    }
+
+    #[cfg(feature = "config")]
+    let settings = {
+        let mut config = Config::find(config_path.as_deref()).unwrap_or_else(|e| {
+            let path = config_path.as_deref().unwrap_or(Path::new(CONFIG_FILE));
+            eprintln!("couldn't load {}: {}", path.display(), e);
+            process::exit(EXIT_READ_ERROR);
+        });
+        if let Some(binarizer) = binarizer {
+            config.scanner.binarizer = binarizer;
+        }
+        config.scanner
+    };
+    #[cfg(feature = "config")]
+    let configure = |scanner: &mut Scanner| settings.configure(scanner);
+    #[cfg(not(feature = "config"))]
+    let configure = |_: &mut Scanner| {};
+
+    // Owns the clipboard, which has to be dropped before exiting for what
+    // was copied to outlive arqr-cli (see `arqr::clipboard`)
+    #[cfg(feature = "clipboard")]
+    let mut copy_payload = {
+        let mut clipboard = copy.then(Clipboard::new);
+        move |payload: &[u8]| {
+            if let Some(clipboard) = &mut clipboard {
+                if let Err(e) = clipboard.copy_payload(payload) {
+                    eprintln!("couldn't copy: {}", e);
+                }
+            }
+        }
+    };
+    #[cfg(not(feature = "clipboard"))]
+    let copy_payload = |_: &[u8]| {};
+
+    let corpus = corpus_dir.map(|dir| {
+        Mutex::new(Corpus::create(&dir).unwrap_or_else(|e| {
+            eprintln!("couldn't open the corpus in {}: {}", dir.display(), e);
+            process::exit(EXIT_READ_ERROR);
+        }))
+    });
+
+    if let Some(out) = annotate_out {
+        // One image in, one image out
+        if inputs.len() != 1 || inputs[0].is_dir() || stdin_size.is_some() || copy {
+            usage();
+        }
+        match annotate(&inputs[0], &out, configure) {
+            Ok(true) => return,
+            Ok(false) => process::exit(EXIT_NOT_FOUND),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(EXIT_READ_ERROR);
+            }
+        }
+    }
+
+    if let Some((width, height)) = stdin_size {
+        if let Err(e) = scan_stdin(width, height, pix_fmt, configure, following, corpus.as_ref(), copy_payload) {
+            eprintln!("couldn't read frames: {}", e);
+            process::exit(EXIT_READ_ERROR);
+        }
+        return;
+    }
+
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let mut found = Vec::new();
+            if let Err(e) = collect_images(&input, &mut found) {
+                eprintln!("couldn't read {}: {}", input.display(), e);
+                process::exit(EXIT_READ_ERROR);
+            }
+            found.sort();
+            files.extend(found);
+        } else {
+            files.push(input);
+        }
+    }
+
+    let start = Instant::now();
+    let reports = scan_all(&files, jobs, configure, corpus.as_ref());
+    if let Some(payload) = reports.iter().flat_map(|r| &r.frames).filter_map(|frame| frame.payload.as_ref()).next_back() {
+        copy_payload(payload.as_bytes());
+    }
+    #[cfg(feature = "clipboard")]
+    drop(copy_payload);
+    if zbar {
+        process::exit(print_zbar(&reports, start.elapsed().as_secs_f64(), quiet));
+    }
+    if quiet {
+        for payload in reports.iter().flat_map(|r| &r.frames).filter_map(|frame| frame.payload.as_ref()) {
+            println!("{}", payload);
+        }
+        for report in &reports {
+            if let Some(error) = &report.error {
+                eprintln!("couldn't read {}: {}", report.path.display(), error);
+            }
+        }
+    } else {
+        match format {
+            Format::Text => print_text(&reports),
+            Format::Json => print_json(&reports),
+            Format::Csv => print_csv(&reports),
+        }
+    }
+
+    let read = reports.iter().flat_map(|r| &r.frames).any(|frame| frame.payload.is_some());
+    if !read {
+        let errors = reports.iter().any(|r| r.error.is_some());
+        process::exit(if errors { EXIT_READ_ERROR } else { EXIT_NOT_FOUND });
+    }
+}
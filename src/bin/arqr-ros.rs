@@ -0,0 +1,156 @@
+//! A ROS 2 node that scans camera images for codes, for robots using them as
+//! landmarks.
+//!
+//! Usage: `cargo run --no-default-features --features ros --bin arqr-ros -- [--image TOPIC] [--camera-info TOPIC] [--code-size SIZE]`
+//!
+//! Scans each `sensor_msgs/Image` on `--image` (`/image_raw` by default),
+//! and publishes an `arqr_msgs/Detection` on `/arqr/detections` for every
+//! frame a code is found in. Build `ros/arqr_msgs` in your workspace for
+//! other nodes to read them. If it falls behind, it skips to the newest
+//! frame.
+//!
+//! Given the codes' side length with `--code-size` (in metres, say), it also
+//! takes the camera's intrinsics from `--camera-info` (`/camera_info` by
+//! default), and publishes each code's pose as a `geometry_msgs/PoseStamped`
+//! on `/arqr/pose`, in the image's frame, as well as in its detection.
+//! Images and camera info are subscribed to best-effort, as sensor data
+//! usually is, which works with reliable publishers too.
+//!
+//! The node talks DDS itself, so it joins ROS 2's default domain without a
+//! ROS install. Set `ROS_DOMAIN_ID` for another one.
+
+use std::{env, process};
+use arqr::{
+    Scanner,
+    calib::CameraIntrinsics,
+    ros::{CameraInfo, Detection, Image, PoseStamped, RosType},
+};
+use ros2_client::{
+    Context, Name, Node, NodeName, NodeOptions, QosProfile,
+    dds::rustdds::{Topic, mio::{Events, Poll, PollOpt, Ready, Token}},
+};
+
+const USAGE: &str = "usage: arqr-ros [--image TOPIC] [--camera-info TOPIC] [--code-size SIZE]";
+const IMAGES: Token = Token(0);
+const CAMERA_INFO: Token = Token(1);
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail(what: &str, e: impl std::fmt::Display) -> ! {
+    eprintln!("couldn't {}: {}", what, e);
+    process::exit(1);
+}
+
+fn topic<T: RosType>(node: &Node, name: &str) -> Topic {
+    let name = Name::parse(name).unwrap_or_else(|e| fail(&format!("use {} as a topic name", name), e));
+    node.create_topic(&name, T::message_type_name(), &QosProfile::subscription_default())
+        .unwrap_or_else(|e| fail("create a topic", e))
+}
+
+fn main() {
+    let mut image_topic = "/image_raw".to_owned();
+    let mut info_topic = "/camera_info".to_owned();
+    let mut code_size: Option<f64> = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--image" => image_topic = args.next().unwrap_or_else(|| usage()),
+            "--camera-info" => info_topic = args.next().unwrap_or_else(|| usage()),
+            "--code-size" => code_size = match args.next().and_then(|s| s.parse().ok()) {
+                Some(size) if size > 0.0 => Some(size),
+                _ => usage(),
+            },
+            _ => usage(),
+        }
+    }
+
+    let context = Context::new().unwrap_or_else(|e| fail("start DDS", e));
+    let node_name = NodeName::new("/", "arqr").unwrap();
+    let mut node = context.new_node(node_name, NodeOptions::new()).unwrap_or_else(|e| fail("create the node", e));
+
+    let images_topic = topic::<Image>(&node, &image_topic);
+    let images = node.create_subscription::<Image>(&images_topic, None)
+        .unwrap_or_else(|e| fail("subscribe to images", e));
+    let detections_topic = topic::<Detection>(&node, "/arqr/detections");
+    let detections = node.create_publisher::<Detection>(&detections_topic, Some(QosProfile::publisher_default()))
+        .unwrap_or_else(|e| fail("publish detections", e));
+    // Poses need the camera's intrinsics, so only bother with either if
+    // they're wanted
+    let poses = code_size.map(|_| {
+        let info_topic = topic::<CameraInfo>(&node, &info_topic);
+        let infos = node.create_subscription::<CameraInfo>(&info_topic, None)
+            .unwrap_or_else(|e| fail("subscribe to camera info", e));
+        let pose_topic = topic::<PoseStamped>(&node, "/arqr/pose");
+        let publisher = node.create_publisher::<PoseStamped>(&pose_topic, Some(QosProfile::publisher_default()))
+            .unwrap_or_else(|e| fail("publish poses", e));
+        (infos, publisher)
+    });
+
+    let poll = Poll::new().unwrap_or_else(|e| fail("poll", e));
+    poll.register(&images, IMAGES, Ready::readable(), PollOpt::edge())
+        .unwrap_or_else(|e| fail("poll", e));
+    if let Some((infos, _)) = &poses {
+        poll.register(infos, CAMERA_INFO, Ready::readable(), PollOpt::edge())
+            .unwrap_or_else(|e| fail("poll", e));
+    }
+    eprintln!("scanning {}", image_topic);
+
+    let mut scanner = Scanner::new();
+    let mut intrinsics: Option<CameraIntrinsics> = None;
+    let (mut warned_encoding, mut warned_intrinsics) = (false, false);
+    let mut events = Events::with_capacity(8);
+    loop {
+        poll.poll(&mut events, None).unwrap_or_else(|e| fail("poll", e));
+        for event in &events {
+            match event.token() {
+                CAMERA_INFO => {
+                    let Some((infos, _)) = &poses else { continue };
+                    // Edge-triggered, so read everything that's arrived
+                    while let Some((info, _)) = infos.take().unwrap_or_else(|e| {
+                        eprintln!("couldn't read camera info: {}", e);
+                        None
+                    }) {
+                        intrinsics = info.intrinsics();
+                        if intrinsics.is_none() && !warned_intrinsics {
+                            eprintln!("camera info has no usable intrinsics, so no poses until it does");
+                            warned_intrinsics = true;
+                        }
+                    }
+                }
+                IMAGES => {
+                    let mut newest = None;
+                    while let Some((image, _)) = images.take().unwrap_or_else(|e| {
+                        eprintln!("couldn't read an image: {}", e);
+                        None
+                    }) {
+                        newest = Some(image);
+                    }
+                    let Some(image) = newest else { continue };
+                    let Some(result) = scanner.scan_ros_image(&image) else {
+                        if !warned_encoding {
+                            eprintln!("can't scan {} images of {}x{}, step {}, with {} bytes",
+                                image.encoding, image.width, image.height, image.step, image.data.len());
+                            warned_encoding = true;
+                        }
+                        continue;
+                    };
+                    let code_pose = intrinsics.zip(code_size).and_then(|(intrinsics, size)| result.pose(&intrinsics, size));
+                    let Some(detection) = Detection::new(image.header.clone(), &result, code_pose.as_ref()) else { continue };
+                    if let Err(e) = detections.publish(detection) {
+                        eprintln!("couldn't publish a detection: {}", e);
+                    }
+                    if let (Some((_, publisher)), Some(code_pose)) = (&poses, code_pose) {
+                        if let Err(e) = publisher.publish(PoseStamped::new(image.header, &code_pose)) {
+                            eprintln!("couldn't publish a pose: {}", e);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! Scans images sent over HTTP, for running arqr as a service.
+//!
+//! Usage: `cargo run --features server --bin arqr-server -- [--addr HOST:PORT] [--threads N]`
+//!
+//! `POST /scan` takes an image, either as the raw request body or as the
+//! first part of a `multipart/form-data` upload, and answers with the scan
+//! result as JSON, from `ScanResult::to_json`, in the versioned layout the
+//! `json` module describes. How long the scan took is its
+//! `timings_ms.total`.
+//! Errors come back as `{"error":"..."}` with a 4xx status. `GET /health`
+//! answers `ok`. Every response closes the connection.
+//!
+//! Requests are handled on a fixed number of threads, which share a pool of
+//! scanners. A client gets `REQUEST_TIMEOUT` to send its whole request, and
+//! as long again to take the response, so idle or slow connections can't
+//! hold on to a thread. Scanner settings come from `arqr.toml` (or `--config`), as with
+//! `arqr-cli`.
+
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    process,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+use arqr::{Scanner, json};
+#[cfg(feature = "config")]
+use arqr::config::{CONFIG_FILE, Config};
+#[cfg(feature = "config")]
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = if cfg!(feature = "config") {
+    "usage: arqr-server [--config FILE] [--addr HOST:PORT] [--threads N]"
+} else {
+    "usage: arqr-server [--addr HOST:PORT] [--threads N]"
+};
+/// Largest request header block accepted
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+/// Largest image accepted
+const MAX_BODY_BYTES: usize = 32 * 1024 * 1024;
+/// How long a client has to send its request, all told, and separately to
+/// take the response
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+/// Scanners shared between the request threads. Each request borrows one,
+/// so they keep their buffers from one request to the next.
+struct ScannerPool {
+    idle: Mutex<Vec<Scanner>>,
+    configure: Box<dyn Fn(&mut Scanner) + Sync>,
+}
+
+impl ScannerPool {
+    fn with<T>(&self, f: impl FnOnce(&mut Scanner) -> T) -> T {
+        let idle = self.idle.lock().unwrap().pop();
+        let mut scanner = idle.unwrap_or_else(|| {
+            let mut scanner = Scanner::new();
+            (self.configure)(&mut scanner);
+            scanner
+        });
+        let out = f(&mut scanner);
+        self.idle.lock().unwrap().push(scanner);
+        out
+    }
+}
+
+/// A response to send back: status line, content type and body
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(status: &'static str, body: String) -> Self {
+        Self { status, content_type: "application/json", body }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self::json(status, format!("{{\"error\":{}}}", json::string(message)))
+    }
+}
+
+/// Position of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Pulls the first part's contents out of a `multipart/form-data` body
+fn first_part<'a>(body: &'a [u8], boundary: &str) -> Option<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let start = find(body, delimiter.as_bytes())? + delimiter.len();
+    let part = &body[start..];
+    // Skip the part's headers
+    let contents = &part[find(part, b"\r\n\r\n")? + 4..];
+    let end = find(contents, format!("\r\n{}", delimiter).as_bytes())?;
+    Some(&contents[..end])
+}
+
+fn scan(pool: &ScannerPool, image: &[u8]) -> Response {
+    let img = match image::load_from_memory(image) {
+        Ok(img) => img.into_luma8(),
+        Err(e) => return Response::error("400 Bad Request", &format!("couldn't read the image: {}", e)),
+    };
+    let result = pool.with(|scanner| scanner.scan(&img));
+    Response::json("200 OK", result.to_json())
+}
+
+/// Limits the next read from `stream` to whatever's left until `deadline`,
+/// or fails if that's already passed. This goes before every read, so a
+/// client trickling bytes in can't stretch the request out past the deadline.
+fn time_left(stream: &TcpStream, deadline: Instant) -> io::Result<()> {
+    let left = deadline.saturating_duration_since(Instant::now());
+    if left.is_zero() {
+        return Err(io::ErrorKind::TimedOut.into());
+    }
+    stream.set_read_timeout(Some(left))
+}
+
+/// Reads one request and works out the response
+fn handle(pool: &ScannerPool, stream: &TcpStream) -> io::Result<Response> {
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    let mut reader = BufReader::new(stream);
+    let mut head = Vec::new();
+    let mut line_start = 0;
+    loop {
+        time_left(stream, deadline)?;
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let n = buf.iter().position(|&b| b == b'\n').map_or(buf.len(), |i| i + 1);
+        head.extend_from_slice(&buf[..n]);
+        reader.consume(n);
+        if head.len() > MAX_HEADER_BYTES {
+            return Ok(Response::error("431 Request Header Fields Too Large", "headers too large"));
+        }
+        if head.ends_with(b"\n") {
+            // A blank line ends the headers
+            if head[line_start..] == *b"\r\n" || head[line_start..] == *b"\n" {
+                break;
+            }
+            line_start = head.len();
+        }
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let (method, path) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+
+    let mut content_length = None;
+    let mut content_type = String::new();
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else { continue };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.parse::<usize>().ok(),
+            "content-type" => content_type = value.to_owned(),
+            "transfer-encoding" => return Ok(Response::error("411 Length Required", "chunked bodies aren't supported")),
+            _ => {}
+        }
+    }
+
+    match (method, path) {
+        ("GET", "/health") => Ok(Response { status: "200 OK", content_type: "text/plain", body: "ok".to_owned() }),
+        ("POST", "/scan") => {
+            let Some(len) = content_length else {
+                return Ok(Response::error("411 Length Required", "needs a Content-Length"));
+            };
+            if len > MAX_BODY_BYTES {
+                return Ok(Response::error("413 Payload Too Large", "image too large"));
+            }
+            let mut body = vec![0; len];
+            let mut filled = 0;
+            while filled < len {
+                time_left(stream, deadline)?;
+                match reader.read(&mut body[filled..])? {
+                    0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    n => filled += n,
+                }
+            }
+
+            let boundary = content_type.strip_prefix("multipart/form-data")
+                .and_then(|params| params.split(';').find_map(|p| p.trim().strip_prefix("boundary=")))
+                .map(|b| b.trim_matches('"'));
+            let image = match boundary {
+                Some(boundary) => match first_part(&body, boundary) {
+                    Some(part) => part,
+                    None => return Ok(Response::error("400 Bad Request", "couldn't read the multipart body")),
+                },
+                None => &body[..],
+            };
+            Ok(scan(pool, image))
+        }
+        (_, "/scan") | (_, "/health") => Ok(Response::error("405 Method Not Allowed", "wrong method")),
+        _ => Ok(Response::error("404 Not Found", "no such endpoint")),
+    }
+}
+
+fn serve(pool: &ScannerPool, mut stream: TcpStream) {
+    let response = match handle(pool, &stream) {
+        Ok(response) => response,
+        // The client went away, sent garbage or took too long, so there's no
+        // one to answer
+        Err(_) => return,
+    };
+    if stream.set_write_timeout(Some(REQUEST_TIMEOUT)).is_err() {
+        return;
+    }
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status, response.content_type, response.body.len(), response.body,
+    );
+}
+
+fn main() {
+    let mut addr = "127.0.0.1:8080".to_owned();
+    let mut threads = thread::available_parallelism().map_or(1, |n| n.get());
+    #[cfg(feature = "config")]
+    let mut config_path: Option<PathBuf> = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--addr" => addr = args.next().unwrap_or_else(|| usage()),
+            "--threads" => threads = match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => n,
+                _ => usage(),
+            },
+            #[cfg(feature = "config")]
+            "--config" => config_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            _ => usage(),
+        }
+    }
+
+    #[cfg(feature = "config")]
+    let configure: Box<dyn Fn(&mut Scanner) + Sync> = {
+        let config = Config::find(config_path.as_deref()).unwrap_or_else(|e| {
+            let path = config_path.as_deref().unwrap_or(Path::new(CONFIG_FILE));
+            eprintln!("couldn't load {}: {}", path.display(), e);
+            process::exit(1);
+        });
+        let settings = config.scanner;
+        Box::new(move |scanner: &mut Scanner| settings.configure(scanner))
+    };
+    #[cfg(not(feature = "config"))]
+    let configure: Box<dyn Fn(&mut Scanner) + Sync> = Box::new(|_: &mut Scanner| {});
+    let pool = ScannerPool { idle: Mutex::new(Vec::new()), configure };
+
+    let listener = TcpListener::bind(&addr).unwrap_or_else(|e| {
+        eprintln!("couldn't listen on {}: {}", addr, e);
+        process::exit(1);
+    });
+    eprintln!("listening on {}", addr);
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => serve(&pool, stream),
+                        Err(e) => eprintln!("couldn't accept a connection: {}", e),
+                    }
+                }
+            });
+        }
+    });
+}
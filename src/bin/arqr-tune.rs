@@ -0,0 +1,392 @@
+//! An egui window for tuning the scanner, where the piston demo only has
+//! hotkeys. The left panel has a control for every scanner setting, the
+//! middle shows the frame with what was found drawn over it next to each
+//! stage the scan got through (the binarized frame, the straightened code and
+//! the modules sampled from it), and the right panel lists what the scan
+//! found and how long each stage took.
+//!
+//! Usage: `cargo run --no-default-features --features tune --bin arqr-tune -- [--config FILE] [--camera N | <image>...]`
+//!
+//! Scans camera 0 unless images are given, in which case the arrows under
+//! the frame step through them. Settings start from `arqr.toml` (or
+//! `--config`), and "Copy settings" puts the current ones on the clipboard
+//! as a `[scanner]` table to paste back into it.
+
+use std::{env, path::PathBuf, process, time::{Duration, Instant}};
+use egui::{Color32, ColorImage, TextureHandle, TextureOptions, Ui};
+use image::{GrayImage, Luma, RgbaImage, buffer::ConvertBuffer};
+use nokhwa::{
+    Camera,
+    pixel_format::RgbAFormat,
+    utils::{CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType, Resolution},
+};
+use arqr::{
+    Point,
+    ScanResult,
+    Scanner,
+    camera::luma_from_nokhwa_frame,
+    config::{BinarizerKind, CameraConfig, Config, FilterKind, ScannerConfig},
+    decode::ModuleGrid,
+    draw::Overlay,
+    filter,
+    texture::{egui_bitmap, egui_frame},
+    tracker::Tracker,
+};
+
+const USAGE: &str = "usage: arqr-tune [--config FILE] [--camera N | <image>...]";
+
+/// How much to pad the regions searched around codes found in earlier
+/// frames, relative to each code's size. The same as the demo's.
+const REGION_MARGIN: f64 = 0.5;
+const CLAHE_TILES: u32 = 8;
+const CLAHE_CLIP: f64 = 3.0;
+
+/// Budget the slider starts at when it's switched on
+const DEFAULT_BUDGET_MS: f64 = 20.0;
+
+const FOUND: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+const SEARCHED: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+
+enum Source {
+    Camera(Box<Camera>),
+    Images { paths: Vec<PathBuf>, images: Vec<GrayImage>, current: usize },
+}
+
+/// Textures for the stage panes. `None` for stages the last scan didn't
+/// reach.
+#[derive(Default)]
+struct Panes {
+    frame: Option<TextureHandle>,
+    bitmap: Option<TextureHandle>,
+    code: Option<TextureHandle>,
+    modules: Option<TextureHandle>,
+}
+
+struct Tune {
+    source: Source,
+    settings: ScannerConfig,
+    /// Kept while the budget is switched off, so switching it back on
+    /// restores it
+    budget_ms: f64,
+    scanner: Scanner,
+    tracker: Tracker,
+    result: ScanResult,
+    panes: Panes,
+    /// Whether a still image needs scanning again
+    rescan: bool,
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail(msg: impl std::fmt::Display) -> ! {
+    eprintln!("arqr-tune: {}", msg);
+    process::exit(1);
+}
+
+fn open_camera(index: u32, options: &CameraConfig) -> Camera {
+    let requested = match (options.width, options.height) {
+        (Some(w), Some(h)) => RequestedFormatType::Closest(
+            CameraFormat::new(Resolution::new(w, h), FrameFormat::MJPEG, options.fps),
+        ),
+        _ => RequestedFormatType::None,
+    };
+    let mut cam = Camera::new(CameraIndex::Index(index), RequestedFormat::new::<RgbAFormat>(requested))
+        .unwrap_or_else(|e| fail(format!("couldn't open camera {}: {}", index, e)));
+    cam.open_stream().unwrap_or_else(|e| fail(format!("couldn't start camera {}: {}", index, e)));
+    cam
+}
+
+/// The current settings as a `[scanner]` table for `arqr.toml`
+fn settings_toml(settings: &ScannerConfig) -> String {
+    let mut toml = String::from("[scanner]\n");
+    toml += &format!("full_sweep_interval = {}\n", settings.full_sweep_interval);
+    if let Some(ms) = settings.budget_ms {
+        toml += &format!("budget_ms = {:.1}\n", ms);
+    }
+    let binarizer = match settings.binarizer {
+        BinarizerKind::Global => "global",
+        BinarizerKind::Adaptive => "adaptive",
+    };
+    toml += &format!("binarizer = \"{}\"\n", binarizer);
+    toml += &format!("try_inverted = {}\n", settings.try_inverted);
+    toml += &format!("adaptive_radius = {}\n", settings.adaptive_radius);
+    toml += &format!("adaptive_offset = {}\n", settings.adaptive_offset);
+    let filter = match settings.filter {
+        FilterKind::None => "none",
+        FilterKind::Edges => "edges",
+        FilterKind::Clahe => "clahe",
+    };
+    toml += &format!("filter = \"{}\"\n", filter);
+    toml
+}
+
+/// A sampled grid as an image, one pixel per module
+fn modules_image(grid: &ModuleGrid) -> ColorImage {
+    let img = GrayImage::from_fn(grid.size, grid.size, |x, y| {
+        Luma([if grid.modules[(y * grid.size + x) as usize] { 0 } else { 255 }])
+    });
+    egui_frame(&img)
+}
+
+/// Puts `image` in `slot`'s texture, making the texture if there isn't one
+fn set_texture(ui: &Ui, slot: &mut Option<TextureHandle>, name: &str, image: Option<ColorImage>, options: TextureOptions) {
+    match (slot.as_mut(), image) {
+        (Some(texture), Some(image)) => texture.set(image, options),
+        (None, Some(image)) => *slot = Some(ui.ctx().load_texture(name, image, options)),
+        (_, None) => *slot = None,
+    }
+}
+
+fn ms(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
+
+impl Tune {
+    /// The next frame to scan, or `None` if there's nothing new
+    fn next_frame(&mut self) -> Option<GrayImage> {
+        match &mut self.source {
+            Source::Camera(cam) => match cam.frame().map_err(|e| e.to_string()).and_then(|buf| {
+                luma_from_nokhwa_frame(&buf).map_err(|e| e.to_string())
+            }) {
+                Ok(frame) => Some(frame),
+                Err(e) => {
+                    eprintln!("couldn't read a frame: {}", e);
+                    None
+                }
+            },
+            Source::Images { images, current, .. } => {
+                self.rescan.then(|| images[*current].clone())
+            }
+        }
+    }
+
+    fn scan(&mut self, ui: &Ui, mut frame: GrayImage) {
+        self.rescan = false;
+        match self.settings.filter {
+            FilterKind::None => {}
+            FilterKind::Edges => filter::edge_2_in_place(&mut frame),
+            FilterKind::Clahe => filter::clahe_in_place(&mut frame, CLAHE_TILES, CLAHE_CLIP),
+        }
+        self.settings.configure(&mut self.scanner);
+        let now = Instant::now();
+        let regions = match self.source {
+            // Only a live feed has codes moving between frames to track
+            Source::Camera(_) => self.tracker.predict_regions(now, REGION_MARGIN),
+            Source::Images { .. } => Vec::new(),
+        };
+        self.scanner.set_regions(regions.iter().copied());
+        self.result = self.scanner.scan(&frame);
+        self.tracker.update_at(&self.result, now);
+
+        let mut overlay = Overlay::from_result(&self.result, FOUND);
+        for region in &regions {
+            let (min, max) = (region.min, region.max);
+            let corners = [min, Point::new(max.x, min.y), max, Point::new(min.x, max.y)];
+            overlay.polygon(&corners, SEARCHED);
+        }
+        let mut shown: RgbaImage = frame.convert();
+        overlay.draw_on(&mut shown);
+
+        let (smooth, sharp) = (TextureOptions::LINEAR, TextureOptions::NEAREST);
+        set_texture(ui, &mut self.panes.frame, "frame", Some(egui_frame(&shown)), smooth);
+        set_texture(ui, &mut self.panes.bitmap, "bitmap", Some(egui_bitmap(self.scanner.bitmap_mut())), sharp);
+        let code = self.result.code_img.as_ref().map(egui_frame);
+        set_texture(ui, &mut self.panes.code, "code", code, sharp);
+        let modules = self.result.modules.as_ref().map(modules_image);
+        set_texture(ui, &mut self.panes.modules, "modules", modules, sharp);
+    }
+
+    fn settings_panel(&mut self, ui: &mut Ui) {
+        let before = self.settings;
+        ui.heading("Scanner");
+
+        egui::ComboBox::from_label("filter")
+            .selected_text(format!("{:?}", self.settings.filter))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.settings.filter, FilterKind::None, "None");
+                ui.selectable_value(&mut self.settings.filter, FilterKind::Edges, "Edges");
+                ui.selectable_value(&mut self.settings.filter, FilterKind::Clahe, "Clahe");
+            });
+
+        ui.separator();
+        ui.label("binarizer");
+        ui.radio_value(&mut self.settings.binarizer, BinarizerKind::Global, "global threshold");
+        ui.radio_value(&mut self.settings.binarizer, BinarizerKind::Adaptive, "adaptive threshold");
+        let adaptive = self.settings.binarizer == BinarizerKind::Adaptive;
+        ui.add_enabled(adaptive, egui::Slider::new(&mut self.settings.adaptive_radius, 1..=64).text("radius"));
+        ui.add_enabled(adaptive, egui::Slider::new(&mut self.settings.adaptive_offset, 0..=64).text("offset"));
+        ui.checkbox(&mut self.settings.try_inverted, "try inverted");
+
+        ui.separator();
+        let mut budgeted = self.settings.budget_ms.is_some();
+        ui.checkbox(&mut budgeted, "time budget");
+        ui.add_enabled(budgeted, egui::Slider::new(&mut self.budget_ms, 1.0..=100.0).text("ms"));
+        self.settings.budget_ms = budgeted.then_some(self.budget_ms);
+        ui.add(egui::Slider::new(&mut self.settings.full_sweep_interval, 0..=60).text("frames between full sweeps"));
+
+        ui.separator();
+        if ui.button("Copy settings").clicked() {
+            ui.ctx().copy_text(settings_toml(&self.settings));
+        }
+
+        if self.settings != before {
+            self.rescan = true;
+        }
+    }
+
+    fn results_panel(&self, ui: &mut Ui) {
+        let result = &self.result;
+        ui.heading("Result");
+        egui::Grid::new("result").num_columns(2).striped(true).show(ui, |ui| {
+            let mut row = |name: &str, value: String| {
+                ui.label(name);
+                ui.label(value);
+                ui.end_row();
+            };
+            row("stage", format!("{:?}", result.stage));
+            row("targets", result.targets.len().to_string());
+            let corners = result.bbox.map_or("-".into(), |bbox| {
+                bbox.iter().map(|p| format!("({:.0}, {:.0})", p.x, p.y)).collect::<Vec<_>>().join(" ")
+            });
+            row("corners", corners);
+            row("version", result.version.map_or("-".into(), |v| v.to_string()));
+            row("EC level", result.format.map_or("-".into(), |f| format!("{:?}", f.ec_level)));
+            row("mask", result.format.map_or("-".into(), |f| f.mask.to_string()));
+            row("mirrored", result.mirrored.to_string());
+            row("inverted", result.inverted.to_string());
+            let payload = result.payload.as_ref().map_or("-".into(), |p| String::from_utf8_lossy(p).into_owned());
+            row("payload", payload);
+            row("decode error", result.decode_error.map_or("-".into(), |e| e.to_string()));
+        });
+
+        ui.separator();
+        ui.heading("Timings");
+        egui::Grid::new("timings").num_columns(2).striped(true).show(ui, |ui| {
+            let t = &result.timings;
+            for (name, time) in [
+                ("binarize", t.binarize),
+                ("targets", t.targets),
+                ("corners", t.corners),
+                ("extract", t.extract),
+                ("decode", t.decode),
+                ("fiducials", t.fiducials),
+                ("total", t.total()),
+            ] {
+                ui.label(name);
+                ui.label(ms(time));
+                ui.end_row();
+            }
+        });
+    }
+
+    fn stage_panes(&mut self, ui: &mut Ui) {
+        if let Source::Images { paths, images, current } = &mut self.source {
+            ui.horizontal(|ui| {
+                if ui.button("<").clicked() {
+                    *current = (*current + images.len() - 1) % images.len();
+                    self.rescan = true;
+                }
+                if ui.button(">").clicked() {
+                    *current = (*current + 1) % images.len();
+                    self.rescan = true;
+                }
+                ui.label(paths[*current].display().to_string());
+            });
+        }
+
+        let panes = [
+            ("frame", &self.panes.frame),
+            ("binarized", &self.panes.bitmap),
+            ("straightened code", &self.panes.code),
+            ("modules", &self.panes.modules),
+        ];
+        let cell = egui::vec2(ui.available_width() / 2.0 - 8.0, ui.available_height() / 2.0 - 24.0);
+        egui::Grid::new("panes").num_columns(2).show(ui, |ui| {
+            for (i, (name, texture)) in panes.into_iter().enumerate() {
+                ui.vertical(|ui| {
+                    ui.label(name);
+                    match texture {
+                        Some(texture) => {
+                            ui.add(egui::Image::new(texture).max_size(cell));
+                        }
+                        None => {
+                            ui.colored_label(Color32::GRAY, "not reached");
+                        }
+                    }
+                });
+                if i % 2 == 1 {
+                    ui.end_row();
+                }
+            }
+        });
+    }
+}
+
+impl eframe::App for Tune {
+    fn ui(&mut self, ui: &mut Ui, _frame: &mut eframe::Frame) {
+        if let Some(frame) = self.next_frame() {
+            self.scan(ui, frame);
+        }
+        if let Source::Camera(_) = self.source {
+            ui.ctx().request_repaint();
+        }
+
+        egui::Panel::left("settings").show(ui, |ui| self.settings_panel(ui));
+        egui::Panel::right("results").min_size(260.0).show(ui, |ui| self.results_panel(ui));
+        egui::CentralPanel::default().show(ui, |ui| self.stage_panes(ui));
+    }
+}
+
+fn main() {
+    let mut config_path = None;
+    let mut camera = None;
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--camera" => camera = Some(args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| usage())),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with('-') => usage(),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if camera.is_some() && !paths.is_empty() {
+        usage();
+    }
+
+    let config = Config::find(config_path.as_deref()).unwrap_or_else(|e| fail(format!("couldn't load settings: {}", e)));
+    let source = if paths.is_empty() {
+        Source::Camera(Box::new(open_camera(camera.unwrap_or(0), &config.camera)))
+    } else {
+        let images = paths.iter().map(|path| match image::open(path) {
+            Ok(img) => img.to_luma8(),
+            Err(e) => fail(format!("couldn't load {}: {}", path.display(), e)),
+        }).collect();
+        Source::Images { paths, images, current: 0 }
+    };
+
+    let tune = Tune {
+        source,
+        settings: config.scanner,
+        budget_ms: config.scanner.budget_ms.unwrap_or(DEFAULT_BUDGET_MS),
+        scanner: Scanner::new(),
+        tracker: Tracker::new(),
+        result: ScanResult::new(),
+        panes: Panes::default(),
+        rescan: true,
+    };
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([1280.0, 800.0]),
+        ..Default::default()
+    };
+    if let Err(e) = eframe::run_native("arqr-tune", options, Box::new(|_| Ok(Box::new(tune)))) {
+        fail(e);
+    }
+}
@@ -0,0 +1,75 @@
+//! Compares arqr against reference decoders over a directory of images.
+//!
+//! Usage: `cargo run --features compare --bin compare -- <corpus dir>`
+
+use std::{env, fs, io, path::{Path, PathBuf}, process};
+use arqr::compare::{Agreement, Reference, run_arqr};
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let corpus = match env::args().nth(1) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!("usage: compare <corpus dir>");
+            process::exit(2);
+        }
+    };
+
+    let mut files = Vec::new();
+    if let Err(e) = collect_files(&corpus, &mut files) {
+        eprintln!("couldn't read {}: {}", corpus.display(), e);
+        process::exit(1);
+    }
+    files.sort();
+
+    let mut tallies = [Agreement::default(); Reference::ALL.len()];
+    for path in files {
+        // Not everything in a corpus directory has to be an image
+        let img = match image::open(&path) {
+            Ok(img) => img,
+            Err(_) => continue,
+        };
+
+        let ours = run_arqr(&img);
+        for (reference, tally) in Reference::ALL.iter().zip(tallies.iter_mut()) {
+            let theirs = reference.run(&img);
+            if ours.detected != theirs.detected {
+                println!(
+                    "{}: arqr {} a code, {} {}",
+                    path.display(),
+                    if ours.detected { "found" } else { "missed" },
+                    reference.name(),
+                    if theirs.detected { "found one" } else { "didn't" },
+                );
+            }
+            tally.add(&ours, &theirs);
+        }
+    }
+
+    println!();
+    println!("{:<12} {:>7} {:>7} {:>7} {:>7} {:>7} {:>9} {:>9}",
+        "reference", "images", "both", "arqr", "ref", "neither", "detect%", "decode%");
+    for (reference, tally) in Reference::ALL.iter().zip(tallies.iter()) {
+        println!("{:<12} {:>7} {:>7} {:>7} {:>7} {:>7} {:>8.1}% {:>8.1}%",
+            reference.name(),
+            tally.images,
+            tally.both_detected,
+            tally.only_arqr,
+            tally.only_reference,
+            tally.neither,
+            tally.detection_rate() * 100.0,
+            tally.decode_rate() * 100.0,
+        );
+    }
+}
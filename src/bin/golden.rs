@@ -0,0 +1,250 @@
+//! Scores arqr against a labelled corpus of images, to measure whether a
+//! change helps or hurts.
+//!
+//! Usage: `cargo run --release --bin golden -- <corpus dir> [--report <file>]`
+//!
+//! Every image in the directory (and those under it) with a `.txt` file of
+//! the same name next to it is expected to hold a code, with the text file's
+//! contents (less any trailing newline) as its payload. Images without one
+//! are expected to hold no code at all, and count as false positives if one
+//! is found. Codes that were missed are broken down by where the pipeline
+//! lost them.
+//!
+//! `--report` also writes the results as JSON: the totals, then a `files`
+//! array with each image's label, verdict and `ScanResult::to_json` object.
+//! A code only counts as decoded if its payload matches the label exactly;
+//! payloads that aren't UTF-8 never do. Codes that were found but couldn't
+//! be read are broken down by which step of reading them failed (see
+//! `arqr::decode::DecodeError`).
+
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+use image::ImageFormat;
+use arqr::{ScanResult, frames::open_frames, json};
+
+fn collect_images(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_images(&path, files)?;
+        } else if ImageFormat::from_path(&path).is_ok() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Where a code that should have been found was lost
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Failure {
+    NoTargets,
+    TooFewTargets,
+    TooManyTargets,
+    /// The targets were picked, but their corners came out as NaN
+    DegenerateCorners,
+}
+
+impl Failure {
+    const ALL: [Failure; 4] = [Self::NoTargets, Self::TooFewTargets, Self::TooManyTargets, Self::DegenerateCorners];
+
+    fn of(result: &ScanResult) -> Option<Self> {
+        match (result.targets.len(), result.bbox) {
+            (0, _) => Some(Self::NoTargets),
+            (1..=2, _) => Some(Self::TooFewTargets),
+            (_, None) => Some(Self::TooManyTargets),
+            (_, Some(bbox)) if bbox.iter().any(|p| !p.x.is_finite() || !p.y.is_finite()) => Some(Self::DegenerateCorners),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::NoTargets => "no_targets",
+            Self::TooFewTargets => "too_few_targets",
+            Self::TooManyTargets => "too_many_targets",
+            Self::DegenerateCorners => "degenerate_corners",
+        }
+    }
+}
+
+struct Entry {
+    path: PathBuf,
+    /// The labelled payload, or None if there shouldn't be a code
+    expected: Option<String>,
+    /// Why no code was found, or None if one was
+    failure: Option<Failure>,
+    /// Whether the code's payload was read and matches the label
+    decoded: bool,
+    result: ScanResult,
+}
+
+/// The payload an image is labelled with, if it has a label
+fn label(path: &Path) -> Option<String> {
+    let text = fs::read_to_string(path.with_extension("txt")).ok()?;
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    Some(text.strip_suffix('\r').unwrap_or(text).to_owned())
+}
+
+fn rate(n: usize, of: usize) -> f64 {
+    if of == 0 { 0.0 } else { n as f64 / of as f64 }
+}
+
+#[derive(Default)]
+struct Totals {
+    /// Images labelled with a code
+    positives: usize,
+    /// Images that shouldn't have a code in them
+    negatives: usize,
+    /// Labelled codes that were found
+    detected: usize,
+    /// Labelled codes whose payload was read correctly
+    decoded: usize,
+    /// Codes found in images without one
+    false_positives: usize,
+    /// Labelled codes that were missed, by `Failure::ALL`
+    failures: [usize; Failure::ALL.len()],
+    /// Labelled codes that were found but couldn't be read, by
+    /// `DecodeError::name`
+    decode_errors: BTreeMap<&'static str, usize>,
+}
+
+impl Totals {
+    fn new(entries: &[Entry]) -> Self {
+        let mut totals = Self::default();
+        for e in entries {
+            match (&e.expected, e.failure) {
+                (Some(_), None) => {
+                    totals.detected += 1;
+                    totals.decoded += e.decoded as usize;
+                    if let Some(error) = e.result.decode_error {
+                        *totals.decode_errors.entry(error.name()).or_default() += 1;
+                    }
+                }
+                (Some(_), Some(f)) => totals.failures[Failure::ALL.iter().position(|&a| a == f).unwrap()] += 1,
+                (None, None) => totals.false_positives += 1,
+                (None, Some(_)) => {}
+            }
+            if e.expected.is_some() {
+                totals.positives += 1;
+            } else {
+                totals.negatives += 1;
+            }
+        }
+        totals
+    }
+}
+
+fn report_json(entries: &[Entry], totals: &Totals, corpus: &Path) -> String {
+    let failures: Vec<String> = Failure::ALL.iter().zip(totals.failures)
+        .map(|(f, n)| format!("\"{}\":{}", f.name(), n))
+        .collect();
+    let decode_errors: Vec<String> = totals.decode_errors.iter()
+        .map(|(name, n)| format!("\"{}\":{}", name, n))
+        .collect();
+    let files: Vec<String> = entries.iter()
+        .map(|e| {
+            let file = e.path.strip_prefix(corpus).unwrap_or(&e.path);
+            format!(
+                "{{\"file\":{},\"expected\":{},\"detected\":{},\"decoded\":{},\"failure\":{},\"result\":{}}}",
+                json::string(&file.to_string_lossy()),
+                e.expected.as_deref().map_or("null".to_owned(), json::string),
+                e.failure.is_none(),
+                e.decoded,
+                e.failure.map_or("null".to_owned(), |f| format!("\"{}\"", f.name())),
+                e.result.to_json(),
+            )
+        })
+        .collect();
+    format!(
+        "{{\"images\":{},\"with_codes\":{},\"detected\":{},\"detection_rate\":{:.4},\"decoded\":{},\"decode_rate\":{:.4},\"false_positives\":{},\"failures\":{{{}}},\"decode_errors\":{{{}}},\"files\":[{}]}}\n",
+        entries.len(), totals.positives, totals.detected, rate(totals.detected, totals.positives),
+        totals.decoded, rate(totals.decoded, totals.positives),
+        totals.false_positives, failures.join(","), decode_errors.join(","), files.join(","),
+    )
+}
+
+fn main() {
+    let usage = || -> ! {
+        eprintln!("usage: golden <corpus dir> [--report <file>]");
+        process::exit(2);
+    };
+    let mut corpus = None;
+    let mut report = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--report" => report = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            _ if arg.starts_with("--") || corpus.is_some() => usage(),
+            _ => corpus = Some(PathBuf::from(arg)),
+        }
+    }
+    let Some(corpus) = corpus else { usage() };
+
+    let mut files = Vec::new();
+    if let Err(e) = collect_images(&corpus, &mut files) {
+        eprintln!("couldn't read {}: {}", corpus.display(), e);
+        process::exit(1);
+    }
+    files.sort();
+
+    let mut entries = Vec::new();
+    for path in files {
+        // Only the first frame of animations and multi-page files is scored
+        let frame = match open_frames(&path).and_then(|mut frames| frames.next().transpose()) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let result = arqr::scan(&image::DynamicImage::ImageRgba8(frame.image).into_luma8());
+        let expected = label(&path);
+        let failure = Failure::of(&result);
+        let payload = result.payload.as_deref().map(String::from_utf8_lossy);
+        let decoded = expected.is_some() && payload.as_deref() == expected.as_deref();
+        match (&expected, failure) {
+            (Some(_), Some(f)) => println!("{}: missed ({})", path.display(), f.name()),
+            (Some(_), None) if !decoded => match &payload {
+                Some(payload) => println!("{}: found, but read {:?}", path.display(), payload),
+                None => match result.decode_error {
+                    Some(e) => println!("{}: found, but couldn't read it ({})", path.display(), e),
+                    None => println!("{}: found, but couldn't read it", path.display()),
+                },
+            },
+            (None, None) => println!("{}: found a code that shouldn't be there", path.display()),
+            _ => {}
+        }
+        entries.push(Entry { path, expected, failure, decoded, result });
+    }
+
+    let t = Totals::new(&entries);
+    println!();
+    println!("{} images: {} with codes, {} without", entries.len(), t.positives, t.negatives);
+    println!("detected  {:>5} / {:<5} {:>6.1}%", t.detected, t.positives, rate(t.detected, t.positives) * 100.0);
+    println!("decoded   {:>5} / {:<5} {:>6.1}%", t.decoded, t.positives, rate(t.decoded, t.positives) * 100.0);
+    println!("false +   {:>5} / {:<5} {:>6.1}%", t.false_positives, t.negatives, rate(t.false_positives, t.negatives) * 100.0);
+    if t.detected < t.positives {
+        println!("missed codes, by where they were lost:");
+        for (f, n) in Failure::ALL.iter().zip(t.failures) {
+            println!("  {:<20} {:>5}", f.name(), n);
+        }
+    }
+    if !t.decode_errors.is_empty() {
+        println!("unread codes, by where reading them failed:");
+        for (name, n) in &t.decode_errors {
+            println!("  {:<20} {:>5}", name, n);
+        }
+    }
+
+    if let Some(report) = report {
+        if let Err(e) = fs::write(&report, report_json(&entries, &t, &corpus)) {
+            eprintln!("couldn't write {}: {}", report.display(), e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,6 @@
+//! Generates Swift or Kotlin source for the bindings in `arqr::bindings`,
+//! from a build of the library. See that module for how to run it.
+
+fn main() {
+    uniffi::uniffi_bindgen_main()
+}
@@ -0,0 +1,309 @@
+//! UniFFI bindings, so iOS and Android apps can use arqr from Swift and
+//! Kotlin without a hand-written Objective-C or JNI bridge. They share their
+//! frame handling with the C interface in `ffi`, but scanners are objects
+//! that free themselves, bad frames throw instead of returning a code, and
+//! results carry the decoded message.
+//!
+//! Build the library, then generate the bindings from it:
+//!
+//! ```text
+//! cargo rustc --lib --release --no-default-features --features uniffi --crate-type cdylib
+//! cargo run --no-default-features --features uniffi-cli --bin uniffi-bindgen -- \
+//!     generate --library target/release/libarqr.so --language swift --out-dir bindings
+//! ```
+//!
+//! (`--language kotlin` for Android.) Frames are copied across as byte
+//! arrays; a camera pipeline that can't afford the copy can scan in place
+//! through the C interface instead.
+
+use std::{fmt, sync::Mutex, time::Duration};
+use crate::{
+    ScanResult, Scanner, bitmap,
+    decode::FormatInfo,
+    encode,
+    ffi::{bgra_lumas, frame_len, gray_lumas},
+};
+
+/// How a scanner binarizes frames. See `bitmap::Binarizer`.
+#[derive(Clone, Copy, Debug, uniffi::Enum)]
+pub enum Binarizer {
+    Global,
+    Adaptive { radius: u32, offset: u8 },
+}
+
+impl From<bitmap::Binarizer> for Binarizer {
+    fn from(binarizer: bitmap::Binarizer) -> Self {
+        match binarizer {
+            bitmap::Binarizer::Global => Self::Global,
+            bitmap::Binarizer::Adaptive { radius, offset } => Self::Adaptive { radius, offset },
+        }
+    }
+}
+
+impl From<Binarizer> for bitmap::Binarizer {
+    fn from(binarizer: Binarizer) -> Self {
+        match binarizer {
+            Binarizer::Global => Self::Global,
+            Binarizer::Adaptive { radius, offset } => Self::Adaptive { radius, offset },
+        }
+    }
+}
+
+/// A scanner's settings, the same ones as the `[scanner]` table of
+/// `arqr.toml`
+#[derive(Clone, Debug, uniffi::Record)]
+pub struct ScannerSettings {
+    /// See `Scanner::full_sweep_interval`
+    pub full_sweep_interval: u32,
+    /// See `Scanner::budget`. Null for no budget.
+    pub budget_ms: Option<f64>,
+    pub binarizer: Binarizer,
+    /// See `Scanner::try_inverted`
+    pub try_inverted: bool,
+}
+
+/// A code's error correction level
+#[derive(Clone, Copy, Debug, PartialEq, Eq, uniffi::Enum)]
+pub enum EcLevel {
+    L,
+    M,
+    Q,
+    H,
+}
+
+impl From<encode::EcLevel> for EcLevel {
+    fn from(ec_level: encode::EcLevel) -> Self {
+        match ec_level {
+            encode::EcLevel::L => Self::L,
+            encode::EcLevel::M => Self::M,
+            encode::EcLevel::Q => Self::Q,
+            encode::EcLevel::H => Self::H,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, uniffi::Record)]
+pub struct Corner {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// What a scan found in a frame
+#[derive(Clone, Debug, uniffi::Record)]
+pub struct ScanReport {
+    /// The code's corners in pixels: top-left, top-right, bottom-right,
+    /// bottom-left. Null if no code was found.
+    pub corners: Option<Vec<Corner>>,
+    /// Number of position targets found, whether or not they made a code
+    pub targets: u32,
+    pub version: Option<u32>,
+    pub ec_level: Option<EcLevel>,
+    /// The code's message, if it could be read
+    pub payload: Option<Vec<u8>>,
+    /// The message as text, if it's UTF-8
+    pub text: Option<String>,
+    /// Why the message couldn't be read, if a code was found but not read
+    pub decode_error: Option<String>,
+    /// Whether the code was mirrored, or light on dark
+    pub mirrored: bool,
+    pub inverted: bool,
+    /// Time taken to scan, in milliseconds
+    pub scan_ms: f64,
+}
+
+/// Why a frame couldn't be scanned
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Error)]
+pub enum FrameError {
+    /// The frame has no pixels
+    Empty,
+    /// Rows are closer together than a row is long
+    StrideTooShort { stride: u32, row_len: u64 },
+    /// The frame is shorter than its size and stride say
+    TooShort { expected: u64, found: u64 },
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "the frame is empty"),
+            Self::StrideTooShort { stride, row_len } => {
+                write!(f, "a stride of {} bytes is shorter than a {} byte row", stride, row_len)
+            }
+            Self::TooShort { expected, found } => {
+                write!(f, "the frame needs {} bytes, but only has {}", expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Checks a frame's size against its pixels, and returns the part of them
+/// it takes up
+fn check_frame(pixels: &[u8], width: u32, height: u32, stride: u32, bytes_per_px: u32) -> Result<&[u8], FrameError> {
+    if width == 0 || height == 0 {
+        return Err(FrameError::Empty);
+    }
+    let row_len = width as u64 * bytes_per_px as u64;
+    let len = frame_len(width, height, stride, bytes_per_px).ok_or(FrameError::StrideTooShort { stride, row_len })?;
+    if pixels.len() < len {
+        return Err(FrameError::TooShort { expected: len as u64, found: pixels.len() as u64 });
+    }
+    Ok(&pixels[..len])
+}
+
+/// A scanner, used for any number of frames. Calls from different threads
+/// take turns.
+#[derive(uniffi::Object)]
+pub struct QrScanner(Mutex<Scanner>);
+
+impl QrScanner {
+    fn scanner(&self) -> std::sync::MutexGuard<'_, Scanner> {
+        // A panic partway through a scan doesn't leave the scanner in a state
+        // the next scan can't start from
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[uniffi::export]
+impl QrScanner {
+    /// A scanner with the default settings
+    #[uniffi::constructor]
+    pub fn new() -> Self {
+        Self(Mutex::new(Scanner::new()))
+    }
+
+    #[uniffi::constructor]
+    pub fn with_settings(settings: ScannerSettings) -> Self {
+        let scanner = Self::new();
+        scanner.set_settings(settings);
+        scanner
+    }
+
+    pub fn settings(&self) -> ScannerSettings {
+        let scanner = self.scanner();
+        ScannerSettings {
+            full_sweep_interval: scanner.full_sweep_interval,
+            budget_ms: scanner.budget.map(|budget| budget.as_secs_f64() * 1000.0),
+            binarizer: scanner.binarizer.into(),
+            try_inverted: scanner.try_inverted,
+        }
+    }
+
+    pub fn set_settings(&self, settings: ScannerSettings) {
+        let mut scanner = self.scanner();
+        scanner.full_sweep_interval = settings.full_sweep_interval;
+        scanner.budget = settings.budget_ms.map(|ms| Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+        scanner.binarizer = settings.binarizer.into();
+        scanner.try_inverted = settings.try_inverted;
+    }
+
+    /// Scans a `width` by `height` greyscale frame, one byte per pixel, with
+    /// rows `stride` bytes apart. The Y plane of a YUV camera frame will do.
+    pub fn scan_gray(&self, pixels: Vec<u8>, width: u32, height: u32, stride: u32) -> Result<ScanReport, FrameError> {
+        let pixels = check_frame(&pixels, width, height, stride, 1)?;
+        let result = self.scanner().scan_lumas(gray_lumas(pixels, width, stride), width, height);
+        Ok(report(&result))
+    }
+
+    /// Scans a `width` by `height` BGRA frame, four bytes per pixel (blue,
+    /// green, red, then alpha, which is ignored), with rows `stride` bytes
+    /// apart
+    pub fn scan_bgra(&self, pixels: Vec<u8>, width: u32, height: u32, stride: u32) -> Result<ScanReport, FrameError> {
+        let pixels = check_frame(&pixels, width, height, stride, 4)?;
+        let result = self.scanner().scan_lumas(bgra_lumas(pixels, width, stride), width, height);
+        Ok(report(&result))
+    }
+}
+
+impl Default for QrScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn report(result: &ScanResult) -> ScanReport {
+    let corners = result.quad().map(|corners| {
+        corners.iter().map(|p| Corner { x: p.x, y: p.y }).collect()
+    });
+    ScanReport {
+        corners,
+        targets: result.targets.len() as u32,
+        version: result.version,
+        ec_level: result.format.map(|FormatInfo { ec_level, .. }| ec_level.into()),
+        payload: result.payload.clone(),
+        text: result.payload.clone().and_then(|payload| String::from_utf8(payload).ok()),
+        decode_error: result.decode_error.map(|err| err.to_string()),
+        mirrored: result.mirrored,
+        inverted: result.inverted,
+        scan_ms: result.timings.total().as_secs_f64() * 1000.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encode::{QrCode, Version},
+        testgen::{Distortion, generate},
+    };
+
+    #[test]
+    fn scans_padded_frames() {
+        let code = QrCode::with_version(b"uniffi", Version::Normal(2), encode::EcLevel::Q).unwrap();
+        let distortion = Distortion { rotation: 0.2, ..Distortion::default() };
+        let image = generate(&code, &distortion).image;
+        let (width, height) = image.dimensions();
+        // Rows padded out to a multiple of 64 bytes, as camera buffers often are
+        let stride = width.div_ceil(64) * 64;
+        let mut pixels = vec![0; (stride * height) as usize];
+        for (row, src) in pixels.chunks_mut(stride as usize).zip(image.rows()) {
+            for (dst, px) in row.iter_mut().zip(src) {
+                *dst = px.0[0];
+            }
+        }
+
+        let scanner = QrScanner::new();
+        let report = scanner.scan_gray(pixels, width, height, stride).unwrap();
+        assert_eq!(report.text.as_deref(), Some("uniffi"));
+        assert_eq!(report.version, Some(2));
+        assert_eq!(report.ec_level, Some(EcLevel::Q));
+        assert_eq!(report.corners.map(|corners| corners.len()), Some(4));
+
+        // Anything past the end of the frame is left alone
+        let mut bgra: Vec<u8> = image.pixels().flat_map(|px| [px.0[0], px.0[0], px.0[0], 0]).collect();
+        bgra.extend([0; 100]);
+        let report = scanner.scan_bgra(bgra, width, height, width * 4).unwrap();
+        assert_eq!(report.payload.as_deref(), Some(&b"uniffi"[..]));
+    }
+
+    #[test]
+    fn rejects_bad_frames() {
+        let scanner = QrScanner::new();
+        assert_eq!(scanner.scan_gray(vec![], 0, 10, 10).unwrap_err(), FrameError::Empty);
+        assert_eq!(
+            scanner.scan_bgra(vec![0; 400], 10, 10, 39).unwrap_err(),
+            FrameError::StrideTooShort { stride: 39, row_len: 40 },
+        );
+        assert_eq!(
+            scanner.scan_gray(vec![0; 99], 10, 10, 12).unwrap_err(),
+            FrameError::TooShort { expected: 118, found: 99 },
+        );
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = ScannerSettings {
+            full_sweep_interval: 3,
+            budget_ms: Some(20.0),
+            binarizer: Binarizer::Adaptive { radius: 12, offset: 5 },
+            try_inverted: true,
+        };
+        let scanner = QrScanner::with_settings(settings);
+        let read = scanner.settings();
+        assert_eq!(read.full_sweep_interval, 3);
+        assert_eq!(read.budget_ms, Some(20.0));
+        assert!(matches!(read.binarizer, Binarizer::Adaptive { radius: 12, offset: 5 }));
+        assert!(read.try_inverted);
+    }
+}
@@ -10,15 +10,10 @@ use image::{ImageBuffer, Pixel, Primitive, buffer::ConvertBuffer};
 
 type U8Histo = [usize; 0x100];
 
-/// Creates a luminosity histogram from an image
-fn img_to_u8_histo<Px, C>(img: &ImageBuffer<Px, C>) -> U8Histo
-where
-    Px: Pixel<Subpixel = u8>,
-    C: Deref<Target = [u8]>,
-{
+/// Creates a luminosity histogram from a stream of luminosity values
+fn luma_to_u8_histo<I: Iterator<Item = u8>>(lumas: I) -> U8Histo {
     let mut histo = [0; 0x100];
-    for px in img.pixels() {
-        let val = px.to_luma().0[0];
+    for val in lumas {
         histo[val as usize] += 1;
     }
     histo
@@ -71,24 +66,55 @@ fn u8_histo_to_threshold(histo: &U8Histo) -> u8 {
     thresh as u8
 }
 
+/// How to decide which pixels are black
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Binarizer {
+    /// One threshold for the whole image, picked from its histogram. Fast,
+    /// but shadows and glare across a code can push part of it to the wrong
+    /// side of the threshold.
+    #[default]
+    Global,
+    /// Compares each pixel with the mean of the square around it, reaching
+    /// `radius` pixels each way. A pixel is black if it's more than `offset`
+    /// darker than its surroundings, so flat areas come out white whatever
+    /// the lighting. `radius` should be a few modules wide.
+    Adaptive { radius: u32, offset: u8 },
+}
+
 /// Discount ImageBuffer with `bool`s for pixels
 #[derive(Clone, Debug, Default)]
 pub struct Bitmap {
     data: Vec<bool>,
     width: u32,
     height: u32,
+    // Scratch space for the adaptive binarizer: the frame's lumas and their
+    // summed-area table. Kept between frames so they're only allocated once.
+    lumas: Vec<u8>,
+    sums: Vec<u64>,
 }
 
 impl Bitmap {
     /// Creates a new all white bitmap with the given width and height.
     pub fn new(width: u32, height: u32) -> Self {
         let data = vec![true; (width * height) as usize];
-        Self { data, width, height }
+        Self { data, width, height, ..Self::default() }
     }
 
     /// Converts an `ImageBuffer` to `Bitmap` by dynamically picking a suitable
     /// binarization threshold
     pub fn from_u8_img_dynamic<Px, C>(img: &ImageBuffer<Px, C>) -> Self
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        let mut bmp = Self::default();
+        bmp.set_from_u8_img_dynamic(img);
+        bmp
+    }
+
+    /// Like `from_u8_img_dynamic`, but overwrites this bitmap, reusing its
+    /// allocation where possible
+    pub fn set_from_u8_img_dynamic<Px, C>(&mut self, img: &ImageBuffer<Px, C>)
     where
         Px: Pixel<Subpixel = u8>,
         C: Deref<Target = [u8]>,
@@ -97,14 +123,109 @@ impl Bitmap {
         // and converts to grayscale both times.
         // can it convert just once... and maybe even reuse the buffer?!
         let (width, height) = img.dimensions();
-        let mut data = Vec::with_capacity((width * height) as usize);
-        let thresh = u8_histo_to_threshold(&img_to_u8_histo(img));
-        for px in img.pixels() {
-            let luma = px.to_luma().0[0];
-            data.push(luma > thresh);
+
+        // Greyscale buffers already hold luma, so read the raw bytes instead
+        // of converting each pixel. Both checks are on constants, so the
+        // branch not taken compiles away.
+        if Px::CHANNEL_COUNT == 1 && Px::COLOR_MODEL == "Y" {
+            let raw: &[u8] = img.as_raw();
+            let len = (width * height) as usize;
+            self.set_from_luma_dynamic(raw[..len].iter().copied(), width, height);
+            return;
+        }
+
+        let lumas = img.pixels().map(|px| px.to_luma().0[0]);
+        self.set_from_luma_dynamic(lumas, width, height);
+    }
+
+    /// Converts a stream of luminosity values (row-major, `width * height` of
+    /// them) to `Bitmap`, picking a threshold the same way as
+    /// `from_u8_img_dynamic`. Useful for frames that aren't `ImageBuffer`s,
+    /// e.g. the Y plane of a YUV camera frame.
+    pub fn from_luma_dynamic<I>(lumas: I, width: u32, height: u32) -> Self
+    where
+        I: Iterator<Item = u8> + Clone,
+    {
+        let mut bmp = Self::default();
+        bmp.set_from_luma_dynamic(lumas, width, height);
+        bmp
+    }
+
+    /// Like `from_luma_dynamic`, but overwrites this bitmap, reusing its
+    /// allocation where possible
+    pub fn set_from_luma_dynamic<I>(&mut self, lumas: I, width: u32, height: u32)
+    where
+        I: Iterator<Item = u8> + Clone,
+    {
+        let thresh = u8_histo_to_threshold(&luma_to_u8_histo(lumas.clone()));
+        self.data.clear();
+        self.data.extend(lumas.map(|luma| luma > thresh));
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Like `set_from_u8_img_dynamic`, but with a choice of binarizer
+    pub fn set_from_u8_img<Px, C>(&mut self, img: &ImageBuffer<Px, C>, binarizer: Binarizer)
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        match binarizer {
+            Binarizer::Global => self.set_from_u8_img_dynamic(img),
+            Binarizer::Adaptive { .. } => {
+                let (width, height) = img.dimensions();
+                self.set_from_luma(img.pixels().map(|px| px.to_luma().0[0]), width, height, binarizer);
+            }
+        }
+    }
+
+    /// Like `set_from_luma_dynamic`, but with a choice of binarizer
+    pub fn set_from_luma<I>(&mut self, lumas: I, width: u32, height: u32, binarizer: Binarizer)
+    where
+        I: Iterator<Item = u8> + Clone,
+    {
+        match binarizer {
+            Binarizer::Global => self.set_from_luma_dynamic(lumas, width, height),
+            Binarizer::Adaptive { radius, offset } => {
+                self.lumas.clear();
+                self.lumas.extend(lumas);
+                self.set_from_luma_adaptive(width, height, radius, offset);
+            }
         }
+    }
 
-        Self { data, width, height }
+    /// Binarizes `self.lumas` against the local mean. See
+    /// `Binarizer::Adaptive`.
+    fn set_from_luma_adaptive(&mut self, width: u32, height: u32, radius: u32, offset: u8) {
+        let (w, h) = (width as usize, height as usize);
+        let (lumas, sums) = (&self.lumas, &mut self.sums);
+        // Summed-area table, with an extra row and column of zeros so every
+        // box sum is four lookups
+        let stride = w + 1;
+        sums.clear();
+        sums.resize(stride * (h + 1), 0);
+        for y in 0..h {
+            let mut row_sum = 0;
+            for x in 0..w {
+                row_sum += lumas[y * w + x] as u64;
+                sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row_sum;
+            }
+        }
+
+        let r = radius as usize;
+        self.data.clear();
+        for y in 0..h {
+            let (y0, y1) = (y.saturating_sub(r), cmp::min(y + r + 1, h));
+            for x in 0..w {
+                let (x0, x1) = (x.saturating_sub(r), cmp::min(x + r + 1, w));
+                let sum = sums[y1 * stride + x1] + sums[y0 * stride + x0]
+                    - sums[y0 * stride + x1] - sums[y1 * stride + x0];
+                let mean = sum / ((x1 - x0) * (y1 - y0)) as u64;
+                self.data.push(lumas[y * w + x] as u64 + offset as u64 > mean);
+            }
+        }
+        self.width = width;
+        self.height = height;
     }
 
     pub fn width(&self) -> u32 {
@@ -178,13 +299,22 @@ impl Bitmap {
         self.get_pixel_mut(cx, cy)
     }
 
+    /// Makes this bitmap a copy of `other` with black and white swapped,
+    /// reusing its allocation where possible
+    pub fn set_inverted(&mut self, other: &Bitmap) {
+        self.data.clear();
+        self.data.extend(other.data.iter().map(|&px| !px));
+        self.width = other.width;
+        self.height = other.height;
+    }
+
     /// Returns an iterator over the rows of pixels in this bitmap
-    pub fn rows(&self) -> Rows {
+    pub fn rows(&self) -> Rows<'_> {
         Rows(self.data.chunks_exact(self.width as usize))
     }
 
     /// Returns an iterator over the mutable rows of this bitmap
-    pub fn rows_mut(&mut self) -> RowsMut {
+    pub fn rows_mut(&mut self) -> RowsMut<'_> {
         RowsMut(self.data.chunks_exact_mut(self.width as usize))
     }
 }
@@ -192,7 +322,7 @@ impl Bitmap {
 impl Deref for Bitmap {
     type Target = [bool];
     fn deref(&self) -> &Self::Target {
-        &*self.data
+        &self.data
     }
 }
 
@@ -300,9 +430,9 @@ pub fn affine_transform_chunk(
     // We want to "pick" result's pixels from source, not map source to result.
     // Therefore, we first invert the matrix.
     let [[a, b, tx], [c, d, ty]] = trans;
-    let det = a * d - b * c;
-    let [[ap, bp], [cp, dp]] = [[d / det, -b / det], [-c / det, a / det]];
-    println!("{:?}", [[ap, bp, -tx], [cp, dp, -ty]]);
+    // let det = a * d - b * c;
+    // let [[ap, bp], [cp, dp]] = [[d / det, -b / det], [-c / det, a / det]];
+    // println!("{:?}", [[ap, bp, -tx], [cp, dp, -ty]]);
 
     for (y, row) in result.rows_mut().enumerate() {
         let y = y as f64;
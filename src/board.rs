@@ -0,0 +1,122 @@
+//! Estimates one pose from several fiducial markers laid out at known
+//! positions on a flat board. Every corner of every visible marker goes into
+//! the fit, so the pose stays steady even when each marker is small in the
+//! frame, and keeps working when some of them are covered up or out of view.
+
+use crate::{
+    Point,
+    calib::CameraIntrinsics,
+    fiducial::Marker,
+    homography::Homography,
+    pose::Pose,
+};
+
+/// Where one marker sits on a board
+#[derive(Clone, Copy, Debug)]
+pub struct BoardMarker {
+    /// Dictionary the marker is from, as in `Marker::dictionary`
+    pub dictionary: usize,
+    pub id: u32,
+    /// The marker's corners on the board, in the same order as
+    /// `Marker::corners`, in any physical unit
+    pub corners: [Point<f64>; 4],
+}
+
+/// Physical layout of a set of markers on a flat board
+#[derive(Clone, Debug, Default)]
+pub struct MarkerBoard {
+    markers: Vec<BoardMarker>,
+}
+
+/// Pose of a board, and how well it fits what was seen
+#[derive(Clone, Copy, Debug)]
+pub struct BoardPose {
+    /// Pose of the board's own frame: origin at its (0, 0), x and y along the
+    /// board, z into it
+    pub pose: Pose,
+    /// Number of the board's markers that were used
+    pub markers_used: usize,
+    /// Root-mean-square distance between where the markers' corners were
+    /// seen and where the pose puts them, in pixels
+    pub rms_error: f64,
+}
+
+impl MarkerBoard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a marker to the board, at the given corners
+    pub fn add(&mut self, dictionary: usize, id: u32, corners: [Point<f64>; 4]) {
+        self.markers.push(BoardMarker { dictionary, id, corners });
+    }
+
+    /// Adds an upright square marker, with its top-left corner at `top_left`
+    /// and sides `size` long
+    pub fn add_square(&mut self, dictionary: usize, id: u32, top_left: Point<f64>, size: f64) {
+        let Point { x, y } = top_left;
+        self.add(dictionary, id, [
+            Point::new(x, y),
+            Point::new(x + size, y),
+            Point::new(x + size, y + size),
+            Point::new(x, y + size),
+        ]);
+    }
+
+    /// A `cols` x `rows` grid of square markers `size` wide with `gap`
+    /// between them, numbered left to right and top to bottom starting at
+    /// `first_id`
+    pub fn grid(dictionary: usize, cols: u32, rows: u32, size: f64, gap: f64, first_id: u32) -> Self {
+        let mut board = Self::new();
+        for row in 0..rows {
+            for col in 0..cols {
+                let top_left = Point::new(col as f64 * (size + gap), row as f64 * (size + gap));
+                board.add_square(dictionary, first_id + row * cols + col, top_left, size);
+            }
+        }
+        board
+    }
+
+    pub fn markers(&self) -> &[BoardMarker] {
+        &self.markers
+    }
+
+    /// Estimates the board's pose from the markers found in a frame. Markers
+    /// that aren't on the board are ignored. Needs at least one of the
+    /// board's markers to be visible.
+    pub fn estimate_pose(&self, detected: &[Marker], intrinsics: &CameraIntrinsics) -> Option<BoardPose> {
+        let mut object = Vec::new();
+        let mut image = Vec::new();
+        let mut markers_used = 0;
+        for marker in detected {
+            let on_board = self.markers.iter()
+                .find(|m| m.dictionary == marker.dictionary && m.id == marker.id);
+            if let Some(on_board) = on_board {
+                object.extend_from_slice(&on_board.corners);
+                image.extend(marker.corners.iter().map(|&p| intrinsics.normalize(p)));
+                markers_used += 1;
+            }
+        }
+        if markers_used == 0 {
+            return None;
+        }
+
+        // Least-squares homography over every corner for a first guess, then
+        // polish it by minimizing reprojection error directly
+        let h = Homography::from_points(&object, &image)?;
+        let guess = Pose::from_normalized_homography(&h)?;
+        let object: Vec<[f64; 3]> = object.iter().map(|p| [p.x, p.y, 0.0]).collect();
+        let pose = guess.refine(&object, &image, 10);
+
+        let mut sq_error = 0.0;
+        for (&p, &q) in object.iter().zip(&image) {
+            let c = pose.transform(p);
+            let seen = intrinsics.denormalize(q);
+            let expected = intrinsics.denormalize(Point::new(c[0] / c[2], c[1] / c[2]));
+            sq_error += seen.dist_to(expected).powi(2);
+        }
+        let rms_error = (sq_error / object.len() as f64).sqrt();
+
+        Some(BoardPose { pose, markers_used, rms_error })
+    }
+}
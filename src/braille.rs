@@ -0,0 +1,107 @@
+//! Draws bitmaps as text made of Unicode braille characters, for looking at
+//! what the scanner sees from a terminal. Each character is a 2x4 grid of
+//! dots, which come out roughly square in most terminal fonts.
+//!
+//! Lines drawn over the bitmap ("marks") are kept separately, and cells with
+//! any marks in them show only the marks, in color.
+
+use crate::{Point, bitmap::Bitmap};
+
+/// Bit for each dot in a braille cell, indexed by `[y][x]`
+const DOT_BITS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+#[derive(Clone, Debug)]
+pub struct BrailleCanvas {
+    cols: u32,
+    rows: u32,
+    ink: Vec<bool>,
+    marks: Vec<bool>,
+}
+
+impl BrailleCanvas {
+    /// A blank canvas `cols` characters wide and `rows` lines high
+    pub fn new(cols: u32, rows: u32) -> Self {
+        let dots = (cols * 2 * rows * 4) as usize;
+        Self { cols, rows, ink: vec![false; dots], marks: vec![false; dots] }
+    }
+
+    /// Width in dots
+    pub fn width(&self) -> u32 {
+        self.cols * 2
+    }
+
+    /// Height in dots
+    pub fn height(&self) -> u32 {
+        self.rows * 4
+    }
+
+    fn index(&self, x: f64, y: f64) -> Option<usize> {
+        let in_bounds = x >= 0.0 && y >= 0.0 && x < self.width() as f64 && y < self.height() as f64;
+        in_bounds.then(|| y as usize * self.width() as usize + x as usize)
+    }
+
+    /// Draws `bmp` scaled down to fit, with a dot for each dark pixel, and
+    /// clears any marks. Returns the scale from the bitmap's pixels to dots,
+    /// for placing marks.
+    pub fn draw_bitmap(&mut self, bmp: &Bitmap) -> f64 {
+        let scale = (self.width() as f64 / bmp.width() as f64).min(self.height() as f64 / bmp.height() as f64);
+        self.marks.fill(false);
+        let width = self.width();
+        for y in 0..self.height() {
+            for x in 0..width {
+                // Sample the middle of the patch of pixels under each dot
+                let px = ((x as f64 + 0.5) / scale) as u32;
+                let py = ((y as f64 + 0.5) / scale) as u32;
+                // `true` is a light pixel
+                let dark = bmp.get_pixel_checked(px, py).is_some_and(|light| !light);
+                self.ink[(y * width + x) as usize] = dark;
+            }
+        }
+        scale
+    }
+
+    /// Marks a line between two points, in dots
+    pub fn mark_line(&mut self, from: Point<f64>, to: Point<f64>) {
+        let steps = from.dist_to(to).ceil().max(1.0) as u32;
+        for i in 0..=steps {
+            let t = i as f64 / steps as f64;
+            let (x, y) = (from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t);
+            if let Some(i) = self.index(x, y) {
+                self.marks[i] = true;
+            }
+        }
+    }
+
+    fn cell(&self, layer: &[bool], col: u32, row: u32) -> u32 {
+        let mut bits = 0;
+        for (dy, row_bits) in DOT_BITS.iter().enumerate() {
+            for (dx, bit) in row_bits.iter().enumerate() {
+                let (x, y) = (col * 2 + dx as u32, row * 4 + dy as u32);
+                if layer[(y * self.width() + x) as usize] {
+                    bits |= bit;
+                }
+            }
+        }
+        bits
+    }
+
+    /// The canvas as lines of text. Marks are drawn in `mark_color` (RGB),
+    /// using 24-bit ANSI color escapes.
+    pub fn to_lines(&self, mark_color: [u8; 3]) -> Vec<String> {
+        let [r, g, b] = mark_color;
+        (0..self.rows).map(|row| {
+            let mut line = String::new();
+            for col in 0..self.cols {
+                let marks = self.cell(&self.marks, col, row);
+                if marks != 0 {
+                    line.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b));
+                    line.push(char::from_u32(0x2800 + marks).unwrap());
+                    line.push_str("\x1b[0m");
+                } else {
+                    line.push(char::from_u32(0x2800 + self.cell(&self.ink, col, row)).unwrap());
+                }
+            }
+            line
+        }).collect()
+    }
+}
@@ -0,0 +1,200 @@
+//! Parses calendar events out of codes on posters and tickets. These hold an
+//! iCalendar VEVENT, on its own or wrapped in a VCALENDAR:
+//!
+//! ```text
+//! BEGIN:VEVENT
+//! SUMMARY:Summer fair
+//! DTSTART;TZID=Europe/London:20250712T100000
+//! DTEND;TZID=Europe/London:20250712T160000
+//! LOCATION:Market square\, Oxford
+//! END:VEVENT
+//! ```
+//!
+//! Only the first event is read, and only the properties most apps show.
+//! Long lines folded onto the next (which then starts with a space) are
+//! joined back up, and escaped commas, semicolons, backslashes and newlines
+//! in text are unescaped.
+
+/// Which clock a `Time` is on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Zone {
+    /// Whatever time zone the reader is in
+    Floating,
+    Utc,
+    /// An IANA zone name, from the property's TZID, e.g. "Europe/London"
+    Named(String),
+}
+
+/// A date, with a time of day unless it's for a whole day
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    /// Hour, minute and second, or None for an all-day date
+    pub time: Option<(u8, u8, u8)>,
+    pub zone: Zone,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub summary: Option<String>,
+    pub start: Time,
+    /// None if the event doesn't say, or gives a DURATION instead
+    pub end: Option<Time>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Event {
+    /// Parses the first VEVENT in `payload`, or returns None if there isn't
+    /// one with a valid start
+    pub fn parse(payload: &str) -> Option<Self> {
+        let lines = unfold(payload);
+        let mut in_event = false;
+        let (mut summary, mut start, mut end, mut location, mut description) = (None, None, None, None, None);
+        for line in &lines {
+            let Some((name_params, value)) = line.split_once(':') else { continue };
+            let mut params = name_params.split(';');
+            let name = params.next().unwrap().to_ascii_uppercase();
+            match (name.as_str(), in_event) {
+                ("BEGIN", false) if value.eq_ignore_ascii_case("VEVENT") => in_event = true,
+                ("END", true) if value.eq_ignore_ascii_case("VEVENT") => break,
+                ("SUMMARY", true) => summary = Some(unescape(value)),
+                ("LOCATION", true) => location = Some(unescape(value)),
+                ("DESCRIPTION", true) => description = Some(unescape(value)),
+                ("DTSTART", true) => start = Some(parse_time(value, params)?),
+                ("DTEND", true) => end = Some(parse_time(value, params)?),
+                _ => {}
+            }
+        }
+        Some(Self { summary, start: start?, end, location, description })
+    }
+}
+
+/// Splits `s` into lines, joining folded ones back together. Trailing space
+/// is only trimmed once they're joined, as a fold can come right after one.
+fn unfold(s: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in s.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    for line in &mut lines {
+        line.truncate(line.trim_end().len());
+    }
+    lines
+}
+
+/// A TEXT value with its escapes undone
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// A DATE ("20250712") or DATE-TIME ("20250712T100000", with a "Z" on the
+/// end for UTC) value, in the zone its TZID parameter names
+fn parse_time<'a>(value: &str, params: impl Iterator<Item = &'a str>) -> Option<Time> {
+    let mut zone = Zone::Floating;
+    for param in params {
+        if let Some((key, tzid)) = param.split_once('=') {
+            if key.eq_ignore_ascii_case("TZID") {
+                zone = Zone::Named(tzid.trim_matches('"').to_owned());
+            }
+        }
+    }
+
+    let (date, time) = value.split_once(['T', 't']).map_or((value, None), |(d, t)| (d, Some(t)));
+    let number = |s: &str, range: std::ops::RangeInclusive<u32>| -> Option<u32> {
+        s.bytes().all(|b| b.is_ascii_digit()).then(|| s.parse().ok()).flatten().filter(|n| range.contains(n))
+    };
+    if date.len() != 8 || !value.is_ascii() {
+        return None;
+    }
+    let year = number(&date[..4], 0..=9999)? as u16;
+    let month = number(&date[4..6], 1..=12)? as u8;
+    let day = number(&date[6..], 1..=31)? as u8;
+    let time = match time {
+        None => None,
+        Some(t) => {
+            let t = match t.strip_suffix(['Z', 'z']) {
+                Some(t) => {
+                    zone = Zone::Utc;
+                    t
+                }
+                None => t,
+            };
+            if t.len() != 6 {
+                return None;
+            }
+            // 60 for a leap second
+            Some((number(&t[..2], 0..=23)? as u8, number(&t[2..4], 0..=59)? as u8, number(&t[4..], 0..=60)? as u8))
+        }
+    };
+    Some(Time { year, month, day, time, zone })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nSUMMARY:Summer \r\n fair\r\n\
+        DTSTART;TZID=\"Europe/London\":20250712T100000\r\nDTEND:20250712T150000Z\r\n\
+        LOCATION:Market square\\, Oxford\r\nDESCRIPTION:Stalls\\nMusic\\;food\r\nEND:VEVENT\r\n\
+        BEGIN:VEVENT\r\nSUMMARY:Second\r\nDTSTART:20250713\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+
+    #[test]
+    fn parses_events() {
+        let event = Event::parse(SAMPLE).unwrap();
+        assert_eq!(event.summary.as_deref(), Some("Summer fair"));
+        assert_eq!(event.start, Time {
+            year: 2025,
+            month: 7,
+            day: 12,
+            time: Some((10, 0, 0)),
+            zone: Zone::Named("Europe/London".to_owned()),
+        });
+        let end = event.end.unwrap();
+        assert_eq!((end.time, end.zone), (Some((15, 0, 0)), Zone::Utc));
+        assert_eq!(event.location.as_deref(), Some("Market square, Oxford"));
+        assert_eq!(event.description.as_deref(), Some("Stalls\nMusic;food"));
+
+        let event = Event::parse("BEGIN:VEVENT\nDTSTART;VALUE=DATE:20251231\nEND:VEVENT").unwrap();
+        assert_eq!((event.start.time, event.start.zone, event.end), (None, Zone::Floating, None));
+    }
+
+    #[test]
+    fn rejects_truncated_events() {
+        assert_eq!(Event::parse(""), None);
+        assert_eq!(Event::parse("BEGIN:VEVENT\nSUMMARY:No start\nEND:VEVENT"), None);
+        assert_eq!(Event::parse("BEGIN:VEVENT\nDTSTART:202507"), None);
+        assert_eq!(Event::parse("BEGIN:VEVENT\nDTSTART:20250712T10"), None);
+        // A start after the event's over doesn't count
+        assert_eq!(Event::parse("BEGIN:VEVENT\nEND:VEVENT\nDTSTART:20250712"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_events() {
+        assert_eq!(Event::parse("DTSTART:20250712"), None);
+        assert_eq!(Event::parse("BEGIN:VEVENT\nDTSTART:20251312\nEND:VEVENT"), None);
+        assert_eq!(Event::parse("BEGIN:VEVENT\nDTSTART:20250712T250000\nEND:VEVENT"), None);
+        assert_eq!(Event::parse("BEGIN:VEVENT\nDTSTART:2025-07-12\nEND:VEVENT"), None);
+        assert_eq!(Event::parse("BEGIN:VEVENT\nDTSTART:+0250712\nEND:VEVENT"), None);
+        // A bad end spoils the event too
+        assert_eq!(Event::parse("BEGIN:VEVENT\nDTSTART:20250712\nDTEND:2025071\nEND:VEVENT"), None);
+    }
+}
@@ -0,0 +1,361 @@
+//! Camera intrinsics, plus simple routines for estimating them from views of
+//! a code, so that pose estimation works without an external calibration
+//! tool.
+
+use image::{ImageBuffer, Pixel};
+use crate::{Point, homography::Homography};
+
+/// Lens distortion model and coefficients
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distortion {
+    /// Brown-Conrady model, for ordinary lenses. Coefficients are the same
+    /// as OpenCV's `k1, k2, p1, p2, k3`; all zeroes means no distortion.
+    BrownConrady {
+        /// Radial coefficients
+        k1: f64,
+        k2: f64,
+        k3: f64,
+        /// Tangential coefficients
+        p1: f64,
+        p2: f64,
+    },
+    /// Equidistant fisheye model (as in OpenCV's `fisheye` module), for
+    /// action cameras, doorbells and other very wide lenses. The distorted
+    /// angle from the optical axis is `t * (1 + k1 t^2 + k2 t^4 + k3 t^6 +
+    /// k4 t^8)` for an undistorted angle `t`.
+    Fisheye { k1: f64, k2: f64, k3: f64, k4: f64 },
+}
+
+impl Default for Distortion {
+    fn default() -> Self {
+        Distortion::BrownConrady { k1: 0.0, k2: 0.0, k3: 0.0, p1: 0.0, p2: 0.0 }
+    }
+}
+
+/// Pinhole camera parameters, in pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraIntrinsics {
+    /// Focal length along x
+    pub fx: f64,
+    /// Focal length along y
+    pub fy: f64,
+    /// Principal point (usually close to the image center)
+    pub cx: f64,
+    pub cy: f64,
+    pub distortion: Distortion,
+}
+
+/// Brown-Conrady radial scale factor and tangential offset at a point
+#[inline]
+fn brown_conrady(k1: f64, k2: f64, k3: f64, p1: f64, p2: f64, x: f64, y: f64) -> (f64, f64, f64) {
+    let r2 = x * x + y * y;
+    let radial = 1.0 + r2 * (k1 + r2 * (k2 + r2 * k3));
+    let dx = 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x);
+    let dy = p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y;
+    (radial, dx, dy)
+}
+
+impl Distortion {
+    pub fn is_none(&self) -> bool {
+        match *self {
+            Distortion::BrownConrady { .. } => *self == Distortion::default(),
+            Distortion::Fisheye { .. } => false,
+        }
+    }
+
+    /// Applies distortion to a point in normalized camera coordinates
+    pub fn distort(&self, p: Point<f64>) -> Point<f64> {
+        match *self {
+            Distortion::BrownConrady { k1, k2, k3, p1, p2 } => {
+                let (radial, dx, dy) = brown_conrady(k1, k2, k3, p1, p2, p.x, p.y);
+                Point::new(p.x * radial + dx, p.y * radial + dy)
+            }
+            Distortion::Fisheye { k1, k2, k3, k4 } => {
+                let r = (p.x * p.x + p.y * p.y).sqrt();
+                if r < 1e-12 {
+                    return p;
+                }
+                let t = r.atan();
+                let t2 = t * t;
+                let td = t * (1.0 + t2 * (k1 + t2 * (k2 + t2 * (k3 + t2 * k4))));
+                Point::new(p.x * td / r, p.y * td / r)
+            }
+        }
+    }
+
+    /// Removes distortion from a point in normalized camera coordinates.
+    /// There's no closed form for this, so it's solved iteratively.
+    ///
+    /// With the fisheye model, points 90 degrees or more off the optical axis
+    /// have no pinhole equivalent, and come back as infinities or NaN.
+    pub fn undistort(&self, p: Point<f64>) -> Point<f64> {
+        if self.is_none() {
+            return p;
+        }
+        match *self {
+            Distortion::BrownConrady { k1, k2, k3, p1, p2 } => {
+                let mut u = p;
+                for _ in 0..20 {
+                    let (radial, dx, dy) = brown_conrady(k1, k2, k3, p1, p2, u.x, u.y);
+                    u = Point::new((p.x - dx) / radial, (p.y - dy) / radial);
+                }
+                u
+            }
+            Distortion::Fisheye { k1, k2, k3, k4 } => {
+                let td = (p.x * p.x + p.y * p.y).sqrt();
+                if td < 1e-12 {
+                    return p;
+                }
+                // Newton's method on f(t) = t (1 + k1 t^2 + ...) - td
+                let mut t = td;
+                for _ in 0..20 {
+                    let t2 = t * t;
+                    let f = t * (1.0 + t2 * (k1 + t2 * (k2 + t2 * (k3 + t2 * k4)))) - td;
+                    let df = 1.0 + t2 * (3.0 * k1 + t2 * (5.0 * k2 + t2 * (7.0 * k3 + t2 * 9.0 * k4)));
+                    let step = f / df;
+                    t -= step;
+                    if step.abs() < 1e-12 { break; }
+                }
+                let scale = t.tan() / td;
+                Point::new(p.x * scale, p.y * scale)
+            }
+        }
+    }
+}
+
+impl CameraIntrinsics {
+    /// Intrinsics for a camera with square pixels, the principal point at the
+    /// center of the image, and no distortion
+    pub fn from_focal(width: u32, height: u32, focal: f64) -> Self {
+        Self {
+            fx: focal,
+            fy: focal,
+            cx: width as f64 / 2.0,
+            cy: height as f64 / 2.0,
+            distortion: Distortion::default(),
+        }
+    }
+
+    /// Rough intrinsics for a camera with the given image size and horizontal
+    /// field of view (in radians), for when no calibration is available
+    pub fn from_fov(width: u32, height: u32, h_fov: f64) -> Self {
+        Self::from_focal(width, height, width as f64 / 2.0 / (h_fov / 2.0).tan())
+    }
+
+    /// Converts a pixel position to normalized camera coordinates, removing
+    /// lens distortion
+    pub fn normalize(&self, p: Point<f64>) -> Point<f64> {
+        let distorted = Point::new((p.x - self.cx) / self.fx, (p.y - self.cy) / self.fy);
+        self.distortion.undistort(distorted)
+    }
+
+    /// Converts normalized camera coordinates back to a pixel position, as
+    /// seen through the lens (i.e. with distortion applied)
+    pub fn denormalize(&self, p: Point<f64>) -> Point<f64> {
+        let d = self.distortion.distort(p);
+        Point::new(d.x * self.fx + self.cx, d.y * self.fy + self.cy)
+    }
+
+    /// Moves a pixel position to where it would be seen by an ideal,
+    /// distortion-free camera with the same focal length and center
+    pub fn undistort_point(&self, p: Point<f64>) -> Point<f64> {
+        let n = self.normalize(p);
+        Point::new(n.x * self.fx + self.cx, n.y * self.fy + self.cy)
+    }
+
+    /// Undistorts the corners found by the scanner, e.g. before using them
+    /// to compute a homography
+    pub fn undistort_corners<const N: usize>(&self, corners: [Point<f64>; N]) -> [Point<f64>; N] {
+        corners.map(|c| self.undistort_point(c))
+    }
+
+    /// Rough distance from the camera to the center of a code of side length
+    /// `code_size`, just from how big it looks in the image, in the same
+    /// units as `code_size`. Uses the longer of the code's sides (like
+    /// `calibrate_from_distances`) since tilting the code only ever shortens
+    /// them, so it holds up well enough for proximity checks without
+    /// estimating the full pose.
+    pub fn distance_to_bbox(&self, bbox: [Point<f64>; 3], code_size: f64) -> f64 {
+        let [tl, tr, bl] = bbox.map(|p| self.normalize(p));
+        let side = tl.dist_to(tr).max(tl.dist_to(bl));
+        let center = Point::new((tr.x + bl.x) / 2.0, (tr.y + bl.y) / 2.0);
+        distance_from_side(side, center, code_size)
+    }
+
+    /// Like `distance_to_bbox`, but for a code's four corners (top-left,
+    /// top-right, bottom-right, bottom-left)
+    pub fn distance_to_quad(&self, quad: [Point<f64>; 4], code_size: f64) -> f64 {
+        let q = quad.map(|p| self.normalize(p));
+        let side = (0..4).map(|i| q[i].dist_to(q[(i + 1) % 4])).fold(0.0, f64::max);
+        let center = Point::new(
+            q.iter().map(|p| p.x).sum::<f64>() / 4.0,
+            q.iter().map(|p| p.y).sum::<f64>() / 4.0,
+        );
+        distance_from_side(side, center, code_size)
+    }
+}
+
+/// By similar triangles, depth / code_size = 1 / side, with `side` in
+/// normalized units. Codes off to the side of the image are further away
+/// than their depth, along the ray through `center`.
+fn distance_from_side(side: f64, center: Point<f64>, code_size: f64) -> f64 {
+    let depth = code_size / side;
+    depth * (1.0 + center.x * center.x + center.y * center.y).sqrt()
+}
+
+/// Precomputed lookup for undistorting whole frames. Build once per camera
+/// and resolution, then `apply` to every frame before scanning.
+#[derive(Clone, Debug)]
+pub struct Undistorter {
+    width: u32,
+    height: u32,
+    // For each output pixel, where to sample the distorted input
+    map: Vec<(f32, f32)>,
+}
+
+impl Undistorter {
+    pub fn new(intrinsics: &CameraIntrinsics, width: u32, height: u32) -> Self {
+        Self::with_zoom(intrinsics, width, height, 1.0)
+    }
+
+    /// Like `new`, but the undistorted frame is rendered with the focal
+    /// length scaled by `zoom`. Fisheye lenses see far more than an ideal
+    /// camera with the same focal length, so zooming out (`zoom < 1`) keeps
+    /// more of the frame.
+    pub fn with_zoom(intrinsics: &CameraIntrinsics, width: u32, height: u32, zoom: f64) -> Self {
+        let (fx, fy) = (intrinsics.fx * zoom, intrinsics.fy * zoom);
+        let mut map = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let ideal = Point::new(
+                    (x as f64 - intrinsics.cx) / fx,
+                    (y as f64 - intrinsics.cy) / fy,
+                );
+                let src = intrinsics.denormalize(ideal);
+                map.push((src.x as f32, src.y as f32));
+            }
+        }
+        Self { width, height, map }
+    }
+
+    /// Produces an undistorted copy of `img`, which must be the size this
+    /// undistorter was built for. Pixels that map from outside the frame are
+    /// left white, so they don't look like part of a code.
+    pub fn apply<Px>(&self, img: &ImageBuffer<Px, Vec<u8>>) -> ImageBuffer<Px, Vec<u8>>
+    where
+        Px: Pixel<Subpixel = u8>,
+    {
+        assert_eq!(img.dimensions(), (self.width, self.height), "undistorter built for a different frame size");
+        let channels = Px::CHANNEL_COUNT as usize;
+        let mut out = ImageBuffer::from_pixel(self.width, self.height, *Px::from_slice(&[u8::MAX; 4][..channels]));
+        let (max_x, max_y) = ((self.width - 1) as f32, (self.height - 1) as f32);
+
+        for (px, &(sx, sy)) in out.pixels_mut().zip(self.map.iter()) {
+            if !(0.0..=max_x).contains(&sx) || !(0.0..=max_y).contains(&sy) {
+                continue;
+            }
+            let (x0, y0) = (sx.floor() as u32, sy.floor() as u32);
+            let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+            let (fx, fy) = (sx - x0 as f32, sy - y0 as f32);
+            let (a, b) = (img.get_pixel(x0, y0).channels(), img.get_pixel(x1, y0).channels());
+            let (c, d) = (img.get_pixel(x0, y1).channels(), img.get_pixel(x1, y1).channels());
+            for (i, val) in px.channels_mut().iter_mut().enumerate() {
+                let top = a[i] as f32 * (1.0 - fx) + b[i] as f32 * fx;
+                let bot = c[i] as f32 * (1.0 - fx) + d[i] as f32 * fx;
+                *val = (top * (1.0 - fy) + bot * fy).round() as u8;
+            }
+        }
+        out
+    }
+}
+
+/// One view of the calibration code, held at a measured distance
+#[derive(Clone, Copy, Debug)]
+pub struct DistanceView {
+    /// Corners of the code, as found by the scanner (`ScanResult::bbox`)
+    pub corners: [Point<f64>; 3],
+    /// Distance from the camera to the code, in the same units as the code
+    /// size
+    pub distance: f64,
+}
+
+/// Estimates the focal length from views of a code of side length
+/// `code_size` at known distances. Hold the code roughly face-on to the camera
+/// for best results. Returns `None` if `views` is empty.
+pub fn calibrate_from_distances(
+    views: &[DistanceView],
+    code_size: f64,
+    width: u32,
+    height: u32,
+) -> Option<CameraIntrinsics> {
+    if views.is_empty() {
+        return None;
+    }
+    // By similar triangles, focal / side_px = distance / code_size. Use the
+    // longer side, since tilting the code can only ever shorten a side.
+    let focal = views.iter()
+        .map(|v| {
+            let [tl, tr, bl] = v.corners;
+            let side_px = tl.dist_to(tr).max(tl.dist_to(bl));
+            side_px * v.distance / code_size
+        })
+        .sum::<f64>() / views.len() as f64;
+    Some(CameraIntrinsics::from_focal(width, height, focal))
+}
+
+/// Estimates the focal length from views of a square code, without needing
+/// distances. Each view is the code's four corners (top-left, top-right,
+/// bottom-right, bottom-left), which must really be measured: a corner
+/// guessed by completing a parallelogram carries no perspective information.
+/// The views should be taken at a variety of steep angles; face-on views tell
+/// us nothing about the focal length and are effectively ignored.
+///
+/// Assumes square pixels and a principal point at the image center.
+pub fn calibrate_from_quads(
+    quads: &[[Point<f64>; 4]],
+    width: u32,
+    height: u32,
+) -> Option<CameraIntrinsics> {
+    let (cx, cy) = (width as f64 / 2.0, height as f64 / 2.0);
+    let square = [
+        Point::new(0.0, 0.0),
+        Point::new(1.0, 0.0),
+        Point::new(1.0, 1.0),
+        Point::new(0.0, 1.0),
+    ];
+
+    // With K = diag(f, f, 1) (after centering), the columns h1, h2 of each
+    // homography must satisfy (Zhang's constraints, with u = 1 / f^2):
+    //   h1^T w h2 = 0         ->  u (h11 h12 + h21 h22) + h31 h32 = 0
+    //   h1^T w h1 = h2^T w h2 ->  u (h11^2 + h21^2 - h12^2 - h22^2) + h31^2 - h32^2 = 0
+    // Each is of the form a u + b = 0; solve for u by least squares.
+    let (mut aa, mut ab) = (0.0, 0.0);
+    for quad in quads {
+        let centered = quad.map(|p| Point::new(p.x - cx, p.y - cy));
+        let h = match Homography::from_points(&square, &centered) {
+            Some(h) => h.0,
+            None => continue,
+        };
+        // Scale the homography so the constraint coefficients are comparable
+        // between views
+        let s = (h[0][0].powi(2) + h[1][0].powi(2)).sqrt();
+        let h = h.map(|row| row.map(|v| v / s));
+        let constraints = [
+            (h[0][0] * h[0][1] + h[1][0] * h[1][1], h[2][0] * h[2][1]),
+            (
+                h[0][0].powi(2) + h[1][0].powi(2) - h[0][1].powi(2) - h[1][1].powi(2),
+                h[2][0].powi(2) - h[2][1].powi(2),
+            ),
+        ];
+        for (a, b) in constraints {
+            aa += a * a;
+            ab += a * b;
+        }
+    }
+
+    let u = -ab / aa;
+    if !u.is_finite() || u <= 0.0 {
+        return None;
+    }
+    Some(CameraIntrinsics::from_focal(width, height, 1.0 / u.sqrt()))
+}
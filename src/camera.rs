@@ -0,0 +1,214 @@
+//! Scans frames straight out of `nokhwa`, binarizing from the camera's native
+//! pixel format instead of decoding to an RGBA `ImageBuffer` first. Also
+//! steers the camera's exposure and gain, where the backend allows it.
+//!
+//! Only luma is ever needed, so YUYV, NV12 and greyscale frames have theirs
+//! picked straight out, and MJPEG frames are decoded to greyscale, which
+//! skips the chroma altogether. `nokhwa`'s own `LumaFormat` goes through RGB
+//! to get there.
+
+use std::{io, ops::Deref};
+use image::{GrayImage, ImageBuffer, Pixel, Rgb};
+use mozjpeg::Decompress;
+use nokhwa::{
+    Buffer,
+    Camera,
+    NokhwaError,
+    utils::{ControlValueDescription, ControlValueSetter, FrameFormat, KnownCameraControl},
+};
+use crate::{
+    ScanResult, Scanner,
+    bitmap::{Binarizer, Bitmap},
+    feedback::{FrameStats, Hint, HintParams},
+    scanner::Stopwatch,
+};
+
+#[inline]
+fn rgb_to_luma(rgb: &[u8]) -> u8 {
+    Rgb([rgb[0], rgb[1], rgb[2]]).to_luma().0[0]
+}
+
+/// Decodes an MJPEG frame to greyscale. The decoder only has to read the
+/// luma channel, so this is much cheaper than decoding to RGB.
+fn mjpeg_to_luma(data: &[u8], width: u32, height: u32) -> Result<Vec<u8>, NokhwaError> {
+    let error = |error: String| NokhwaError::ProcessFrameError {
+        src: FrameFormat::MJPEG,
+        destination: "luma".to_owned(),
+        error,
+    };
+    let io_error = |e: io::Error| error(e.to_string());
+    let mut jpeg = Decompress::new_mem(data).map_err(io_error)?.grayscale().map_err(io_error)?;
+    if (jpeg.width(), jpeg.height()) != (width as usize, height as usize) {
+        return Err(error(format!("frame is {}x{}, not {}x{}", jpeg.width(), jpeg.height(), width, height)));
+    }
+    let luma = jpeg.read_scanlines_flat().ok_or_else(|| error("frame is cut short".to_owned()))?;
+    jpeg.finish_decompress();
+    Ok(luma)
+}
+
+/// A camera frame's luma, as a greyscale image. Use this rather than
+/// `Buffer::decode_image::<LumaFormat>()`, which decodes to RGB first.
+pub fn luma_from_nokhwa_frame(frame: &Buffer) -> Result<GrayImage, NokhwaError> {
+    let res = frame.resolution();
+    let (width, height) = (res.width(), res.height());
+    let len = (width * height) as usize;
+    let buf = frame.buffer();
+
+    let luma = match frame.source_frame_format() {
+        FrameFormat::YUYV => buf.iter().step_by(2).copied().collect(),
+        FrameFormat::NV12 | FrameFormat::GRAY => buf[..len].to_vec(),
+        FrameFormat::RAWRGB => buf.chunks_exact(3).map(rgb_to_luma).collect(),
+        FrameFormat::MJPEG => mjpeg_to_luma(buf, width, height)?,
+    };
+    ImageBuffer::from_raw(width, height, luma).ok_or_else(|| NokhwaError::ProcessFrameError {
+        src: frame.source_frame_format(),
+        destination: "luma".to_owned(),
+        error: "frame is cut short".to_owned(),
+    })
+}
+
+/// Binarizes a camera frame without going through an intermediate image.
+///
+/// YUYV, NV12 and greyscale frames are read directly from their luma bytes;
+/// MJPEG frames have to be decoded, but only their luma.
+pub fn bitmap_from_nokhwa_frame(frame: &Buffer) -> Result<Bitmap, NokhwaError> {
+    let mut bmp = Bitmap::default();
+    set_bitmap_from_nokhwa_frame(&mut bmp, frame)?;
+    Ok(bmp)
+}
+
+/// Like `bitmap_from_nokhwa_frame`, but overwrites an existing bitmap
+pub fn set_bitmap_from_nokhwa_frame(bmp: &mut Bitmap, frame: &Buffer) -> Result<(), NokhwaError> {
+    set_bitmap_from_nokhwa_frame_with(bmp, frame, Binarizer::Global)
+}
+
+/// Like `set_bitmap_from_nokhwa_frame`, but with a choice of binarizer
+pub fn set_bitmap_from_nokhwa_frame_with(
+    bmp: &mut Bitmap,
+    frame: &Buffer,
+    binarizer: Binarizer,
+) -> Result<(), NokhwaError> {
+    let res = frame.resolution();
+    let (width, height) = (res.width(), res.height());
+    let len = (width * height) as usize;
+    let buf = frame.buffer();
+
+    match frame.source_frame_format() {
+        // Y0 U Y1 V - every other byte is luma
+        FrameFormat::YUYV => {
+            bmp.set_from_luma(buf.iter().step_by(2).copied(), width, height, binarizer)
+        }
+        // Full-resolution Y plane comes first, then interleaved UV
+        FrameFormat::NV12 | FrameFormat::GRAY => {
+            bmp.set_from_luma(buf[..len].iter().copied(), width, height, binarizer)
+        }
+        FrameFormat::RAWRGB => {
+            bmp.set_from_luma(buf.chunks_exact(3).map(rgb_to_luma), width, height, binarizer)
+        }
+        FrameFormat::MJPEG => {
+            let luma = mjpeg_to_luma(buf, width, height)?;
+            bmp.set_from_luma(luma.into_iter(), width, height, binarizer)
+        }
+    }
+    Ok(())
+}
+
+/// Scans a frame captured by `nokhwa`. See `bitmap_from_nokhwa_frame`.
+pub fn scan_nokhwa_frame(frame: &Buffer) -> Result<ScanResult, NokhwaError> {
+    Scanner::new().scan_nokhwa_frame(frame)
+}
+
+impl Scanner {
+    /// Scans a frame captured by `nokhwa`, binarizing straight into the
+    /// scanner's own bitmap with its `binarizer`. See
+    /// `bitmap_from_nokhwa_frame`.
+    pub fn scan_nokhwa_frame(&mut self, frame: &Buffer) -> Result<ScanResult, NokhwaError> {
+        let start = Stopwatch::start();
+        let binarizer = self.binarizer;
+        set_bitmap_from_nokhwa_frame_with(self.bitmap_mut(), frame, binarizer)?;
+        Ok(self.scan_own_bitmap_since(start))
+    }
+}
+
+/// Moves one of the camera's controls (e.g. `KnownCameraControl::Exposure`
+/// or `Gain`) by `steps` of the camera's own step size, keeping within its
+/// range. Returns the new value.
+///
+/// Fails if the backend doesn't support the control, or it isn't a number.
+/// Some cameras also ignore manual exposure while their own auto-exposure is
+/// on.
+pub fn nudge_control(cam: &mut Camera, control: KnownCameraControl, steps: i64) -> Result<f64, NokhwaError> {
+    let (value, setter) = match *cam.camera_control(control)?.description() {
+        ControlValueDescription::Integer { value, step, .. } => {
+            let value = value + step.max(1) * steps;
+            (value as f64, ControlValueSetter::Integer(value))
+        }
+        ControlValueDescription::IntegerRange { min, max, value, step, .. } => {
+            let value = (value + step.max(1) * steps).clamp(min, max);
+            (value as f64, ControlValueSetter::Integer(value))
+        }
+        ControlValueDescription::Float { value, step, .. } => {
+            let value = value + step * steps as f64;
+            (value, ControlValueSetter::Float(value))
+        }
+        ControlValueDescription::FloatRange { min, max, value, step, .. } => {
+            let value = (value + step * steps as f64).clamp(min, max);
+            (value, ControlValueSetter::Float(value))
+        }
+        ref other => {
+            return Err(NokhwaError::SetPropertyError {
+                property: control.to_string(),
+                value: steps.to_string(),
+                error: format!("not a numeric control: {:?}", other),
+            });
+        }
+    };
+    cam.set_camera_control(control, setter)?;
+    Ok(value)
+}
+
+/// Steers a camera's exposure a step at a time, following the exposure hints
+/// from `feedback`, since webcams' own auto-exposure tends to blow out paper
+/// codes under lamps
+#[derive(Clone, Copy, Debug)]
+pub struct AutoExposure {
+    pub params: HintParams,
+    /// Frames between adjustments, giving the camera time to settle
+    pub interval: u32,
+    frames: u32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self { params: HintParams::default(), interval: 15, frames: 0 }
+    }
+}
+
+impl AutoExposure {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call with every frame captured. Every `interval` frames, measures the
+    /// frame and moves the exposure a step if it's too dark or too bright.
+    /// Returns the new exposure if it was changed.
+    pub fn update<Px, C>(&mut self, cam: &mut Camera, frame: &ImageBuffer<Px, C>) -> Result<Option<f64>, NokhwaError>
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        self.frames += 1;
+        if self.frames < self.interval {
+            return Ok(None);
+        }
+        self.frames = 0;
+        // Coarser than usual, since this runs on the capture thread
+        let stats = FrameStats::measure(frame, None, self.params.step * 4);
+        let steps = match self.params.exposure_hints(&stats).first() {
+            Some(Hint::IncreaseExposure) => 1,
+            Some(Hint::DecreaseExposure) => -1,
+            _ => return Ok(None),
+        };
+        nudge_control(cam, KnownCameraControl::Exposure, steps).map(Some)
+    }
+}
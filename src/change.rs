@@ -0,0 +1,112 @@
+//! Cheap frame-level change detection, so a fixed camera looking at a scene
+//! where nothing's happening doesn't have to rescan every frame.
+//!
+//! Each frame is shrunk to a small grid of average brightnesses and compared
+//! with the grid from the last frame that was scanned. Comparing against the
+//! last *scanned* frame, rather than the previous one, means slow changes
+//! still add up to a rescan eventually.
+
+use std::ops::Deref;
+use image::{ImageBuffer, Pixel};
+
+/// Cells along each side of the thumbnail grid
+const GRID: u32 = 32;
+/// Pixels sampled along each side of a cell
+const SAMPLES: u32 = 4;
+
+/// Decides which frames are worth scanning
+#[derive(Clone, Debug)]
+pub struct ChangeDetector {
+    reference: Option<Vec<u8>>,
+    frames_since_scan: u32,
+    /// How much a cell's average brightness has to change by to count
+    pub cell_threshold: u8,
+    /// How many cells have to change for the frame to count as changed. A
+    /// code coming into view at a distance may only change a couple.
+    pub min_changed_cells: usize,
+    /// Rescan after this many checks even if nothing seems to have changed
+    pub heartbeat: u32,
+}
+
+impl Default for ChangeDetector {
+    fn default() -> Self {
+        Self {
+            reference: None,
+            frames_since_scan: 0,
+            cell_threshold: 12,
+            min_changed_cells: 2,
+            heartbeat: 30,
+        }
+    }
+}
+
+/// Average brightness of each cell of a `GRID` x `GRID` grid over the image,
+/// from a sparse sample of its pixels
+fn thumbnail<Px, C>(img: &ImageBuffer<Px, C>) -> Vec<u8>
+where
+    Px: Pixel<Subpixel = u8>,
+    C: Deref<Target = [u8]>,
+{
+    let (width, height) = img.dimensions();
+    let mut cells = Vec::with_capacity((GRID * GRID) as usize);
+    let steps = GRID * SAMPLES;
+    for cy in 0..GRID {
+        for cx in 0..GRID {
+            let mut sum = 0u32;
+            for sy in 0..SAMPLES {
+                let y = ((cy * SAMPLES + sy) * 2 + 1) * height / (steps * 2);
+                for sx in 0..SAMPLES {
+                    let x = ((cx * SAMPLES + sx) * 2 + 1) * width / (steps * 2);
+                    sum += img.get_pixel(x, y).to_luma().0[0] as u32;
+                }
+            }
+            cells.push((sum / (SAMPLES * SAMPLES)) as u8);
+        }
+    }
+    cells
+}
+
+impl ChangeDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `img` should be scanned: it's the first frame, it's changed
+    /// enough since the last frame this returned true for, or the heartbeat
+    /// is due. Call once for every frame that would otherwise be scanned.
+    pub fn should_scan<Px, C>(&mut self, img: &ImageBuffer<Px, C>) -> bool
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        if img.width() == 0 || img.height() == 0 {
+            return false;
+        }
+        let thumb = thumbnail(img);
+        let changed = match &self.reference {
+            None => true,
+            Some(reference) => {
+                let changed_cells = reference.iter()
+                    .zip(thumb.iter())
+                    .filter(|(a, b)| a.abs_diff(**b) > self.cell_threshold)
+                    .count();
+                changed_cells >= self.min_changed_cells
+            }
+        };
+
+        self.frames_since_scan += 1;
+        if changed || self.frames_since_scan >= self.heartbeat {
+            self.reference = Some(thumb);
+            self.frames_since_scan = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Forgets the reference frame, so the next frame is always scanned
+    pub fn reset(&mut self) {
+        self.reference = None;
+        self.frames_since_scan = 0;
+    }
+}
@@ -0,0 +1,38 @@
+//! Putting payloads on the system clipboard, for the demo's copy hotkey and
+//! `arqr-cli --copy`.
+//!
+//! On Linux the clipboard belongs to whichever program last copied to it, so
+//! what arqr copies is only kept after it exits if a clipboard manager is
+//! running to take it over. Every desktop environment has one. It's handed
+//! over when the `Clipboard` is dropped, so drop it before `process::exit`.
+
+use std::fmt;
+
+/// The system clipboard, opened the first time something's copied, so
+/// programs that never copy don't fail without a display to copy to
+#[derive(Default)]
+pub struct Clipboard {
+    inner: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copies a payload as text. Bytes that aren't UTF-8 are replaced with
+    /// U+FFFD, as `arqr-cli` prints them.
+    pub fn copy_payload(&mut self, payload: &[u8]) -> Result<(), arboard::Error> {
+        let clipboard = match &mut self.inner {
+            Some(clipboard) => clipboard,
+            None => self.inner.insert(arboard::Clipboard::new()?),
+        };
+        clipboard.set_text(String::from_utf8_lossy(payload))
+    }
+}
+
+impl fmt::Debug for Clipboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Clipboard").field("open", &self.inner.is_some()).finish()
+    }
+}
@@ -0,0 +1,151 @@
+//! Runs arqr side by side with other QR decoders (`rqrr`, `quircs` and
+//! `bardecoder`) so their results can be compared. Only built with the
+//! `compare` feature - none of this is meant to ship in real applications.
+
+use image::{DynamicImage, GrayImage};
+use crate::{Point, ScanResult, scan};
+
+/// A decoder to compare arqr against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reference {
+    Rqrr,
+    Quircs,
+    Bardecoder,
+}
+
+impl Reference {
+    pub const ALL: [Reference; 3] = [Reference::Rqrr, Reference::Quircs, Reference::Bardecoder];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Reference::Rqrr => "rqrr",
+            Reference::Quircs => "quircs",
+            Reference::Bardecoder => "bardecoder",
+        }
+    }
+
+    /// Runs this decoder over an image
+    pub fn run(&self, img: &DynamicImage) -> Outcome {
+        match self {
+            Reference::Rqrr => run_rqrr(&img.to_luma8()),
+            Reference::Quircs => run_quircs(&img.to_luma8()),
+            Reference::Bardecoder => run_bardecoder(img),
+        }
+    }
+}
+
+/// What a single decoder made of a single image
+#[derive(Clone, Debug, Default)]
+pub struct Outcome {
+    /// Whether the decoder thinks there's a code in the image at all
+    pub detected: bool,
+    /// Contents of every code the decoder managed to read
+    pub payloads: Vec<String>,
+    /// Corners of every code found (top-left, top-right, bottom-right,
+    /// bottom-left), if the decoder says where they are
+    pub quads: Vec<[Point<f64>; 4]>,
+}
+
+/// Runs arqr over an image. "Detected" means the scanner found a bounding
+/// box, whether or not its payload could be read.
+pub fn run_arqr(img: &DynamicImage) -> Outcome {
+    arqr_outcome(&scan(&img.to_luma8()))
+}
+
+/// Puts a result arqr's already come up with in the same terms as the other
+/// decoders
+pub fn arqr_outcome(result: &ScanResult) -> Outcome {
+    Outcome {
+        detected: result.quad().is_some(),
+        payloads: result.payload.iter().map(|p| String::from_utf8_lossy(p).into_owned()).collect(),
+        quads: result.quad().into_iter().collect(),
+    }
+}
+
+fn run_rqrr(img: &GrayImage) -> Outcome {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(width, height, |x, y| {
+        img.get_pixel(x as u32, y as u32).0[0]
+    });
+    let grids = prepared.detect_grids();
+    let payloads = grids.iter()
+        .filter_map(|grid| grid.decode().ok())
+        .map(|(_, content)| content)
+        .collect();
+    let quads = grids.iter()
+        .map(|grid| grid.bounds.map(|p| Point::new(p.x as f64, p.y as f64)))
+        .collect();
+    Outcome { detected: !grids.is_empty(), payloads, quads }
+}
+
+fn run_quircs(img: &GrayImage) -> Outcome {
+    let mut decoder = quircs::Quirc::default();
+    let codes = decoder.identify(img.width() as usize, img.height() as usize, img.as_raw());
+    let mut outcome = Outcome::default();
+    for code in codes.flatten() {
+        outcome.detected = true;
+        outcome.quads.push(code.corners.map(|p| Point::new(p.x as f64, p.y as f64)));
+        if let Ok(data) = code.decode() {
+            outcome.payloads.push(String::from_utf8_lossy(&data.payload).into_owned());
+        }
+    }
+    outcome
+}
+
+fn run_bardecoder(img: &DynamicImage) -> Outcome {
+    let results = bardecoder::default_decoder().decode(img);
+    let payloads = results.iter()
+        .filter_map(|res| res.as_ref().ok())
+        .cloned()
+        .collect();
+    Outcome { detected: !results.is_empty(), payloads, quads: Vec::new() }
+}
+
+/// Running tally of how often arqr agrees with one reference decoder
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Agreement {
+    pub images: usize,
+    pub both_detected: usize,
+    pub only_arqr: usize,
+    pub only_reference: usize,
+    pub neither: usize,
+    /// Images where the reference decoded something and arqr decoded exactly
+    /// the same set of payloads
+    pub decode_matches: usize,
+    /// Images where the reference decoded something at all
+    pub reference_decodes: usize,
+}
+
+impl Agreement {
+    pub fn add(&mut self, arqr: &Outcome, reference: &Outcome) {
+        self.images += 1;
+        match (arqr.detected, reference.detected) {
+            (true, true) => self.both_detected += 1,
+            (true, false) => self.only_arqr += 1,
+            (false, true) => self.only_reference += 1,
+            (false, false) => self.neither += 1,
+        }
+
+        if !reference.payloads.is_empty() {
+            self.reference_decodes += 1;
+            let mut ours = arqr.payloads.clone();
+            let mut theirs = reference.payloads.clone();
+            ours.sort();
+            theirs.sort();
+            if ours == theirs {
+                self.decode_matches += 1;
+            }
+        }
+    }
+
+    /// Fraction of images where arqr and the reference agreed on whether
+    /// there's a code
+    pub fn detection_rate(&self) -> f64 {
+        (self.both_detected + self.neither) as f64 / self.images.max(1) as f64
+    }
+
+    /// Fraction of the reference's successful decodes that arqr matched
+    pub fn decode_rate(&self) -> f64 {
+        self.decode_matches as f64 / self.reference_decodes.max(1) as f64
+    }
+}
@@ -0,0 +1,316 @@
+//! Settings for the demo and `arqr-cli`, read from `arqr.toml`. Every field
+//! can be left out, keeping its default, and command line flags override
+//! whatever the file says. All of it, with the defaults:
+//!
+//! ```toml
+//! [camera]
+//! # width = 1280      (no default: the camera picks)
+//! # height = 720
+//! fps = 30
+//!
+//! [scanner]
+//! full_sweep_interval = 10
+//! # budget_ms = 20.0  (no default: no budget)
+//! binarizer = "global"  # or "adaptive"
+//! try_inverted = false
+//! adaptive_radius = 15
+//! adaptive_offset = 7
+//! filter = "none"       # or "edges", "clahe"; demo only
+//! scan_interval = 2     # scan every Nth frame; demo only
+//!
+//! [overlay]
+//! line = [0.0, 0.0, 1.0, 1.0]
+//! axes = [[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]]
+//!
+//! [keys]
+//! snapshot = "S"
+//! filter = "F"
+//! binarizer = "B"
+//! pause = "Space"
+//! step = "Right"
+//! step_back = "Left"
+//! exposure_up = "Up"
+//! exposure_down = "Down"
+//! gain_up = "G"
+//! gain_down = "H"
+//! auto_exposure = "A"
+//! copy = "C"            # with the `clipboard` feature
+//! open_url = "Y"        # with the `urls` feature
+//! dismiss_url = "N"
+//!
+//! [urls]                # with the `urls` feature
+//! allow = []            # e.g. ["example.com"]; empty allows any host
+//! confirm = true
+//! ```
+//!
+//! The demo watches the file with `ConfigWatcher` and applies changes to the
+//! scanner and overlay sections as soon as they're saved.
+
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use serde::Deserialize;
+use crate::{Scanner, bitmap::Binarizer, url};
+
+/// Read from the working directory when no other file is named
+pub const CONFIG_FILE: &str = "arqr.toml";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub camera: CameraConfig,
+    pub scanner: ScannerConfig,
+    pub overlay: OverlayConfig,
+    pub keys: KeyConfig,
+    pub urls: UrlConfig,
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Loads `path` if there is one, or else `arqr.toml` if it exists, or
+    /// else the defaults
+    pub fn find(path: Option<&Path>) -> io::Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None if Path::new(CONFIG_FILE).exists() => Self::load(CONFIG_FILE),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// Notices when a config file is saved, by checking its modification time
+#[derive(Clone, Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+    /// How often to look at the file
+    pub interval: Duration,
+}
+
+impl ConfigWatcher {
+    /// Starts watching `path`, taking whatever's there now as already
+    /// loaded. The file doesn't have to exist yet.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_owned();
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        Self { path, modified, last_check: Instant::now(), interval: Duration::from_millis(500) }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads the file again if it's changed since last time, checking at
+    /// most once per `interval`. Deleting the file doesn't count as a
+    /// change.
+    pub fn poll(&mut self) -> Option<io::Result<Config>> {
+        if self.last_check.elapsed() < self.interval {
+            return None;
+        }
+        self.last_check = Instant::now();
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok()?;
+        if self.modified == Some(modified) {
+            return None;
+        }
+        self.modified = Some(modified);
+        Some(Config::load(&self.path))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    /// Resolution to ask for. Needs `height` too.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fps: u32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self { width: None, height: None, fps: 30 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BinarizerKind {
+    #[default]
+    Global,
+    Adaptive,
+}
+
+/// Filters the demo can run over frames before scanning them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterKind {
+    #[default]
+    None,
+    Edges,
+    Clahe,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScannerConfig {
+    /// See `Scanner::full_sweep_interval`
+    pub full_sweep_interval: u32,
+    /// See `Scanner::budget`
+    pub budget_ms: Option<f64>,
+    pub binarizer: BinarizerKind,
+    /// See `Scanner::try_inverted`
+    pub try_inverted: bool,
+    /// See `Binarizer::Adaptive`. Also used when the demo switches to
+    /// adaptive binarization.
+    pub adaptive_radius: u32,
+    pub adaptive_offset: u8,
+    pub filter: FilterKind,
+    pub scan_interval: u32,
+}
+
+impl Default for ScannerConfig {
+    fn default() -> Self {
+        Self {
+            full_sweep_interval: 10,
+            budget_ms: None,
+            binarizer: BinarizerKind::Global,
+            try_inverted: false,
+            adaptive_radius: 15,
+            adaptive_offset: 7,
+            filter: FilterKind::None,
+            scan_interval: 2,
+        }
+    }
+}
+
+impl ScannerConfig {
+    /// The adaptive binarizer with the configured settings
+    pub fn adaptive(&self) -> Binarizer {
+        Binarizer::Adaptive { radius: self.adaptive_radius, offset: self.adaptive_offset }
+    }
+
+    pub fn binarizer(&self) -> Binarizer {
+        match self.binarizer {
+            BinarizerKind::Global => Binarizer::Global,
+            BinarizerKind::Adaptive => self.adaptive(),
+        }
+    }
+
+    /// Applies the settings that belong to the scanner itself
+    pub fn configure(&self, scanner: &mut Scanner) {
+        scanner.full_sweep_interval = self.full_sweep_interval;
+        scanner.budget = self.budget_ms.map(|ms| Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+        scanner.binarizer = self.binarizer();
+        scanner.try_inverted = self.try_inverted;
+    }
+}
+
+/// Colors of the demo's overlay, as RGBA from 0 to 1
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OverlayConfig {
+    /// Targets, code outlines and text
+    pub line: [f32; 4],
+    /// The x, y and z axes drawn on codes
+    pub axes: [[f32; 4]; 3],
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            line: [0.0, 0.0, 1.0, 1.0],
+            axes: [[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]],
+        }
+    }
+}
+
+/// The demo's hotkeys, by name: a letter or digit, "Space", "Tab", "Return",
+/// an arrow ("Left", "Right", "Up", "Down") or "F1" to "F12"
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyConfig {
+    pub snapshot: String,
+    pub filter: String,
+    pub binarizer: String,
+    pub pause: String,
+    pub step: String,
+    pub step_back: String,
+    /// Camera exposure and gain, where the camera allows changing them
+    pub exposure_up: String,
+    pub exposure_down: String,
+    pub gain_up: String,
+    pub gain_down: String,
+    /// Turns on exposure control driven by `feedback`'s hints (see
+    /// `camera::AutoExposure`)
+    pub auto_exposure: String,
+    /// Copies the last payload read to the clipboard, when built with the
+    /// `clipboard` feature
+    pub copy: String,
+    /// Answer the demo's offer to open a link, when built with the `urls`
+    /// feature
+    pub open_url: String,
+    pub dismiss_url: String,
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        Self {
+            snapshot: "S".to_owned(),
+            filter: "F".to_owned(),
+            binarizer: "B".to_owned(),
+            pause: "Space".to_owned(),
+            step: "Right".to_owned(),
+            step_back: "Left".to_owned(),
+            exposure_up: "Up".to_owned(),
+            exposure_down: "Down".to_owned(),
+            gain_up: "G".to_owned(),
+            gain_down: "H".to_owned(),
+            auto_exposure: "A".to_owned(),
+            copy: "C".to_owned(),
+            open_url: "Y".to_owned(),
+            dismiss_url: "N".to_owned(),
+        }
+    }
+}
+
+/// What the demo does with web links it reads, when built with the `urls`
+/// feature. Once a confirmed code has read the same link a few times over,
+/// the demo offers to open it in the default browser.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UrlConfig {
+    /// Hosts links may go to, such as "example.com", which allows its
+    /// subdomains too. Links anywhere else are ignored. Empty to allow any
+    /// host.
+    pub allow: Vec<String>,
+    /// Whether to ask before opening a link. A kiosk can turn this off to
+    /// open links straight away, but only for an allow list: with any host
+    /// allowed, the demo always asks.
+    pub confirm: bool,
+}
+
+impl UrlConfig {
+    /// Whether `url` goes to an allowed host
+    pub fn allows(&self, url: &str) -> bool {
+        self.allow.is_empty() || url::host(url).is_some_and(|host| url::host_allowed(host, &self.allow))
+    }
+
+    /// Whether to ask before opening a link
+    pub fn should_confirm(&self) -> bool {
+        self.confirm || self.allow.is_empty()
+    }
+}
+
+impl Default for UrlConfig {
+    fn default() -> Self {
+        Self { allow: Vec::new(), confirm: true }
+    }
+}
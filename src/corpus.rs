@@ -0,0 +1,98 @@
+//! Collects frames the scanner struggled with, so everyday use turns into
+//! regression-test material. A frame counts as a failure when a code was
+//! found in it but couldn't be read, or when position targets were found but
+//! no code came out of them.
+//!
+//! Each failure is saved as a greyscale PNG (`fail-000012.png`) next to a
+//! JSON file with what the scanner made of it (`fail-000012.json`). That's
+//! the same object as a line of a recording's results log (see `record`),
+//! with three more fields up front:
+//!
+//! ```text
+//! {"source":"camera 0","decode_error":"too_many_errors","timings_ms":{"binarize":1.234,"targets":0.456,"corners":0.012,"extract":0.000,"decode":0.000,"fiducials":0.000,"total":1.702},
+//!  "frame":"fail-000012.png","sequence":340,...}
+//! ```
+//!
+//! `decode_error` says why a code that was found couldn't be read (see
+//! `DecodeError::name`), and is null when no code was found.
+//!
+//! Numbering carries on from whatever's already in the directory, so one
+//! corpus can build up over many sessions.
+
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use image::{GrayImage, ImageResult};
+use crate::{ScanResult, Stage, json, record::result_json};
+
+/// Saves failing frames into a directory
+#[derive(Debug)]
+pub struct Corpus {
+    dir: PathBuf,
+    next: u64,
+    last_saved: Option<Instant>,
+    /// Shortest time between saves. Anything failing in between is skipped,
+    /// so a code held in front of a camera doesn't fill the disk with
+    /// near-identical frames.
+    pub min_gap: Duration,
+}
+
+impl Corpus {
+    /// Opens (or creates) a corpus in `dir`
+    pub fn create<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+        let mut next = 0;
+        for entry in fs::read_dir(&dir)? {
+            let name = entry?.file_name();
+            let n = name.to_str()
+                .and_then(|name| name.strip_prefix("fail-")?.strip_suffix(".json")?.parse::<u64>().ok());
+            if let Some(n) = n {
+                next = next.max(n + 1);
+            }
+        }
+        Ok(Self { dir, next, last_saved: None, min_gap: Duration::ZERO })
+    }
+
+    /// Whether a result is worth keeping: a code was found but couldn't be
+    /// read, or targets were found but no code came out of them. Scans cut
+    /// short before the corners were picked don't count.
+    pub fn is_failure(result: &ScanResult) -> bool {
+        let unreadable = result.quad().is_some() && result.decode_error.is_some();
+        let lost = result.stage >= Stage::Corners && !result.targets.is_empty() && result.quad().is_none();
+        unreadable || lost
+    }
+
+    /// Saves `img` and its diagnostics if `result` is a failure, and it's
+    /// been `min_gap` since the last save. `source` says where the frame
+    /// came from. Returns the image's path if it was saved.
+    pub fn offer(&mut self, img: &GrayImage, result: &ScanResult, source: &str) -> ImageResult<Option<PathBuf>> {
+        if !Self::is_failure(result) {
+            return Ok(None);
+        }
+        let now = Instant::now();
+        if self.last_saved.is_some_and(|last| now.duration_since(last) < self.min_gap) {
+            return Ok(None);
+        }
+        self.last_saved = Some(now);
+
+        let name = format!("fail-{:06}", self.next);
+        self.next += 1;
+        let image_path = self.dir.join(format!("{}.png", name));
+        img.save(&image_path)?;
+
+        let decode_error = result.decode_error.map_or("null".to_owned(), |e| json::string(e.name()));
+        let mut json = format!(
+            "{{\"source\":{},\"decode_error\":{},\"timings_ms\":{},",
+            json::string(source), decode_error, json::timings(&result.timings),
+        );
+        // Splice the rest in after the opening brace
+        json.push_str(&result_json(result, &format!("{}.png", name), None)[1..]);
+        json.push('\n');
+        fs::write(self.dir.join(format!("{}.json", name)), json)?;
+        Ok(Some(image_path))
+    }
+}
@@ -0,0 +1,909 @@
+//! Turns an extracted code into something a decoder can read. The scanner's
+//! `code_img` is a picture of the code straightened out, with its top-left
+//! corner at the origin; this samples it into a grid of modules, one dark or
+//! light value per module. How many modules there are is counted along the
+//! timing patterns, or failing that guessed from how big the position
+//! targets are. The grid is pinned to the finders and alignment patterns
+//! wherever they turn up in the picture, since straightening from three
+//! corners leaves perspective in. From the grid it reads the format
+//! information, which says how the code was error-corrected and masked, and
+//! gives away codes that were seen mirrored.
+//!
+//! Bigger codes split their codewords into several blocks, each with its own
+//! check words, and interleave them: the first codeword of every block, then
+//! the second, and so on, with the check words after all the data.
+//! `ModuleGrid::codewords` reads them off the grid, `deinterleave` takes
+//! them apart again, and `correct_codewords` corrects each block and joins
+//! their data back up in order. `decode_data` turns that data into the
+//! message. `read_payload` does all of that for a grid the scanner sampled,
+//! and when it can't, its `DecodeError` says which step failed, so damaged
+//! prints can be told apart from codes the scanner got wrong.
+
+use std::fmt;
+use crate::{
+    Point,
+    bitmap::Bitmap,
+    encode::{EcLevel, Layout, Version, alignment_positions},
+    homography::Homography,
+    list::{List, MAX_BLOCK_LEN, MAX_BLOCKS, MAX_CODEWORDS, MAX_MODULES, MAX_RUNS, MAX_VERSION},
+    mask,
+    reed_solomon::Field,
+    target::Target,
+};
+
+/// A code's modules, read off the image
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModuleGrid {
+    /// Width and height in modules
+    pub size: u32,
+    /// Row-major, true for dark modules
+    pub modules: List<bool, MAX_MODULES>,
+}
+
+/// What a code's format information says about it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FormatInfo {
+    pub ec_level: EcLevel,
+    /// Mask pattern, from 0 to 7
+    pub mask: u8,
+    /// How many of the 15 bits had to be corrected, in whichever copy needed
+    /// fewer. Up to 3 can be.
+    pub errors: u32,
+}
+
+/// Where reading a code's message failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The code was found, but its modules couldn't be sampled, because how
+    /// big they are couldn't be worked out
+    Unsampled,
+    /// The grid was sampled at a size QR codes don't come in
+    NotAVersion { size: u32 },
+    /// Neither copy of the format information was within 3 bits of a valid
+    /// word
+    FormatUnreadable,
+    /// The version information, which codes from version 7 up carry, says a
+    /// different version from the one the grid was sampled at
+    VersionMismatch { sampled: u32, encoded: u32 },
+    /// There were the wrong number of codewords for the version and error
+    /// correction level
+    CodewordCount { expected: usize, found: usize },
+    /// Block `block`, counting from 0 in the order the data was split, had
+    /// more wrong codewords than its check words can correct
+    TooManyErrors { block: usize },
+    /// A segment started with a mode indicator that isn't numeric,
+    /// alphanumeric, byte or Kanji
+    UnsupportedMode { mode: u8 },
+    /// A segment's character count ran past the end of the data
+    SegmentOverrun { mode: u8 },
+    /// A numeric or alphanumeric segment held a value that isn't a character
+    BadCharacter { mode: u8 },
+}
+
+impl DecodeError {
+    /// A short, stable name for the kind of failure, for reports
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Unsampled => "unsampled",
+            Self::NotAVersion { .. } => "not_a_version",
+            Self::FormatUnreadable => "format_unreadable",
+            Self::VersionMismatch { .. } => "version_mismatch",
+            Self::CodewordCount { .. } => "codeword_count",
+            Self::TooManyErrors { .. } => "too_many_errors",
+            Self::UnsupportedMode { .. } => "unsupported_mode",
+            Self::SegmentOverrun { .. } => "segment_overrun",
+            Self::BadCharacter { .. } => "bad_character",
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Unsampled => write!(f, "couldn't work out the module size to sample the code"),
+            Self::NotAVersion { size } => write!(f, "{} modules across isn't a QR code size", size),
+            Self::FormatUnreadable => write!(f, "the format information is unreadable"),
+            Self::VersionMismatch { sampled, encoded } => {
+                write!(f, "sampled as version {}, but the version information says {}", sampled, encoded)
+            }
+            Self::CodewordCount { expected, found } => write!(f, "expected {} codewords, found {}", expected, found),
+            Self::TooManyErrors { block } => write!(f, "too many errors to correct in block {}", block),
+            Self::UnsupportedMode { mode } => write!(f, "unsupported segment mode {:04b}", mode),
+            Self::SegmentOverrun { mode } => write!(f, "a segment in mode {:04b} runs past the end of the data", mode),
+            Self::BadCharacter { mode } => write!(f, "a segment in mode {:04b} holds a value that isn't a character", mode),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// The 15 bits stored for `data` (the level's two bits then the mask's
+/// three), with their BCH check bits
+fn format_word(data: u32) -> u32 {
+    let mut rem = data;
+    for _ in 0..10 {
+        rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+    }
+    (data << 10 | rem) ^ 0x5412
+}
+
+/// The 18 bits stored for `version`, with their BCH check bits
+fn version_word(version: u32) -> u32 {
+    let mut rem = version;
+    for _ in 0..12 {
+        rem = (rem << 1) ^ ((rem >> 11) * 0x1f25);
+    }
+    version << 12 | rem
+}
+
+impl ModuleGrid {
+    /// Whether the module at (`x`, `y`) is dark. Anything outside the grid
+    /// is light.
+    pub fn is_dark(&self, x: u32, y: u32) -> bool {
+        x < self.size && y < self.size && self.modules[(y * self.size + x) as usize]
+    }
+
+    /// The QR version a grid this size would be, if it's a size QR codes
+    /// come in, up to `MAX_VERSION`
+    pub fn version(&self) -> Option<u32> {
+        let version = self.size.checked_sub(17)? / 4;
+        ((1..=MAX_VERSION.min(40) as u32).contains(&version) && (self.size - 17).is_multiple_of(4)).then_some(version)
+    }
+
+    /// The grid flipped over its top-left to bottom-right diagonal
+    pub fn transposed(&self) -> Self {
+        let size = self.size;
+        let modules = (0..size * size).map(|i| self.is_dark(i / size, i % size)).collect();
+        Self { size, modules }
+    }
+
+    /// Reads the format information both ways round, and flips the grid if
+    /// it reads better flipped. A code seen in a mirror, or through the back
+    /// of glass, still has its targets in the same three corners, so it's
+    /// found and sampled as if it weren't, and comes out flipped over its
+    /// diagonal. The format information is the giveaway: read the wrong way
+    /// round, it's at best a few bits off a valid word. Returns the format
+    /// information and whether the grid was flipped.
+    pub fn orient(&mut self) -> Option<(FormatInfo, bool)> {
+        let transposed = self.transposed();
+        match (self.format_info(), transposed.format_info()) {
+            (Some(format), Some(flipped)) if flipped.errors < format.errors => {
+                *self = transposed;
+                Some((flipped, true))
+            }
+            (None, Some(flipped)) => {
+                *self = transposed;
+                Some((flipped, true))
+            }
+            (format, _) => format.map(|format| (format, false)),
+        }
+    }
+
+    /// Reads the codewords out of the data modules, in the order they were
+    /// put down: up and down two-module-wide columns from the bottom-right
+    /// corner, skipping the vertical timing pattern and every other function
+    /// module. The mask has to have been taken off first (see
+    /// `mask::remove`). Any bits left over at the end, which don't make a
+    /// whole codeword, are dropped. Returns None if the grid isn't a size QR
+    /// codes come in.
+    pub fn codewords(&self) -> Option<List<u8, MAX_CODEWORDS>> {
+        let function = mask::function_modules(self.version()?);
+        let size = self.size as i32;
+        let mut out = List::new();
+        let (mut byte, mut bits) = (0u8, 0);
+        let mut right = size - 1;
+        let mut upward = true;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = (right - j) as u32;
+                    let y = if upward { size - 1 - vert } else { vert } as u32;
+                    if function[(y * self.size + x) as usize] {
+                        continue;
+                    }
+                    byte = byte << 1 | self.is_dark(x, y) as u8;
+                    bits += 1;
+                    if bits == 8 {
+                        out.push(byte);
+                        (byte, bits) = (0, 0);
+                    }
+                }
+            }
+            right -= 2;
+            upward = !upward;
+        }
+        Some(out)
+    }
+
+    /// Reads the version information, which codes from version 7 up carry
+    /// in two copies, next to the top-right and bottom-left finders. Each is
+    /// matched against every version's word, and the closest match wins, as
+    /// long as it's no more than 3 bits out. Returns None for smaller codes,
+    /// or if neither copy is that close.
+    pub fn version_info(&self) -> Option<u32> {
+        let size = self.size;
+        if size < 45 {
+            return None;
+        }
+        let (mut top_right, mut bottom_left) = (0, 0);
+        for i in 0..18 {
+            let (a, b) = (size - 11 + i % 3, i / 3);
+            top_right |= (self.is_dark(a, b) as u32) << i;
+            bottom_left |= (self.is_dark(b, a) as u32) << i;
+        }
+        let (version, errors) = (7..=40)
+            .map(|version| {
+                let word = version_word(version);
+                (version, (word ^ top_right).count_ones().min((word ^ bottom_left).count_ones()))
+            })
+            .min_by_key(|&(_, errors)| errors)?;
+        (errors <= 3).then_some(version)
+    }
+
+    /// Reads the format information. There are two copies: one wrapped
+    /// around the top-left finder, and one split between the other two.
+    /// Each is matched against all 32 valid words, and the closest match
+    /// wins, as long as it's no more than 3 bits out. Returns None if
+    /// neither copy is that close.
+    pub fn format_info(&self) -> Option<FormatInfo> {
+        let size = self.size;
+        if size < 21 {
+            return None;
+        }
+        let mut top_left = 0;
+        let mut split = 0;
+        for i in 0..15 {
+            let (x, y) = match i {
+                0..=5 => (8, i),
+                6 => (8, 7),
+                7 => (8, 8),
+                8 => (7, 8),
+                _ => (14 - i, 8),
+            };
+            top_left |= (self.is_dark(x, y) as u32) << i;
+            let (x, y) = if i < 8 { (size - 1 - i, 8) } else { (8, size - 15 + i) };
+            split |= (self.is_dark(x, y) as u32) << i;
+        }
+
+        let (data, errors) = (0..32)
+            .map(|data| {
+                let word = format_word(data);
+                let errors = (word ^ top_left).count_ones().min((word ^ split).count_ones());
+                (data, errors)
+            })
+            .min_by_key(|&(_, errors)| errors)?;
+        (errors <= 3).then_some(FormatInfo {
+            ec_level: EcLevel::from_format_bits(data >> 3),
+            mask: (data & 7) as u8,
+            errors,
+        })
+    }
+}
+
+/// Estimates the size of a module in pixels of the original image, from the
+/// position targets, which are 7 modules across. The targets' extents are
+/// measured along the image's rows and columns, so they're corrected for how
+/// far the code (whose corners are `bbox`) is turned.
+pub fn module_size(targets: &[Target<f64>], bbox: [Point<f64>; 3]) -> Option<f64> {
+    if targets.is_empty() {
+        return None;
+    }
+    let angle = bbox[0].angle_to(bbox[1]);
+    let squash = angle.cos().abs().max(angle.sin().abs());
+    let across: f64 = targets.iter()
+        .map(|t| (t.max.x - t.min.x) + (t.max.y - t.min.y))
+        .sum();
+    let module = across / (2 * targets.len()) as f64 * squash / 7.0;
+    (module.is_finite() && module > 0.0).then_some(module)
+}
+
+/// The size QR codes come in nearest `side_len` over `module`: the number of
+/// modules across a straightened code `side_len` pixels across, going by
+/// geometry alone
+pub fn geometric_size(side_len: f64, module: f64) -> Option<u32> {
+    let estimate = side_len / module;
+    if !estimate.is_finite() {
+        return None;
+    }
+    let version = ((estimate - 17.0) / 4.0).round().clamp(1.0, 40.0) as u32;
+    Some(17 + 4 * version)
+}
+
+/// Counts the modules across a code by walking its timing patterns, the
+/// lines of alternating modules that join the finders along row and column
+/// 6. Between the finders' separators each one starts and ends dark, so n
+/// dark runs make a code 2n + 15 modules across. The walk is over `bmp`
+/// itself, from finder centre to finder centre and three modules in, so it
+/// doesn't depend on the code's corners (`bbox`, which only picks out which
+/// target is which) being spot on. `module` is in `bmp`'s pixels, and runs
+/// shorter than a third of it are put down to noise. If the two patterns
+/// disagree, or only one gives a size QR codes come in, the one closest to
+/// what the distance between the finders suggests wins. Returns None if
+/// neither does.
+pub fn timing_size(bmp: &Bitmap, targets: &[Target<f64>], bbox: [Point<f64>; 3], module: f64) -> Option<u32> {
+    let centre = |corner: Point<f64>| {
+        targets.iter()
+            .map(|t| t.mid)
+            .min_by(|a, b| a.dist_to(corner).total_cmp(&b.dist_to(corner)))
+    };
+    let (top_left, top_right, bottom_left) = (centre(bbox[0])?, centre(bbox[1])?, centre(bbox[2])?);
+    let dark_at = |p: Point<f64>| {
+        p.x >= 0.0 && p.y >= 0.0 && !*bmp.get_pixel_checked(p.x as u32, p.y as u32).unwrap_or(&true)
+    };
+    let min_run = (module / 3.0).round().max(1.0) as usize;
+
+    // Along from `from` to `to`, shifted three modules towards `side`
+    let walk = |from: Point<f64>, to: Point<f64>, side: Point<f64>| {
+        let len = from.dist_to(to);
+        let side_len = from.dist_to(side);
+        let (ux, uy) = ((to.x - from.x) / len, (to.y - from.y) / len);
+        let (vx, vy) = ((side.x - from.x) / side_len, (side.y - from.y) / side_len);
+        let start = Point::new(from.x + 4.0 * module * ux + 3.0 * module * vx, from.y + 4.0 * module * uy + 3.0 * module * vy);
+        let steps = (len - 8.0 * module).max(0.0) as usize;
+        // A vote across the line, so a jagged edge doesn't make a run
+        let across = module / 3.0;
+        let pixels = (0..steps).map(|i| {
+            let (x, y) = (start.x + i as f64 * ux, start.y + i as f64 * uy);
+            [-across, 0.0, across].iter()
+                .filter(|&&d| dark_at(Point::new(x + d * vx, y + d * vy)))
+                .count() >= 2
+        });
+        (2 * dark_runs(pixels, min_run) + 15, len / module + 7.0)
+    };
+    [walk(top_left, top_right, bottom_left), walk(top_left, bottom_left, top_right)]
+        .into_iter()
+        .filter(|&(size, _)| (21..=177).contains(&size) && (size - 17).is_multiple_of(4))
+        .min_by(|&(a, a_est), &(b, b_est)| (a as f64 - a_est).abs().total_cmp(&(b as f64 - b_est).abs()))
+        .map(|(size, _)| size)
+}
+
+/// How many runs of dark pixels there are in `pixels`, after folding runs
+/// shorter than `min_run` into the run before them. Returns 0 if there are
+/// more than `MAX_RUNS` runs to begin with.
+fn dark_runs(pixels: impl Iterator<Item = bool>, min_run: usize) -> u32 {
+    let mut runs: List<(bool, usize), MAX_RUNS> = List::new();
+    for dark in pixels {
+        match runs.last_mut() {
+            Some((color, len)) if *color == dark => *len += 1,
+            _ => {
+                if !runs.push((dark, 1)) {
+                    return 0;
+                }
+            }
+        }
+    }
+    let mut merged: List<(bool, usize), MAX_RUNS> = List::new();
+    for &(dark, len) in &runs {
+        match merged.last_mut() {
+            Some((_, last_len)) if len < min_run => *last_len += len,
+            Some((color, last_len)) if *color == dark => *last_len += len,
+            _ => {
+                merged.push((dark, len));
+            }
+        }
+    }
+    merged.iter().filter(|&&(dark, len)| dark && len >= min_run).count() as u32
+}
+
+/// Searches `code` for a square pattern `width` modules across, whose
+/// modules are dark where `dark` says, within `reach` modules of `expected`
+/// (in pixels). Returns where it matches best, and how many of its modules
+/// match there. Every offset that matches best counts, and the pattern is
+/// put in the middle of them.
+fn find_pattern(
+    code: &Bitmap,
+    pitch: f64,
+    expected: Point<f64>,
+    reach: f64,
+    width: i32,
+    dark: impl Fn(i32, i32) -> bool,
+) -> (Point<f64>, u32) {
+    let dark_at = |x: f64, y: f64| {
+        x >= 0.0 && y >= 0.0 && !*code.get_pixel_checked(x as u32, y as u32).unwrap_or(&true)
+    };
+    let half = width / 2;
+    let score = |x: f64, y: f64| {
+        let mut matches = 0;
+        for dy in -half..=half {
+            for dx in -half..=half {
+                matches += (dark_at(x + dx as f64 * pitch, y + dy as f64 * pitch) == dark(dx, dy)) as u32;
+            }
+        }
+        matches
+    };
+
+    let reach = (reach * pitch) as i32;
+    let step = ((pitch / 3.0) as usize).max(1);
+    let (mut best, mut sum, mut count) = (0, Point::new(0.0, 0.0), 0.0);
+    for oy in (-reach..=reach).step_by(step) {
+        for ox in (-reach..=reach).step_by(step) {
+            let (x, y) = (expected.x + ox as f64, expected.y + oy as f64);
+            let matches = score(x, y);
+            if matches > best {
+                (best, sum, count) = (matches, Point::new(0.0, 0.0), 0.0);
+            }
+            if matches == best {
+                sum = Point::new(sum.x + x, sum.y + y);
+                count += 1.0;
+            }
+        }
+    }
+    (Point::new(sum.x / count, sum.y / count), best)
+}
+
+/// Looks for the three finders of a code `size` modules across in its
+/// straightened image, `side_len` pixels across, each within a couple of
+/// modules of where it would be if the image were perfectly straightened.
+/// Returns where each finder's centre should be, in modules, and where it
+/// was found, in pixels, or None if any of them can't be found.
+pub fn find_finders(code: &Bitmap, side_len: f64, size: u32) -> Option<[(Point<f64>, Point<f64>); 3]> {
+    let pitch = side_len / size as f64;
+    if !pitch.is_finite() || pitch < 1.0 {
+        return None;
+    }
+    let far = size as f64 - 3.5;
+    let mut found = [(Point::new(3.5, 3.5), Point::new(0.0, 0.0)); 3];
+    for (i, module) in [Point::new(3.5, 3.5), Point::new(far, 3.5), Point::new(3.5, far)].into_iter().enumerate() {
+        let expected = Point::new(module.x * pitch, module.y * pitch);
+        let (centre, matches) = find_pattern(code, pitch, expected, 4.0, 7, |dx, dy| dx.abs().max(dy.abs()) != 2);
+        if matches < 45 {
+            return None;
+        }
+        found[i] = (module, centre);
+    }
+    Some(found)
+}
+
+/// Fits a grid to `pairs` of where points should be, in modules, and where
+/// they were found, in pixels. With just the three finders, the fourth
+/// corner of the parallelogram they make stands in for a fourth point.
+fn fit_grid(pairs: &[(Point<f64>, Point<f64>)]) -> Option<Homography> {
+    let mut pairs = pairs.to_vec();
+    if let [(m0, p0), (m1, p1), (m2, p2)] = pairs[..] {
+        pairs.push((
+            Point::new(m1.x + m2.x - m0.x, m1.y + m2.y - m0.y),
+            Point::new(p1.x + p2.x - p0.x, p1.y + p2.y - p0.y),
+        ));
+    }
+    let (from, to): (Vec<_>, Vec<_>) = pairs.into_iter().unzip();
+    Homography::from_points(&from, &to)
+}
+
+/// Looks for the alignment patterns of a code `size` modules across in its
+/// straightened image, `side_len` pixels across, given where its `finders`
+/// are (see `find_finders`). They're searched for nearest the top-left
+/// first, each within a couple of modules of where the grid fitted to
+/// everything found so far puts it, so the search follows the code as it
+/// bends away. Each is only kept if at least 23 of its 25 modules match.
+/// Returns where each one found should be, in modules, and where it was
+/// found, in pixels.
+pub fn find_alignment_patterns(
+    code: &Bitmap,
+    side_len: f64,
+    size: u32,
+    finders: [(Point<f64>, Point<f64>); 3],
+) -> Vec<(Point<f64>, Point<f64>)> {
+    let pitch = side_len / size as f64;
+    let version = size.saturating_sub(17) / 4;
+    if !(2..=40).contains(&version) || !pitch.is_finite() || pitch < 1.0 {
+        return Vec::new();
+    }
+    let centres = alignment_positions(version);
+    let last = centres.len() - 1;
+    let mut modules = Vec::new();
+    for (i, &row) in centres.iter().enumerate() {
+        for (j, &col) in centres.iter().enumerate() {
+            // The ones that would land on the finders aren't there
+            if !((i == 0 && (j == 0 || j == last)) || (i == last && j == 0)) {
+                modules.push(Point::new(col as f64 + 0.5, row as f64 + 0.5));
+            }
+        }
+    }
+    modules.sort_by(|a, b| (a.x + a.y).total_cmp(&(b.x + b.y)));
+
+    let mut pairs = finders.to_vec();
+    let Some(mut grid) = fit_grid(&pairs) else { return Vec::new() };
+    for module in modules {
+        let expected = grid.apply(module);
+        let (centre, matches) = find_pattern(code, pitch, expected, 2.0, 5, |dx, dy| dx.abs().max(dy.abs()) != 1);
+        if matches >= 23 {
+            pairs.push((module, centre));
+            grid = fit_grid(&pairs).unwrap_or(grid);
+        }
+    }
+    pairs.split_off(3)
+}
+
+/// Samples a straightened code `side_len` pixels across into a grid of
+/// `size` modules each way. Left to itself the grid is spread evenly over
+/// the whole side, so small errors in the module size don't add up across
+/// the code. If the finders can be found, the grid is fitted to them
+/// instead, and bent to fit whichever alignment patterns can be found too,
+/// to take out the perspective and warping that straightening from three
+/// corners leaves in. Each module is a vote between its centre and four
+/// points around it. Returns None for codes bigger than `MAX_VERSION`.
+pub fn sample(code: &Bitmap, side_len: f64, size: u32) -> Option<ModuleGrid> {
+    let pitch = side_len / size as f64;
+    if !pitch.is_finite() || pitch < 1.0 || (size * size) as usize > MAX_MODULES {
+        return None;
+    }
+
+    let even = Homography([[pitch, 0.0, 0.0], [0.0, pitch, 0.0], [0.0, 0.0, 1.0]]);
+    let grid = find_finders(code, side_len, size)
+        .and_then(|finders| {
+            let mut pairs = finders.to_vec();
+            pairs.extend(find_alignment_patterns(code, side_len, size, finders));
+            fit_grid(&pairs)
+        })
+        .unwrap_or(even);
+
+    let dark_at = |x: f64, y: f64| {
+        x >= 0.0 && y >= 0.0 && !*code.get_pixel_checked(x as u32, y as u32).unwrap_or(&true)
+    };
+    let offset = pitch / 4.0;
+    let mut modules = List::new();
+    for row in 0..size {
+        for col in 0..size {
+            let Point { x, y } = grid.apply(Point::new(col as f64 + 0.5, row as f64 + 0.5));
+            let votes = [(0.0, 0.0), (-offset, -offset), (offset, -offset), (-offset, offset), (offset, offset)]
+                .iter()
+                .filter(|(dx, dy)| dark_at(x + dx, y + dy))
+                .count();
+            modules.push(votes >= 3);
+        }
+    }
+    Some(ModuleGrid { size, modules })
+}
+
+/// Splits the codewords read from a version `version` code at `ec_level`
+/// back into its blocks, each its data followed by its check words. The
+/// later blocks have one more data codeword than the earlier ones. Returns
+/// None if there's no such version, `codewords` is the wrong length for it,
+/// or it has more than `MAX_BLOCKS` blocks.
+pub fn deinterleave(
+    codewords: &[u8],
+    version: u32,
+    ec_level: EcLevel,
+) -> Option<List<List<u8, MAX_BLOCK_LEN>, MAX_BLOCKS>> {
+    let layout = Layout::new(Version::Normal(version), ec_level)?;
+    let (blocks, ecc_len, raw) = (layout.blocks, layout.ecc_len, layout.codewords);
+    if codewords.len() != raw || blocks > MAX_BLOCKS {
+        return None;
+    }
+    let short_blocks = blocks - raw % blocks;
+    let short_len = raw / blocks - ecc_len;
+
+    let mut out: List<List<u8, MAX_BLOCK_LEN>, MAX_BLOCKS> = (0..blocks).map(|_| List::new()).collect();
+    let mut words = codewords.iter();
+    for i in 0..=short_len {
+        for (j, block) in out.iter_mut().enumerate() {
+            if i < short_len || j >= short_blocks {
+                block.push(*words.next()?);
+            }
+        }
+    }
+    for _ in 0..ecc_len {
+        for block in &mut out {
+            block.push(*words.next()?);
+        }
+    }
+    Some(out)
+}
+
+/// Corrects every block of the codewords read from a version `version` code
+/// at `ec_level`, and joins up their data. Returns the data and how many
+/// codewords were wrong.
+pub fn correct_codewords(
+    codewords: &[u8],
+    version: u32,
+    ec_level: EcLevel,
+) -> Result<(List<u8, MAX_CODEWORDS>, usize), DecodeError> {
+    let layout = Layout::new(Version::Normal(version), ec_level)
+        .ok_or(DecodeError::NotAVersion { size: version * 4 + 17 })?;
+    let mut blocks = deinterleave(codewords, version, ec_level)
+        .ok_or(DecodeError::CodewordCount { expected: layout.codewords, found: codewords.len() })?;
+    let field = Field::qr();
+    let mut data = List::new();
+    let mut errors = 0;
+    for (i, block) in blocks.iter_mut().enumerate() {
+        errors += field.correct_bytes(block, layout.ecc_len, 0).ok_or(DecodeError::TooManyErrors { block: i })?;
+        data.extend(block[..block.len() - layout.ecc_len].iter().copied());
+    }
+    Ok((data, errors))
+}
+
+/// How many codewords a version `version` code at `ec_level` can have wrong
+/// and still be corrected, if they're spread evenly over its blocks
+pub fn correctable_codewords(version: u32, ec_level: EcLevel) -> Option<usize> {
+    let layout = Layout::new(Version::Normal(version), ec_level)?;
+    Some(layout.blocks * (layout.ecc_len / 2))
+}
+
+const ALPHANUMERIC: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Decodes the corrected data codewords of a version `version` code into the
+/// message they hold, segment by segment. Numeric, alphanumeric, byte and
+/// Kanji segments are understood. Kanji comes out as the Shift JIS it was
+/// packed from, two bytes a character, and bytes as they are, so the message
+/// is only UTF-8 if that's what was encoded. Fails on any other kind of
+/// segment, or if a segment runs off the end of the data.
+pub fn decode_data(data: &[u8], version: u32) -> Result<Vec<u8>, DecodeError> {
+    let len = data.len() * 8;
+    let mut pos = 0;
+    let mut take = |n: usize| -> Option<u32> {
+        if pos + n > len {
+            return None;
+        }
+        let value = (pos..pos + n).fold(0, |acc, i| acc << 1 | (data[i / 8] >> (7 - i % 8) & 1) as u32);
+        pos += n;
+        Some(value)
+    };
+    // Which column of character count widths to use
+    let size_class = match version {
+        1..=9 => 0,
+        10..=26 => 1,
+        _ => 2,
+    };
+
+    let mut out = Vec::new();
+    // The terminator can be cut short, or left out entirely, when the data
+    // runs right up to the end
+    while let Some(mode) = take(4) {
+        let mode = mode as u8;
+        let mut read = |n: usize| take(n).ok_or(DecodeError::SegmentOverrun { mode });
+        let bad = DecodeError::BadCharacter { mode };
+        match mode {
+            0b0000 => break,
+            0b0001 => {
+                let mut count = read([10, 12, 14][size_class])?;
+                while count > 0 {
+                    let digits = count.min(3);
+                    let value = read([0, 4, 7, 10][digits as usize])?;
+                    if value >= 10u32.pow(digits) {
+                        return Err(bad);
+                    }
+                    out.extend(format!("{:0width$}", value, width = digits as usize).bytes());
+                    count -= digits;
+                }
+            }
+            0b0010 => {
+                let mut count = read([9, 11, 13][size_class])?;
+                while count > 0 {
+                    if count >= 2 {
+                        let value = read(11)?;
+                        let (hi, lo) = ((value / 45) as usize, (value % 45) as usize);
+                        out.push(*ALPHANUMERIC.get(hi).ok_or(bad)?);
+                        out.push(ALPHANUMERIC[lo]);
+                        count -= 2;
+                    } else {
+                        out.push(*ALPHANUMERIC.get(read(6)? as usize).ok_or(bad)?);
+                        count -= 1;
+                    }
+                }
+            }
+            0b0100 => {
+                let count = read([8, 16, 16][size_class])?;
+                for _ in 0..count {
+                    out.push(read(8)? as u8);
+                }
+            }
+            0b1000 => {
+                let count = read([8, 10, 12][size_class])?;
+                for _ in 0..count {
+                    // Each character is its Shift JIS code, less 0x8140 or
+                    // 0xc140, with the high byte scaled by 0xc0
+                    let value = read(13)?;
+                    let packed = ((value / 0xc0) << 8) | (value % 0xc0);
+                    let code = if packed + 0x8140 <= 0x9ffc { packed + 0x8140 } else { packed + 0xc140 };
+                    out.extend_from_slice(&[(code >> 8) as u8, code as u8]);
+                }
+            }
+            _ => return Err(DecodeError::UnsupportedMode { mode }),
+        }
+    }
+    Ok(out)
+}
+
+/// Reads the message out of a sampled grid whose format information says
+/// `format`: checks the version information, takes the mask off a copy of
+/// the grid, reads the codewords, corrects them and decodes the data.
+pub fn read_payload(grid: &ModuleGrid, format: FormatInfo) -> Result<Vec<u8>, DecodeError> {
+    read_corrected(grid, format).map(|(payload, _)| payload)
+}
+
+/// Like `read_payload`, but also says how many codewords had to be corrected
+pub fn read_corrected(grid: &ModuleGrid, format: FormatInfo) -> Result<(Vec<u8>, usize), DecodeError> {
+    let not_a_version = DecodeError::NotAVersion { size: grid.size };
+    let version = grid.version().ok_or(not_a_version)?;
+    // The version information is only a check: if it can't be read, the
+    // size the grid was sampled at goes
+    if let Some(encoded) = grid.version_info().filter(|&encoded| encoded != version) {
+        return Err(DecodeError::VersionMismatch { sampled: version, encoded });
+    }
+    let mut unmasked = grid.clone();
+    mask::remove(&mut unmasked, format.mask);
+    let codewords = unmasked.codewords().ok_or(not_a_version)?;
+    let (data, errors) = correct_codewords(&codewords, version, format.ec_level)?;
+    Ok((decode_data(&data, version)?, errors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encode::QrCode,
+        testgen::{Distortion, generate},
+    };
+
+    /// Encodes `data`, draws it distorted by `distortion`, scans it and
+    /// returns what the scanner read
+    fn round_trip(data: &[u8], version: u32, ec_level: EcLevel, distortion: Distortion) -> Option<Vec<u8>> {
+        let code = QrCode::with_version(data, Version::Normal(version), ec_level).unwrap();
+        let sample = generate(&code, &distortion);
+        let result = crate::scan(&sample.image);
+        assert_eq!(result.version, Some(version), "wrong size for {:?}", data);
+        assert_eq!(result.format.map(|f| f.ec_level), Some(ec_level));
+        result.payload
+    }
+
+    fn turned(rotation: f64) -> Distortion {
+        Distortion { rotation, ..Distortion::default() }
+    }
+
+    #[test]
+    fn reads_every_ec_level() {
+        for ec_level in [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H] {
+            let data = b"https://example.com/arqr";
+            assert_eq!(round_trip(data, 3, ec_level, turned(0.2)).as_deref(), Some(&data[..]));
+        }
+    }
+
+    #[test]
+    fn reads_upright_codes() {
+        for quarters in 0..4 {
+            let data = b"upright";
+            let rotation = quarters as f64 * std::f64::consts::FRAC_PI_2;
+            assert_eq!(round_trip(data, 2, EcLevel::M, turned(rotation)).as_deref(), Some(&data[..]));
+        }
+    }
+
+    #[test]
+    fn reads_every_mode() {
+        let messages: [&[u8]; 4] = [
+            b"0123456789012345",
+            b"HELLO WORLD $%*+-./:",
+            "bytes, caf\u{e9}".as_bytes(),
+            // "kanji" in Shift JIS
+            &[0x8a, 0xbf, 0x8e, 0x9a],
+        ];
+        for data in messages {
+            assert_eq!(round_trip(data, 2, EcLevel::M, turned(0.2)).as_deref(), Some(data));
+        }
+    }
+
+    #[test]
+    fn reads_bigger_codes() {
+        for (version, rotation) in [(5, 0.1), (7, 0.4), (10, -0.2)] {
+            let data: Vec<u8> = (0..40).map(|i| b'a' + i % 26).collect();
+            let distortion = Distortion { scale: 0.8, ..turned(rotation) };
+            assert_eq!(round_trip(&data, version, EcLevel::L, distortion), Some(data));
+        }
+    }
+
+    #[test]
+    fn reads_through_noise_and_tilt() {
+        let data = b"tilted";
+        let distortion = Distortion { pitch: 0.2, yaw: -0.2, noise: 20.0, blur: 0.8, ..turned(0.5) };
+        assert_eq!(round_trip(data, 2, EcLevel::H, distortion).as_deref(), Some(&data[..]));
+    }
+
+    #[test]
+    fn decodes_mirrored_codes() {
+        let code = QrCode::with_version(b"mirror", Version::Normal(2), EcLevel::Q).unwrap();
+        let mut sample = generate(&code, &turned(0.3));
+        image::imageops::flip_horizontal_in_place(&mut sample.image);
+        let result = crate::scan(&sample.image);
+        assert!(result.mirrored);
+        assert_eq!(result.payload.as_deref(), Some(&b"mirror"[..]));
+    }
+
+    #[test]
+    fn corrects_damaged_blocks() {
+        let data = b"some data to damage";
+        let code = QrCode::with_version(data, Version::Normal(4), EcLevel::H).unwrap();
+        let size = code.size();
+        let modules = (0..size * size).map(|i| code.is_dark(i % size, i / size)).collect();
+        let mut grid = ModuleGrid { size, modules };
+        let format = grid.format_info().unwrap();
+        // Scribble over a patch in the data area
+        for y in 12..18 {
+            for x in 20..26 {
+                grid.modules[(y * size + x) as usize] ^= true;
+            }
+        }
+        let (payload, errors) = read_corrected(&grid, format).unwrap();
+        assert_eq!(payload, data);
+        assert!(errors > 0 && errors <= correctable_codewords(4, EcLevel::H).unwrap());
+    }
+
+    /// The grid `QrCode` drew, exactly
+    fn grid_of(code: &QrCode) -> ModuleGrid {
+        let size = code.size();
+        ModuleGrid { size, modules: (0..size * size).map(|i| code.is_dark(i % size, i / size)).collect() }
+    }
+
+    #[test]
+    fn reads_the_biggest_codes() {
+        // Version 40 has the most blocks at H, and the longest at L
+        for ec_level in [EcLevel::L, EcLevel::H] {
+            let code = QrCode::with_version(b"forty", Version::Normal(40), ec_level).unwrap();
+            let grid = grid_of(&code);
+            let format = grid.format_info().unwrap();
+            assert_eq!(read_payload(&grid, format).unwrap(), b"forty");
+        }
+    }
+
+    #[test]
+    fn reads_version_information() {
+        for version in [7, 8, 21, 40] {
+            let code = QrCode::with_version(b"v", Version::Normal(version), EcLevel::L).unwrap();
+            assert_eq!(grid_of(&code).version_info(), Some(version));
+        }
+        let code = QrCode::with_version(b"v", Version::Normal(6), EcLevel::L).unwrap();
+        assert_eq!(grid_of(&code).version_info(), None);
+    }
+
+    #[test]
+    fn says_which_block_failed() {
+        // Version 4 at H has four blocks the same length; wreck every
+        // codeword of the third
+        let code = QrCode::with_version(b"damaged", Version::Normal(4), EcLevel::H).unwrap();
+        let mut grid = grid_of(&code);
+        let format = grid.format_info().unwrap();
+        mask::remove(&mut grid, format.mask);
+        let mut codewords = grid.codewords().unwrap();
+        for (i, word) in codewords.iter_mut().enumerate() {
+            // Blocks are interleaved, a codeword at a time
+            if i % 4 == 2 {
+                *word ^= 0xff;
+            }
+        }
+        assert_eq!(correct_codewords(&codewords, 4, EcLevel::H), Err(DecodeError::TooManyErrors { block: 2 }));
+        assert_eq!(
+            correct_codewords(&codewords[1..], 4, EcLevel::H),
+            Err(DecodeError::CodewordCount { expected: codewords.len(), found: codewords.len() - 1 }),
+        );
+    }
+
+    #[test]
+    fn says_what_was_wrong_with_the_data() {
+        // ECI, which isn't understood
+        assert_eq!(decode_data(&[0b0111_0000], 1), Err(DecodeError::UnsupportedMode { mode: 0b0111 }));
+        // Byte mode, 3 bytes, but only 1 there
+        assert_eq!(decode_data(&[0b0100_0000, 0b0011_0110, 0b0001_0000], 1), Err(DecodeError::SegmentOverrun { mode: 0b0100 }));
+        // Numeric mode, 1 digit, with the value 15
+        assert_eq!(decode_data(&[0b0001_0000, 0b0000_0111, 0b1100_0000], 1), Err(DecodeError::BadCharacter { mode: 0b0001 }));
+    }
+
+    #[test]
+    fn says_when_the_format_or_version_is_unreadable() {
+        let code = QrCode::with_version(b"format", Version::Normal(7), EcLevel::M).unwrap();
+        let mut grid = grid_of(&code);
+        let format = grid.format_info().unwrap();
+
+        // Overwrite both copies of the version information with version 8's
+        let wrong = version_word(8);
+        for i in 0..18 {
+            let (a, b) = (grid.size - 11 + i % 3, i / 3);
+            grid.modules[(b * grid.size + a) as usize] = (wrong >> i) & 1 == 1;
+            grid.modules[(a * grid.size + b) as usize] = (wrong >> i) & 1 == 1;
+        }
+        assert_eq!(read_payload(&grid, format), Err(DecodeError::VersionMismatch { sampled: 7, encoded: 8 }));
+
+        let blank = ModuleGrid { size: 21, modules: [false; 21 * 21].into_iter().collect() };
+        assert_eq!(blank.format_info(), None);
+    }
+}
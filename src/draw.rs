@@ -0,0 +1,193 @@
+//! Overlays showing what the scanner found: crosses over position targets,
+//! outlines around codes and text labels. An `Overlay` is built once, in
+//! image coordinates, and can then be drawn onto an `RgbaImage` (for
+//! `arqr-cli --annotate` and the demo's snapshots) or handed to something
+//! else to draw, as the demo window does. `to_svg` gives the same overlay
+//! as an SVG, for layering over the original image without rasterizing.
+//!
+//! Colors are RGBA with components from 0 to 1, as in `config`.
+
+use std::fmt::Write as _;
+use image::{Rgba, RgbaImage};
+use crate::{
+    Point, ScanResult,
+    font::{self, GLYPH_HEIGHT, GLYPH_WIDTH},
+    target::Target,
+};
+
+pub type Color = [f32; 4];
+
+/// Color `to_svg` draws in
+pub const SVG_COLOR: Color = [0.0, 1.0, 0.0, 1.0];
+
+#[derive(Clone, Copy, Debug)]
+pub struct Line {
+    pub from: Point<f64>,
+    pub to: Point<f64>,
+    pub color: Color,
+    /// Thickness in pixels
+    pub width: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub text: String,
+    /// Left end of the text's baseline
+    pub at: Point<f64>,
+    /// Height of the text in pixels
+    pub size: f64,
+    pub color: Color,
+}
+
+/// Shapes to draw over an image
+#[derive(Clone, Debug, Default)]
+pub struct Overlay {
+    pub lines: Vec<Line>,
+    pub labels: Vec<Label>,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What the scanner found in a frame: a cross over each position target,
+    /// numbered, and an outline around the code, if one was found
+    pub fn from_result(result: &ScanResult, color: Color) -> Self {
+        let mut overlay = Self::new();
+        overlay.targets(&result.targets, color);
+        if let Some(quad) = result.quad() {
+            overlay.polygon(&quad, color);
+        }
+        overlay
+    }
+
+    pub fn line(&mut self, from: Point<f64>, to: Point<f64>, color: Color) {
+        self.lines.push(Line { from, to, color, width: 1.0 });
+    }
+
+    /// Outlines a closed polygon
+    pub fn polygon(&mut self, points: &[Point<f64>], color: Color) {
+        for (i, &from) in points.iter().enumerate() {
+            self.line(from, points[(i + 1) % points.len()], color);
+        }
+    }
+
+    pub fn label(&mut self, text: impl Into<String>, at: Point<f64>, size: f64, color: Color) {
+        self.labels.push(Label { text: text.into(), at, size, color });
+    }
+
+    /// A cross over each target, through its middle, labelled with its index
+    /// at its top-left
+    pub fn targets(&mut self, targets: &[Target<f64>], color: Color) {
+        for (n, t) in targets.iter().enumerate() {
+            self.line(Point::new(t.min.x, t.mid.y), Point::new(t.max.x, t.mid.y), color);
+            self.line(Point::new(t.mid.x, t.min.y), Point::new(t.mid.x, t.max.y), color);
+            self.label(n.to_string(), t.min, 12.0, color);
+        }
+    }
+
+    /// Draws the overlay onto `img`, clipped to its bounds. Labels use the
+    /// built-in bitmap font (see `font`).
+    pub fn draw_on(&self, img: &mut RgbaImage) {
+        for line in &self.lines {
+            draw_line(img, line);
+        }
+        for label in &self.labels {
+            draw_label(img, label);
+        }
+    }
+
+    /// The overlay as a standalone SVG document, `width` by `height` pixels
+    /// (the size of the image it goes over) with a transparent background
+    pub fn to_svg(&self, width: u32, height: u32) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+            w = width, h = height,
+        );
+        for line in &self.lines {
+            let (a, b) = (line.from, line.to);
+            if ![a.x, a.y, b.x, b.y].iter().all(|v| v.is_finite()) {
+                continue;
+            }
+            let _ = writeln!(
+                svg,
+                "  <line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" stroke-opacity=\"{:.3}\" stroke-width=\"{}\"/>",
+                a.x, a.y, b.x, b.y, svg_color(line.color), line.color[3], line.width,
+            );
+        }
+        for label in &self.labels {
+            let _ = writeln!(
+                svg,
+                "  <text x=\"{:.2}\" y=\"{:.2}\" font-family=\"sans-serif\" font-size=\"{}\" fill=\"{}\" fill-opacity=\"{:.3}\">{}</text>",
+                label.at.x, label.at.y, label.size, svg_color(label.color), label.color[3], xml_escape(&label.text),
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// What the scanner found in a frame (see `Overlay::from_result`) as an SVG
+/// the size of the frame
+pub fn to_svg(result: &ScanResult, width: u32, height: u32) -> String {
+    Overlay::from_result(result, SVG_COLOR).to_svg(width, height)
+}
+
+fn svg_color(color: Color) -> String {
+    let [r, g, b, _] = to_rgba(color).0;
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn to_rgba(color: Color) -> Rgba<u8> {
+    Rgba(color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+}
+
+/// Fills a `size` pixel square centred on `(x, y)`, clipped to the image
+fn dab(img: &mut RgbaImage, x: f64, y: f64, size: u32, color: Rgba<u8>) {
+    let (x0, y0) = (x - (size - 1) as f64 / 2.0, y - (size - 1) as f64 / 2.0);
+    for dy in 0..size {
+        for dx in 0..size {
+            let (px, py) = (x0 + dx as f64, y0 + dy as f64);
+            if px >= 0.0 && py >= 0.0 && (px as u32) < img.width() && (py as u32) < img.height() {
+                img.put_pixel(px as u32, py as u32, color);
+            }
+        }
+    }
+}
+
+fn draw_line(img: &mut RgbaImage, line: &Line) {
+    let (from, to) = (line.from, line.to);
+    if ![from.x, from.y, to.x, to.y].iter().all(|v| v.is_finite()) {
+        return;
+    }
+    let (color, size) = (to_rgba(line.color), line.width.round().max(1.0) as u32);
+    let steps = from.dist_to(to).ceil().max(1.0) as u32;
+    for i in 0..=steps {
+        let t = i as f64 / steps as f64;
+        dab(img, from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t, size, color);
+    }
+}
+
+fn draw_label(img: &mut RgbaImage, label: &Label) {
+    let color = to_rgba(label.color);
+    let scale = (label.size / GLYPH_HEIGHT as f64).round().max(1.0) as u32;
+    let top = label.at.y - (GLYPH_HEIGHT * scale) as f64;
+    for (i, c) in label.text.chars().enumerate() {
+        let left = label.at.x + (i as u32 * (GLYPH_WIDTH + 1) * scale) as f64;
+        for (row, bits) in font::glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                let x = left + (col * scale) as f64 + (scale - 1) as f64 / 2.0;
+                let y = top + (row as u32 * scale) as f64 + (scale - 1) as f64 / 2.0;
+                dab(img, x, y, scale, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Parses EMVCo merchant-presented QR payloads, the codes shops show for
+//! customers to pay with a banking or wallet app (UPI, PIX, PayNow, DuitNow
+//! and many more are built on it). A payload is a run of data objects, each
+//! a two-digit ID, a two-digit length and that many characters of value:
+//!
+//! ```text
+//! 000201 010211 26..(template) 52045812 5303840 5802US 5905Shop1 6006Oxford 6304ABCD
+//! ```
+//!
+//! (without the spaces). Some objects, like the merchant account templates,
+//! hold more data objects in their values. The payload ends in a CRC of
+//! everything before it, which is checked.
+
+/// One ID, length and value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataObject {
+    pub id: u8,
+    pub value: String,
+}
+
+impl DataObject {
+    /// The data objects inside this one's value, if it's a template
+    pub fn template(&self) -> Option<Vec<DataObject>> {
+        parse_objects(&self.value)
+    }
+}
+
+/// How the merchant can be paid. IDs 2 to 25 are reserved for card
+/// networks, and hold the merchant's ID with them directly; 26 to 51 are
+/// templates for anyone else's scheme.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerchantAccount {
+    pub id: u8,
+    pub value: String,
+    /// The template's data objects, or empty for a card network's
+    pub fields: Vec<DataObject>,
+}
+
+impl MerchantAccount {
+    /// Which scheme a template is for, as a reverse domain name or AID
+    /// (its data object 0), e.g. "br.gov.bcb.pix"
+    pub fn guid(&self) -> Option<&str> {
+        field(&self.fields, 0)
+    }
+}
+
+/// Whether the customer is asked to add a tip or fee
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Tip {
+    /// The app asks the customer how much to tip
+    Prompt,
+    /// A set convenience fee, as an amount
+    Fixed(String),
+    /// A convenience fee as a percentage of the amount, e.g. "3.00"
+    Percentage(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerchantPayload {
+    /// Format version, "01" for now
+    pub format: String,
+    /// Whether the code is made fresh for one payment ("12"), rather than
+    /// printed for many ("11"), if it says
+    pub dynamic: Option<bool>,
+    /// At least one
+    pub accounts: Vec<MerchantAccount>,
+    /// ISO 18245 merchant category code
+    pub category_code: String,
+    /// ISO 4217 numeric currency code, e.g. "978" for euros
+    pub currency: String,
+    /// Left to the customer if None
+    pub amount: Option<String>,
+    pub tip: Option<Tip>,
+    /// ISO 3166-1 alpha-2
+    pub country: String,
+    pub merchant_name: String,
+    pub merchant_city: String,
+    pub postal_code: Option<String>,
+    /// Bill number, store label, terminal and the like (data object 62)
+    pub additional_data: Vec<DataObject>,
+    /// Merchant name and city in another language (data object 64)
+    pub language: Vec<DataObject>,
+    /// Reserved and unreserved templates (65 to 99), unparsed
+    pub other: Vec<DataObject>,
+}
+
+impl MerchantPayload {
+    /// Parses a payload, or returns None if it's malformed, is missing a
+    /// required data object, or fails its CRC
+    pub fn parse(payload: &str) -> Option<Self> {
+        let objects = parse_objects(payload)?;
+        // The CRC covers everything up to its own value, ID and length
+        // included
+        let (last, rest) = objects.split_last()?;
+        let crc_start = payload.len().checked_sub(4)?;
+        if last.id != 63 || last.value.len() != 4 || u16::from_str_radix(&last.value, 16).ok()? != crc16(&payload.as_bytes()[..crc_start]) {
+            return None;
+        }
+        if rest.first()?.id != 0 || rest.iter().any(|o| rest.iter().filter(|p| p.id == o.id).count() > 1) {
+            return None;
+        }
+
+        let get = |id| field(rest, id).map(str::to_owned);
+        let template = |id| rest.iter().find(|o| o.id == id).map_or(Some(Vec::new()), DataObject::template);
+
+        let format = get(0).filter(|f| f == "01")?;
+        let dynamic = match field(rest, 1) {
+            None => None,
+            Some("11") => Some(false),
+            Some("12") => Some(true),
+            Some(_) => return None,
+        };
+        let accounts: Vec<MerchantAccount> = rest.iter()
+            .filter(|o| (2..=51).contains(&o.id))
+            .map(|o| {
+                let fields = if o.id >= 26 { o.template().filter(|t| field(t, 0).is_some())? } else { Vec::new() };
+                Some(MerchantAccount { id: o.id, value: o.value.clone(), fields })
+            })
+            .collect::<Option<_>>()?;
+        if accounts.is_empty() {
+            return None;
+        }
+        let tip = match field(rest, 55) {
+            None => None,
+            Some("01") => Some(Tip::Prompt),
+            Some("02") => Some(Tip::Fixed(get(56)?)),
+            Some("03") => Some(Tip::Percentage(get(57)?)),
+            Some(_) => return None,
+        };
+
+        Some(Self {
+            format,
+            dynamic,
+            accounts,
+            category_code: get(52).filter(|c| is_digits(c, 4))?,
+            currency: get(53).filter(|c| is_digits(c, 3))?,
+            amount: get(54),
+            tip,
+            country: get(58).filter(|c| c.len() == 2)?,
+            merchant_name: get(59)?,
+            merchant_city: get(60)?,
+            postal_code: get(61),
+            additional_data: template(62)?,
+            language: template(64)?,
+            other: rest.iter().filter(|o| o.id >= 65).cloned().collect(),
+        })
+    }
+}
+
+/// The value of data object `id` in `objects`
+fn field(objects: &[DataObject], id: u8) -> Option<&str> {
+    objects.iter().find(|o| o.id == id).map(|o| o.value.as_str())
+}
+
+fn is_digits(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Splits `s` into data objects, or None if it doesn't divide into them
+/// exactly. Lengths count characters, since names in the language template
+/// can be in any script.
+fn parse_objects(s: &str) -> Option<Vec<DataObject>> {
+    let mut objects = Vec::new();
+    let mut rest = s;
+    while !rest.is_empty() {
+        let header = rest.get(..4).filter(|h| h.bytes().all(|b| b.is_ascii_digit()))?;
+        let (id, len) = (header[..2].parse().ok()?, header[2..].parse::<usize>().ok()?);
+        if len == 0 {
+            return None;
+        }
+        rest = &rest[4..];
+        let end = rest.char_indices().map(|(i, _)| i).chain([rest.len()]).nth(len)?;
+        objects.push(DataObject { id, value: rest[..end].to_owned() });
+        rest = &rest[end..];
+    }
+    Some(objects)
+}
+
+/// CRC-16/CCITT-FALSE: polynomial 0x1021, starting from 0xffff
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `body` with its CRC data object added
+    fn with_crc(body: &str) -> String {
+        let body = format!("{body}6304");
+        format!("{body}{:04X}", crc16(body.as_bytes()))
+    }
+
+    const BODY: &str = "000201010212\
+        26360014br.gov.bcb.pix0114+5561999999999\
+        0416411111111111111152045812530398654041.995802BR\
+        5913Fulano de Tal6008BRASILIA6105700006207\
+        0503***64180002PT0108Brasília";
+
+    #[test]
+    fn parses_payloads() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+        let payload = MerchantPayload::parse(&with_crc(BODY)).unwrap();
+        assert_eq!(payload.dynamic, Some(true));
+        assert_eq!(payload.accounts.len(), 2);
+        assert_eq!(payload.accounts[0].guid(), Some("br.gov.bcb.pix"));
+        assert_eq!((payload.accounts[1].id, payload.accounts[1].fields.len()), (4, 0));
+        assert_eq!(payload.category_code, "5812");
+        assert_eq!(payload.currency, "986");
+        assert_eq!(payload.amount.as_deref(), Some("1.99"));
+        assert_eq!(payload.country, "BR");
+        assert_eq!(payload.merchant_name, "Fulano de Tal");
+        assert_eq!(payload.merchant_city, "BRASILIA");
+        assert_eq!(payload.postal_code.as_deref(), Some("70000"));
+        assert_eq!(payload.additional_data, [DataObject { id: 5, value: "***".to_owned() }]);
+        // Lengths count characters, not bytes
+        assert_eq!(payload.language[1].value, "Brasília");
+    }
+
+    #[test]
+    fn rejects_truncated_payloads() {
+        let full = with_crc(BODY);
+        assert_eq!(MerchantPayload::parse(&full[..full.len() - 1]), None);
+        assert_eq!(MerchantPayload::parse(&full[..full.len() - 8]), None);
+        assert_eq!(MerchantPayload::parse(BODY), None);
+        assert_eq!(MerchantPayload::parse(""), None);
+    }
+
+    #[test]
+    fn rejects_malformed_payloads() {
+        // A wrong CRC
+        let mut bad = with_crc(BODY);
+        bad.replace_range(bad.len() - 4.., "0000");
+        assert_eq!(MerchantPayload::parse(&bad), None);
+        // The rest have good CRCs
+        let with = |from: &str, to: &str| MerchantPayload::parse(&with_crc(&BODY.replace(from, to)));
+        assert_eq!(with("000201", "000202"), None);
+        assert_eq!(with("010212", "010213"), None);
+        assert_eq!(with("5802BR", "5803BRA"), None);
+        // No merchant account
+        assert_eq!(with("26360014br.gov.bcb.pix0114+55619999999990416411111111111111", ""), None);
+        // A template without its GUID
+        assert_eq!(with("0014br.gov.bcb.pix", "0114br.gov.bcb.pix"), None);
+        // A length running past the end of the template
+        assert_eq!(with("0503***", "0504***"), None);
+        assert_eq!(with("5204", "52X4"), None);
+    }
+}
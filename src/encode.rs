@@ -0,0 +1,933 @@
+//! Builds QR codes, for printing test targets and for generating symbols to
+//! scan without a camera in the loop. Data is encoded in a single mode (the
+//! most compact of numeric, alphanumeric and byte that fits it all) at the
+//! smallest version that holds it, with Reed-Solomon error correction and the
+//! mask that scores lowest by the standard's penalty rules.
+//!
+//! Micro QR codes, with their single finder, can be made too, and the
+//! version can be forced rather than picked, for testing how small a symbol
+//! the scanner copes with.
+
+use image::{GrayImage, Luma};
+use crate::{bitmap::Bitmap, mask::is_masked};
+
+/// How much of the symbol is given over to error correction, from about 7%
+/// of it recoverable at `L` to 30% at `H`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EcLevel {
+    L,
+    M,
+    Q,
+    H,
+}
+
+impl EcLevel {
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The two bits the format information stores the level as
+    fn format_bits(self) -> u32 {
+        match self {
+            Self::L => 1,
+            Self::M => 0,
+            Self::Q => 3,
+            Self::H => 2,
+        }
+    }
+
+    /// The level stored as `bits` in the format information
+    pub(crate) fn from_format_bits(bits: u32) -> Self {
+        match bits & 3 {
+            1 => Self::L,
+            0 => Self::M,
+            3 => Self::Q,
+            _ => Self::H,
+        }
+    }
+}
+
+/// Which kind of symbol, and how big
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    /// A QR code, from version 1 (21 modules across) to 40 (177)
+    Normal(u32),
+    /// A Micro QR code, from M1 (11 modules across) to M4 (17)
+    Micro(u32),
+}
+
+impl Version {
+    /// Width and height in modules, not counting the quiet zone
+    pub fn size(self) -> u32 {
+        match self {
+            Self::Normal(v) => v * 4 + 17,
+            Self::Micro(v) => v * 2 + 9,
+        }
+    }
+
+    /// Modules of white the standard asks for around the symbol
+    pub fn quiet_zone(self) -> u32 {
+        match self {
+            Self::Normal(_) => 4,
+            Self::Micro(_) => 2,
+        }
+    }
+}
+
+/// Error correction codewords per block, by level and version
+const ECC_PER_BLOCK: [[u8; 41]; 4] = [
+    [0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+    [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28],
+    [0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30, 30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+    [0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+];
+
+/// Error correction blocks, by level and version
+const BLOCKS: [[u8; 41]; 4] = [
+    [0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13, 14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25],
+    [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49],
+    [0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29, 34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68],
+    [0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32, 35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81],
+];
+
+const ALPHANUMERIC: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Numeric,
+    Alphanumeric,
+    Byte,
+}
+
+impl Mode {
+    /// The most compact mode that can hold all of `data`
+    fn for_data(data: &[u8]) -> Self {
+        if data.iter().all(u8::is_ascii_digit) {
+            Self::Numeric
+        } else if data.iter().all(|b| ALPHANUMERIC.contains(b)) {
+            Self::Alphanumeric
+        } else {
+            Self::Byte
+        }
+    }
+
+    /// The mode indicator and its width in bits. Micro QR codes use
+    /// narrower ones, down to none at all in M1, which only does numeric.
+    fn indicator(self, version: Version) -> (u32, u32) {
+        match version {
+            Version::Normal(_) => match self {
+                Self::Numeric => (0b0001, 4),
+                Self::Alphanumeric => (0b0010, 4),
+                Self::Byte => (0b0100, 4),
+            },
+            Version::Micro(v) => (self as u32, v - 1),
+        }
+    }
+
+    /// Width of the character count field, which grows with the version, or
+    /// None if the mode can't be used at this version
+    fn count_bits(self, version: Version) -> Option<u32> {
+        match version {
+            Version::Normal(v) => {
+                let i = match v {
+                    1..=9 => 0,
+                    10..=26 => 1,
+                    _ => 2,
+                };
+                Some(match self {
+                    Self::Numeric => [10, 12, 14][i],
+                    Self::Alphanumeric => [9, 11, 13][i],
+                    Self::Byte => [8, 16, 16][i],
+                })
+            }
+            Version::Micro(v) => match self {
+                Self::Numeric => Some(v + 2),
+                Self::Alphanumeric if v >= 2 => Some(v + 1),
+                Self::Byte if v >= 3 => Some(v + 1),
+                _ => None,
+            },
+        }
+    }
+
+    /// Length of `len` characters' worth of data, in bits
+    fn data_bits(self, len: usize) -> usize {
+        match self {
+            Self::Numeric => len / 3 * 10 + [0, 4, 7][len % 3],
+            Self::Alphanumeric => len / 2 * 11 + len % 2 * 6,
+            Self::Byte => len * 8,
+        }
+    }
+}
+
+#[derive(Default)]
+struct BitBuffer {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitBuffer {
+    /// Appends the low `n` bits of `value`, most significant first
+    fn push(&mut self, value: u32, n: u32) {
+        for i in (0..n).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                self.bytes[self.len / 8] |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+/// Multiplies in GF(256), modulo the QR polynomial x^8 + x^4 + x^3 + x^2 + 1
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut out = 0;
+    while b != 0 {
+        if b & 1 == 1 {
+            out ^= a;
+        }
+        a = (a << 1) ^ if a & 0x80 != 0 { 0x1d } else { 0 };
+        b >>= 1;
+    }
+    out
+}
+
+/// The Reed-Solomon generator polynomial of the given degree, highest
+/// coefficient first with the leading 1 left out
+fn rs_generator(degree: usize) -> Vec<u8> {
+    let mut poly = vec![0; degree];
+    poly[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        // Multiply by (x - root)
+        for i in 0..degree {
+            poly[i] = gf_mul(poly[i], root);
+            if i + 1 < degree {
+                poly[i] ^= poly[i + 1];
+            }
+        }
+        root = gf_mul(root, 2);
+    }
+    poly
+}
+
+/// The error correction codewords for `data`
+fn rs_remainder(data: &[u8], generator: &[u8]) -> Vec<u8> {
+    let mut out = vec![0; generator.len()];
+    for &b in data {
+        let factor = b ^ out.remove(0);
+        out.push(0);
+        for (o, &g) in out.iter_mut().zip(generator) {
+            *o ^= gf_mul(g, factor);
+        }
+    }
+    out
+}
+
+/// Modules in a symbol of `version` that aren't taken up by function
+/// patterns, format or version information
+fn raw_data_modules(version: u32) -> usize {
+    let v = version as usize;
+    let mut n = (16 * v + 128) * v + 64;
+    if v >= 2 {
+        let align = v / 7 + 2;
+        n -= (25 * align - 10) * align - 55;
+        if v >= 7 {
+            n -= 36;
+        }
+    }
+    n
+}
+
+/// How a symbol's codewords are split up
+pub(crate) struct Layout {
+    /// Codewords in the whole symbol
+    pub(crate) codewords: usize,
+    pub(crate) blocks: usize,
+    /// Error correction codewords per block
+    pub(crate) ecc_len: usize,
+    /// Room for data, in bits. In M1 and M3-M it isn't a whole number of
+    /// codewords, as the last data codeword is only 4 bits.
+    pub(crate) data_bits: usize,
+}
+
+impl Layout {
+    /// None if there's no such version, or it doesn't come at `ec_level`
+    pub(crate) fn new(version: Version, ec_level: EcLevel) -> Option<Self> {
+        match version {
+            Version::Normal(v @ 1..=40) => {
+                let (l, i) = (ec_level.index(), v as usize);
+                let codewords = raw_data_modules(v) / 8;
+                let (blocks, ecc_len) = (BLOCKS[l][i] as usize, ECC_PER_BLOCK[l][i] as usize);
+                Some(Self { codewords, blocks, ecc_len, data_bits: (codewords - blocks * ecc_len) * 8 })
+            }
+            Version::Micro(v) => {
+                // M1 only detects errors, and counts as L here
+                let (codewords, ecc_len, data_bits) = match (v, ec_level) {
+                    (1, EcLevel::L) => (5, 2, 20),
+                    (2, EcLevel::L) => (10, 5, 40),
+                    (2, EcLevel::M) => (10, 6, 32),
+                    (3, EcLevel::L) => (17, 6, 84),
+                    (3, EcLevel::M) => (17, 8, 68),
+                    (4, EcLevel::L) => (24, 8, 128),
+                    (4, EcLevel::M) => (24, 10, 112),
+                    (4, EcLevel::Q) => (24, 14, 80),
+                    _ => return None,
+                };
+                Some(Self { codewords, blocks: 1, ecc_len, data_bits })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Centres of the alignment patterns along each axis
+pub(crate) fn alignment_positions(version: u32) -> Vec<u32> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let size = version * 4 + 17;
+    let count = version / 7 + 2;
+    let step = (version * 8 + count * 3 + 5) / (count * 4 - 4) * 2;
+    let mut out: Vec<u32> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    out.push(6);
+    out.reverse();
+    out
+}
+
+/// Micro QR codes only have four of the masks, numbered 0 to 3
+const MICRO_MASKS: [u8; 4] = [1, 4, 6, 7];
+
+/// An encoded QR code
+#[derive(Clone, Debug)]
+pub struct QrCode {
+    version: Version,
+    ec_level: EcLevel,
+    mask: u8,
+    size: u32,
+    /// Row-major, true for dark modules
+    modules: Vec<bool>,
+    /// Which modules belong to function patterns, and aren't masked
+    function: Vec<bool>,
+}
+
+/// Whether `data` fits in `version` at `ec_level`, and in which mode
+fn fit(data: &[u8], version: Version, ec_level: EcLevel) -> Option<(Mode, Layout)> {
+    let mode = Mode::for_data(data);
+    let layout = Layout::new(version, ec_level)?;
+    let count_bits = mode.count_bits(version)?;
+    let bits = mode.indicator(version).1 + count_bits;
+    (data.len() < 1 << count_bits && bits as usize + mode.data_bits(data.len()) <= layout.data_bits)
+        .then_some((mode, layout))
+}
+
+impl QrCode {
+    /// Encodes `data` at the smallest version that holds it at `ec_level`.
+    /// Returns None if it's too long for even version 40.
+    pub fn encode(data: &[u8], ec_level: EcLevel) -> Option<Self> {
+        (1..=40).find_map(|v| Self::with_version(data, Version::Normal(v), ec_level))
+    }
+
+    /// Encodes `data` as the smallest Micro QR code that holds it at
+    /// `ec_level`. Returns None if it's too long for M4, or needs a mode
+    /// the versions that come at `ec_level` don't have. M1 is only used for
+    /// `EcLevel::L`, though it can only detect errors, not correct them.
+    pub fn encode_micro(data: &[u8], ec_level: EcLevel) -> Option<Self> {
+        (1..=4).find_map(|v| Self::with_version(data, Version::Micro(v), ec_level))
+    }
+
+    /// Encodes `data` at exactly `version`. Returns None if it doesn't fit,
+    /// or the version doesn't exist or doesn't come at `ec_level`.
+    pub fn with_version(data: &[u8], version: Version, ec_level: EcLevel) -> Option<Self> {
+        let (mode, layout) = fit(data, version, ec_level)?;
+        let codewords = Self::data_codewords(data, mode, version, &layout);
+        let mut code = Self::blank(version, ec_level);
+        code.draw_data(&Self::interleave(&codewords, &layout), &layout);
+
+        // Keep whichever mask scores lowest
+        let masks = match version {
+            Version::Normal(_) => 8,
+            Version::Micro(_) => 4,
+        };
+        let mut best = (u32::MAX, 0);
+        for mask in 0..masks {
+            code.apply_mask(mask);
+            code.draw_format(mask);
+            let penalty = code.penalty();
+            if penalty < best.0 {
+                best = (penalty, mask);
+            }
+            code.apply_mask(mask);
+        }
+        code.mask = best.1;
+        code.apply_mask(code.mask);
+        code.draw_format(code.mask);
+        Some(code)
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn ec_level(&self) -> EcLevel {
+        self.ec_level
+    }
+
+    /// Mask pattern, from 0 to 7, or 0 to 3 for Micro QR codes
+    pub fn mask(&self) -> u8 {
+        self.mask
+    }
+
+    /// Width and height in modules, not counting the quiet zone
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Whether the module at (`x`, `y`) is dark. Anything outside the symbol
+    /// is light.
+    pub fn is_dark(&self, x: u32, y: u32) -> bool {
+        x < self.size && y < self.size && self.modules[(y * self.size + x) as usize]
+    }
+
+    /// The symbol as a bitmap, `module_px` pixels to a module, with
+    /// `quiet_zone` modules of white around it (see `Version::quiet_zone`)
+    pub fn to_bitmap(&self, module_px: u32, quiet_zone: u32) -> Bitmap {
+        let side = (self.size + quiet_zone * 2) * module_px;
+        let mut bitmap = Bitmap::new(side, side);
+        for y in 0..side {
+            for x in 0..side {
+                let (col, row) = (x / module_px, y / module_px);
+                if col >= quiet_zone && row >= quiet_zone && self.is_dark(col - quiet_zone, row - quiet_zone) {
+                    *bitmap.get_pixel_mut(x, y) = false;
+                }
+            }
+        }
+        bitmap
+    }
+
+    /// Draws the symbol for printing, with each module `module_px` pixels
+    /// wide and the standard's quiet zone
+    pub fn render(&self, module_px: u32) -> GrayImage {
+        let quiet = self.version.quiet_zone();
+        let side = (self.size + quiet * 2) * module_px;
+        GrayImage::from_fn(side, side, |x, y| {
+            let (col, row) = (x / module_px, y / module_px);
+            let dark = col >= quiet && row >= quiet && self.is_dark(col - quiet, row - quiet);
+            Luma([if dark { 0 } else { 255 }])
+        })
+    }
+
+    /// Mode, count, data, terminator and padding, in codewords
+    fn data_codewords(data: &[u8], mode: Mode, version: Version, layout: &Layout) -> Vec<u8> {
+        let mut bits = BitBuffer::default();
+        let (indicator, indicator_bits) = mode.indicator(version);
+        bits.push(indicator, indicator_bits);
+        bits.push(data.len() as u32, mode.count_bits(version).unwrap());
+        match mode {
+            Mode::Numeric => {
+                for chunk in data.chunks(3) {
+                    let n = chunk.iter().fold(0, |n, &d| n * 10 + (d - b'0') as u32);
+                    bits.push(n, chunk.len() as u32 * 3 + 1);
+                }
+            }
+            Mode::Alphanumeric => {
+                let value = |c: &u8| ALPHANUMERIC.iter().position(|a| a == c).unwrap() as u32;
+                for chunk in data.chunks(2) {
+                    match chunk {
+                        [a, b] => bits.push(value(a) * 45 + value(b), 11),
+                        [a] => bits.push(value(a), 6),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            Mode::Byte => {
+                for &b in data {
+                    bits.push(b as u32, 8);
+                }
+            }
+        }
+
+        let capacity = layout.data_bits;
+        let terminator = match version {
+            Version::Normal(_) => 4,
+            Version::Micro(v) => v * 2 + 1,
+        };
+        bits.push(0, (capacity - bits.len).min(terminator as usize) as u32);
+        bits.push(0, ((8 - bits.len % 8) % 8).min(capacity - bits.len) as u32);
+        for pad in [0xec, 0x11].into_iter().cycle() {
+            if bits.len + 8 > capacity {
+                break;
+            }
+            bits.push(pad, 8);
+        }
+        // A short last codeword is left as zeros
+        bits.push(0, (capacity - bits.len) as u32);
+        bits.bytes
+    }
+
+    /// Splits the data into blocks, adds each one's error correction and
+    /// interleaves the lot, as it's laid out in the symbol
+    fn interleave(data: &[u8], layout: &Layout) -> Vec<u8> {
+        let (blocks, ecc_len, raw) = (layout.blocks, layout.ecc_len, layout.codewords);
+        // The later blocks hold one more data codeword than the earlier ones
+        let short_blocks = blocks - raw % blocks;
+        let short_len = raw / blocks - ecc_len;
+
+        let generator = rs_generator(ecc_len);
+        let mut data_blocks = Vec::with_capacity(blocks);
+        let mut ecc_blocks = Vec::with_capacity(blocks);
+        let mut pos = 0;
+        for i in 0..blocks {
+            let len = short_len + (i >= short_blocks) as usize;
+            let block = &data[pos..pos + len];
+            pos += len;
+            ecc_blocks.push(rs_remainder(block, &generator));
+            data_blocks.push(block);
+        }
+
+        let mut out = Vec::with_capacity(raw);
+        for i in 0..=short_len {
+            for block in &data_blocks {
+                if let Some(&b) = block.get(i) {
+                    out.push(b);
+                }
+            }
+        }
+        for i in 0..ecc_len {
+            for block in &ecc_blocks {
+                out.push(block[i]);
+            }
+        }
+        out
+    }
+
+    /// A symbol with only its function patterns drawn, and room reserved
+    /// for the format information
+    fn blank(version: Version, ec_level: EcLevel) -> Self {
+        let size = version.size();
+        let n = (size * size) as usize;
+        let mut code = Self {
+            version,
+            ec_level,
+            mask: 0,
+            size,
+            modules: vec![false; n],
+            function: vec![false; n],
+        };
+
+        let Version::Normal(v) = version else {
+            // One finder, with the timing patterns along the top and left
+            // edges
+            for i in 0..size {
+                code.set_function(0, i, i % 2 == 0);
+                code.set_function(i, 0, i % 2 == 0);
+            }
+            code.draw_finder(3, 3);
+            code.draw_format(0);
+            return code;
+        };
+        for i in 0..size {
+            code.set_function(6, i, i % 2 == 0);
+            code.set_function(i, 6, i % 2 == 0);
+        }
+        for (cx, cy) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            code.draw_finder(cx, cy);
+        }
+        let align = alignment_positions(v);
+        let last = align.len().saturating_sub(1);
+        for (i, &cy) in align.iter().enumerate() {
+            for (j, &cx) in align.iter().enumerate() {
+                // Skip the ones that would land on the finders
+                if (i == 0 && (j == 0 || j == last)) || (i == last && j == 0) {
+                    continue;
+                }
+                for dy in 0..5 {
+                    for dx in 0..5 {
+                        let ring = (dx as i32 - 2).abs().max((dy as i32 - 2).abs());
+                        code.set_function(cx + dx - 2, cy + dy - 2, ring != 1);
+                    }
+                }
+            }
+        }
+        // Placeholder until a mask is picked
+        code.draw_format(0);
+        code.draw_version();
+        code
+    }
+
+    fn set_function(&mut self, x: u32, y: u32, dark: bool) {
+        let i = (y * self.size + x) as usize;
+        self.modules[i] = dark;
+        self.function[i] = true;
+    }
+
+    /// A finder pattern centred on (`cx`, `cy`), with its white separator
+    fn draw_finder(&mut self, cx: u32, cy: u32) {
+        for dy in -4..=4i32 {
+            for dx in -4..=4i32 {
+                let (x, y) = (cx as i32 + dx, cy as i32 + dy);
+                if x < 0 || y < 0 || x >= self.size as i32 || y >= self.size as i32 {
+                    continue;
+                }
+                let ring = dx.abs().max(dy.abs());
+                self.set_function(x as u32, y as u32, ring != 2 && ring != 4);
+            }
+        }
+    }
+
+    /// Both copies of the level and mask, with their BCH check bits
+    fn draw_format(&mut self, mask: u8) {
+        let (data, xor) = match self.version {
+            Version::Normal(_) => (self.ec_level.format_bits() << 3 | mask as u32, 0x5412),
+            // Micro QR codes number each version and level together, and
+            // only have the one copy
+            Version::Micro(v) => {
+                let symbol = match v {
+                    1 => 0,
+                    _ => v * 2 - 3 + self.ec_level.index() as u32,
+                };
+                (symbol << 2 | mask as u32, 0x4445)
+            }
+        };
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = (data << 10 | rem) ^ xor;
+        let bit = |i: u32| (bits >> i) & 1 == 1;
+
+        if let Version::Micro(_) = self.version {
+            for i in 0..8 {
+                self.set_function(8, i + 1, bit(i));
+            }
+            for i in 8..15 {
+                self.set_function(15 - i, 8, bit(i));
+            }
+            return;
+        }
+        // Around the top-left finder
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+        // Split between the other two
+        let size = self.size;
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        // Always dark
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Both copies of the version, for versions 7 and up
+    fn draw_version(&mut self) {
+        let Version::Normal(v @ 7..) = self.version else { return };
+        let mut rem = v;
+        for _ in 0..12 {
+            rem = (rem << 1) ^ ((rem >> 11) * 0x1f25);
+        }
+        let bits = v << 12 | rem;
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 == 1;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    /// Lays the codewords out in the zigzag of two-module columns, from the
+    /// bottom-right corner
+    fn draw_data(&mut self, codewords: &[u8], layout: &Layout) {
+        // A short last data codeword only puts down its top 4 bits
+        let data_len = layout.data_bits.div_ceil(8);
+        let short = !layout.data_bits.is_multiple_of(8);
+        let mut bits = codewords.iter().enumerate().flat_map(|(i, &b)| {
+            let n = if short && i == data_len - 1 { 4 } else { 8 };
+            (0..n).map(move |j| (b >> (7 - j)) & 1 == 1)
+        });
+
+        let size = self.size as i32;
+        let mut right = size - 1;
+        let mut upward = true;
+        while right >= 1 {
+            // A QR code's vertical timing pattern takes a whole column
+            if right == 6 && matches!(self.version, Version::Normal(_)) {
+                right = 5;
+            }
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = (right - j) as u32;
+                    let y = if upward { size - 1 - vert } else { vert } as u32;
+                    let idx = (y * self.size + x) as usize;
+                    // Any modules left over after the codewords stay light
+                    if !self.function[idx] {
+                        self.modules[idx] = bits.next().unwrap_or(false);
+                    }
+                }
+            }
+            right -= 2;
+            upward = !upward;
+        }
+    }
+
+    /// Flips the data modules picked out by `mask`. Doing it twice undoes it.
+    fn apply_mask(&mut self, mask: u8) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let i = (y * self.size + x) as usize;
+                let pattern = match self.version {
+                    Version::Normal(_) => mask,
+                    Version::Micro(_) => MICRO_MASKS[mask as usize],
+                };
+                if !self.function[i] && is_masked(pattern, x, y) {
+                    self.modules[i] ^= true;
+                }
+            }
+        }
+    }
+
+    /// The standard's score for how hard the symbol is to read: long runs,
+    /// 2x2 blocks, finder-like patterns and an uneven balance of dark and
+    /// light all count against it. Micro QR codes are scored on how many
+    /// dark modules there are along their right and bottom edges instead,
+    /// which they need to be found by.
+    fn penalty(&self) -> u32 {
+        let size = self.size;
+        if let Version::Micro(_) = self.version {
+            let right = (1..size).filter(|&y| self.is_dark(size - 1, y)).count() as u32;
+            let bottom = (1..size).filter(|&x| self.is_dark(x, size - 1)).count() as u32;
+            // The standard's score is higher for better masks; turn it around
+            return 17 * (size - 1) - (16 * right.min(bottom) + right.max(bottom));
+        }
+        let mut score = 0;
+        for horizontal in [true, false] {
+            let at = |line: u32, i: u32| if horizontal { self.is_dark(i, line) } else { self.is_dark(line, i) };
+            for line in 0..size {
+                // Runs of five or more of the same colour
+                let mut run = 1;
+                for i in 1..size {
+                    if at(line, i) == at(line, i - 1) {
+                        run += 1;
+                    } else {
+                        run = 1;
+                    }
+                    if run == 5 {
+                        score += 3;
+                    } else if run > 5 {
+                        score += 1;
+                    }
+                }
+                // 1:1:3:1:1 with four light modules to one side
+                for i in 0..size.saturating_sub(10) {
+                    let pattern: Vec<bool> = (i..i + 11).map(|j| at(line, j)).collect();
+                    let finder = [true, false, true, true, true, false, true];
+                    if (pattern[..7] == finder && pattern[7..].iter().all(|&d| !d))
+                        || (pattern[4..] == finder && pattern[..4].iter().all(|&d| !d))
+                    {
+                        score += 40;
+                    }
+                }
+            }
+        }
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let c = self.is_dark(x, y);
+                if c == self.is_dark(x + 1, y) && c == self.is_dark(x, y + 1) && c == self.is_dark(x + 1, y + 1) {
+                    score += 3;
+                }
+            }
+        }
+        let dark = self.modules.iter().filter(|&&d| d).count() as u32;
+        let total = size * size;
+        // 10 points for every 5% away from half dark
+        let k = (dark * 20).abs_diff(total * 10).div_ceil(total).saturating_sub(1);
+        score + k * 10
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::{self, ModuleGrid};
+
+    /// What the decoder reads off `code`'s modules, checking its format
+    /// information on the way
+    fn read_back(code: &QrCode) -> Vec<u8> {
+        let size = code.size();
+        let grid = ModuleGrid { size, modules: (0..size * size).map(|i| code.is_dark(i % size, i / size)).collect() };
+        let format = grid.format_info().unwrap();
+        assert_eq!((format.ec_level, format.mask, format.errors), (code.ec_level(), code.mask(), 0));
+        decode::read_payload(&grid, format).unwrap()
+    }
+
+    const LEVELS: [EcLevel; 4] = [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H];
+
+    #[test]
+    fn round_trips_every_mode() {
+        let messages: [(&[u8], Mode); 6] = [
+            // Numeric, ending in one and two digits
+            (b"0123456789012345", Mode::Numeric),
+            (b"31415926535", Mode::Numeric),
+            // Alphanumeric, ending in one character and in a pair
+            (b"HELLO WORLD $%*+-./:", Mode::Alphanumeric),
+            (b"AC-42", Mode::Alphanumeric),
+            ("bytes, caf\u{e9}".as_bytes(), Mode::Byte),
+            (&[0x00, 0xff, 0x80, b'a'], Mode::Byte),
+        ];
+        for (data, mode) in messages {
+            assert_eq!(Mode::for_data(data), mode);
+            for ec_level in LEVELS {
+                let code = QrCode::encode(data, ec_level).unwrap();
+                assert_eq!(read_back(&code), data, "{:?} at {:?}", String::from_utf8_lossy(data), ec_level);
+            }
+        }
+    }
+
+    #[test]
+    fn picks_the_smallest_version() {
+        // Version 1 holds 41 digits at L and 17 at H
+        let digits = |n| vec![b'7'; n];
+        assert_eq!(QrCode::encode(&digits(41), EcLevel::L).unwrap().version(), Version::Normal(1));
+        assert_eq!(QrCode::encode(&digits(42), EcLevel::L).unwrap().version(), Version::Normal(2));
+        assert_eq!(QrCode::encode(&digits(17), EcLevel::H).unwrap().version(), Version::Normal(1));
+        assert_eq!(QrCode::encode(&digits(18), EcLevel::H).unwrap().version(), Version::Normal(2));
+        // Version 40 holds 2953 bytes at L
+        assert!(QrCode::encode(&[b'a'; 2953], EcLevel::L).is_some());
+        assert!(QrCode::encode(&[b'a'; 2954], EcLevel::L).is_none());
+    }
+
+    #[test]
+    fn round_trips_many_blocks() {
+        let data: Vec<u8> = (0..600).map(|i| (i * 7 % 256) as u8).collect();
+        for ec_level in [EcLevel::L, EcLevel::H] {
+            let code = QrCode::encode(&data, ec_level).unwrap();
+            assert_eq!(read_back(&code), data);
+        }
+    }
+
+    #[test]
+    fn round_trips_forced_versions() {
+        let data = b"FORCED 123";
+        for version in [1, 6, 7, 14, 27, 40] {
+            for ec_level in [EcLevel::M, EcLevel::Q] {
+                let code = QrCode::with_version(data, Version::Normal(version), ec_level).unwrap();
+                assert_eq!((code.version(), code.size()), (Version::Normal(version), 17 + 4 * version));
+                assert_eq!(read_back(&code), data);
+            }
+        }
+        assert!(QrCode::with_version(&[b'7'; 42], Version::Normal(1), EcLevel::L).is_none());
+        assert!(QrCode::with_version(data, Version::Normal(0), EcLevel::L).is_none());
+        assert!(QrCode::with_version(data, Version::Normal(41), EcLevel::L).is_none());
+    }
+
+    /// What a Micro QR code holds, read back from its modules the way the
+    /// encoder put them down, since the decoder doesn't read Micro QR codes
+    fn read_back_micro(code: &QrCode) -> Vec<u8> {
+        let Version::Micro(v) = code.version() else { panic!("not a Micro QR code") };
+        let size = code.size();
+        let dark = |x: u32, y: u32| code.is_dark(x, y) as u32;
+        let format = (0..8).fold(0, |acc, i| acc | dark(8, i + 1) << i)
+            | (8..15).fold(0, |acc, i| acc | dark(15 - i, 8) << i);
+        let format = format ^ 0x4445;
+        let symbol = match v {
+            1 => 0,
+            _ => v * 2 - 3 + code.ec_level().index() as u32,
+        };
+        assert_eq!(format >> 10, symbol << 2 | code.mask() as u32);
+
+        let mut bits = Vec::new();
+        let (mut right, mut upward) = (size as i32 - 1, true);
+        while right >= 1 {
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = (right - j) as u32;
+                    let y = if upward { size - 1 - vert } else { vert };
+                    let i = (y * size + x) as usize;
+                    if !code.function[i] {
+                        bits.push(code.modules[i] ^ is_masked(MICRO_MASKS[code.mask() as usize], x, y));
+                    }
+                }
+            }
+            right -= 2;
+            upward = !upward;
+        }
+
+        // The data, whose last codeword is only 4 bits in M1 and M3-M, then
+        // the check words
+        let layout = Layout::new(code.version(), code.ec_level()).unwrap();
+        let byte = |bits: &[bool]| bits.iter().enumerate().fold(0u8, |acc, (j, &b)| acc | (b as u8) << (7 - j));
+        let (data_bits, rest) = bits.split_at(layout.data_bits);
+        let mut words: Vec<u8> = data_bits.chunks(8).map(byte).collect();
+        words.extend(rest.chunks_exact(8).take(layout.ecc_len).map(byte));
+        assert_eq!(crate::reed_solomon::Field::qr().correct_bytes(&mut words, layout.ecc_len, 0), Some(0));
+
+        let mut pos = 0;
+        let mut take = |n: u32| {
+            let value = data_bits[pos..pos + n as usize].iter().fold(0, |acc, &b| acc << 1 | b as u32);
+            pos += n as usize;
+            value
+        };
+        let mode = [Mode::Numeric, Mode::Alphanumeric, Mode::Byte][take(v - 1) as usize];
+        let mut count = take(mode.count_bits(code.version()).unwrap()) as usize;
+        let mut out = Vec::new();
+        while count > 0 {
+            match mode {
+                Mode::Numeric => {
+                    let digits = count.min(3);
+                    out.extend(format!("{:0digits$}", take(digits as u32 * 3 + 1)).bytes());
+                    count -= digits;
+                }
+                Mode::Alphanumeric if count >= 2 => {
+                    let pair = take(11) as usize;
+                    out.extend([ALPHANUMERIC[pair / 45], ALPHANUMERIC[pair % 45]]);
+                    count -= 2;
+                }
+                Mode::Alphanumeric => {
+                    out.push(ALPHANUMERIC[take(6) as usize]);
+                    count -= 1;
+                }
+                Mode::Byte => {
+                    out.push(take(8) as u8);
+                    count -= 1;
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn round_trips_micro_codes() {
+        let cases: [(&[u8], EcLevel, u32); 8] = [
+            // M1 only holds digits, and only at L
+            (b"12345", EcLevel::L, 1),
+            (b"12345", EcLevel::M, 2),
+            (b"123456", EcLevel::L, 2),
+            (b"AC-42", EcLevel::L, 2),
+            (b"HELLO WORLD", EcLevel::M, 3),
+            // M2 has no byte mode
+            (b"abc", EcLevel::L, 3),
+            ("caf\u{e9} bytes".as_bytes(), EcLevel::M, 4),
+            (b"0123456789", EcLevel::Q, 4),
+        ];
+        for (data, ec_level, version) in cases {
+            let code = QrCode::encode_micro(data, ec_level).unwrap();
+            assert_eq!((code.version(), code.size()), (Version::Micro(version), 9 + 2 * version));
+            assert!(code.mask() < 4);
+            assert_eq!(read_back_micro(&code), data, "{:?} at {:?}", String::from_utf8_lossy(data), ec_level);
+        }
+        // Nothing comes at H, and M4 holds 35 digits at most
+        assert!(QrCode::encode_micro(b"1", EcLevel::H).is_none());
+        assert!(QrCode::encode_micro(&[b'7'; 35], EcLevel::L).is_some());
+        assert!(QrCode::encode_micro(&[b'7'; 36], EcLevel::L).is_none());
+        assert!(QrCode::with_version(b"1", Version::Micro(5), EcLevel::L).is_none());
+    }
+}
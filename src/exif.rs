@@ -0,0 +1,140 @@
+//! Reads the EXIF orientation tag out of JPEG and WebP files. Phones save
+//! photos the way the sensor saw them and leave it to this tag to say which
+//! way up they go, so without it codes come out sideways, with their corners
+//! in the wrong places. `image` doesn't look at the tag, so this digs it out
+//! of the file's EXIF block directly.
+
+use image::DynamicImage;
+
+/// How an image has to be turned to be shown upright. The values are the
+/// tag's own, 1 to 8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    Normal = 1,
+    FlipHorizontal = 2,
+    Rotate180 = 3,
+    FlipVertical = 4,
+    /// Flipped about the top-left to bottom-right diagonal
+    Transpose = 5,
+    /// Turned 90 degrees clockwise
+    Rotate90 = 6,
+    /// Flipped about the top-right to bottom-left diagonal
+    Transverse = 7,
+    /// Turned 270 degrees clockwise
+    Rotate270 = 8,
+}
+
+impl Orientation {
+    pub fn from_tag(value: u16) -> Option<Self> {
+        Some(match value {
+            1 => Self::Normal,
+            2 => Self::FlipHorizontal,
+            3 => Self::Rotate180,
+            4 => Self::FlipVertical,
+            5 => Self::Transpose,
+            6 => Self::Rotate90,
+            7 => Self::Transverse,
+            8 => Self::Rotate270,
+            _ => return None,
+        })
+    }
+
+    /// Turns `img` upright
+    pub fn apply(self, img: DynamicImage) -> DynamicImage {
+        match self {
+            Self::Normal => img,
+            Self::FlipHorizontal => img.fliph(),
+            Self::Rotate180 => img.rotate180(),
+            Self::FlipVertical => img.flipv(),
+            Self::Transpose => img.rotate90().fliph(),
+            Self::Rotate90 => img.rotate90(),
+            Self::Transverse => img.rotate270().fliph(),
+            Self::Rotate270 => img.rotate270(),
+        }
+    }
+}
+
+/// The orientation of a JPEG or WebP file's contents, if it says. Anything
+/// else, or a file with no EXIF orientation, gives None.
+pub fn orientation(data: &[u8]) -> Option<Orientation> {
+    let tiff = if data.starts_with(&[0xff, 0xd8]) {
+        jpeg_exif(data)?
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        webp_exif(data)?
+    } else {
+        return None;
+    };
+    Orientation::from_tag(tiff_orientation(tiff)?)
+}
+
+/// The TIFF structure in a JPEG's APP1 segment
+fn jpeg_exif(data: &[u8]) -> Option<&[u8]> {
+    let mut pos = 2;
+    loop {
+        if *data.get(pos)? != 0xff {
+            return None;
+        }
+        // Markers can be padded with any number of 0xff bytes
+        while data.get(pos + 1) == Some(&0xff) {
+            pos += 1;
+        }
+        let marker = *data.get(pos + 1)?;
+        // The EXIF block has to come before the image data
+        if marker == 0xda || marker == 0xd9 {
+            return None;
+        }
+        let len = u16::from_be_bytes(data.get(pos + 2..pos + 4)?.try_into().ok()?) as usize;
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xe1 {
+            if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                return Some(tiff);
+            }
+        }
+        pos += 2 + len;
+    }
+}
+
+/// The TIFF structure in a WebP's EXIF chunk
+fn webp_exif(data: &[u8]) -> Option<&[u8]> {
+    let mut pos = 12;
+    loop {
+        let fourcc = data.get(pos..pos + 4)?;
+        let len = u32::from_le_bytes(data.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+        let chunk = data.get(pos + 8..pos + 8 + len)?;
+        if fourcc == b"EXIF" {
+            // Some writers keep the JPEG-style header
+            return Some(chunk.strip_prefix(b"Exif\0\0").unwrap_or(chunk));
+        }
+        // Chunks are padded to an even length
+        pos += 8 + len + len % 2;
+    }
+}
+
+/// The orientation tag's value, from the first IFD of a TIFF structure
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..2)? {
+        b"II" => false,
+        b"MM" => true,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| -> Option<u16> {
+        let bytes = tiff.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    };
+    let u32_at = |pos: usize| -> Option<u32> {
+        let bytes = tiff.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    };
+    if u16_at(2)? != 42 {
+        return None;
+    }
+    let ifd = u32_at(4)? as usize;
+    for i in 0..u16_at(ifd)? as usize {
+        let entry = ifd + 2 + i * 12;
+        // A single SHORT, stored in the start of the value field
+        if u16_at(entry)? == 0x0112 && u16_at(entry + 2)? == 3 {
+            return u16_at(entry + 8);
+        }
+    }
+    None
+}
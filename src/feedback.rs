@@ -0,0 +1,158 @@
+//! Suggestions for getting a better picture of a code, for apps to feed back
+//! into camera controls or show to the user. Based on cheap statistics of the
+//! frame (or just the code, once one's been found): how much is blown out or
+//! crushed to black, and how sharp the edges are.
+
+use std::ops::Deref;
+use image::{ImageBuffer, Pixel};
+use crate::{Point, Rect, ScanResult};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hint {
+    /// Too much of the image is dark or crushed to black
+    IncreaseExposure,
+    /// Too much of the image is blown out to white
+    DecreaseExposure,
+    /// Edges are too soft to read, probably from being out of focus
+    RefocusNeeded,
+    /// A code was found, but it's too small to read reliably
+    MoveCloser,
+}
+
+/// Brightness and sharpness measurements of (part of) a frame
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    /// Mean luma, 0 to 255
+    pub mean: f64,
+    /// Luma of the darkest 5% of pixels, i.e. how black the black parts are
+    pub dark: u8,
+    /// Luma of the brightest 5% of pixels
+    pub bright: u8,
+    /// How crisp edges are: the Laplacian's strength relative to the
+    /// gradient's. Blur spreads an edge's gradient out while flattening its
+    /// Laplacian, so this drops as focus gets worse, and doesn't depend on
+    /// exposure or on how much of the frame is featureless.
+    pub sharpness: f64,
+}
+
+impl FrameStats {
+    /// Measures `img`, only looking inside `region` if it's given. Only
+    /// every `step`th pixel in each direction is looked at, to keep it cheap
+    /// on big frames.
+    pub fn measure<Px, C>(img: &ImageBuffer<Px, C>, region: Option<Rect<u32>>, step: u32) -> Self
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        let (width, height) = img.dimensions();
+        let region = region.unwrap_or(Rect { min: Point::new(0, 0), max: Point::new(width, height) });
+        // Leave a pixel around the edge for the derivatives
+        let (x0, y0) = (region.min.x.max(1), region.min.y.max(1));
+        let (x1, y1) = (region.max.x.min(width.saturating_sub(1)), region.max.y.min(height.saturating_sub(1)));
+        let step = step.max(1) as usize;
+        let luma = |x: u32, y: u32| img.get_pixel(x, y).to_luma().0[0];
+
+        let mut histo = [0u32; 256];
+        let (mut lap_sum, mut grad_sum) = (0.0, 0.0);
+        for y in (y0..y1).step_by(step) {
+            for x in (x0..x1).step_by(step) {
+                let v = luma(x, y);
+                histo[v as usize] += 1;
+                let [l, r, u, d] = [luma(x - 1, y), luma(x + 1, y), luma(x, y - 1), luma(x, y + 1)]
+                    .map(|n| n as f64);
+                lap_sum += (l + r + u + d - 4.0 * v as f64).abs();
+                grad_sum += (r - l).abs() + (d - u).abs();
+            }
+        }
+        let count: u32 = histo.iter().sum();
+        if count == 0 {
+            return Self::default();
+        }
+
+        let percentile = |frac: f64| {
+            let target = (count as f64 * frac) as u32;
+            let mut seen = 0;
+            histo.iter().position(|&n| { seen += n; seen > target }).unwrap_or(255) as u8
+        };
+        let mean = histo.iter().enumerate().map(|(v, &n)| v as f64 * n as f64).sum::<f64>() / count as f64;
+        Self {
+            mean,
+            dark: percentile(0.05),
+            bright: percentile(0.95),
+            sharpness: if grad_sum > 0.0 { lap_sum / grad_sum } else { 0.0 },
+        }
+    }
+}
+
+/// Thresholds for turning `FrameStats` into `Hint`s
+#[derive(Clone, Copy, Debug)]
+pub struct HintParams {
+    /// `FrameStats::dark` above which blacks are washed out and exposure
+    /// should come down
+    pub max_dark: u8,
+    /// `FrameStats::bright` below which whites are murky and exposure
+    /// should go up
+    pub min_bright: u8,
+    /// Smallest difference between `dark` and `bright` that's enough
+    /// contrast to read. Below this, exposure is nudged towards mid-grey.
+    pub min_contrast: u8,
+    /// `FrameStats::sharpness` below which to suggest refocusing
+    pub min_sharpness: f64,
+    /// Shortest code side, in pixels, below which to suggest moving closer
+    pub min_code_size: f64,
+    /// See `FrameStats::measure`
+    pub step: u32,
+}
+
+impl Default for HintParams {
+    fn default() -> Self {
+        Self {
+            max_dark: 120,
+            min_bright: 80,
+            min_contrast: 50,
+            min_sharpness: 0.3,
+            // About 2 pixels per module on a version 1 code
+            min_code_size: 50.0,
+            step: 2,
+        }
+    }
+}
+
+impl HintParams {
+    /// Suggests what would help scan `img`, given what was found in it. If a
+    /// code was found, only the code is measured, since that's what needs to
+    /// be readable.
+    pub fn hints<Px, C>(&self, img: &ImageBuffer<Px, C>, result: &ScanResult) -> Vec<Hint>
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        let quad = result.quad();
+        let region = quad.map(|q| Rect::bounding(&q).to_pixels());
+        let stats = FrameStats::measure(img, region, self.step);
+
+        let mut hints = self.exposure_hints(&stats);
+        if stats.sharpness < self.min_sharpness {
+            hints.push(Hint::RefocusNeeded);
+        }
+        if let Some([tl, tr, bl]) = result.bbox {
+            if tl.dist_to(tr).min(tl.dist_to(bl)) < self.min_code_size {
+                hints.push(Hint::MoveCloser);
+            }
+        }
+        hints
+    }
+
+    /// Just the exposure hints for a set of measurements
+    pub fn exposure_hints(&self, stats: &FrameStats) -> Vec<Hint> {
+        let mut hints = Vec::new();
+        if stats.dark > self.max_dark {
+            hints.push(Hint::DecreaseExposure);
+        } else if stats.bright < self.min_bright {
+            hints.push(Hint::IncreaseExposure);
+        } else if stats.bright.saturating_sub(stats.dark) < self.min_contrast {
+            hints.push(if stats.mean > 128.0 { Hint::DecreaseExposure } else { Hint::IncreaseExposure });
+        }
+        hints
+    }
+}
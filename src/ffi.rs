@@ -0,0 +1,232 @@
+//! A C interface, for linking arqr into C and C++ vision pipelines. The
+//! declarations are in `include/arqr.h`, generated from this module with
+//! `cbindgen --config cbindgen.toml --output include/arqr.h`.
+//!
+//! Build a shared (or static) library with
+//!
+//! ```text
+//! cargo rustc --lib --release --no-default-features --features ffi --crate-type cdylib
+//! cargo rustc --lib --release --no-default-features --features ffi --crate-type staticlib
+//! ```
+//!
+//! A scanner is made with `arqr_scanner_new`, used for any number of frames
+//! (from one thread at a time), and given back with `arqr_scanner_free`.
+//!
+//! A decoded payload stays owned by arqr: `ArqrResult::payload` points into
+//! the scanner, and is only valid until the scanner's next scan or until
+//! it's freed. Copy it out before then to keep it.
+//!
+//! On iOS, AVFoundation's `CVPixelBuffer`s can be scanned in place, without
+//! repacking: `kCVPixelFormatType_32BGRA` buffers with `arqr_scan_bgra`,
+//! and the bi-planar (NV12) `420YpCbCr8BiPlanar` formats by handing plane
+//! 0, the luma, to `arqr_scan_gray`. Either way the stride is the buffer's
+//! `CVPixelBufferGetBytesPerRow(OfPlane)`, and the buffer has to stay
+//! locked for the call. `ios/Arqr` has a Swift wrapper that does all that,
+//! and hands back each code's corners, payload, version and error
+//! correction level.
+
+use std::slice;
+use image::{Pixel, Rgb};
+use crate::{ScanResult, Scanner, encode::EcLevel};
+
+/// Returned by the `arqr_scan_*` functions when the frame was scanned
+pub const ARQR_OK: i32 = 0;
+/// Returned by the `arqr_scan_*` functions for a null pointer, an empty
+/// frame or a stride shorter than a row
+pub const ARQR_BAD_ARGUMENT: i32 = -1;
+
+/// `ArqrResult::ec_level` for a code whose error correction level couldn't
+/// be read
+pub const ARQR_EC_UNKNOWN: i32 = -1;
+/// `ArqrResult::ec_level` for each error correction level, from least to
+/// most redundant
+pub const ARQR_EC_L: i32 = 0;
+pub const ARQR_EC_M: i32 = 1;
+pub const ARQR_EC_Q: i32 = 2;
+pub const ARQR_EC_H: i32 = 3;
+
+/// What `arqr_scan_gray` or `arqr_scan_bgra` found in a frame
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ArqrResult {
+    /// 1 if a code was found, 0 if not
+    pub found: u32,
+    /// The code's corners in pixels, as x, y pairs: top-left, top-right,
+    /// bottom-right, bottom-left. Zero if no code was found.
+    pub corners: [f64; 8],
+    /// Number of position targets found, whether or not they made a code
+    pub targets: u32,
+    /// Time taken to scan, in milliseconds
+    pub scan_ms: f64,
+    /// The code's message, `payload_len` bytes long, or null if it couldn't
+    /// be read. It's the bytes that were encoded, so it's only text if text
+    /// was encoded, and it isn't NUL-terminated. Owned by the scanner: valid
+    /// until its next scan or until it's freed.
+    pub payload: *const u8,
+    pub payload_len: usize,
+    /// The code's version, from 1 to 40, or 0 if it wasn't sampled
+    pub version: u32,
+    /// One of the `ARQR_EC_*` levels, or `ARQR_EC_UNKNOWN` if the code's
+    /// format information couldn't be read
+    pub ec_level: i32,
+}
+
+impl Default for ArqrResult {
+    fn default() -> Self {
+        Self {
+            found: 0,
+            corners: [0.0; 8],
+            targets: 0,
+            scan_ms: 0.0,
+            payload: std::ptr::null(),
+            payload_len: 0,
+            version: 0,
+            ec_level: ARQR_EC_UNKNOWN,
+        }
+    }
+}
+
+/// A scanner, along with the payload of its last scan for `ArqrResult` to
+/// point into
+#[derive(Debug, Default)]
+pub struct ArqrScanner {
+    scanner: Scanner,
+    payload: Vec<u8>,
+}
+
+/// Makes a scanner with the default settings. Free it with
+/// `arqr_scanner_free`.
+#[no_mangle]
+pub extern "C" fn arqr_scanner_new() -> *mut ArqrScanner {
+    Box::into_raw(Box::new(ArqrScanner { scanner: Scanner::new(), payload: Vec::new() }))
+}
+
+/// Frees a scanner from `arqr_scanner_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `scanner` has to have come from `arqr_scanner_new`, and not been freed
+/// already.
+#[no_mangle]
+pub unsafe extern "C" fn arqr_scanner_free(scanner: *mut ArqrScanner) {
+    if !scanner.is_null() {
+        drop(Box::from_raw(scanner));
+    }
+}
+
+/// How many bytes a `width` by `height` frame with `bytes_per_px` byte
+/// pixels and rows `stride` bytes apart takes up, or `None` if it's empty or
+/// the stride is shorter than a row. The last row doesn't need padding out to
+/// the stride.
+pub(crate) fn frame_len(width: u32, height: u32, stride: u32, bytes_per_px: u32) -> Option<usize> {
+    let row_len = width as usize * bytes_per_px as usize;
+    if width == 0 || height == 0 || (stride as usize) < row_len {
+        return None;
+    }
+    Some(stride as usize * (height as usize - 1) + row_len)
+}
+
+/// The lumas of a greyscale frame, one byte per pixel, with rows `stride`
+/// bytes apart. `pixels` has to be `frame_len` long.
+pub(crate) fn gray_lumas(pixels: &[u8], width: u32, stride: u32) -> impl Iterator<Item = u8> + Clone + '_ {
+    let w = width as usize;
+    pixels.chunks(stride as usize).flat_map(move |row| row[..w].iter().copied())
+}
+
+/// The lumas of a BGRA frame, four bytes per pixel, with rows `stride` bytes
+/// apart. Alpha is ignored. `pixels` has to be `frame_len` long.
+pub(crate) fn bgra_lumas(pixels: &[u8], width: u32, stride: u32) -> impl Iterator<Item = u8> + Clone + '_ {
+    let row_len = width as usize * 4;
+    pixels.chunks(stride as usize)
+        .flat_map(move |row| row[..row_len].chunks_exact(4))
+        .map(|bgra| Rgb([bgra[2], bgra[1], bgra[0]]).to_luma().0[0])
+}
+
+/// Fills in `out` from a scan, keeping its payload in `scanner`
+unsafe fn report(scanner: &mut ArqrScanner, result: ScanResult, out: *mut ArqrResult) {
+    let mut report = ArqrResult {
+        targets: result.targets.len() as u32,
+        scan_ms: result.timings.total().as_secs_f64() * 1000.0,
+        version: result.version.unwrap_or(0),
+        ec_level: result.format.map_or(ARQR_EC_UNKNOWN, |format| match format.ec_level {
+            EcLevel::L => ARQR_EC_L,
+            EcLevel::M => ARQR_EC_M,
+            EcLevel::Q => ARQR_EC_Q,
+            EcLevel::H => ARQR_EC_H,
+        }),
+        ..Default::default()
+    };
+    if let Some(corners) = result.quad() {
+        report.found = 1;
+        for (i, p) in corners.iter().enumerate() {
+            report.corners[i * 2] = p.x;
+            report.corners[i * 2 + 1] = p.y;
+        }
+    }
+    scanner.payload.clear();
+    if let Some(payload) = &result.payload {
+        scanner.payload.extend_from_slice(payload);
+        report.payload = scanner.payload.as_ptr();
+        report.payload_len = scanner.payload.len();
+    }
+    *out = report;
+}
+
+/// Scans a `width` by `height` greyscale frame, one byte per pixel, with rows
+/// `stride` bytes apart. Fills in `out` and returns `ARQR_OK`, or returns
+/// `ARQR_BAD_ARGUMENT` without touching `out`.
+///
+/// # Safety
+///
+/// `scanner` has to be a live scanner from `arqr_scanner_new`, not in use on
+/// another thread. `pixels` has to point to `stride * (height - 1) + width`
+/// readable bytes, and `out` to an `ArqrResult`.
+#[no_mangle]
+pub unsafe extern "C" fn arqr_scan_gray(
+    scanner: *mut ArqrScanner,
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    stride: u32,
+    out: *mut ArqrResult,
+) -> i32 {
+    let len = match frame_len(width, height, stride, 1) {
+        Some(len) if !scanner.is_null() && !pixels.is_null() && !out.is_null() => len,
+        _ => return ARQR_BAD_ARGUMENT,
+    };
+    let pixels = slice::from_raw_parts(pixels, len);
+    let scanner = &mut *scanner;
+    let result = scanner.scanner.scan_lumas(gray_lumas(pixels, width, stride), width, height);
+    report(scanner, result, out);
+    ARQR_OK
+}
+
+/// Scans a `width` by `height` BGRA frame, four bytes per pixel (blue, green,
+/// red, then alpha, which is ignored), with rows `stride` bytes apart. Fills
+/// in `out` and returns `ARQR_OK`, or returns `ARQR_BAD_ARGUMENT` without
+/// touching `out`.
+///
+/// # Safety
+///
+/// `scanner` has to be a live scanner from `arqr_scanner_new`, not in use on
+/// another thread. `pixels` has to point to `stride * (height - 1) + width *
+/// 4` readable bytes, and `out` to an `ArqrResult`.
+#[no_mangle]
+pub unsafe extern "C" fn arqr_scan_bgra(
+    scanner: *mut ArqrScanner,
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    stride: u32,
+    out: *mut ArqrResult,
+) -> i32 {
+    let len = match frame_len(width, height, stride, 4) {
+        Some(len) if !scanner.is_null() && !pixels.is_null() && !out.is_null() => len,
+        _ => return ARQR_BAD_ARGUMENT,
+    };
+    let pixels = slice::from_raw_parts(pixels, len);
+    let scanner = &mut *scanner;
+    let result = scanner.scanner.scan_lumas(bgra_lumas(pixels, width, stride), width, height);
+    report(scanner, result, out);
+    ARQR_OK
+}
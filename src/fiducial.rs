@@ -0,0 +1,450 @@
+//! Detects square binary fiducial markers, in the style of ArUco and
+//! AprilTag: a black square border around a grid of black and white cells
+//! that spells out the marker's ID. They're much simpler than QR codes, so
+//! they can be found smaller, further away and faster, but only carry an ID.
+//!
+//! Markers are found by looking for dark blobs in the binarized image, fitting
+//! a quad to each, sampling the grid of cells through the quad's homography
+//! and looking the bits up in a `Dictionary`, correcting a few wrong bits if
+//! needed.
+
+use image::{GrayImage, Luma};
+use crate::{
+    Point,
+    bitmap::Bitmap,
+    calib::CameraIntrinsics,
+    homography::Homography,
+    pose::Pose,
+};
+
+/// The set of markers a detector knows about. Each code is a `grid` x `grid`
+/// block of bits, stored row-major with the top-left cell in the most
+/// significant bit, where 1 is a white cell and 0 a black one.
+#[derive(Clone, Debug)]
+pub struct Dictionary {
+    grid: u32,
+    codes: Vec<u64>,
+    max_correction: u32,
+}
+
+/// 50 4x4 markers, any two at least 4 bits apart, so one wrong bit can be
+/// corrected. Generated with `Dictionary::generate(4, 50, 4)`, but fixed here
+/// so printed markers keep working if the generator changes.
+const ARQR_4X4_50: [u64; 50] = [
+    0xabe0, 0xa0b7, 0x1c36, 0x678e, 0xded0, 0x916d, 0x0b0f, 0x26d9, 0xf995, 0xb526,
+    0x39cc, 0x3abf, 0x34dc, 0x05cb, 0xcf00, 0x4955, 0x7087, 0x671d, 0xc36e, 0x7aca,
+    0xe3bf, 0x629a, 0xad88, 0xca65, 0xe5da, 0x8e5a, 0xfa35, 0x4c42, 0x7889, 0x8798,
+    0x8cd6, 0xf1e8, 0xe2a8, 0x9ddf, 0xdd24, 0xe73a, 0xcc74, 0x88ec, 0x547f, 0xc317,
+    0x1679, 0x7bed, 0xf678, 0x210b, 0x1558, 0x8b29, 0xb1af, 0x5b2a, 0x90e0, 0x0c45,
+];
+
+impl Default for Dictionary {
+    /// The built-in 4x4 dictionary of 50 markers
+    fn default() -> Self {
+        Self { grid: 4, codes: ARQR_4X4_50.to_vec(), max_correction: 1 }
+    }
+}
+
+/// Rotates a code's grid a quarter turn anticlockwise
+fn rotate(code: u64, grid: u32) -> u64 {
+    let n = grid as usize;
+    let bit = |code: u64, row: usize, col: usize| (code >> (n * n - 1 - (row * n + col))) & 1;
+    let mut out = 0;
+    for row in 0..n {
+        for col in 0..n {
+            out = (out << 1) | bit(code, col, n - 1 - row);
+        }
+    }
+    out
+}
+
+/// A code in all four rotations
+fn rotations(code: u64, grid: u32) -> [u64; 4] {
+    let mut out = [code; 4];
+    for i in 1..4 {
+        out[i] = rotate(out[i - 1], grid);
+    }
+    out
+}
+
+impl Dictionary {
+    /// Creates a dictionary from your own codes, e.g. to recognize an
+    /// existing marker set. See `Dictionary` for how codes are laid out.
+    ///
+    /// `min_distance` is the smallest number of bits any two codes differ by
+    /// (in any rotation), and decides how many bits can be corrected. Returns
+    /// `None` if the grid is bigger than 8x8, a code doesn't fit in the grid,
+    /// or the codes are actually closer together than `min_distance`.
+    pub fn new(grid: u32, codes: Vec<u64>, min_distance: u32) -> Option<Self> {
+        let bits = grid * grid;
+        if !(1..=64).contains(&bits) {
+            return None;
+        }
+        let mask = if bits == 64 { u64::MAX } else { (1 << bits) - 1 };
+        if codes.iter().any(|&c| c & !mask != 0) {
+            return None;
+        }
+
+        let rotated: Vec<[u64; 4]> = codes.iter().map(|&c| rotations(c, grid)).collect();
+        for (i, &code) in codes.iter().enumerate() {
+            let too_close = rotated[i][1..].iter()
+                .chain(rotated[i + 1..].iter().flatten())
+                .any(|&other| (code ^ other).count_ones() < min_distance);
+            if too_close {
+                return None;
+            }
+        }
+
+        Some(Self { grid, codes, max_correction: min_distance.saturating_sub(1) / 2 })
+    }
+
+    /// Deterministically picks up to `count` codes on a `grid` x `grid` grid,
+    /// each at least `min_distance` bits away from every other code in every
+    /// rotation, and from its own rotations (so a marker's orientation is
+    /// never ambiguous). Returns fewer codes if it can't find enough.
+    pub fn generate(grid: u32, count: usize, min_distance: u32) -> Self {
+        let bits = grid * grid;
+        assert!((1..=64).contains(&bits), "grid must be between 1x1 and 8x8");
+        let mask = if bits == 64 { u64::MAX } else { (1 << bits) - 1 };
+
+        // xorshift64, so the same arguments always give the same markers
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut codes: Vec<u64> = Vec::with_capacity(count);
+        // Every rotation of every code picked so far
+        let mut taken: Vec<u64> = Vec::with_capacity(count * 4);
+        for _ in 0..1_000_000 {
+            if codes.len() >= count {
+                break;
+            }
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let code = state & mask;
+
+            // Mostly-black codes blend into the border, and mostly-white
+            // ones make a flimsy blob to detect
+            let ones = code.count_ones();
+            if ones < bits / 4 || ones > bits * 3 / 4 {
+                continue;
+            }
+            let rotated = rotations(code, grid);
+            if rotated[1..].iter().any(|&r| (code ^ r).count_ones() < min_distance) {
+                continue;
+            }
+            if taken.iter().all(|&c| (code ^ c).count_ones() >= min_distance) {
+                codes.push(code);
+                taken.extend(rotated);
+            }
+        }
+
+        Self { grid, codes, max_correction: min_distance.saturating_sub(1) / 2 }
+    }
+
+    /// Number of cells along each side of the marker's bit grid, not counting
+    /// the border
+    pub fn grid(&self) -> u32 {
+        self.grid
+    }
+
+    pub fn codes(&self) -> &[u64] {
+        &self.codes
+    }
+
+    /// Most wrong bits that will be corrected when reading a marker
+    pub fn max_correction(&self) -> u32 {
+        self.max_correction
+    }
+
+    /// Finds the marker matching `bits` as read from the image, in any
+    /// rotation. Returns the marker's ID, how many quarter turns
+    /// anticlockwise the bits were rotated to match, and how many bits were
+    /// wrong.
+    pub fn lookup(&self, bits: u64) -> Option<(u32, u32, u32)> {
+        let mut best: Option<(u32, u32, u32)> = None;
+        let mut rotated = bits;
+        for rotation in 0..4 {
+            for (id, &code) in self.codes.iter().enumerate() {
+                let errors = (rotated ^ code).count_ones();
+                if errors <= self.max_correction && best.is_none_or(|b| errors < b.2) {
+                    best = Some((id as u32, rotation, errors));
+                }
+            }
+            rotated = rotate(rotated, self.grid);
+        }
+        best
+    }
+
+    /// Draws marker `id` for printing, with each cell `cell_px` pixels wide
+    /// and a one-cell white margin around the border
+    pub fn render(&self, id: u32, cell_px: u32) -> Option<GrayImage> {
+        let code = *self.codes.get(id as usize)?;
+        let n = self.grid;
+        let cells = n + 4;
+        Some(GrayImage::from_fn(cells * cell_px, cells * cell_px, |x, y| {
+            let (col, row) = (x / cell_px, y / cell_px);
+            let white = if col == 0 || row == 0 || col == cells - 1 || row == cells - 1 {
+                true
+            } else if col == 1 || row == 1 || col == cells - 2 || row == cells - 2 {
+                false
+            } else {
+                let i = (row - 2) * n + (col - 2);
+                (code >> (n * n - 1 - i)) & 1 == 1
+            };
+            Luma([if white { 255 } else { 0 }])
+        }))
+    }
+}
+
+/// A marker found in an image
+#[derive(Clone, Copy, Debug)]
+pub struct Marker {
+    /// Which of the detector's dictionaries the marker is from, in the order
+    /// they were added (the one it was created with is 0)
+    pub dictionary: usize,
+    /// Index of the marker in its dictionary
+    pub id: u32,
+    /// Outer corners of the marker's border, starting at the marker's own
+    /// top-left and going clockwise, whichever way up it is in the image
+    pub corners: [Point<f64>; 4],
+    /// Number of bits that had to be corrected
+    pub errors: u32,
+}
+
+impl Marker {
+    /// Pose of the marker, given its physical side length (border included).
+    /// See `Pose::from_quad`.
+    pub fn pose(&self, intrinsics: &CameraIntrinsics, size: f64) -> Option<Pose> {
+        Pose::from_quad(self.corners, size, intrinsics)
+    }
+
+    /// Estimated distance to the marker, given its physical side length
+    /// (border included). See `CameraIntrinsics::distance_to_quad`.
+    pub fn distance(&self, intrinsics: &CameraIntrinsics, size: f64) -> f64 {
+        intrinsics.distance_to_quad(self.corners, size)
+    }
+}
+
+/// Finds markers from one or more `Dictionary`s in binarized images
+#[derive(Clone, Debug)]
+pub struct FiducialDetector {
+    dictionaries: Vec<Dictionary>,
+    /// Smallest marker to look for, in pixels along its bounding box's
+    /// longer side. Each cell needs a couple of pixels to be read reliably.
+    pub min_size: u32,
+    /// How many border cells may be read as white before a quad is rejected
+    pub max_border_errors: u32,
+}
+
+impl Default for FiducialDetector {
+    fn default() -> Self {
+        Self::new(Dictionary::default())
+    }
+}
+
+/// A connected blob of black pixels
+struct Blob {
+    pixels: Vec<(u32, u32)>,
+    min: (u32, u32),
+    max: (u32, u32),
+}
+
+impl FiducialDetector {
+    pub fn new(dictionary: Dictionary) -> Self {
+        let min_size = (dictionary.grid + 2) * 2;
+        Self { dictionaries: vec![dictionary], min_size, max_border_errors: 1 }
+    }
+
+    /// Adds another dictionary to look for markers from, returning the index
+    /// that its markers' `Marker::dictionary` will have. If a marker could be
+    /// from more than one dictionary, the one with fewest wrong bits wins,
+    /// then the one added first.
+    pub fn register(&mut self, dictionary: Dictionary) -> usize {
+        self.dictionaries.push(dictionary);
+        self.dictionaries.len() - 1
+    }
+
+    pub fn dictionaries(&self) -> &[Dictionary] {
+        &self.dictionaries
+    }
+
+    /// Finds every marker in `bmp`
+    pub fn detect(&self, bmp: &Bitmap) -> Vec<Marker> {
+        let mut markers = Vec::new();
+        for_each_blob(bmp, |blob| {
+            let (w, h) = (blob.max.0 - blob.min.0 + 1, blob.max.1 - blob.min.1 + 1);
+            if w.max(h) < self.min_size {
+                return;
+            }
+            // A marker's border alone covers over half its area, and its
+            // bounding box is at most twice its area
+            if (blob.pixels.len() as u64) * 4 < (w as u64) * (h as u64) {
+                return;
+            }
+            if let Some(marker) = fit_quad(&blob.pixels).and_then(|quad| self.read_marker(bmp, quad)) {
+                markers.push(marker);
+            }
+        });
+        markers
+    }
+
+    /// Samples the cells inside `quad` and looks them up in every dictionary
+    fn read_marker(&self, bmp: &Bitmap, quad: [Point<f64>; 4]) -> Option<Marker> {
+        // Dictionaries usually share grid sizes, so only sample each size once
+        let mut sampled: Vec<(u32, Option<u64>)> = Vec::new();
+        let mut best: Option<(usize, u32, u32, u32)> = None;
+        for (index, dictionary) in self.dictionaries.iter().enumerate() {
+            let grid = dictionary.grid;
+            let bits = match sampled.iter().find(|(g, _)| *g == grid) {
+                Some(&(_, bits)) => bits,
+                None => {
+                    let bits = self.read_bits(bmp, quad, grid);
+                    sampled.push((grid, bits));
+                    bits
+                }
+            };
+            if let Some((id, rotation, errors)) = bits.and_then(|bits| dictionary.lookup(bits)) {
+                if best.is_none_or(|b| errors < b.3) {
+                    best = Some((index, id, rotation, errors));
+                }
+            }
+        }
+
+        let (dictionary, id, rotation, errors) = best?;
+        // Turning the bits anticlockwise `rotation` times made them match, so
+        // the marker's own top-left is that many corners clockwise from the
+        // image's
+        let r = rotation as usize;
+        let corners = [0, 1, 2, 3].map(|i| quad[(i + r) % 4]);
+        Some(Marker { dictionary, id, corners, errors })
+    }
+
+    /// Reads the bits of a marker with a `grid` x `grid` bit grid from inside
+    /// `quad`, or `None` if the quad doesn't have a black border
+    fn read_bits(&self, bmp: &Bitmap, quad: [Point<f64>; 4], grid: u32) -> Option<u64> {
+        let n = grid;
+        let cells = (n + 2) as f64;
+        let square = [
+            Point::new(0.0, 0.0),
+            Point::new(cells, 0.0),
+            Point::new(cells, cells),
+            Point::new(0.0, cells),
+        ];
+        let h = Homography::from_points(&square, &quad)?;
+
+        // Majority vote over a few points in each cell, which keeps blurry
+        // edges between cells from flipping bits
+        let offsets = [(0.5, 0.5), (0.3, 0.3), (0.7, 0.3), (0.3, 0.7), (0.7, 0.7)];
+        let cell_is_white = |col: u32, row: u32| -> Option<bool> {
+            let mut white = 0;
+            for (dx, dy) in offsets {
+                let p = h.apply(Point::new(col as f64 + dx, row as f64 + dy));
+                if p.x < 0.0 || p.y < 0.0 {
+                    return None;
+                }
+                if *bmp.get_pixel_checked(p.x as u32, p.y as u32)? {
+                    white += 1;
+                }
+            }
+            Some(white * 2 > offsets.len())
+        };
+
+        let mut border_errors = 0;
+        let mut bits = 0u64;
+        for row in 0..n + 2 {
+            for col in 0..n + 2 {
+                let white = cell_is_white(col, row)?;
+                if row == 0 || col == 0 || row == n + 1 || col == n + 1 {
+                    border_errors += white as u32;
+                    if border_errors > self.max_border_errors {
+                        return None;
+                    }
+                } else {
+                    bits = (bits << 1) | white as u64;
+                }
+            }
+        }
+        Some(bits)
+    }
+}
+
+/// Finds every 4-connected blob of black pixels and passes it to `f`
+fn for_each_blob<F: FnMut(&Blob)>(bmp: &Bitmap, mut f: F) {
+    let (width, height) = bmp.dimensions();
+    let mut seen = vec![false; bmp.len()];
+    let mut stack = Vec::new();
+    let mut blob = Blob { pixels: Vec::new(), min: (0, 0), max: (0, 0) };
+
+    for start in 0..bmp.len() {
+        if bmp[start] || seen[start] {
+            continue;
+        }
+        blob.pixels.clear();
+        let (sx, sy) = (start as u32 % width, start as u32 / width);
+        blob.min = (sx, sy);
+        blob.max = (sx, sy);
+        seen[start] = true;
+        stack.push((sx, sy));
+
+        while let Some((x, y)) = stack.pop() {
+            blob.pixels.push((x, y));
+            blob.min = (blob.min.0.min(x), blob.min.1.min(y));
+            blob.max = (blob.max.0.max(x), blob.max.1.max(y));
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for (nx, ny) in neighbours {
+                if nx >= width || ny >= height {
+                    continue;
+                }
+                let i = (ny * width + nx) as usize;
+                if !bmp[i] && !seen[i] {
+                    seen[i] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+        f(&blob);
+    }
+}
+
+/// Fits a quad around a blob by finding its four most extreme points.
+/// Returns the corners clockwise (in image coordinates) or `None` if the
+/// blob isn't very quad-shaped.
+fn fit_quad(pixels: &[(u32, u32)]) -> Option<[Point<f64>; 4]> {
+    let points = || pixels.iter().map(|&(x, y)| Point::new(x as f64 + 0.5, y as f64 + 0.5));
+    let count = pixels.len() as f64;
+    let center = Point::new(
+        points().map(|p| p.x).sum::<f64>() / count,
+        points().map(|p| p.y).sum::<f64>() / count,
+    );
+    let farthest_from = |q: Point<f64>| points().max_by(|a, b| a.dist_to(q).total_cmp(&b.dist_to(q))).unwrap();
+
+    // Two opposite corners...
+    let a = farthest_from(center);
+    let c = farthest_from(a);
+    // ...then the points furthest from the diagonal between them, on each side
+    let side = |p: Point<f64>| (c.x - a.x) * (p.y - a.y) - (c.y - a.y) * (p.x - a.x);
+    let b = points().max_by(|p, q| side(*p).total_cmp(&side(*q))).unwrap();
+    let d = points().min_by(|p, q| side(*p).total_cmp(&side(*q))).unwrap();
+
+    // For a square, both are half the diagonal away from it
+    let diag = a.dist_to(c);
+    let (db, dd) = (side(b) / diag, -side(d) / diag);
+    if db < diag * 0.25 || dd < diag * 0.25 {
+        return None;
+    }
+
+    // Pixel centres are half a pixel inside the blob's outline
+    let quad_center = Point::new((a.x + b.x + c.x + d.x) / 4.0, (a.y + b.y + c.y + d.y) / 4.0);
+    let grow = |p: Point<f64>| {
+        let len = quad_center.dist_to(p);
+        let s = (len + std::f64::consts::FRAC_1_SQRT_2) / len;
+        Point::new(quad_center.x + (p.x - quad_center.x) * s, quad_center.y + (p.y - quad_center.y) * s)
+    };
+    // `side` is positive for b, which puts it anticlockwise of the diagonal
+    // with y pointing down, so go a -> d -> c -> b to run clockwise
+    Some([a, d, c, b].map(grow))
+}
@@ -217,3 +217,78 @@ where
         px.apply_without_alpha(|_| luma);
     }
 }
+
+/// Contrast-limited adaptive histogram equalization (CLAHE). Equalizes each
+/// tile of a `tiles` x `tiles` grid separately, so shadowed and brightly lit
+/// parts of the image both get stretched to full contrast. Histogram bins are
+/// clipped at `clip_limit` times their average height first, so flat areas
+/// don't have their noise blown up, and each pixel blends the mappings of the
+/// four nearest tiles so there are no seams.
+pub fn clahe_in_place<Px, C>(img: &mut ImageBuffer<Px, C>, tiles: u32, clip_limit: f64)
+where
+    Px: Pixel<Subpixel = u8>,
+    C: Deref<Target = [u8]> + DerefMut,
+{
+    let (width, height) = img.dimensions();
+    let tiles = tiles.clamp(1, width.min(height).max(1));
+    if width == 0 || height == 0 {
+        return;
+    }
+    let tile_w = width.div_ceil(tiles);
+    let tile_h = height.div_ceil(tiles);
+
+    // Lookup table mapping luma to equalized luma, for each tile
+    let mut luts = vec![[0u8; 0x100]; (tiles * tiles) as usize];
+    for ty in 0..tiles {
+        for tx in 0..tiles {
+            let (x0, y0) = (tx * tile_w, ty * tile_h);
+            let (x1, y1) = ((x0 + tile_w).min(width), (y0 + tile_h).min(height));
+            let mut histo = [0u32; 0x100];
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    histo[img.get_pixel(x, y).to_luma().0[0] as usize] += 1;
+                }
+            }
+            let count = (x1.saturating_sub(x0) * y1.saturating_sub(y0)).max(1);
+
+            // Clip, then share what was cut off evenly between every bin
+            let limit = ((clip_limit * count as f64 / 256.0) as u32).max(1);
+            let mut excess = 0;
+            for bin in histo.iter_mut() {
+                if *bin > limit {
+                    excess += *bin - limit;
+                    *bin = limit;
+                }
+            }
+            let share = excess / 256;
+            let mut cdf = 0;
+            let lut = &mut luts[(ty * tiles + tx) as usize];
+            for (bin, out) in histo.iter().zip(lut.iter_mut()) {
+                cdf += bin + share;
+                *out = (cdf as u64 * 255 / count as u64).min(255) as u8;
+            }
+        }
+    }
+
+    // Position of a pixel between tile centres: the tile before it, the one
+    // after, and how far along it is
+    let between = |pos: u32, size: u32| {
+        let f = ((pos as f64 + 0.5) / size as f64 - 0.5).max(0.0);
+        let a = (f as u32).min(tiles - 1);
+        let b = (a + 1).min(tiles - 1);
+        (a, b, (f - a as f64).min(1.0))
+    };
+    for y in 0..height {
+        let (ya, yb, wy) = between(y, tile_h);
+        for x in 0..width {
+            let (xa, xb, wx) = between(x, tile_w);
+            let px = img.get_pixel_mut(x, y);
+            let luma = px.to_luma().0[0] as usize;
+            let at = |tx: u32, ty: u32| luts[(ty * tiles + tx) as usize][luma] as f64;
+            let top = at(xa, ya) * (1.0 - wx) + at(xb, ya) * wx;
+            let bottom = at(xa, yb) * (1.0 - wx) + at(xb, yb) * wx;
+            let val = (top * (1.0 - wy) + bottom * wy).round() as u8;
+            px.apply_without_alpha(|_| val);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Lightweight pyramidal Lucas-Kanade optical flow, for following a code's
+//! corners through the frames in between full scans.
+
+use image::GrayImage;
+use crate::Point;
+
+/// Tuning knobs for `track_points`
+#[derive(Clone, Copy, Debug)]
+pub struct FlowParams {
+    /// Half the width of the square window matched around each point
+    pub half_window: i32,
+    /// Number of pyramid levels. Each extra level roughly doubles the largest
+    /// motion that can be followed.
+    pub levels: u32,
+    /// Maximum refinement steps per pyramid level
+    pub iterations: u32,
+    /// Stop refining once a step moves less than this many pixels
+    pub epsilon: f64,
+    /// Points whose window has less texture than this (smallest eigenvalue of
+    /// the gradient matrix, per pixel) are considered lost
+    pub min_eigen: f64,
+}
+
+impl Default for FlowParams {
+    fn default() -> Self {
+        Self { half_window: 7, levels: 3, iterations: 10, epsilon: 0.03, min_eigen: 1.0 }
+    }
+}
+
+/// A frame and successively half-sized copies of it
+#[derive(Clone, Debug)]
+pub struct Pyramid {
+    levels: Vec<GrayImage>,
+}
+
+impl Pyramid {
+    pub fn new(img: &GrayImage, levels: u32) -> Self {
+        let mut pyr = vec![img.clone()];
+        for _ in 1..levels.max(1) {
+            let last = pyr.last().unwrap();
+            let (w, h) = (last.width() / 2, last.height() / 2);
+            if w < 8 || h < 8 { break; }
+            let next = GrayImage::from_fn(w, h, |x, y| {
+                let sum = [(0, 0), (1, 0), (0, 1), (1, 1)].iter()
+                    .map(|(dx, dy)| last.get_pixel(x * 2 + dx, y * 2 + dy).0[0] as u32)
+                    .sum::<u32>();
+                image::Luma([(sum / 4) as u8])
+            });
+            pyr.push(next);
+        }
+        Self { levels: pyr }
+    }
+}
+
+/// Bilinearly samples an image, clamping to its edges
+#[inline]
+fn sample(img: &GrayImage, x: f64, y: f64) -> f64 {
+    let max_x = (img.width() - 1) as f64;
+    let max_y = (img.height() - 1) as f64;
+    let x = x.clamp(0.0, max_x);
+    let y = y.clamp(0.0, max_y);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as u32, y0 as u32);
+    let x1 = (x0 + 1).min(img.width() - 1);
+    let y1 = (y0 + 1).min(img.height() - 1);
+    let px = |x, y| img.get_pixel(x, y).0[0] as f64;
+    let top = px(x0, y0) * (1.0 - fx) + px(x1, y0) * fx;
+    let bot = px(x0, y1) * (1.0 - fx) + px(x1, y1) * fx;
+    top * (1.0 - fy) + bot * fy
+}
+
+/// Tracks one point from `prev` to `next`, starting from a guessed
+/// displacement. Returns the refined displacement, or `None` if the window
+/// around the point is too featureless to track.
+fn track_level(
+    prev: &GrayImage,
+    next: &GrayImage,
+    pt: Point<f64>,
+    guess: Point<f64>,
+    params: &FlowParams,
+) -> Option<Point<f64>> {
+    let hw = params.half_window;
+    let n = ((2 * hw + 1) * (2 * hw + 1)) as f64;
+
+    // Spatial gradient matrix over the window in the previous frame
+    let mut grads = Vec::with_capacity(n as usize);
+    let (mut gxx, mut gxy, mut gyy) = (0.0, 0.0, 0.0);
+    for dy in -hw..=hw {
+        for dx in -hw..=hw {
+            let (x, y) = (pt.x + dx as f64, pt.y + dy as f64);
+            let ix = (sample(prev, x + 1.0, y) - sample(prev, x - 1.0, y)) / 2.0;
+            let iy = (sample(prev, x, y + 1.0) - sample(prev, x, y - 1.0)) / 2.0;
+            gxx += ix * ix;
+            gxy += ix * iy;
+            gyy += iy * iy;
+            grads.push((sample(prev, x, y), ix, iy));
+        }
+    }
+
+    let det = gxx * gyy - gxy * gxy;
+    let min_eigen = ((gxx + gyy) - ((gxx - gyy).powi(2) + 4.0 * gxy * gxy).sqrt()) / 2.0;
+    if det.abs() < f64::EPSILON || min_eigen / n < params.min_eigen {
+        return None;
+    }
+
+    let mut v = guess;
+    for _ in 0..params.iterations {
+        let (mut bx, mut by) = (0.0, 0.0);
+        let mut i = 0;
+        for dy in -hw..=hw {
+            for dx in -hw..=hw {
+                let (val, ix, iy) = grads[i];
+                let moved = sample(next, pt.x + v.x + dx as f64, pt.y + v.y + dy as f64);
+                let diff = val - moved;
+                bx += diff * ix;
+                by += diff * iy;
+                i += 1;
+            }
+        }
+        // Solve G * step = b
+        let step_x = (gyy * bx - gxy * by) / det;
+        let step_y = (gxx * by - gxy * bx) / det;
+        v.x += step_x;
+        v.y += step_y;
+        if step_x.abs() < params.epsilon && step_y.abs() < params.epsilon {
+            break;
+        }
+    }
+    Some(v)
+}
+
+/// Finds where each of `points` in `prev` moved to in `next`. Points that
+/// couldn't be tracked come back as `None`.
+pub fn track_points(
+    prev: &Pyramid,
+    next: &Pyramid,
+    points: &[Point<f64>],
+    params: &FlowParams,
+) -> Vec<Option<Point<f64>>> {
+    let levels = prev.levels.len().min(next.levels.len());
+    points.iter().map(|&pt| {
+        let mut guess = Point::new(0.0, 0.0);
+        for level in (0..levels).rev() {
+            let scale = (1 << level) as f64;
+            let scaled = Point::new(pt.x / scale, pt.y / scale);
+            let flow = track_level(&prev.levels[level], &next.levels[level], scaled, guess, params)?;
+            guess = if level > 0 {
+                Point::new(flow.x * 2.0, flow.y * 2.0)
+            } else {
+                flow
+            };
+        }
+        let moved = Point::new(pt.x + guess.x, pt.y + guess.y);
+        let (w, h) = next.levels[0].dimensions();
+        let inside = moved.x >= 0.0 && moved.y >= 0.0 && moved.x < w as f64 && moved.y < h as f64;
+        if inside { Some(moved) } else { None }
+    }).collect()
+}
+
+/// Follows a code's corners from frame to frame. Reset it with the corners
+/// from each full scan, then `step` it with every frame in between.
+#[derive(Clone, Debug, Default)]
+pub struct CornerFlow {
+    pub params: FlowParams,
+    prev: Option<Pyramid>,
+    corners: Option<[Point<f64>; 3]>,
+}
+
+impl CornerFlow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Corners as of the last frame, if they're still being followed
+    pub fn corners(&self) -> Option<[Point<f64>; 3]> {
+        self.corners
+    }
+
+    /// Starts following `corners` (or stops following anything, for `None`),
+    /// which were found in `frame`
+    pub fn reset(&mut self, frame: &GrayImage, corners: Option<[Point<f64>; 3]>) {
+        self.prev = Some(Pyramid::new(frame, self.params.levels));
+        self.corners = corners;
+    }
+
+    /// Moves the corners along to a new frame. If any corner is lost, they're
+    /// all dropped until the next `reset`.
+    pub fn step(&mut self, frame: &GrayImage) -> Option<[Point<f64>; 3]> {
+        let next = Pyramid::new(frame, self.params.levels);
+        if let (Some(prev), Some(corners)) = (&self.prev, self.corners) {
+            let moved = track_points(prev, &next, &corners, &self.params);
+            self.corners = match moved[..] {
+                [Some(a), Some(b), Some(c)] => Some([a, b, c]),
+                _ => None,
+            };
+        }
+        self.prev = Some(next);
+        self.corners
+    }
+}
@@ -0,0 +1,194 @@
+//! Loads every frame of multi-frame images: animated GIFs, PNGs and WebPs,
+//! and multi-page TIFFs. With the `pdf` feature, each page of a PDF is a frame
+//! too (see `pdf`), and with the `heif` feature, each image in a HEIF or AVIF
+//! file (see `heif`). Anything else `image` can open comes back as a single
+//! frame, so callers don't need to care which kind of file they were given.
+//!
+//! JPEG and WebP photos are turned upright according to their EXIF
+//! orientation (see `exif`), so corners come out in the picture's
+//! coordinates as it's usually shown.
+
+use std::{fs::{self, File}, io::{BufReader, Cursor}, path::Path, time::Duration};
+use image::{
+    AnimationDecoder,
+    DynamicImage,
+    ImageBuffer,
+    ImageError,
+    ImageFormat,
+    ImageResult,
+    RgbaImage,
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    error::{DecodingError, ImageFormatHint},
+    io::Reader,
+};
+use tiff::{ColorType, decoder::{Decoder as TiffDecoder, DecodingResult}};
+use crate::{FrameMeta, ScanResult, Scanner, exif};
+
+pub struct Frame {
+    /// Position in the file, counting from 0
+    pub index: usize,
+    /// How long the frame is shown for, if it's part of an animation
+    pub delay: Option<Duration>,
+    pub image: RgbaImage,
+}
+
+pub type Frames = Box<dyn Iterator<Item = ImageResult<Frame>>>;
+
+/// Opens an image file and returns an iterator over its frames. Frames are
+/// decoded as they're iterated over, so long animations aren't held in
+/// memory all at once.
+pub fn open_frames<P: AsRef<Path>>(path: P) -> ImageResult<Frames> {
+    let path = path.as_ref();
+    #[cfg(feature = "pdf")]
+    if crate::pdf::is_pdf(path) {
+        let pages = crate::pdf::PdfPages::open(path)?.map(|page| {
+            let page = page?;
+            Ok(Frame { index: page.index, delay: None, image: DynamicImage::ImageLuma8(page.image).to_rgba8() })
+        });
+        return Ok(Box::new(pages));
+    }
+    #[cfg(feature = "heif")]
+    if crate::heif::is_heif(path) {
+        let images = crate::heif::HeifImages::open(path)?.enumerate().map(|(index, image)| {
+            Ok(Frame { index, delay: None, image: image? })
+        });
+        return Ok(Box::new(images));
+    }
+    let format = Reader::open(path)?.with_guessed_format()?.format();
+    let reader = || -> ImageResult<_> { Ok(BufReader::new(File::open(path)?)) };
+
+    match format {
+        Some(ImageFormat::Gif) => {
+            let frames = GifDecoder::new(reader()?)?.into_frames();
+            Ok(from_animation(frames))
+        }
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader()?)?;
+            if decoder.is_apng() {
+                Ok(from_animation(decoder.apng().into_frames()))
+            } else {
+                single_frame(DynamicImage::from_decoder(decoder)?)
+            }
+        }
+        Some(ImageFormat::Tiff) => {
+            let decoder = TiffDecoder::new(reader()?).map_err(tiff_err)?;
+            Ok(Box::new(TiffPages { decoder, index: 0, done: false }))
+        }
+        _ => {
+            let data = fs::read(path)?;
+            if format == Some(ImageFormat::WebP) && is_animated_webp(&data) {
+                let frames = WebPDecoder::new(Cursor::new(data))?.into_frames();
+                return Ok(from_animation(frames));
+            }
+            let img = match format {
+                Some(format) => image::load_from_memory_with_format(&data, format)?,
+                None => image::load_from_memory(&data)?,
+            };
+            match exif::orientation(&data) {
+                Some(orientation) => single_frame(orientation.apply(img)),
+                None => single_frame(img),
+            }
+        }
+    }
+}
+
+/// Whether a WebP's extended header has its animation flag set. Still WebPs
+/// come out of `WebPDecoder::into_frames` with no frames at all.
+fn is_animated_webp(data: &[u8]) -> bool {
+    data.len() > 20 && &data[12..16] == b"VP8X" && data[20] & 0x02 != 0
+}
+
+fn single_frame(img: DynamicImage) -> ImageResult<Frames> {
+    let frame = Frame { index: 0, delay: None, image: img.to_rgba8() };
+    Ok(Box::new(std::iter::once(Ok(frame))))
+}
+
+fn from_animation(frames: image::Frames<'static>) -> Frames {
+    Box::new(frames.enumerate().map(|(index, frame)| {
+        let frame = frame?;
+        let delay = Duration::from(frame.delay());
+        Ok(Frame { index, delay: Some(delay), image: frame.into_buffer() })
+    }))
+}
+
+fn tiff_err(err: tiff::TiffError) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(ImageFormat::Tiff), err))
+}
+
+/// `image` only ever reads the first page of a TIFF, so this walks the pages
+/// with the `tiff` crate directly
+struct TiffPages {
+    decoder: TiffDecoder<BufReader<File>>,
+    index: usize,
+    done: bool,
+}
+
+impl TiffPages {
+    fn read_page(&mut self) -> ImageResult<RgbaImage> {
+        let (width, height) = self.decoder.dimensions().map_err(tiff_err)?;
+        let color = self.decoder.colortype().map_err(tiff_err)?;
+        // Only the top byte of 16-bit samples matters for scanning
+        let data = match self.decoder.read_image().map_err(tiff_err)? {
+            DecodingResult::U8(data) => data,
+            DecodingResult::U16(data) => data.into_iter().map(|v| (v >> 8) as u8).collect(),
+            _ => return Err(unsupported(color)),
+        };
+        let img = match color {
+            ColorType::Gray(_) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8),
+            ColorType::GrayA(_) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8),
+            ColorType::RGB(_) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8),
+            ColorType::RGBA(_) => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8),
+            _ => return Err(unsupported(color)),
+        };
+        img.map(|img| img.to_rgba8()).ok_or_else(|| unsupported(color))
+    }
+}
+
+fn unsupported(color: ColorType) -> ImageError {
+    tiff_err(tiff::TiffError::UnsupportedError(tiff::TiffUnsupportedError::UnsupportedColorType(color)))
+}
+
+impl Iterator for TiffPages {
+    type Item = ImageResult<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.index > 0 {
+            if !self.decoder.more_images() {
+                self.done = true;
+                return None;
+            }
+            if let Err(e) = self.decoder.next_image() {
+                self.done = true;
+                return Some(Err(tiff_err(e)));
+            }
+        }
+
+        let index = self.index;
+        self.index += 1;
+        let page = self.read_page();
+        if page.is_err() {
+            self.done = true;
+        }
+        Some(page.map(|image| Frame { index, delay: None, image }))
+    }
+}
+
+/// Scans every frame of an image file, calling `cb` with each frame and its
+/// result as it goes
+pub fn scan_frames<P, F>(path: P, mut cb: F) -> ImageResult<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Frame, ScanResult),
+{
+    let mut scanner = Scanner::new();
+    for frame in open_frames(path)? {
+        let frame = frame?;
+        scanner.set_frame_meta(FrameMeta { sequence: frame.index as u64, timestamp: None });
+        let result = scanner.scan(&frame.image);
+        cb(&frame, result);
+    }
+    Ok(())
+}
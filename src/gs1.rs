@@ -0,0 +1,256 @@
+//! Parses GS1 Digital Link URIs, the web links retail packaging is moving to
+//! in place of raw GS1 element strings. A link like
+//!
+//! ```text
+//! https://id.example.com/01/09506000134352/10/ABC123/21/7?17=251231
+//! ```
+//!
+//! carries GS1 application identifiers (AIs) and their values: a primary key
+//! (here 01, the GTIN) and any qualifiers that narrow it down (10, the lot,
+//! and 21, the serial number) as pairs of path segments, then attributes
+//! (17, the expiry date) in the query string. The domain and anything in the
+//! path before the key are up to whoever made the link, and are kept but not
+//! interpreted.
+//!
+//! Only numeric AIs are understood, not the short names (`gtin`, `lot`, ...)
+//! from the first version of the standard.
+
+/// One application identifier and its value, percent-decoded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Element {
+    pub ai: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DigitalLink {
+    /// Host the link points at, e.g. "id.gs1.org"
+    pub domain: String,
+    /// Path segments before the primary key, usually none
+    pub prefix: Vec<String>,
+    /// The identifier the link is for. GTINs are padded to 14 digits.
+    pub primary: Element,
+    /// Qualifiers of the primary key, in the order the standard puts them
+    pub qualifiers: Vec<Element>,
+    /// Attributes from the query string, in the order they came. Query
+    /// parameters that aren't AIs (`linkType`, say) are left out.
+    pub attributes: Vec<Element>,
+}
+
+/// The AIs that can be a link's primary key, and the qualifiers each can
+/// take, in the order they have to appear in
+const PRIMARY_KEYS: &[(&str, &[&str])] = &[
+    ("00", &[]),                 // SSCC
+    ("01", &["22", "10", "21"]), // GTIN: consumer product variant, lot, serial
+    ("253", &[]),                // GDTI
+    ("255", &[]),                // GCN
+    ("401", &[]),                // GINC
+    ("402", &[]),                // GSIN
+    ("414", &["254"]),           // GLN: extension
+    ("417", &["7040"]),          // party GLN: process
+    ("8003", &[]),               // GRAI
+    ("8004", &[]),               // GIAI
+    ("8006", &["22", "10", "21"]), // ITIP
+    ("8010", &["8011"]),         // CPID: serial
+    ("8013", &[]),               // GMN
+    ("8017", &["8019"]),         // GSRN of a provider: service relation instance
+    ("8018", &["8019"]),         // GSRN of a recipient: likewise
+];
+
+impl DigitalLink {
+    /// Parses `uri`, or returns None if it isn't an http or https link with
+    /// a valid primary key in its path. Check digits of GTINs, SSCCs and
+    /// GLNs are verified.
+    pub fn parse(uri: &str) -> Option<Self> {
+        let rest = strip_prefix_ignore_case(uri, "https://")
+            .or_else(|| strip_prefix_ignore_case(uri, "http://"))?;
+        let rest = rest.split('#').next().unwrap();
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        // Drop any user info and port
+        let host = authority.rsplit('@').next().unwrap();
+        let host = host.split(':').next().unwrap();
+        if host.is_empty() {
+            return None;
+        }
+
+        let segments: Vec<String> = path.split('/')
+            .filter(|s| !s.is_empty())
+            .map(percent_decode)
+            .collect::<Option<_>>()?;
+        // The prefix is free-form, so it could contain something that looks
+        // like a key; the key is the first place the rest of the path parses
+        let (start, primary, qualifiers) = (0..segments.len())
+            .find_map(|i| parse_path(&segments[i..]).map(|(p, q)| (i, p, q)))?;
+
+        let mut attributes = Vec::new();
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            if is_ai(key) {
+                attributes.push(Element { ai: key.to_owned(), value: percent_decode(&value.replace('+', " "))? });
+            }
+        }
+
+        Some(Self {
+            domain: host.to_ascii_lowercase(),
+            prefix: segments[..start].to_vec(),
+            primary,
+            qualifiers,
+            attributes,
+        })
+    }
+
+    /// The value of `ai`, from the primary key, qualifiers or attributes
+    pub fn get(&self, ai: &str) -> Option<&str> {
+        std::iter::once(&self.primary)
+            .chain(&self.qualifiers)
+            .chain(&self.attributes)
+            .find(|e| e.ai == ai)
+            .map(|e| e.value.as_str())
+    }
+
+    /// The 14-digit GTIN, if the link is for a trade item
+    pub fn gtin(&self) -> Option<&str> {
+        (self.primary.ai == "01").then_some(self.primary.value.as_str())
+    }
+
+    /// Batch or lot number (AI 10)
+    pub fn lot(&self) -> Option<&str> {
+        self.get("10")
+    }
+
+    /// Serial number (AI 21)
+    pub fn serial(&self) -> Option<&str> {
+        self.get("21")
+    }
+
+    /// Expiry date (AI 17), as YYMMDD
+    pub fn expiry(&self) -> Option<&str> {
+        self.get("17")
+    }
+}
+
+/// The primary key and qualifiers, if `segments` is exactly those
+fn parse_path(segments: &[String]) -> Option<(Element, Vec<Element>)> {
+    let (ai, value) = (segments.first()?, segments.get(1)?);
+    let &(_, allowed) = PRIMARY_KEYS.iter().find(|(key, _)| key == ai)?;
+    let primary = Element { ai: ai.clone(), value: check_key(ai, value)? };
+
+    let mut qualifiers = Vec::new();
+    // Qualifiers can be left out, but not reordered or repeated
+    let mut allowed = allowed.iter();
+    for pair in segments[2..].chunks(2) {
+        let [ai, value] = pair else { return None };
+        allowed.find(|&&q| q == ai)?;
+        if value.is_empty() {
+            return None;
+        }
+        qualifiers.push(Element { ai: ai.clone(), value: value.clone() });
+    }
+    Some((primary, qualifiers))
+}
+
+/// `value` as the value of primary key `ai`, normalized, or None if it isn't
+/// valid
+fn check_key(ai: &str, value: &str) -> Option<String> {
+    let digits = |len: usize| value.len() == len && value.bytes().all(|b| b.is_ascii_digit()) && check_digit_ok(value);
+    match ai {
+        "01" => {
+            // GTIN-8, -12 and -13 are padded out to 14
+            if ![8, 12, 13, 14].contains(&value.len()) {
+                return None;
+            }
+            let padded = format!("{:0>14}", value);
+            (padded.bytes().all(|b| b.is_ascii_digit()) && check_digit_ok(&padded)).then_some(padded)
+        }
+        "00" => digits(18).then(|| value.to_owned()),
+        "414" | "417" => digits(13).then(|| value.to_owned()),
+        _ => (!value.is_empty()).then(|| value.to_owned()),
+    }
+}
+
+/// Whether the last digit of `digits` is the GS1 mod-10 check digit of the
+/// rest
+fn check_digit_ok(digits: &str) -> bool {
+    let digits = digits.as_bytes();
+    let Some((&last, rest)) = digits.split_last() else { return false };
+    // Weights alternate 3, 1, ... from the digit next to the check digit
+    let sum: u32 = rest.iter().rev().enumerate()
+        .map(|(i, &d)| (d - b'0') as u32 * if i % 2 == 0 { 3 } else { 1 })
+        .sum();
+    (10 - sum % 10) % 10 == (last - b'0') as u32
+}
+
+/// Whether a query parameter's name is an AI: 2 to 4 digits
+fn is_ai(key: &str) -> bool {
+    (2..=4).contains(&key.len()) && key.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &s[prefix.len()..])
+}
+
+/// `s` with %XX escapes decoded, or None if they're malformed or don't make
+/// UTF-8
+fn percent_decode(s: &str) -> Option<String> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            out.push(u8::from_str_radix(std::str::from_utf8(&hex).unwrap(), 16).unwrap());
+        } else {
+            out.push(b);
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_links() {
+        let link = DigitalLink::parse("https://ID.example.com:443/shop/01/9506000134352/10/ABC%20123/21/7?17=251231&linkType=gs1:pip#x").unwrap();
+        assert_eq!(link.domain, "id.example.com");
+        assert_eq!(link.prefix, ["shop"]);
+        assert_eq!(link.gtin(), Some("09506000134352"));
+        assert_eq!(link.lot(), Some("ABC 123"));
+        assert_eq!(link.serial(), Some("7"));
+        assert_eq!(link.expiry(), Some("251231"));
+        assert_eq!(link.attributes.len(), 1);
+
+        let link = DigitalLink::parse("http://example.com/414/9520123456788/254/32a%2Fb").unwrap();
+        assert_eq!(link.gtin(), None);
+        assert_eq!(link.get("254"), Some("32a/b"));
+    }
+
+    #[test]
+    fn rejects_truncated_links() {
+        assert_eq!(DigitalLink::parse("https://example.com/01"), None);
+        assert_eq!(DigitalLink::parse("https://example.com/01/0950600013435"), None);
+        // A qualifier without its value
+        assert_eq!(DigitalLink::parse("https://example.com/01/09506000134352/10"), None);
+        assert_eq!(DigitalLink::parse("https://example.com/01/09506000134352/10/"), None);
+        assert_eq!(DigitalLink::parse("https://"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_links() {
+        // Wrong check digit
+        assert_eq!(DigitalLink::parse("https://example.com/01/09506000134353"), None);
+        // Qualifiers out of order
+        assert_eq!(DigitalLink::parse("https://example.com/01/09506000134352/21/7/10/ABC"), None);
+        // Not a primary key
+        assert_eq!(DigitalLink::parse("https://example.com/10/ABC"), None);
+        // Broken escapes
+        assert_eq!(DigitalLink::parse("https://example.com/01/09506000134352/10/A%2"), None);
+        assert_eq!(DigitalLink::parse("https://example.com/01/09506000134352?17=%zz"), None);
+        assert_eq!(DigitalLink::parse("ftp://example.com/01/09506000134352"), None);
+    }
+}
@@ -0,0 +1,153 @@
+//! A GStreamer plugin, behind the `gstreamer` feature, with one element:
+//! `arqrscan`. It passes video through untouched, scanning each frame as it
+//! goes, and posts an element message on the bus for every frame a code was
+//! found in. The message's structure is named `arqr`, with fields:
+//!
+//! - `json`: the frame's result, as one line of JSON laid out as in the
+//!   `json` module
+//! - `payload`: the code's message, if it could be read, as text (bytes that
+//!   aren't UTF-8 are replaced with U+FFFD, as `arqr-cli` prints them)
+//! - `pts`: the frame's presentation timestamp, if it had one
+//!
+//! It takes GRAY8 and the common planar YUV formats, and only scans the luma
+//! plane, so put a `videoconvert` in front of anything else. Build the
+//! plugin with
+//!
+//! ```text
+//! cargo rustc --lib --release --no-default-features --features gstreamer --crate-type cdylib
+//! ```
+//!
+//! and point `GST_PLUGIN_PATH` at `target/release`, e.g.
+//!
+//! ```text
+//! GST_PLUGIN_PATH=target/release gst-launch-1.0 -m v4l2src ! videoconvert ! arqrscan ! fakesink
+//! ```
+
+use gst::glib;
+use gst::prelude::*;
+
+mod imp {
+    use std::sync::{LazyLock, Mutex};
+    use gst::glib;
+    use gst::prelude::*;
+    use gst::subclass::prelude::*;
+    use gst_base::subclass::prelude::*;
+    use gst_video::prelude::*;
+    use gst_video::{VideoFormat, VideoFrameRef, VideoInfo};
+    use crate::Scanner;
+
+    #[derive(Default)]
+    pub struct ArqrScan {
+        scanner: Mutex<Scanner>,
+        // Set once the caps are agreed, before any buffers arrive
+        info: Mutex<Option<VideoInfo>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ArqrScan {
+        const NAME: &'static str = "ArqrScan";
+        type Type = super::ArqrScan;
+        type ParentType = gst_base::BaseTransform;
+    }
+
+    impl ObjectImpl for ArqrScan {}
+
+    impl GstObjectImpl for ArqrScan {}
+
+    impl ElementImpl for ArqrScan {
+        fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+            static METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+                gst::subclass::ElementMetadata::new(
+                    "arqr scanner",
+                    "Filter/Analyzer/Video",
+                    "Scans video for QR codes, posting what it finds on the bus",
+                    "arqr contributors",
+                )
+            });
+            Some(&*METADATA)
+        }
+
+        fn pad_templates() -> &'static [gst::PadTemplate] {
+            static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+                // Formats whose first plane is the luma, one byte a pixel
+                let caps = gst_video::VideoCapsBuilder::new()
+                    .format_list([VideoFormat::Gray8, VideoFormat::I420, VideoFormat::Yv12, VideoFormat::Nv12, VideoFormat::Nv21])
+                    .build();
+                let pad = |name, direction| gst::PadTemplate::new(name, direction, gst::PadPresence::Always, &caps).unwrap();
+                vec![pad("src", gst::PadDirection::Src), pad("sink", gst::PadDirection::Sink)]
+            });
+            PAD_TEMPLATES.as_ref()
+        }
+    }
+
+    impl BaseTransformImpl for ArqrScan {
+        // The element never changes the video, so always passes it through
+        const MODE: gst_base::subclass::BaseTransformMode = gst_base::subclass::BaseTransformMode::AlwaysInPlace;
+        const PASSTHROUGH_ON_SAME_CAPS: bool = true;
+        const TRANSFORM_IP_ON_PASSTHROUGH: bool = true;
+
+        fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
+            let info = VideoInfo::from_caps(incaps).map_err(|_| gst::loggable_error!(gst::CAT_RUST, "bad caps {}", incaps))?;
+            *self.info.lock().unwrap() = Some(info);
+            self.parent_set_caps(incaps, outcaps)
+        }
+
+        fn stop(&self) -> Result<(), gst::ErrorMessage> {
+            // A fresh scanner, so tracking and frame numbers start over
+            *self.scanner.lock().unwrap() = Scanner::new();
+            *self.info.lock().unwrap() = None;
+            self.parent_stop()
+        }
+
+        fn transform_ip_passthrough(&self, buf: &gst::Buffer) -> Result<gst::FlowSuccess, gst::FlowError> {
+            let info = self.info.lock().unwrap();
+            let Some(info) = info.as_ref() else { return Err(gst::FlowError::NotNegotiated) };
+            let frame = VideoFrameRef::from_buffer_ref_readable(buf.as_ref(), info).map_err(|_| gst::FlowError::Error)?;
+            let (width, height) = (frame.width(), frame.height());
+            let stride = frame.plane_stride()[0] as usize;
+            let luma = frame.plane_data(0).map_err(|_| gst::FlowError::Error)?;
+            let (w, h) = (width as usize, height as usize);
+            if luma.len() < stride * (h - 1) + w {
+                return Err(gst::FlowError::Error);
+            }
+            let lumas = (0..h).flat_map(|row| luma[row * stride..][..w].iter().copied());
+
+            let result = self.scanner.lock().unwrap().scan_lumas(lumas, width, height);
+            if result.bbox.is_none() {
+                return Ok(gst::FlowSuccess::Ok);
+            }
+            let mut structure = gst::Structure::builder("arqr").field("json", result.to_json());
+            if let Some(payload) = &result.payload {
+                structure = structure.field("payload", String::from_utf8_lossy(payload).as_ref());
+            }
+            if let Some(pts) = buf.pts() {
+                structure = structure.field("pts", pts);
+            }
+            let obj = self.obj();
+            let message = gst::message::Element::builder(structure.build()).src(&*obj).build();
+            // Only fails if the element isn't in a pipeline yet
+            let _ = obj.post_message(message);
+            Ok(gst::FlowSuccess::Ok)
+        }
+    }
+}
+
+glib::wrapper! {
+    /// The `arqrscan` element; see the module docs
+    pub struct ArqrScan(ObjectSubclass<imp::ArqrScan>) @extends gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(Some(plugin), "arqrscan", gst::Rank::NONE, ArqrScan::static_type())
+}
+
+gst::plugin_define!(
+    arqr,
+    "Scans video for QR codes",
+    plugin_init,
+    env!("CARGO_PKG_VERSION"),
+    "unknown",
+    "arqr",
+    "arqr",
+    "https://github.com/frasercl/arqr"
+);
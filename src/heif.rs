@@ -0,0 +1,69 @@
+//! Decodes HEIF and AVIF pictures, for scanning photos straight off a phone
+//! without converting them first.
+//!
+//! Unlike `pdf` and `video`, this links a decoder in: libheif, 1.17 or
+//! later, so building with the `heif` feature needs its development files
+//! (libheif-dev). AVIF also needs libheif built with an AV1 decoder, dav1d
+//! or libaom, as distributions' packages are.
+//!
+//! Each top-level image in a file is a frame, in the order the file lists
+//! them; a photo usually has just the one. libheif applies the rotation and
+//! mirroring the file asks for, so images come out upright, as with JPEGs'
+//! EXIF orientation in `frames`.
+
+use std::{error::Error, fs::File, io::BufReader, path::Path, vec};
+use image::{ImageError, ImageResult, RgbaImage, error::{DecodingError, ImageFormatHint}};
+use libheif_rs::{ColorSpace, HeifContext, ImageHandle, LibHeif, RgbChroma, StreamReader};
+
+/// Whether `path` looks like a HEIF or AVIF file, going by its extension
+pub fn is_heif(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ["heic", "heif", "hif", "avif"].iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+fn heif_err(err: impl Into<Box<dyn Error + Send + Sync>>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("HEIF".to_owned()), err))
+}
+
+/// Iterator over the top-level images of a HEIF or AVIF file, decoded as
+/// they're iterated over
+pub struct HeifImages {
+    lib: LibHeif,
+    // The handles keep the file open within libheif, but this is what owns it
+    _context: HeifContext<'static>,
+    handles: vec::IntoIter<ImageHandle>,
+}
+
+impl HeifImages {
+    pub fn open(path: &Path) -> ImageResult<Self> {
+        let lib = LibHeif::new();
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let reader = StreamReader::new(BufReader::new(file), len);
+        let context = HeifContext::read_from_reader(Box::new(reader)).map_err(heif_err)?;
+        let handles = context.top_level_image_handles().into_iter();
+        Ok(Self { lib, _context: context, handles })
+    }
+
+    fn decode(&self, handle: &ImageHandle) -> ImageResult<RgbaImage> {
+        // Deeper images are brought down to 8 bits a channel on the way
+        let image = self.lib.decode(handle, ColorSpace::Rgb(RgbChroma::Rgba), None).map_err(heif_err)?;
+        let planes = image.planes();
+        let plane = planes.interleaved.ok_or_else(|| heif_err("libheif didn't give RGBA"))?;
+        let row_len = plane.width as usize * 4;
+        let data = plane.data.chunks(plane.stride)
+            .take(plane.height as usize)
+            .flat_map(|row| row.get(..row_len).unwrap_or_default())
+            .copied()
+            .collect();
+        RgbaImage::from_raw(plane.width, plane.height, data).ok_or_else(|| heif_err("libheif's RGBA was cut short"))
+    }
+}
+
+impl Iterator for HeifImages {
+    type Item = ImageResult<RgbaImage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let handle = self.handles.next()?;
+        Some(self.decode(&handle))
+    }
+}
@@ -0,0 +1,219 @@
+//! Plane-to-plane projective transforms, estimated from point
+//! correspondences.
+
+use crate::Point;
+
+/// Solves the square system `a * x = b` by Gaussian elimination with partial
+/// pivoting. Returns `None` if the system is singular.
+pub(crate) fn solve<const N: usize>(mut a: [[f64; N]; N], mut b: [f64; N]) -> Option<[f64; N]> {
+    for col in 0..N {
+        let pivot = (col..N).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let (pivot_row, pivot_b) = (a[col], b[col]);
+        for (row, rhs) in a[(col + 1)..].iter_mut().zip(b[(col + 1)..].iter_mut()) {
+            let factor = row[col] / pivot_row[col];
+            for (val, pivot_val) in row[col..].iter_mut().zip(pivot_row[col..].iter()) {
+                *val -= factor * pivot_val;
+            }
+            *rhs -= factor * pivot_b;
+        }
+    }
+
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let sum: f64 = ((row + 1)..N).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// 3x3 projective transform, mapping `(x, y)` to `(u, v)` by
+/// `[u', v', w'] = H * [x, y, 1]`, `u = u' / w'`, `v = v' / w'`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Homography(pub [[f64; 3]; 3]);
+
+impl Homography {
+    pub const IDENTITY: Homography = Homography([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+
+    /// Estimates the homography which maps each of `src` onto the matching
+    /// point in `dst`. Needs at least 4 pairs; with more than 4 the result is
+    /// a least-squares fit. Returns `None` for degenerate input (e.g. three
+    /// collinear points).
+    pub fn from_points(src: &[Point<f64>], dst: &[Point<f64>]) -> Option<Self> {
+        if src.len() < 4 || src.len() != dst.len() {
+            return None;
+        }
+
+        // Normalize both point sets for numerical stability (Hartley)
+        let (src_t, src_n) = normalize(src);
+        let (dst_t, dst_n) = normalize(dst);
+
+        // Each pair gives two equations in the 8 unknowns (fixing h33 = 1).
+        // Accumulate the normal equations A^T A h = A^T b.
+        let mut ata = [[0.0; 8]; 8];
+        let mut atb = [0.0; 8];
+        for (p, q) in src_n.iter().zip(dst_n.iter()) {
+            let rows = [
+                ([p.x, p.y, 1.0, 0.0, 0.0, 0.0, -q.x * p.x, -q.x * p.y], q.x),
+                ([0.0, 0.0, 0.0, p.x, p.y, 1.0, -q.y * p.x, -q.y * p.y], q.y),
+            ];
+            for (row, rhs) in rows.iter() {
+                for i in 0..8 {
+                    for j in 0..8 {
+                        ata[i][j] += row[i] * row[j];
+                    }
+                    atb[i] += row[i] * rhs;
+                }
+            }
+        }
+        let h = solve(ata, atb)?;
+        let normalized = Homography([[h[0], h[1], h[2]], [h[3], h[4], h[5]], [h[6], h[7], 1.0]]);
+
+        // Undo the normalization: H = T_dst^-1 * Hn * T_src
+        Some(dst_t.inverse()?.then(&normalized.then(&src_t)))
+    }
+
+    /// Applies the transform to a point
+    pub fn apply(&self, p: Point<f64>) -> Point<f64> {
+        let m = &self.0;
+        let w = m[2][0] * p.x + m[2][1] * p.y + m[2][2];
+        Point::new(
+            (m[0][0] * p.x + m[0][1] * p.y + m[0][2]) / w,
+            (m[1][0] * p.x + m[1][1] * p.y + m[1][2]) / w,
+        )
+    }
+
+    /// Matrix product `self * other`, i.e. the transform which applies
+    /// `other` first and then `self`
+    pub fn then(&self, other: &Homography) -> Homography {
+        let (a, b) = (&self.0, &other.0);
+        let mut out = [[0.0; 3]; 3];
+        for (i, row) in out.iter_mut().enumerate() {
+            for (j, val) in row.iter_mut().enumerate() {
+                *val = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+        Homography(out)
+    }
+
+    pub fn inverse(&self) -> Option<Homography> {
+        let m = &self.0;
+        let cof = |r0: usize, r1: usize, c0: usize, c1: usize| {
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+        let inv = [
+            [cof(1, 2, 1, 2), -cof(0, 2, 1, 2), cof(0, 1, 1, 2)],
+            [-cof(1, 2, 0, 2), cof(0, 2, 0, 2), -cof(0, 1, 0, 2)],
+            [cof(1, 2, 0, 1), -cof(0, 2, 0, 1), cof(0, 1, 0, 1)],
+        ];
+        let det = m[0][0] * inv[0][0] + m[0][1] * inv[1][0] + m[0][2] * inv[2][0];
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        Some(Homography(inv.map(|row| row.map(|v| v / det))))
+    }
+}
+
+/// Finds a similarity transform which moves the points' centroid to the
+/// origin and scales their mean distance from it to sqrt(2). Returns the
+/// transform and the transformed points.
+fn normalize(points: &[Point<f64>]) -> (Homography, Vec<Point<f64>>) {
+    let n = points.len() as f64;
+    let cx = points.iter().map(|p| p.x).sum::<f64>() / n;
+    let cy = points.iter().map(|p| p.y).sum::<f64>() / n;
+    let centroid = Point::new(cx, cy);
+    let mean_dist = points.iter().map(|p| p.dist_to(centroid)).sum::<f64>() / n;
+    let s = if mean_dist > 0.0 { std::f64::consts::SQRT_2 / mean_dist } else { 1.0 };
+
+    let t = Homography([[s, 0.0, -s * cx], [0.0, s, -s * cy], [0.0, 0.0, 1.0]]);
+    let normalized = points.iter().map(|&p| t.apply(p)).collect();
+    (t, normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const SQUARE: [Point<f64>; 4] = [
+        Point { x: 0.0, y: 0.0 },
+        Point { x: 1.0, y: 0.0 },
+        Point { x: 1.0, y: 1.0 },
+        Point { x: 0.0, y: 1.0 },
+    ];
+
+    /// A square of side `side` somewhere near the origin, each corner pushed
+    /// up to a quarter of the side out of place, like a code seen at an angle
+    fn quad(side: f64) -> impl Strategy<Value = [Point<f64>; 4]> {
+        let nudge = || (-0.25..0.25, -0.25..0.25);
+        (-1000.0..1000.0, -1000.0..1000.0, [nudge(), nudge(), nudge(), nudge()]).prop_map(move |(ox, oy, nudges)| {
+            let mut quad = SQUARE;
+            for (p, (dx, dy)) in quad.iter_mut().zip(nudges) {
+                *p = Point::new(ox + (p.x + dx) * side, oy + (p.y + dy) * side);
+            }
+            quad
+        })
+    }
+
+    /// A point in the unit square
+    fn unit_point() -> impl Strategy<Value = Point<f64>> {
+        (0.0..1.0, 0.0..1.0).prop_map(Point::from)
+    }
+
+    fn assert_close(a: Point<f64>, b: Point<f64>, tolerance: f64) -> Result<(), TestCaseError> {
+        prop_assert!(a.dist_to(b) < tolerance, "{:?} isn't {:?}", a, b);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn maps_corners_onto_corners((side, quad) in (10.0..1000.0).prop_flat_map(|side| (Just(side), quad(side)))) {
+            let h = Homography::from_points(&SQUARE, &quad).unwrap();
+            for (&s, &q) in SQUARE.iter().zip(&quad) {
+                assert_close(h.apply(s), q, 1e-9 * side)?;
+            }
+        }
+
+        #[test]
+        fn inverse_round_trips(quad in (10.0..1000.0).prop_flat_map(quad), p in unit_point()) {
+            let h = Homography::from_points(&SQUARE, &quad).unwrap();
+            let inv = h.inverse().unwrap();
+            assert_close(inv.apply(h.apply(p)), p, 1e-9)?;
+            assert_close(h.then(&inv).apply(p), p, 1e-9)?;
+        }
+
+        #[test]
+        fn then_applies_the_right_side_first(a in quad(100.0), b in quad(2.0), p in unit_point()) {
+            let a = Homography::from_points(&SQUARE, &a).unwrap();
+            let b = Homography::from_points(&SQUARE, &b).unwrap();
+            let expected = a.apply(b.apply(p));
+            assert_close(a.then(&b).apply(p), expected, 1e-6 * expected.dist_to(Point::new(0.0, 0.0)).max(1.0))?;
+        }
+
+        #[test]
+        fn fits_more_than_four_exact_points(
+            quad in quad(300.0),
+            src in prop::collection::vec(unit_point(), 12),
+            p in unit_point(),
+        ) {
+            let h = Homography::from_points(&SQUARE, &quad).unwrap();
+            let dst: Vec<_> = src.iter().map(|&p| h.apply(p)).collect();
+            let fit = Homography::from_points(&src, &dst).unwrap();
+            assert_close(fit.apply(p), h.apply(p), 1e-6)?;
+        }
+    }
+
+    #[test]
+    fn rejects_degenerate_input() {
+        let line = [0.0, 1.0, 2.0, 3.0].map(|t| Point::new(t, t * 2.0));
+        assert!(Homography::from_points(&line, &SQUARE).is_none());
+        assert!(Homography::from_points(&SQUARE[..3], &SQUARE[..3]).is_none());
+        assert!(Homography::from_points(&SQUARE, &SQUARE[..3]).is_none());
+        assert!(Homography([[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [0.0, 0.0, 1.0]]).inverse().is_none());
+    }
+}
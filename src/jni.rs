@@ -0,0 +1,118 @@
+//! JNI glue for Android, behind the `jni` feature. It backs the one native
+//! method of `io.github.frasercl.arqr.Arqr` (in the example app in
+//! `android/`):
+//!
+//! ```text
+//! public static native ScanResult scanYuvFrame(ByteBuffer y, int width, int height, int rowStride, int pixelStride);
+//! ```
+//!
+//! which scans the Y plane of a camera2 or CameraX `YUV_420_888` image,
+//! straight out of its direct `ByteBuffer`, with the plane's own strides.
+//! It returns an `io.github.frasercl.arqr.ScanResult`, with the code's
+//! corners as x, y pairs (top-left, top-right, bottom-right, bottom-left),
+//! its payload (null if it couldn't be read), version and error correction
+//! level, or null if no code was found. Bad arguments throw an
+//! `IllegalArgumentException`.
+//!
+//! Build `libarqr.so` for each ABI the app ships, e.g.
+//!
+//! ```text
+//! cargo rustc --lib --release --target aarch64-linux-android \
+//!     --no-default-features --features jni --crate-type cdylib
+//! ```
+//!
+//! with the NDK's clang as the target's linker, and copy it to
+//! `android/app/src/main/jniLibs/arm64-v8a/`.
+
+use std::{cell::RefCell, ffi::CStr, slice};
+use jni_sys::{jbyte, jclass, jfloat, jint, jobject, jsize, jvalue, JNIEnv};
+use crate::Scanner;
+
+thread_local! {
+    // Frames come in on one analysis thread, so a scanner per thread keeps
+    // its buffers between frames without any locking
+    static SCANNER: RefCell<Scanner> = RefCell::new(Scanner::new());
+}
+
+/// Throws an `IllegalArgumentException`. The caller has to return to Java
+/// straight after.
+unsafe fn throw_illegal_argument(env: *mut JNIEnv, message: &CStr) {
+    let class = (**env).FindClass.unwrap()(env, c"java/lang/IllegalArgumentException".as_ptr());
+    if !class.is_null() {
+        (**env).ThrowNew.unwrap()(env, class, message.as_ptr());
+    }
+}
+
+/// `Arqr.scanYuvFrame`; see the module docs
+///
+/// # Safety
+///
+/// Only to be called by the JVM.
+#[no_mangle]
+pub unsafe extern "system" fn Java_io_github_frasercl_arqr_Arqr_scanYuvFrame(
+    env: *mut JNIEnv,
+    _class: jclass,
+    y: jobject,
+    width: jint,
+    height: jint,
+    row_stride: jint,
+    pixel_stride: jint,
+) -> jobject {
+    if y.is_null() || width <= 0 || height <= 0 || pixel_stride <= 0 || (row_stride as i64) < width as i64 * pixel_stride as i64 {
+        throw_illegal_argument(env, c"bad frame size or strides");
+        return std::ptr::null_mut();
+    }
+    let pixels = (**env).GetDirectBufferAddress.unwrap()(env, y) as *const u8;
+    let capacity = (**env).GetDirectBufferCapacity.unwrap()(env, y);
+    // The last row usually stops at its last pixel, without the padding.
+    // Worked out in 64 bits, as 32-bit ABIs could overflow.
+    let len = row_stride as i64 * (height as i64 - 1) + pixel_stride as i64 * (width as i64 - 1) + 1;
+    if pixels.is_null() || capacity < len {
+        throw_illegal_argument(env, c"the Y plane has to be a direct ByteBuffer holding the whole frame");
+        return std::ptr::null_mut();
+    }
+    let (w, h) = (width as usize, height as usize);
+    let (row_stride, pixel_stride) = (row_stride as usize, pixel_stride as usize);
+    let pixels = slice::from_raw_parts(pixels, len as usize);
+    let lumas = (0..h).flat_map(|row| pixels[row * row_stride..].iter().step_by(pixel_stride).take(w).copied());
+
+    let result = SCANNER.with(|scanner| scanner.borrow_mut().scan_lumas(lumas, width as u32, height as u32));
+    let Some(quad) = result.quad() else { return std::ptr::null_mut() };
+    let mut corners = [0.0 as jfloat; 8];
+    for (i, p) in quad.iter().enumerate() {
+        corners[i * 2] = p.x as jfloat;
+        corners[i * 2 + 1] = p.y as jfloat;
+    }
+    // Null from any of these means an exception is already on its way
+    let class = (**env).FindClass.unwrap()(env, c"io/github/frasercl/arqr/ScanResult".as_ptr());
+    if class.is_null() {
+        return std::ptr::null_mut();
+    }
+    let init = (**env).GetMethodID.unwrap()(env, class, c"<init>".as_ptr(), c"([F[BII)V".as_ptr());
+    if init.is_null() {
+        return std::ptr::null_mut();
+    }
+    let corner_array = (**env).NewFloatArray.unwrap()(env, corners.len() as jsize);
+    if corner_array.is_null() {
+        return std::ptr::null_mut();
+    }
+    (**env).SetFloatArrayRegion.unwrap()(env, corner_array, 0, corners.len() as jsize, corners.as_ptr());
+    let mut payload_array = std::ptr::null_mut();
+    if let Some(payload) = &result.payload {
+        payload_array = (**env).NewByteArray.unwrap()(env, payload.len() as jsize);
+        if payload_array.is_null() {
+            return std::ptr::null_mut();
+        }
+        let bytes = payload.as_ptr() as *const jbyte;
+        (**env).SetByteArrayRegion.unwrap()(env, payload_array, 0, payload.len() as jsize, bytes);
+    }
+    // Levels count up from L, as in `ScanResult.EC_L` and on
+    let ec_level = result.format.map_or(-1, |format| format.ec_level as jint);
+    let args = [
+        jvalue { l: corner_array },
+        jvalue { l: payload_array },
+        jvalue { i: result.version.unwrap_or(0) as jint },
+        jvalue { i: ec_level },
+    ];
+    (**env).NewObjectA.unwrap()(env, class, init, args.as_ptr())
+}
@@ -0,0 +1,124 @@
+//! Scan results as JSON, in a versioned layout for other programs to rely
+//! on. `ScanResult::to_json` gives one object per frame:
+//!
+//! ```text
+//! {"schema":1,"sequence":12,"stage":"complete","targets":3,
+//!  "codes":[{"corners":[[x,y],[x,y],[x,y],[x,y]],
+//!            "homography":[[h11,h12,h13],[h21,h22,h23],[h31,h32,h33]],
+//!            "version":2,"ec_level":"M","payload":"hello","decode_error":null,"confidence":0.875}],
+//!  "timings_ms":{"binarize":1.234,"targets":0.456,"corners":0.012,"extract":0.789,"decode":0.050,"fiducials":0.000,"total":2.541}}
+//! ```
+//!
+//! (all on one line). `corners` are in pixels, top-left first and going
+//! clockwise. `homography` maps the code's own square, from (0, 0) at its
+//! top-left corner to (1, 1) at its bottom-right, onto the image, and is null
+//! if the corners are degenerate. `targets` counts every position target
+//! found, whether or not it was part of a code. `version` is the version the
+//! code was sampled at, and `ec_level` one of `"L"`, `"M"`, `"Q"` or `"H"`
+//! when the code's format information could be read. `payload` is the
+//! code's message when it could be read and corrected, as a string, with any
+//! bytes that aren't UTF-8 replaced by U+FFFD; it's null otherwise, and
+//! `decode_error` names the step that failed (see `DecodeError::name`).
+//! `confidence`, from 0 to 1, says how much error correction the payload
+//! had to spare (see `ScanResult::confidence`), and is null along with it.
+//!
+//! Fields may be added without changing `schema`, so readers should ignore
+//! ones they don't know. Removing a field or changing what one means bumps
+//! `SCHEMA_VERSION`.
+
+use std::{fmt::Write as _, time::Duration};
+use crate::{
+    Point, ScanResult, Stage, Timings,
+    homography::Homography,
+};
+
+/// The `schema` field of every object from `ScanResult::to_json`
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `s` as a quoted JSON string
+pub fn string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A number to 3 decimal places, or null if it isn't finite
+pub(crate) fn number(n: f64) -> String {
+    if n.is_finite() { format!("{:.3}", n) } else { "null".to_owned() }
+}
+
+pub(crate) fn point(p: Point<f64>) -> String {
+    format!("[{},{}]", number(p.x), number(p.y))
+}
+
+pub(crate) fn stage_name(stage: Stage) -> &'static str {
+    match stage {
+        Stage::Binarized => "binarized",
+        Stage::Targets => "targets",
+        Stage::Corners => "corners",
+        Stage::Extracted => "extracted",
+        Stage::Decoded => "decoded",
+        Stage::Complete => "complete",
+    }
+}
+
+/// Each stage's time in milliseconds, and the total
+pub(crate) fn timings(t: &Timings) -> String {
+    let ms = |d: Duration| number(d.as_secs_f64() * 1000.0);
+    format!(
+        "{{\"binarize\":{},\"targets\":{},\"corners\":{},\"extract\":{},\"decode\":{},\"fiducials\":{},\"total\":{}}}",
+        ms(t.binarize), ms(t.targets), ms(t.corners), ms(t.extract), ms(t.decode), ms(t.fiducials), ms(t.total()),
+    )
+}
+
+/// The homography's entries at full precision, since the perspective terms
+/// are tiny
+fn homography(h: &Homography) -> String {
+    let rows: Vec<String> = h.0.iter()
+        .map(|row| {
+            let entries: Vec<String> = row.iter()
+                .map(|&v| if v.is_finite() { format!("{:?}", v) } else { "null".to_owned() })
+                .collect();
+            format!("[{}]", entries.join(","))
+        })
+        .collect();
+    format!("[{}]", rows.join(","))
+}
+
+impl ScanResult {
+    /// The result as one line of JSON, laid out as described in the `json`
+    /// module
+    pub fn to_json(&self) -> String {
+        let mut out = format!(
+            "{{\"schema\":{},\"sequence\":{},\"stage\":\"{}\",\"targets\":{},\"codes\":[",
+            SCHEMA_VERSION, self.meta.sequence, stage_name(self.stage), self.targets.len(),
+        );
+        if let Some(quad) = self.quad() {
+            let unit = [Point::new(0.0, 0.0), Point::new(1.0, 0.0), Point::new(1.0, 1.0), Point::new(0.0, 1.0)];
+            let h = Homography::from_points(&unit, &quad).map_or("null".to_owned(), |h| homography(&h));
+            let version = self.version.map_or("null".to_owned(), |v| v.to_string());
+            let ec_level = self.format.map_or("null".to_owned(), |f| string(&format!("{:?}", f.ec_level)));
+            let payload = self.payload.as_ref().map_or("null".to_owned(), |p| string(&String::from_utf8_lossy(p)));
+            let decode_error = self.decode_error.map_or("null".to_owned(), |e| string(e.name()));
+            let confidence = self.confidence().map_or("null".to_owned(), number);
+            let _ = write!(
+                out,
+                "{{\"corners\":[{}],\"homography\":{},\"version\":{},\"ec_level\":{},\"payload\":{},\"decode_error\":{},\"confidence\":{}}}",
+                quad.map(point).join(","), h, version, ec_level, payload, decode_error, confidence,
+            );
+        }
+        let _ = write!(out, "],\"timings_ms\":{}}}", timings(&self.timings));
+        out
+    }
+}
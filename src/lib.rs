@@ -1,18 +1,85 @@
 
-use std::{ops::Deref, f64::consts::PI};
-use image::{ImageBuffer, Rgba, buffer::ConvertBuffer, Pixel};
+use std::{ops::Deref, f64::consts::PI, time::{Duration, Instant}};
+use image::{GenericImageView, ImageBuffer, Rgba, Pixel};
 
+pub mod aztec;
+pub mod best_frame;
 pub mod bitmap;
+pub mod board;
+pub mod braille;
+pub mod calendar;
+pub mod calib;
+pub mod change;
+pub mod corpus;
+pub mod decode;
+pub mod draw;
+pub mod emv;
+pub mod encode;
+pub mod epc;
+pub mod exif;
+pub mod fiducial;
 pub mod target;
+pub mod feedback;
 pub mod filter;
+pub mod flow;
+pub mod font;
+pub mod frames;
+pub mod gs1;
+pub mod homography;
+pub mod json;
+pub mod list;
+pub mod mask;
+pub mod pdf417;
+pub mod pose;
+pub mod record;
+pub mod reed_solomon;
+pub mod scanner;
+pub mod smooth;
+pub mod superres;
+pub mod tracker;
+pub mod url;
+#[cfg(feature = "ndarray")]
+pub mod array;
+#[cfg(feature = "uniffi")]
+pub mod bindings;
+#[cfg(feature = "camera")]
+pub mod camera;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+#[cfg(feature = "compare")]
+pub mod compare;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "gstreamer")]
+pub mod gstreamer;
+#[cfg(feature = "heif")]
+pub mod heif;
+#[cfg(feature = "jni")]
+pub mod jni;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+#[cfg(feature = "proto")]
+pub mod proto;
+#[cfg(feature = "ros")]
+pub mod ros;
+#[cfg(any(test, feature = "testgen"))]
+pub mod testgen;
+#[cfg(any(feature = "egui", feature = "bevy"))]
+pub mod texture;
+#[cfg(feature = "video")]
+pub mod video;
+#[cfg(feature = "web")]
+pub mod web;
 
-use target::{
-    find_pos_targets,
-    pick_corners,
-    to_side_len,
-    to_affine_transform,
-};
-use bitmap::{Bitmap, affine_transform_chunk};
+use bitmap::Bitmap;
+use list::{List, MAX_TARGETS};
+
+pub use scanner::Scanner;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
 
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Point<T> { pub x: T, pub y: T }
@@ -61,41 +128,200 @@ impl Point<f64> {
     }
 }
 
+/// Axis-aligned rectangle covering `min` (inclusive) to `max` (exclusive)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rect<T> { pub min: Point<T>, pub max: Point<T> }
+
+impl Rect<f64> {
+    /// Smallest rectangle containing all of `points`
+    pub fn bounding(points: &[Point<f64>]) -> Self {
+        let mut rect = Rect {
+            min: Point::new(f64::INFINITY, f64::INFINITY),
+            max: Point::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+        };
+        for p in points {
+            rect.min.x = rect.min.x.min(p.x);
+            rect.min.y = rect.min.y.min(p.y);
+            rect.max.x = rect.max.x.max(p.x);
+            rect.max.y = rect.max.y.max(p.y);
+        }
+        rect
+    }
+
+    /// Grows the rectangle by `margin` on every side
+    pub fn inflate(self, margin: f64) -> Self {
+        Rect {
+            min: Point::new(self.min.x - margin, self.min.y - margin),
+            max: Point::new(self.max.x + margin, self.max.y + margin),
+        }
+    }
+
+    /// Converts to whole pixel coordinates, covering every pixel the
+    /// rectangle touches and cutting off anything left of or above the image
+    pub fn to_pixels(self) -> Rect<u32> {
+        Rect {
+            min: Point::new(self.min.x.floor().max(0.0) as u32, self.min.y.floor().max(0.0) as u32),
+            max: Point::new(self.max.x.ceil().max(0.0) as u32, self.max.y.ceil().max(0.0) as u32),
+        }
+    }
+}
+
+/// Identifies the frame a result came from. Set it with
+/// `Scanner::set_frame_meta` before scanning, and it's copied into the
+/// `ScanResult`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameMeta {
+    /// Frame number. If none is given, the scanner numbers frames itself,
+    /// counting up from the last one it was given.
+    pub sequence: u64,
+    /// When the frame was captured. Used by the tracker in place of the time
+    /// the result arrives, if it's set.
+    pub timestamp: Option<Instant>,
+}
+
+/// How far a scan got. Each stage includes the ones before it. Scans only
+/// stop early when the scanner has a time budget; see `Scanner::budget`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// The image was binarized, but there was no time to search it
+    Binarized,
+    /// Position targets were found, but not grouped into a code
+    Targets,
+    /// A code's corners were picked from the targets (if there was one), but
+    /// its image wasn't extracted
+    Corners,
+    /// The code's image was extracted, but its modules weren't sampled
+    Extracted,
+    /// The code's modules were sampled and its message read (if it had an
+    /// image), but fiducial markers weren't searched for
+    Decoded,
+    /// Every stage ran
+    #[default]
+    Complete,
+}
+
+/// How long each stage of a scan took. Stages that didn't run are zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Binarizing the frame. Zero if the scanner was handed a bitmap.
+    pub binarize: Duration,
+    /// Searching for position targets
+    pub targets: Duration,
+    /// Picking a code's corners out of the targets
+    pub corners: Duration,
+    /// Extracting the code's image
+    pub extract: Duration,
+    /// Sampling the code's modules and reading its message
+    pub decode: Duration,
+    /// Searching for fiducial markers
+    pub fiducials: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.binarize + self.targets + self.corners + self.extract + self.decode + self.fiducials
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ScanResult {
-    pub targets: Vec<target::Target<f64>>,
+    pub meta: FrameMeta,
+    pub stage: Stage,
+    pub timings: Timings,
+    pub targets: List<target::Target<f64>, MAX_TARGETS>,
     pub bbox: Option<[Point<f64>; 3]>,
     pub code_img: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
     pub vectors: Option<[Point<f64>; 2]>,
+    /// The code's modules, sampled from its image. See the `decode` module.
+    pub modules: Option<decode::ModuleGrid>,
+    /// The code's version, from how many modules across it was sampled
+    pub version: Option<u32>,
+    /// The error correction level and mask the code says it uses, if its
+    /// format information could be read
+    pub format: Option<decode::FormatInfo>,
+    /// The message the code holds, if it could be read and corrected. It's
+    /// the bytes that were encoded, so it's only text if text was encoded;
+    /// see `decode::decode_data`.
+    pub payload: Option<Vec<u8>>,
+    /// Why the message couldn't be read, when a code was found but
+    /// `payload` is None
+    pub decode_error: Option<decode::DecodeError>,
+    /// How many of the code's codewords error correction had to fix, when
+    /// `payload` was read
+    pub codeword_errors: Option<usize>,
+    /// Whether the code was seen mirrored. If it was, `modules` has already
+    /// been flipped back.
+    pub mirrored: bool,
+    /// Whether the code was found light-on-dark, with
+    /// `Scanner::try_inverted`
+    pub inverted: bool,
+    /// Fiducial markers found in the frame, if the scanner was given a
+    /// `FiducialDetector`
+    pub markers: Vec<fiducial::Marker>,
 }
 
 impl ScanResult {
     pub fn new() -> Self {
-        Self { targets: Vec::new(), ..Default::default() }
+        Self { targets: List::new(), ..Default::default() }
+    }
+
+    /// All four corners of the code that was found: top-left, top-right,
+    /// bottom-right, bottom-left. Degenerate target layouts can come out
+    /// with NaN corners, which count as no code.
+    pub fn quad(&self) -> Option<[Point<f64>; 4]> {
+        self.bbox
+            .filter(|bbox| bbox.iter().all(|p| p.x.is_finite() && p.y.is_finite()))
+            .map(target::complete_quad)
+    }
+
+    /// How much of its error correction a read code had to spare, from 0 to
+    /// 1: the share of its correctable codewords that weren't wrong, times
+    /// the share of the 4 format information bit errors that weren't needed
+    /// (3 can be corrected). 1 means nothing had to be fixed. None if the
+    /// payload wasn't read.
+    pub fn confidence(&self) -> Option<f64> {
+        let format = self.format?;
+        let correctable = decode::correctable_codewords(self.version?, format.ec_level)?;
+        let codewords = 1.0 - self.codeword_errors? as f64 / correctable as f64;
+        let format_bits = 1.0 - format.errors as f64 / 4.0;
+        Some((codewords * format_bits).clamp(0.0, 1.0))
+    }
+
+    /// Estimates the pose of the detected code, given the camera's intrinsics
+    /// and the code's physical side length. See `Pose::from_bbox`.
+    pub fn pose(&self, intrinsics: &calib::CameraIntrinsics, code_size: f64) -> Option<pose::Pose> {
+        pose::Pose::from_bbox(self.bbox?, code_size, intrinsics)
+    }
+
+    /// Estimates how far away the detected code is, given the camera's
+    /// intrinsics and the code's physical side length. See
+    /// `CameraIntrinsics::distance_to_bbox`.
+    pub fn distance(&self, intrinsics: &calib::CameraIntrinsics, code_size: f64) -> Option<f64> {
+        Some(intrinsics.distance_to_bbox(self.bbox?, code_size))
     }
 }
 
+/// Scans a single image. To scan a stream of frames, hold on to a `Scanner`
+/// instead, which reuses its buffers between frames.
 pub fn scan<Px, C>(img: &ImageBuffer<Px, C>) -> ScanResult
 where
     Px: Pixel<Subpixel = u8>,
     C: Deref<Target = [u8]>
 {
-    let bmp = Bitmap::from_u8_img_dynamic(img);
-    let targets = find_pos_targets(&bmp);
-    let bbox = pick_corners(&targets);
-    let mut vectors = None;
-    let code_img = if let Some(bbox) = bbox {
-        let len = to_side_len(bbox);
-        let trans = to_affine_transform(bbox, len);
-        // println!("{:?}", trans);
-        let width = img.width() / 2;
-        let angle_h = bbox[0].angle_to(bbox[1]);
-        let angle_v = bbox[0].angle_to(bbox[1]);
-        let vector_h = Point::new(200.0 * angle_h.cos(), 200.0 * angle_h.sin());
-        let vector_v = Point::new(200.0 * angle_v.cos(), 200.0 * angle_v.sin());
-        vectors = Some([vector_h, vector_v]);
-        Some(affine_transform_chunk(&bmp, trans, width, width).convert())
-    } else { None };
-    let targets = targets.into_iter().map(|t| t.to_f64()).collect();
-    ScanResult { targets, bbox, code_img, vectors }
+    Scanner::new().scan(img)
+}
+
+/// Scans a single image view, such as a `SubImage`, without copying it. See
+/// `Scanner::scan_view`.
+pub fn scan_view<I>(img: &I) -> ScanResult
+where
+    I: GenericImageView,
+    I::Pixel: Pixel<Subpixel = u8>,
+{
+    Scanner::new().scan_view(img)
+}
+
+/// Runs the scanner over an already binarized image
+pub fn scan_bitmap(bmp: &Bitmap) -> ScanResult {
+    Scanner::new().scan_bitmap(bmp)
 }
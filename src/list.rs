@@ -0,0 +1,152 @@
+//! The list type the scanner uses for its per-frame collections, and for the
+//! buffers it reads codes into.
+//!
+//! Normally a `List` is just a `Vec`. With the `heapless` feature it's backed
+//! by a fixed-capacity `heapless::Vec` instead, so that the scanner's memory
+//! footprint is known at compile time (e.g. for microcontrollers). In that
+//! mode, anything pushed past a list's capacity is dropped.
+//!
+//! The lists are the position targets (the scanner's, and
+//! `ScanResult::targets`), the runs `decode::timing_size` counts, and
+//! everything a code is read into: the `ModuleGrid`, the function modules
+//! `mask` skips, the codewords, their blocks, and the corrected data. The
+//! frame's `Bitmap`, the straightened `code_img` and the payload are still
+//! `Vec`s, as they're sized by the frame and the message.
+//!
+//! The capacities can be tuned at build time through environment variables,
+//! e.g. `ARQR_MAX_TARGETS=8 ARQR_MAX_VERSION=10 cargo build --features heapless`.
+
+use std::ops::{Deref, DerefMut};
+
+/// Parses a build-time environment variable as a `usize`, for use in consts
+const fn parse_max(var: Option<&str>, default: usize) -> usize {
+    let bytes = match var {
+        Some(s) => s.as_bytes(),
+        None => return default,
+    };
+    let mut n = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii_digit(), "arqr capacity variables must be whole numbers");
+        n = n * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    n
+}
+
+/// Maximum number of position targets kept per frame
+pub const MAX_TARGETS: usize = parse_max(option_env!("ARQR_MAX_TARGETS"), 32);
+
+/// Largest QR version read. Bigger codes are found, but not sampled. This
+/// holds without the `heapless` feature too.
+pub const MAX_VERSION: usize = parse_max(option_env!("ARQR_MAX_VERSION"), 40);
+
+/// Modules in the largest code read
+pub const MAX_MODULES: usize = (MAX_VERSION * 4 + 17) * (MAX_VERSION * 4 + 17);
+
+/// Codewords in the largest code read, or a few more
+pub const MAX_CODEWORDS: usize = MAX_MODULES / 8;
+
+/// Maximum number of error correction blocks in a code read. Version 40 at
+/// level H has the most, 81; codes with more aren't read.
+pub const MAX_BLOCKS: usize = parse_max(option_env!("ARQR_MAX_BLOCKS"), 81);
+
+/// Codewords in the longest error correction block QR codes have
+pub const MAX_BLOCK_LEN: usize = 153;
+
+/// Maximum number of runs of light and dark pixels along a timing pattern.
+/// A line with more than that isn't counted.
+pub const MAX_RUNS: usize = parse_max(option_env!("ARQR_MAX_RUNS"), 512);
+
+/// A list which is either a `Vec` or, with the `heapless` feature, a
+/// fixed-capacity array holding at most `N` elements
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct List<T, const N: usize> {
+    #[cfg(not(feature = "heapless"))]
+    inner: Vec<T>,
+    #[cfg(feature = "heapless")]
+    inner: heapless::Vec<T, N>,
+}
+
+impl<T, const N: usize> List<T, N> {
+    pub fn new() -> Self {
+        Self { inner: Default::default() }
+    }
+
+    /// Appends an element. Returns `false` (and drops the element) if the
+    /// list is at capacity, which can only happen with the `heapless` feature.
+    pub fn push(&mut self, val: T) -> bool {
+        #[cfg(not(feature = "heapless"))]
+        {
+            self.inner.push(val);
+            true
+        }
+        #[cfg(feature = "heapless")]
+        {
+            self.inner.push(val).is_ok()
+        }
+    }
+
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        self.inner.swap_remove(index)
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+}
+
+impl<T, const N: usize> Default for List<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Deref for List<T, N> {
+    type Target = [T];
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<T, const N: usize> DerefMut for List<T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl<T, const N: usize> FromIterator<T> for List<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = Self::new();
+        for val in iter {
+            if !list.push(val) { break; }
+        }
+        list
+    }
+}
+
+impl<T, const N: usize> Extend<T> for List<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for val in iter {
+            if !self.push(val) { break; }
+        }
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a List<T, N> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut List<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = std::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.iter_mut()
+    }
+}
@@ -1,12 +1,38 @@
 
-use std::{thread, sync::mpsc, path::Path};
-use image::{ImageBuffer, buffer::ConvertBuffer};
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    thread,
+    sync::{Arc, mpsc, atomic::{AtomicU32, Ordering}},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use image::{GrayImage, ImageBuffer, Pixel, RgbaImage, buffer::ConvertBuffer, imageops};
 use nokhwa::{
+    Buffer,
     Camera,
     pixel_format::RgbAFormat,
-    utils::{CameraIndex, RequestedFormat, RequestedFormatType}
+    utils::{
+        ApiBackend,
+        CameraFormat,
+        CameraIndex,
+        FrameFormat,
+        KnownCameraControl,
+        RequestedFormat,
+        RequestedFormatType,
+        Resolution,
+    },
 };
 use piston_window::{
+    Button,
+    Context,
+    Key,
+    PressEvent,
+    G2d,
+    G2dTexture,
+    G2dTextureContext,
+    ImageSize,
+    math::Matrix2d,
     PistonWindow,
     Texture,
     TextureSettings,
@@ -15,158 +41,1773 @@ use piston_window::{
     text::Text,
     Transformed,
 };
-use arqr::{scan, ScanResult};
+use arqr::{
+    FrameMeta,
+    Point,
+    Rect,
+    ScanResult,
+    Scanner,
+    bitmap::{Binarizer, Bitmap},
+    braille::BrailleCanvas,
+    calib::CameraIntrinsics,
+    change::ChangeDetector,
+    camera::{AutoExposure, luma_from_nokhwa_frame, nudge_control},
+    config::{BinarizerKind, CONFIG_FILE, Config, ConfigWatcher, FilterKind, KeyConfig, OverlayConfig, ScannerConfig},
+    corpus::Corpus,
+    draw::{Line, Overlay},
+    filter,
+    flow::CornerFlow,
+    pose::{mul_mat4, project_mvp},
+    record::{Recorder, Recording},
+    target::complete_quad,
+    tracker::{Track, TrackId, Tracker},
+};
+#[cfg(feature = "clipboard")]
+use arqr::clipboard::Clipboard;
+#[cfg(feature = "beep")]
+use rodio::{OutputStream, OutputStreamBuilder, Source as _, source::SineWave};
+#[cfg(feature = "urls")]
+use std::collections::HashSet;
+#[cfg(feature = "urls")]
+use arqr::{config::UrlConfig, url::web_url};
+#[cfg(feature = "video")]
+use arqr::video::VideoFrames;
+#[cfg(feature = "compare")]
+use arqr::compare::{self, Agreement, Outcome, Reference};
+
+/// Built in, so the demo runs from any directory
+const FONT: &[u8] = include_bytes!("../assets/Roboto-Regular.ttf");
 
+/// Frame rate assumed for recordings without timestamps
 const FPS: u32 = 30;
-const SCAN_INTERVAL: u32 = 2;
-const LINE_COLOR: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+/// How much to pad the regions the scanner searches around predicted codes,
+/// relative to each code's size
+const REGION_MARGIN: f64 = 0.5;
+/// Number of times in a row a code has to read the same link before the
+/// demo offers to open it, so a misread can't send anyone anywhere
+#[cfg(feature = "urls")]
+const URL_STABLE_READS: u32 = 3;
+/// How long a code's outline flashes green when it's first read
+const FLASH_LENGTH: Duration = Duration::from_millis(500);
+const FLASH_COLOR: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+/// Pitch and length of the beep when a code is first read
+#[cfg(feature = "beep")]
+const BEEP_HZ: f32 = 1000.0;
+#[cfg(feature = "beep")]
+const BEEP_LENGTH: Duration = Duration::from_millis(80);
+/// Shortest time between frames saved with `--corpus`
+const CORPUS_MIN_GAP: Duration = Duration::from_secs(1);
+/// Guess at the webcam's horizontal field of view, since it isn't calibrated
+const CAMERA_FOV: f64 = 60.0 * std::f64::consts::PI / 180.0;
 
-fn main() {
-    let mut cam = Camera::new(
-        CameraIndex::Index(0),
-        RequestedFormat::new::<RgbAFormat>(RequestedFormatType::None)
-    ).unwrap();
-    let res = cam.resolution();
-    let width = res.width();
-    let height = res.height();
-    
-    // CAM THREAD gets frames from the camera
-    let (cam_tx, cam_rx) = mpsc::channel();
-    let (scan_tx, scan_rx) = mpsc::channel();
-    let cam_thread = thread::spawn(move || {
-        cam.set_frame_rate(FPS).unwrap();
-        cam.open_stream().unwrap();
-        let mut frame_counter = 0;
-
-        let mut send_result = Ok(());
-        while send_result.is_ok() {
-            let frame_buf = cam.frame().unwrap();
-            let frame = frame_buf.decode_image::<RgbAFormat>().unwrap();
-
-            send_result = cam_tx.send(frame.convert());
-
-            frame_counter += 1;
-            if frame_counter >= SCAN_INTERVAL {
-                scan_tx.send(frame).unwrap();
-                frame_counter = 0;
+const CLAHE_TILES: u32 = 8;
+const CLAHE_CLIP: f64 = 3.0;
+
+/// Outlines of codes found by the reference decoder, with `compare`
+#[cfg(feature = "compare")]
+const REFERENCE_COLOR: [f32; 4] = [1.0, 0.5, 0.0, 1.0];
+
+/// Number of panels down the side of each feed (see `Feed::side_panels`)
+const SIDE_PANELS: u32 = 2;
+
+/// Height of the text drawn under each code
+const CODE_LABEL_SIZE: f64 = 16.0;
+/// Most characters of a payload shown under its code
+const CODE_LABEL_CHARS: usize = 40;
+
+/// Filter run over frames before they're scanned and shown
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Preprocess {
+    #[default]
+    None,
+    /// See `filter::edge_2_in_place`
+    Edges,
+    /// See `filter::clahe_in_place`
+    Clahe,
+}
+
+impl Preprocess {
+    fn next(self) -> Self {
+        match self {
+            Preprocess::None => Preprocess::Edges,
+            Preprocess::Edges => Preprocess::Clahe,
+            Preprocess::Clahe => Preprocess::None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Preprocess::None => "no filter",
+            Preprocess::Edges => "edges",
+            Preprocess::Clahe => "CLAHE",
+        }
+    }
+
+    fn from_kind(kind: FilterKind) -> Self {
+        match kind {
+            FilterKind::None => Preprocess::None,
+            FilterKind::Edges => Preprocess::Edges,
+            FilterKind::Clahe => Preprocess::Clahe,
+        }
+    }
+
+    fn apply<Px, C>(self, img: &mut ImageBuffer<Px, C>)
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]> + DerefMut,
+    {
+        match self {
+            Preprocess::None => {}
+            Preprocess::Edges => filter::edge_2_in_place(img),
+            Preprocess::Clahe => filter::clahe_in_place(img, CLAHE_TILES, CLAHE_CLIP),
+        }
+    }
+}
+
+/// How frames are prepared for scanning. Switched at runtime with F (cycle
+/// filters) and B (global or adaptive binarization).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct ScanConfig {
+    preprocess: Preprocess,
+    binarizer: Binarizer,
+}
+
+impl ScanConfig {
+    /// How frames are prepared to begin with
+    fn from_settings(settings: &ScannerConfig) -> Self {
+        Self { preprocess: Preprocess::from_kind(settings.filter), binarizer: settings.binarizer() }
+    }
+
+    fn describe(&self) -> String {
+        let binarizer = match self.binarizer {
+            Binarizer::Global => "global threshold",
+            Binarizer::Adaptive { .. } => "adaptive threshold",
+        };
+        format!("{}, {}", self.preprocess.name(), binarizer)
+    }
+}
+
+/// Counts events to work out how often they happen, averaged over about a
+/// second
+#[derive(Clone, Copy, Debug)]
+struct RateMeter {
+    count: u32,
+    since: Instant,
+    /// Events per second over the last full period
+    rate: f64,
+}
+
+impl RateMeter {
+    fn new() -> Self {
+        Self { count: 0, since: Instant::now(), rate: 0.0 }
+    }
+
+    fn tick(&mut self) {
+        self.count += 1;
+        let elapsed = self.since.elapsed().as_secs_f64();
+        if elapsed >= 1.0 {
+            self.rate = self.count as f64 / elapsed;
+            self.count = 0;
+            self.since = Instant::now();
+        }
+    }
+}
+
+/// Where a feed's frames come from
+enum Source {
+    /// Live camera, with its index
+    Camera(u32, Camera),
+    /// Replays a recording, paced at its own frame rate
+    #[cfg(feature = "video")]
+    Video(PathBuf, VideoFrames),
+    /// Replays a session saved with `--record`, at its original timing, with
+    /// the size of its frames
+    Replay(PathBuf, Recording, (u32, u32)),
+}
+
+impl Source {
+    fn label(&self) -> String {
+        match self {
+            Source::Camera(index, _) => format!("cam {}", index),
+            #[cfg(feature = "video")]
+            Source::Video(path, _) => path.display().to_string(),
+            Source::Replay(path, ..) => format!("replay {}", path.display()),
+        }
+    }
+
+    fn resolution(&self) -> (u32, u32) {
+        match self {
+            Source::Camera(_, cam) => (cam.resolution().width(), cam.resolution().height()),
+            #[cfg(feature = "video")]
+            Source::Video(_, video) => (video.width(), video.height()),
+            Source::Replay(_, _, resolution) => *resolution,
+        }
+    }
+
+    fn frame_rate(&self) -> f64 {
+        match self {
+            Source::Camera(_, cam) => cam.frame_rate() as f64,
+            #[cfg(feature = "video")]
+            Source::Video(_, video) => video.fps(),
+            Source::Replay(_, recording, _) => recording.fps().unwrap_or(FPS as f64),
+        }
+    }
+}
+
+/// What the reference decoder made of the last frame scanned, and how often
+/// it's agreed with arqr so far. Only with the `compare` feature.
+#[cfg(feature = "compare")]
+struct Comparison {
+    reference: Outcome,
+    agreement: Agreement,
+}
+
+/// Frame as handed to the scan thread, in whatever form the source produced
+enum RawFrame {
+    Camera(Buffer),
+    /// From a video or a replay
+    Gray(GrayImage),
+    /// The frame on screen, sent back by the main thread to be scanned again
+    /// after the config changes. Not recorded a second time.
+    Rescan(GrayImage),
+}
+
+/// Sent from the main thread to a cam thread to pause or step through
+/// frames, or change the camera's settings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Control {
+    TogglePause,
+    /// Moves this many frames on (or back, for replays) and stays paused
+    Step(i64),
+    /// Only sent to cameras
+    Camera(CameraAdjust),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CameraAdjust {
+    /// Moves the exposure this many of the camera's steps up or down
+    Exposure(i64),
+    Gain(i64),
+    /// Turns `AutoExposure` on or off
+    ToggleAuto,
+}
+
+/// Pause and step state on the cam thread side
+struct Playback {
+    rx: mpsc::Receiver<Control>,
+    paused: bool,
+    /// Camera settings to change, left for the camera loop to pick up
+    adjustments: Vec<CameraAdjust>,
+}
+
+impl Playback {
+    /// Returns the size of the step asked for, if `control` was one
+    fn apply(&mut self, control: Control) -> Option<i64> {
+        match control {
+            Control::Camera(adjust) => {
+                self.adjustments.push(adjust);
+                None
+            }
+            Control::TogglePause => {
+                self.paused = !self.paused;
+                None
+            }
+            Control::Step(step) => {
+                self.paused = true;
+                Some(step)
             }
         }
+    }
+
+    /// For sources that can wait for the user: blocks while paused, until
+    /// there's a step or playback resumes. Returns how many frames to move on
+    /// by (1 when playing), and whether playback was held up, in which case
+    /// frame timing should restart from the next frame. Returns None once the
+    /// main thread has gone away.
+    fn wait(&mut self) -> Option<(i64, bool)> {
+        let mut held = self.paused;
+        while let Ok(control) = self.rx.try_recv() {
+            if let Some(step) = self.apply(control) {
+                return Some((step, true));
+            }
+            held |= self.paused;
+        }
+        while self.paused {
+            let control = self.rx.recv().ok()?;
+            if let Some(step) = self.apply(control) {
+                return Some((step, true));
+            }
+        }
+        Some((1, held))
+    }
+
+    /// Like `wait`, for sources that can't go backwards. Steps back are
+    /// ignored.
+    #[cfg(feature = "video")]
+    fn wait_forward(&mut self) -> Option<(i64, bool)> {
+        let mut held = false;
+        loop {
+            let (step, h) = self.wait()?;
+            held |= h;
+            if step > 0 {
+                return Some((step, held));
+            }
+        }
+    }
+
+    /// For live sources, which can't wait: returns whether a step was asked
+    /// for since last time. Frames should only go through while playing, or
+    /// on a step.
+    fn poll(&mut self) -> bool {
+        let mut stepped = false;
+        while let Ok(control) = self.rx.try_recv() {
+            stepped |= self.apply(control).is_some();
+        }
+        stepped
+    }
+}
+
+/// Starts the clock for frames timed relative to the start of a video or
+/// recording, so that a frame at `time` is due now
+fn clock_start(time: Duration) -> Instant {
+    let now = Instant::now();
+    now.checked_sub(time).unwrap_or(now)
+}
+
+/// The threads capturing and scanning one source's frames
+struct Pipeline {
+    /// Every frame captured, for display. Nothing's sent here in headless
+    /// mode.
+    frame_rx: mpsc::Receiver<RgbaImage>,
+    /// Where the scan thread should look in its next frame
+    region_tx: mpsc::Sender<Vec<Rect<f64>>>,
+    /// Changes to how the scan thread prepares frames
+    config_tx: mpsc::Sender<ScanConfig>,
+    /// New scanner settings, from a reloaded config file. Sent ahead of a
+    /// `ScanConfig`, which overrides the binarizer.
+    settings_tx: mpsc::Sender<ScannerConfig>,
+    /// Scan every this many frames. Read by the cam thread for each frame,
+    /// so it can be changed while running.
+    scan_interval: Arc<AtomicU32>,
+    /// Pauses and steps the cam thread
+    control_tx: mpsc::Sender<Control>,
+    /// What the cam thread did with the last `CameraAdjust`, or what auto
+    /// exposure did, for showing on screen
+    camera_rx: mpsc::Receiver<String>,
+    /// Hands frames straight to the scan thread. Only kept when displaying,
+    /// since it keeps the scan thread running after the source runs out.
+    rescan_tx: Option<mpsc::Sender<(FrameMeta, RawFrame)>>,
+    /// Every scanned frame is also run through `rqrr` when displaying, to
+    /// compare against
+    #[cfg(feature = "compare")]
+    compare_rx: mpsc::Receiver<Comparison>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl Pipeline {
+    /// Starts capturing from `source`. Scan results are sent to `result_tx`
+    /// tagged with `id`. Captured frames are only passed on for display if
+    /// `display` is set. Scanned frames are saved with their results if
+    /// there's a `recorder`, and frames the scanner failed on go into the
+    /// `corpus`, if there is one.
+    fn start(
+        id: usize,
+        source: Source,
+        result_tx: mpsc::Sender<(usize, ScanResult)>,
+        display: bool,
+        mut recorder: Option<Recorder>,
+        mut corpus: Option<Corpus>,
+        settings: ScannerConfig,
+    ) -> Self {
+        let label = source.label();
+        let scan_interval = Arc::new(AtomicU32::new(settings.scan_interval.max(1)));
+        let interval = scan_interval.clone();
+        // CAM THREAD gets frames from the camera (or video file)
+        let (cam_tx, cam_rx) = mpsc::channel();
+        let cam_tx = display.then_some(cam_tx);
+        let (scan_tx, scan_rx) = mpsc::channel();
+        let rescan_tx = display.then(|| scan_tx.clone());
+        let (control_tx, control_rx) = mpsc::channel();
+        let mut playback = Playback { rx: control_rx, paused: false, adjustments: Vec::new() };
+        let (camera_tx, camera_rx) = mpsc::channel();
+        let cam_thread = thread::spawn(move || match source {
+            Source::Camera(_, mut cam) => {
+                cam.open_stream().unwrap();
+                let mut frame_counter = 0;
+                let mut sequence = 0;
+                // Skips scans while the camera's looking at an unchanging scene
+                let mut change = ChangeDetector::new();
+                let mut auto_exposure: Option<AutoExposure> = None;
+
+                loop {
+                    let frame_buf = cam.frame().unwrap();
+                    let meta = FrameMeta { sequence, timestamp: Some(Instant::now()) };
+                    sequence += 1;
+                    // Cameras don't stop, so frames are just dropped while
+                    // paused
+                    let stepped = playback.poll();
+                    for adjust in playback.adjustments.drain(..) {
+                        let status = match adjust {
+                            CameraAdjust::Exposure(steps) => nudge_control(&mut cam, KnownCameraControl::Exposure, steps)
+                                .map(|value| format!("exposure {}", value)),
+                            CameraAdjust::Gain(steps) => nudge_control(&mut cam, KnownCameraControl::Gain, steps)
+                                .map(|value| format!("gain {}", value)),
+                            CameraAdjust::ToggleAuto => {
+                                auto_exposure = match auto_exposure {
+                                    Some(_) => None,
+                                    None => Some(AutoExposure::new()),
+                                };
+                                Ok(format!("auto exposure {}", if auto_exposure.is_some() { "on" } else { "off" }))
+                            }
+                        };
+                        camera_tx.send(status.unwrap_or_else(|e| e.to_string())).ok();
+                    }
+                    if playback.paused && !stepped {
+                        continue;
+                    }
+                    let frame = frame_buf.decode_image::<RgbAFormat>().unwrap();
+                    if let Some(auto) = &mut auto_exposure {
+                        match auto.update(&mut cam, &frame) {
+                            Ok(Some(value)) => { camera_tx.send(format!("auto exposure {}", value)).ok(); }
+                            Ok(None) => {}
+                            // Not worth trying again every few frames
+                            Err(e) => {
+                                camera_tx.send(format!("auto exposure off: {}", e)).ok();
+                                auto_exposure = None;
+                            }
+                        }
+                    }
+
+                    frame_counter += 1;
+                    let scan_due = frame_counter >= interval.load(Ordering::Relaxed);
+                    if scan_due {
+                        frame_counter = 0;
+                    }
+                    let scan = stepped || (scan_due && change.should_scan(&frame));
+
+                    if cam_tx.as_ref().is_some_and(|tx| tx.send(frame).is_err()) { break; }
+
+                    if scan {
+                        // The scanner reads the raw frame, so it doesn't need to wait
+                        // on (or copy) the RGBA image decoded for display
+                        if scan_tx.send((meta, RawFrame::Camera(frame_buf))).is_err() { break; }
+                    }
+                }
+            }
+            #[cfg(feature = "video")]
+            Source::Video(_, video) => {
+                let mut start = None;
+                let mut skip = 0;
+                for (n, frame) in video.enumerate() {
+                    let frame = match frame {
+                        Ok(frame) => frame,
+                        Err(e) => {
+                            eprintln!("video: {}", e);
+                            break;
+                        }
+                    };
+                    if skip > 0 {
+                        skip -= 1;
+                        continue;
+                    }
+                    // Play back in real time, so the tracker sees the same
+                    // motion it would have live
+                    let shown_at = *start.get_or_insert_with(|| clock_start(frame.timestamp)) + frame.timestamp;
+                    if let Some(wait) = shown_at.checked_duration_since(Instant::now()) {
+                        thread::sleep(wait);
+                    }
+                    let meta = FrameMeta { sequence: frame.index, timestamp: Some(shown_at) };
+
+                    if let Some(tx) = &cam_tx {
+                        let rgba = image::DynamicImage::ImageLuma8(frame.image.clone()).to_rgba8();
+                        if tx.send(rgba).is_err() { break; }
+                    }
+                    // Scan every frame stepped to
+                    let interval = interval.load(Ordering::Relaxed);
+                    let scan = playback.paused || n as u32 % interval == interval - 1;
+                    if scan && scan_tx.send((meta, RawFrame::Gray(frame.image))).is_err() {
+                        break;
+                    }
+
+                    let Some((step, held)) = playback.wait_forward() else { break };
+                    skip = step - 1;
+                    if held {
+                        start = None;
+                    }
+                }
+            }
+            Source::Replay(_, recording, _) => {
+                let mut start = None;
+                let frame_time = Duration::from_secs_f64(1.0 / FPS as f64);
+                let frames = recording.frames();
+                let mut n = 0;
+                while let Some(frame) = frames.get(n) {
+                    let image = match frame.load() {
+                        Ok(image) => image,
+                        Err(e) => {
+                            eprintln!("replay: {}: {}", frame.path.display(), e);
+                            break;
+                        }
+                    };
+                    let time = frame.time.unwrap_or(frame_time * n as u32);
+                    let shown_at = *start.get_or_insert_with(|| clock_start(time)) + time;
+                    if let Some(wait) = shown_at.checked_duration_since(Instant::now()) {
+                        thread::sleep(wait);
+                    }
+                    let meta = FrameMeta { sequence: frame.sequence, timestamp: Some(shown_at) };
+
+                    if let Some(tx) = &cam_tx {
+                        let rgba = image::DynamicImage::ImageLuma8(image.clone()).to_rgba8();
+                        if tx.send(rgba).is_err() { break; }
+                    }
+                    // Only scanned frames were recorded, so scan all of them
+                    if scan_tx.send((meta, RawFrame::Gray(image))).is_err() { break; }
+
+                    let Some((step, held)) = playback.wait() else { break };
+                    // Stepping back from the first frame stays there
+                    n = n.saturating_add_signed(step as isize);
+                    if held {
+                        start = None;
+                    }
+                }
+            }
+        });
+
+        // SCAN THREAD hands frames to the scanner and passes back the results
+        let (region_tx, region_rx) = mpsc::channel();
+        let (config_tx, config_rx) = mpsc::channel();
+        let (settings_tx, settings_rx) = mpsc::channel::<ScannerConfig>();
+        #[cfg(feature = "compare")]
+        let (compare_tx, compare_rx) = mpsc::channel();
+        let scan_thread = thread::spawn(move || {
+            #[cfg(feature = "compare")]
+            let mut agreement = Agreement::default();
+            let mut scanner = Scanner::new();
+            settings.configure(&mut scanner);
+            let mut config = ScanConfig::from_settings(&settings);
+            while let Ok((meta, frame)) = scan_rx.recv() {
+                if let Some(new_settings) = settings_rx.try_iter().last() {
+                    new_settings.configure(&mut scanner);
+                }
+                if let Some(new_config) = config_rx.try_iter().last() {
+                    config = new_config;
+                    scanner.binarizer = config.binarizer;
+                }
+                // Only search where the tracker expects codes to be, if it's
+                // sent any predictions since the last scan
+                if let Some(regions) = region_rx.try_iter().last() {
+                    scanner.set_regions(regions);
+                }
+                scanner.set_frame_meta(meta);
+                // Filtering needs a decoded image, so raw camera frames can
+                // only be scanned directly when there's no filter
+                let result = match &frame {
+                    RawFrame::Camera(buf) if config.preprocess == Preprocess::None => {
+                        scanner.scan_nokhwa_frame(buf).unwrap_or_default()
+                    }
+                    RawFrame::Camera(buf) => match luma_from_nokhwa_frame(buf) {
+                        Ok(mut img) => {
+                            config.preprocess.apply(&mut img);
+                            scanner.scan(&img)
+                        }
+                        Err(_) => ScanResult::default(),
+                    },
+                    RawFrame::Gray(img) | RawFrame::Rescan(img) if config.preprocess == Preprocess::None => {
+                        scanner.scan(img)
+                    }
+                    RawFrame::Gray(img) | RawFrame::Rescan(img) => {
+                        let mut img = img.clone();
+                        config.preprocess.apply(&mut img);
+                        scanner.scan(&img)
+                    }
+                };
+                // Runs on the same (filtered) image arqr scanned, and goes
+                // ahead of the result so it's there when the result arrives
+                #[cfg(feature = "compare")]
+                if display {
+                    let img = match &frame {
+                        RawFrame::Camera(buf) => luma_from_nokhwa_frame(buf).ok(),
+                        RawFrame::Gray(img) | RawFrame::Rescan(img) => Some(img.clone()),
+                    };
+                    if let Some(mut img) = img {
+                        config.preprocess.apply(&mut img);
+                        let reference = Reference::Rqrr.run(&image::DynamicImage::ImageLuma8(img));
+                        agreement.add(&compare::arqr_outcome(&result), &reference);
+                        compare_tx.send(Comparison { reference, agreement }).ok();
+                    }
+                }
+                // Rescans are of frames that were already offered
+                let rescan = matches!(frame, RawFrame::Rescan(_));
+                if let Some(corp) = corpus.as_mut().filter(|_| !rescan && Corpus::is_failure(&result)) {
+                    let img = match &frame {
+                        RawFrame::Camera(buf) => luma_from_nokhwa_frame(buf).ok(),
+                        RawFrame::Gray(img) | RawFrame::Rescan(img) => Some(img.clone()),
+                    };
+                    let source = format!("{} frame {}", label, result.meta.sequence);
+                    if let Some(Err(e)) = img.map(|img| corp.offer(&img, &result, &source)) {
+                        eprintln!("stopped saving failed frames: {}", e);
+                        corpus = None;
+                    }
+                }
+                if let Some(rec) = &mut recorder {
+                    let img = match frame {
+                        RawFrame::Camera(buf) => luma_from_nokhwa_frame(&buf).ok(),
+                        RawFrame::Gray(img) => Some(img),
+                        RawFrame::Rescan(_) => None,
+                    };
+                    if let Some(Err(e)) = img.map(|img| rec.record(&img, &result)) {
+                        eprintln!("recording stopped: {}", e);
+                        recorder = None;
+                    }
+                }
+                if result_tx.send((id, result)).is_err() { break; }
+            }
+        });
+
+        Pipeline {
+            frame_rx: cam_rx,
+            region_tx,
+            config_tx,
+            settings_tx,
+            scan_interval,
+            control_tx,
+            camera_rx,
+            rescan_tx,
+            #[cfg(feature = "compare")]
+            compare_rx,
+            threads: vec![cam_thread, scan_thread],
+        }
+    }
+}
+
+/// One camera or video, with the threads capturing and scanning its frames,
+/// and everything the main thread keeps around to draw it
+struct Feed {
+    /// Shown in the corner of the feed's tile
+    label: String,
+    width: u32,
+    height: u32,
+    fps: f64,
+    pipeline: Pipeline,
+    config: ScanConfig,
+    scan_interval: u32,
+    colors: OverlayConfig,
+    /// Whether the source is paused (see `Control`)
+    paused: bool,
+    /// Whether the source is a camera, with settings to change
+    live: bool,
+    /// Latest news from the cam thread about the camera's settings
+    camera_status: Option<String>,
+    /// How often frames arrive from the camera
+    frame_rate: RateMeter,
+    /// How often scan results arrive
+    scan_rate: RateMeter,
+    tex: G2dTexture,
+    /// What the scanner made of the latest frame it was sent
+    bitmap_tex: G2dTexture,
+    code_tex: G2dTexture,
+    empty_img: RgbaImage,
+    /// The newest frame, kept for snapshots
+    frame: RgbaImage,
+    gray: GrayImage,
+    scan_result: ScanResult,
+    #[cfg(feature = "compare")]
+    comparison: Option<Comparison>,
+    // Smooths out the bbox so it doesn't jitter around with a handheld camera,
+    // and stops it flickering in and out when detection is spotty
+    tracker: Tracker,
+    // Follows the code between scans so the overlay doesn't lag behind
+    flow: CornerFlow,
+    /// When each tracked code was first read, for the flash around it
+    first_read: HashMap<TrackId, Instant>,
+    intrinsics: CameraIntrinsics,
+    projection: [f32; 16],
+}
+
+impl Feed {
+    /// Starts capturing from `source`. Scan results are sent to `result_tx`
+    /// tagged with `id`, the feed's position in the window. See
+    /// `Pipeline::start`.
+    fn start(
+        id: usize,
+        source: Source,
+        result_tx: mpsc::Sender<(usize, ScanResult)>,
+        recorder: Option<Recorder>,
+        corpus: Option<Corpus>,
+        settings: &Config,
+        ctx: &mut G2dTextureContext,
+    ) -> Self {
+        let label = source.label();
+        let (width, height) = source.resolution();
+        let fps = source.frame_rate();
+        let live = matches!(source, Source::Camera(..));
+        let pipeline = Pipeline::start(id, source, result_tx, true, recorder, corpus, settings.scanner);
+
+        let img = pipeline.frame_rx.recv().unwrap();
+        let tex = Texture::from_image(ctx, &img, &TextureSettings::new()).unwrap();
+        let bitmap_tex = Texture::from_image(ctx, &img, &TextureSettings::new()).unwrap();
+
+        let code_dim = width / 2;
+        let empty_img = RgbaImage::new(code_dim, code_dim);
+        let code_tex = Texture::from_image(ctx, &empty_img, &TextureSettings::new()).unwrap();
+
+        let intrinsics = CameraIntrinsics::from_fov(width, height, CAMERA_FOV);
+        let projection = intrinsics.gl_projection(width, height, 0.1, 100.0);
+
+        Feed {
+            label,
+            width,
+            height,
+            fps,
+            pipeline,
+            config: ScanConfig::from_settings(&settings.scanner),
+            scan_interval: settings.scanner.scan_interval.max(1),
+            colors: settings.overlay,
+            paused: false,
+            live,
+            camera_status: None,
+            frame_rate: RateMeter::new(),
+            scan_rate: RateMeter::new(),
+            tex,
+            bitmap_tex,
+            code_tex,
+            empty_img,
+            gray: imageops::grayscale(&img),
+            frame: img,
+            scan_result: ScanResult::new(),
+            #[cfg(feature = "compare")]
+            comparison: None,
+            tracker: Tracker::new(),
+            flow: CornerFlow::new(),
+            first_read: HashMap::new(),
+            intrinsics,
+            projection,
+        }
+    }
+
+    /// Picks up the newest camera frame, if there is one
+    fn update_frame(&mut self, ctx: &mut G2dTextureContext) {
+        if let Some(status) = self.pipeline.camera_rx.try_iter().last() {
+            self.camera_status = Some(status);
+        }
+        let mut newest = None;
+        for img in self.pipeline.frame_rx.try_iter() {
+            self.frame_rate.tick();
+            newest = Some(img);
+        }
+        if let Some(img) = newest {
+            // Show frames the way the scanner sees them, but track the
+            // unfiltered frame
+            if self.config.preprocess == Preprocess::None {
+                self.tex.update(ctx, &img).unwrap();
+            } else {
+                let mut shown = img.clone();
+                self.config.preprocess.apply(&mut shown);
+                self.tex.update(ctx, &shown).unwrap();
+            }
+            self.gray = imageops::grayscale(&img);
+            self.flow.step(&self.gray);
+            self.frame = img;
+        }
+    }
+
+    /// Changes how frames are scanned. While paused, the frame on screen is
+    /// scanned again straight away to show the difference.
+    fn set_config(&mut self, config: ScanConfig) {
+        self.config = config;
+        self.pipeline.config_tx.send(config).ok();
+        if self.paused {
+            self.rescan();
+        }
+    }
+
+    /// Switches to the settings in a reloaded config file. Whatever filter
+    /// and binarizer the hotkeys picked are replaced by the file's.
+    fn reload(&mut self, settings: &Config) {
+        self.colors = settings.overlay;
+        self.scan_interval = settings.scanner.scan_interval.max(1);
+        self.pipeline.scan_interval.store(self.scan_interval, Ordering::Relaxed);
+        self.pipeline.settings_tx.send(settings.scanner).ok();
+        self.set_config(ScanConfig::from_settings(&settings.scanner));
+    }
+
+    /// Sends the frame on screen to be scanned again
+    fn rescan(&self) {
+        let meta = FrameMeta { sequence: self.scan_result.meta.sequence, timestamp: Some(Instant::now()) };
+        if let Some(tx) = &self.pipeline.rescan_tx {
+            tx.send((meta, RawFrame::Rescan(self.gray.clone()))).ok();
+        }
+    }
+
+    fn control(&mut self, control: Control) {
+        match control {
+            Control::TogglePause => self.paused = !self.paused,
+            Control::Step(_) => self.paused = true,
+            // Videos and replays have no settings to change
+            Control::Camera(_) if !self.live => return,
+            Control::Camera(_) => {}
+        }
+        self.pipeline.control_tx.send(control).ok();
+    }
+
+    /// Saves the current frame, its binarized bitmap, the rectified code (if
+    /// there is one) and the frame with the overlay drawn on, as PNGs named
+    /// `<prefix>-frame.png` and so on
+    fn save_snapshot(&self, prefix: &str) -> image::ImageResult<()> {
+        self.frame.save(format!("{}-frame.png", prefix))?;
+        let bitmap: GrayImage = Bitmap::from_u8_img_dynamic(&self.frame).convert();
+        bitmap.save(format!("{}-bitmap.png", prefix))?;
+        if let Some(code) = &self.scan_result.code_img {
+            code.save(format!("{}-code.png", prefix))?;
+        }
+
+        let mut annotated = self.frame.clone();
+        self.overlay().draw_on(&mut annotated);
+        annotated.save(format!("{}-overlay.png", prefix))
+    }
+
+    /// Takes in the latest scan. Returns whether it read a code for the first
+    /// time since the code came into view.
+    fn update_result(&mut self, result: ScanResult, ctx: &mut G2dTextureContext) -> bool {
+        self.scan_rate.tick();
+        self.scan_result = result;
+        #[cfg(feature = "compare")]
+        if let Some(comparison) = self.pipeline.compare_rx.try_iter().last() {
+            self.comparison = Some(comparison);
+        }
+        self.tracker.update(&self.scan_result);
+        let tracks = self.tracker.tracks();
+        self.first_read.retain(|id, _| tracks.iter().any(|track| track.id == *id));
+        let mut newly_read = false;
+        for track in tracks.iter().filter(|track| track.payload.is_some()) {
+            self.first_read.entry(track.id).or_insert_with(|| {
+                newly_read = true;
+                Instant::now()
+            });
+        }
+        self.flow.reset(&self.gray, self.scan_result.bbox);
+        let next_scan = Instant::now() + Duration::from_secs_f64(self.scan_interval as f64 / self.fps);
+        self.pipeline.region_tx.send(self.tracker.predict_regions(next_scan, REGION_MARGIN)).ok();
+        match &self.scan_result.code_img {
+            Some(img) => self.code_tex.update(ctx, img).unwrap(),
+            None => self.code_tex.update(ctx, &self.empty_img).unwrap(),
+        }
+
+        // The scan thread keeps its bitmap to itself, so binarize the frame
+        // on screen the same way for the bitmap panel
+        let mut gray = self.gray.clone();
+        self.config.preprocess.apply(&mut gray);
+        let mut bmp = Bitmap::new(gray.width(), gray.height());
+        bmp.set_from_u8_img(&gray, self.config.binarizer);
+        let bitmap: RgbaImage = bmp.convert();
+        self.bitmap_tex.update(ctx, &bitmap).unwrap();
+        newly_read
+    }
+
+    /// Textures shown down the side of the feed, with their labels
+    fn side_panels(&self) -> [(&'static str, &G2dTexture); SIDE_PANELS as usize] {
+        [("binarized", &self.bitmap_tex), ("code", &self.code_tex)]
+    }
+
+    /// Everything drawn over the frame, in the frame's pixels
+    fn overlay(&self) -> Overlay {
+        let line_color = self.colors.line;
+        let mut overlay = Overlay::new();
+        overlay.targets(&self.scan_result.targets, line_color);
+
+        let confirmed = self.tracker.confirmed().next().is_some();
+        let boxes: Vec<_> = match self.flow.corners().filter(|_| confirmed) {
+            Some(corners) => vec![corners],
+            None => self.tracker.confirmed().map(|t| t.corners).collect(),
+        };
+        for points in boxes {
+            overlay.polygon(&points, line_color);
+        }
+
+        // Codes that have just been read flash green, fading out
+        for track in self.tracker.confirmed() {
+            let Some(read_at) = self.first_read.get(&track.id) else { continue };
+            let fade = 1.0 - read_at.elapsed().as_secs_f32() / FLASH_LENGTH.as_secs_f32();
+            if fade > 0.0 {
+                let quad = complete_quad(track.corners);
+                let color = [FLASH_COLOR[0], FLASH_COLOR[1], FLASH_COLOR[2], FLASH_COLOR[3] * fade];
+                for (i, &from) in quad.iter().enumerate() {
+                    overlay.lines.push(Line { from, to: quad[(i + 1) % quad.len()], color, width: 4.0 });
+                }
+            }
+        }
+
+        // What each code says, under it
+        for track in self.tracker.confirmed() {
+            let quad = complete_quad(track.corners);
+            let left = quad.iter().map(|p| p.x).fold(f64::INFINITY, f64::min);
+            let bottom = quad.iter().map(|p| p.y).fold(f64::NEG_INFINITY, f64::max);
+            for (i, text) in code_labels(track).into_iter().enumerate() {
+                let at = Point::new(left, bottom + (CODE_LABEL_SIZE + 4.0) * (i + 1) as f64);
+                overlay.label(text, at, CODE_LABEL_SIZE, line_color);
+            }
+        }
+
+        #[cfg(feature = "compare")]
+        if let Some(comparison) = &self.comparison {
+            for quad in &comparison.reference.quads {
+                overlay.polygon(quad, REFERENCE_COLOR);
+            }
+        }
+
+        // Draw 3D axes anchored to the code's top-left corner. Size is
+        // arbitrary here, so axes are as long as the code is wide.
+        let (width, height) = (self.width, self.height);
+        for track in self.tracker.confirmed() {
+            let mvp = match track.model_view(&self.intrinsics, 1.0) {
+                Some(mv) => mul_mat4(&self.projection, &mv),
+                None => continue,
+            };
+            let origin = project_mvp(&mvp, [0.0; 3], width, height);
+            let axes = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]];
+            for (axis, &color) in axes.iter().zip(self.colors.axes.iter()) {
+                if let (Some(from), Some(to)) = (origin, project_mvp(&mvp, *axis, width, height)) {
+                    overlay.lines.push(Line { from, to, color, width: 2.0 });
+                }
+            }
+        }
+        overlay
+    }
+
+    fn draw(&self, transform: Matrix2d, c: &Context, g: &mut G2d, glyphs: &mut Glyphs) {
+        let line_color = self.colors.line;
+        piston_window::image(&self.tex, transform, g);
+        let overlay = self.overlay();
+        for line in &overlay.lines {
+            let (a, b) = (line.from, line.to);
+            piston_window::line(line.color, line.width, [a.x, a.y, b.x, b.y], transform, g);
+        }
+        for label in &overlay.labels {
+            Text::new_color(label.color, label.size as u32).draw(
+                &label.text,
+                glyphs,
+                &c.draw_state,
+                transform.trans(label.at.x, label.at.y),
+                g
+            ).unwrap();
+        }
+        let (width, height) = (self.width, self.height);
+
+        // Each side panel is scaled to fit a half-size box to the right of
+        // the feed
+        let (panel_w, panel_h) = (width as f64 / 2.0, height as f64 / 2.0);
+        let panel_transform = |i: usize, tex: &G2dTexture| {
+            let (tex_w, tex_h) = tex.get_size();
+            let scale = (panel_w / tex_w as f64).min(panel_h / tex_h as f64);
+            transform.trans(width as f64, panel_h * i as f64).scale(scale, scale)
+        };
+        for (i, (name, tex)) in self.side_panels().into_iter().enumerate() {
+            piston_window::image(tex, panel_transform(i, tex), g);
+            Text::new_color(line_color, 14).draw(
+                name,
+                glyphs,
+                &c.draw_state,
+                transform.trans(width as f64 + 4.0, panel_h * i as f64 + 16.0),
+                g
+            ).unwrap();
+        }
+
+        if let Some(vs) = self.scan_result.vectors {
+            // The code is the second panel
+            let code = panel_transform(1, &self.code_tex);
+            piston_window::line(line_color, 1.0, [0.0, 0.0, vs[0].x, vs[0].y], code, g);
+            piston_window::line(line_color, 1.0, [0.0, 0.0, vs[1].x, vs[1].y], code, g);
+        }
+
+        // Timings are from the last scan, rates from the last second
+        let t = &self.scan_result.timings;
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let mut hud = Vec::new();
+        #[cfg(feature = "compare")]
+        if let Some(Comparison { agreement: a, .. }) = &self.comparison {
+            hud.push(format!(
+                "vs rqrr: agree {:.0}% of {} frames, only arqr {}, only rqrr {}",
+                a.detection_rate() * 100.0, a.images, a.only_arqr, a.only_reference,
+            ));
+        }
+        hud.extend([
+            format!(
+                "binarize {:.1} ms, targets {:.1} ms, corners {:.1} ms, extract {:.1} ms, decode {:.1} ms, markers {:.1} ms",
+                ms(t.binarize), ms(t.targets), ms(t.corners), ms(t.extract), ms(t.decode), ms(t.fiducials),
+            ),
+            format!(
+                "camera {:.1} fps, scanning {:.1} fps, last scan {:.1} ms",
+                self.frame_rate.rate, self.scan_rate.rate, ms(t.total()),
+            ),
+            format!(
+                "{} ({}){}{}",
+                self.label,
+                self.config.describe(),
+                if self.paused { ", paused" } else { "" },
+                self.camera_status.as_ref().map_or(String::new(), |status| format!(", {}", status)),
+            ),
+        ]);
+        for (i, line) in hud.iter().enumerate() {
+            let y = height as f64 - 6.0 - 18.0 * (hud.len() - 1 - i) as f64;
+            Text::new_color(line_color, 16).draw(
+                line,
+                glyphs,
+                &c.draw_state,
+                transform.trans(4.0, y),
+                g
+            ).unwrap();
+        }
+    }
+}
+
+/// Lines of text shown under a tracked code: what it says, then its version
+/// and error correction level, once they've been read
+fn code_labels(track: &Track) -> Vec<String> {
+    let mut labels = Vec::new();
+    if let Some(payload) = &track.payload {
+        let text = String::from_utf8_lossy(payload);
+        let mut shown: String = text.chars()
+            .take(CODE_LABEL_CHARS)
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        if text.chars().count() > CODE_LABEL_CHARS {
+            shown.push_str("...");
+        }
+        labels.push(format!("\"{}\"", shown));
+    }
+    match (track.version, track.ec_level) {
+        (Some(version), Some(ec_level)) => labels.push(format!("version {}, EC level {:?}", version, ec_level)),
+        (Some(version), None) => labels.push(format!("version {}", version)),
+        (None, _) => {}
+    }
+    labels
+}
+
+/// Size of the tile a feed of the given resolution is drawn in: the feed
+/// itself, with a column of half-size panels down its right side
+fn tile_size((width, height): (u32, u32)) -> (u32, u32) {
+    (width + width / 2, height.max(height / 2 * SIDE_PANELS))
+}
+
+const USAGE: &str = if cfg!(feature = "video") {
+    "usage: arqr [--config FILE] [--headless | --tui] [--record DIR] [--corpus DIR] [--binarizer global|adaptive] [--camera INDEX]... [--width W --height H] [--fps FPS] [--video PATH]... [--replay DIR|VIDEO]... [CAMERA_INDEX...]\n       arqr --list-cameras"
+} else {
+    "usage: arqr [--config FILE] [--headless | --tui] [--record DIR] [--corpus DIR] [--binarizer global|adaptive] [--camera INDEX]... [--width W --height H] [--fps FPS] [--replay DIR]... [CAMERA_INDEX...]\n       arqr --list-cameras"
+};
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+/// How to set up every camera opened
+#[derive(Clone, Copy, Debug)]
+struct CameraOptions {
+    /// Resolution to ask for. The camera picks the closest it supports.
+    resolution: Option<(u32, u32)>,
+    fps: u32,
+}
+
+/// Parses a numeric flag's value
+fn flag_value(args: &mut impl Iterator<Item = String>) -> u32 {
+    args.next().and_then(|v| v.parse().ok()).unwrap_or_else(|| usage())
+}
+
+/// What the command line asked for
+struct Args {
+    sources: Vec<Source>,
+    headless: bool,
+    /// Draw in the terminal instead of a window
+    tui: bool,
+    /// Where to record scanned frames and results
+    record: Option<PathBuf>,
+    /// Where to save frames the scanner failed on
+    corpus: Option<PathBuf>,
+    /// From `arqr.toml` (or `--config`), with the command line's overrides
+    config: Config,
+    /// Where the config came from, or would have, for watching for changes
+    config_path: PathBuf,
+}
+
+impl Args {
+    /// Starts a recording for feed `id`, if one was asked for. With more
+    /// than one feed, each gets its own subdirectory.
+    fn recorder(&self, id: usize) -> Option<Recorder> {
+        let dir = self.record.as_ref()?;
+        let dir = if self.sources.len() > 1 { dir.join(format!("feed{}", id)) } else { dir.clone() };
+        Recorder::create(&dir).map_err(|e| {
+            eprintln!("couldn't record to {}: {}", dir.display(), e);
+            std::process::exit(1);
+        }).ok()
+    }
+
+    /// Opens the failed-frame corpus for feed `id`, if one was asked for.
+    /// Like recordings, each feed gets its own subdirectory when there's
+    /// more than one.
+    fn corpus(&self, id: usize) -> Option<Corpus> {
+        let dir = self.corpus.as_ref()?;
+        let dir = if self.sources.len() > 1 { dir.join(format!("feed{}", id)) } else { dir.clone() };
+        let mut corpus = Corpus::create(&dir).map_err(|e| {
+            eprintln!("couldn't save failed frames to {}: {}", dir.display(), e);
+            std::process::exit(1);
+        }).ok()?;
+        corpus.min_gap = CORPUS_MIN_GAP;
+        Some(corpus)
+    }
+}
+
+/// Opens the cameras and videos named on the command line, and loads the
+/// config. With no sources given, just opens camera 0.
+fn parse_args() -> Args {
+    let mut cameras = Vec::new();
+    #[cfg(feature = "video")]
+    let mut videos = Vec::new();
+    let (mut width, mut height, mut fps) = (None, None, None);
+    let mut headless = false;
+    let mut tui = false;
+    let mut record = None;
+    let mut corpus = None;
+    let mut replays = Vec::new();
+    let mut config_path = None;
+    let mut binarizer = None;
+
+    // Not a `for` loop, since flags take the next argument too
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list-cameras" => {
+                list_cameras();
+                std::process::exit(0);
+            }
+            "--headless" => headless = true,
+            "--tui" => tui = true,
+            "--config" => config_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--binarizer" => binarizer = match args.next().as_deref() {
+                Some("global") => Some(BinarizerKind::Global),
+                Some("adaptive") => Some(BinarizerKind::Adaptive),
+                _ => usage(),
+            },
+            "--replay" => replays.push(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--record" => record = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--corpus" => corpus = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--camera" => cameras.push(flag_value(&mut args)),
+            "--width" => width = Some(flag_value(&mut args)),
+            "--height" => height = Some(flag_value(&mut args)),
+            "--fps" => fps = Some(flag_value(&mut args)),
+            #[cfg(feature = "video")]
+            "--video" => videos.push(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            _ => cameras.push(arg.parse().unwrap_or_else(|_| usage())),
+        }
+    }
+    let mut config = Config::find(config_path.as_deref()).unwrap_or_else(|e| {
+        let path = config_path.as_deref().unwrap_or(Path::new(CONFIG_FILE));
+        eprintln!("couldn't load {}: {}", path.display(), e);
+        std::process::exit(1);
+    });
+    if let Some(binarizer) = binarizer {
+        config.scanner.binarizer = binarizer;
+    }
+
+    // The resolution on the command line replaces the config's as a whole
+    let resolution = match (width, height) {
+        (Some(w), Some(h)) => Some((w, h)),
+        (None, None) => match (config.camera.width, config.camera.height) {
+            (Some(w), Some(h)) => Some((w, h)),
+            (None, None) => None,
+            _ => {
+                eprintln!("the config needs both a camera width and height, or neither");
+                std::process::exit(1);
+            }
+        },
+        _ => usage(),
+    };
+    let options = CameraOptions { resolution, fps: fps.unwrap_or(config.camera.fps) };
+
+    let mut sources: Vec<Source> = cameras.into_iter()
+        .map(|index| Source::Camera(index, open_camera(index, options)))
+        .collect();
+    #[cfg(feature = "video")]
+    for path in videos {
+        let video = VideoFrames::open(&path).unwrap_or_else(|e| {
+            eprintln!("couldn't open {}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+        sources.push(Source::Video(path, video));
+    }
+    for path in replays {
+        sources.push(open_replay(path));
+    }
+    if sources.is_empty() {
+        sources.push(Source::Camera(0, open_camera(0, options)));
+    }
+    if headless && tui {
+        usage();
+    }
+    let config_path = config_path.unwrap_or_else(|| PathBuf::from(CONFIG_FILE));
+    Args { sources, headless, tui, record, corpus, config, config_path }
+}
+
+/// Opens a recording made with `--record`, or a video file
+fn open_replay(path: PathBuf) -> Source {
+    let fail = |e: &dyn std::fmt::Display| -> ! {
+        eprintln!("couldn't open {}: {}", path.display(), e);
+        std::process::exit(1);
+    };
+    if !path.is_dir() {
+        #[cfg(feature = "video")]
+        {
+            let video = VideoFrames::open(&path).unwrap_or_else(|e| fail(&e));
+            return Source::Video(path, video);
+        }
+        #[cfg(not(feature = "video"))]
+        fail(&"replaying video files needs the `video` feature");
+    }
+    let recording = Recording::open(&path).unwrap_or_else(|e| fail(&e));
+    let first = match recording.frames().first() {
+        Some(frame) => frame.load().unwrap_or_else(|e| fail(&e)),
+        None => fail(&"the recording is empty"),
+    };
+    Source::Replay(path, recording, first.dimensions())
+}
+
+/// Prints every camera that can be opened, with its index
+fn list_cameras() {
+    match nokhwa::query(ApiBackend::Auto) {
+        Ok(cameras) if cameras.is_empty() => println!("no cameras found"),
+        Ok(cameras) => {
+            for info in cameras {
+                println!("{}: {} ({})", info.index(), info.human_name(), info.description());
+            }
+        }
+        Err(e) => {
+            eprintln!("couldn't list cameras: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn open_camera(index: u32, options: CameraOptions) -> Camera {
+    let requested = match options.resolution {
+        Some((w, h)) => RequestedFormatType::Closest(
+            CameraFormat::new(Resolution::new(w, h), FrameFormat::MJPEG, options.fps),
+        ),
+        None => RequestedFormatType::None,
+    };
+    let mut cam = Camera::new(CameraIndex::Index(index), RequestedFormat::new::<RgbAFormat>(requested))
+        .unwrap_or_else(|e| {
+            eprintln!("couldn't open camera {}: {}", index, e);
+            std::process::exit(1);
+        });
+    if options.resolution.is_none() {
+        if let Err(e) = cam.set_frame_rate(options.fps) {
+            eprintln!("camera {} can't run at {} fps: {}", index, options.fps, e);
+        }
+    }
+    cam
+}
+
+/// Offers to open web links that codes read, or opens them straight away
+/// if the config says so (see `UrlConfig`)
+#[cfg(feature = "urls")]
+struct UrlHandler {
+    settings: UrlConfig,
+    /// Codes whose link has been dealt with, by feed and track, so each is
+    /// only offered once while it stays in view
+    handled: HashSet<(usize, TrackId)>,
+    /// The link waiting for an answer
+    pending: Option<String>,
+}
+
+#[cfg(feature = "urls")]
+impl UrlHandler {
+    fn new(settings: UrlConfig) -> Self {
+        Self { settings, handled: HashSet::new(), pending: None }
+    }
+
+    /// Looks for links among the codes feed `id` is tracking
+    fn check(&mut self, id: usize, tracker: &Tracker) {
+        self.handled.retain(|&(feed, track)| feed != id || tracker.tracks().iter().any(|t| t.id == track));
+        for track in tracker.confirmed().filter(|track| track.reads >= URL_STABLE_READS) {
+            let Some(url) = track.payload.as_deref().and_then(web_url) else { continue };
+            if self.handled.contains(&(id, track.id)) {
+                continue;
+            }
+            if !self.settings.allows(url) {
+                eprintln!("not opening {}: its host isn't allowed", url);
+            } else if !self.settings.should_confirm() {
+                open_url(url);
+            } else if self.pending.is_none() {
+                eprintln!("open {}?", url);
+                self.pending = Some(url.to_owned());
+            } else {
+                // Offered once the link already on screen is answered
+                continue;
+            }
+            self.handled.insert((id, track.id));
+        }
+    }
+
+    /// Opens the pending link, if there is one
+    fn accept(&mut self) {
+        if let Some(url) = self.pending.take() {
+            open_url(&url);
+        }
+    }
+
+    fn dismiss(&mut self) {
+        self.pending = None;
+    }
+}
+
+#[cfg(feature = "urls")]
+fn open_url(url: &str) {
+    match webbrowser::open(url) {
+        Ok(()) => eprintln!("opened {}", url),
+        Err(e) => eprintln!("couldn't open {}: {}", url, e),
+    }
+}
+
+/// Beeps when a code is first read, if there's a sound device to beep on
+#[cfg(feature = "beep")]
+struct Beeper(Option<OutputStream>);
+
+#[cfg(feature = "beep")]
+impl Beeper {
+    fn new() -> Self {
+        match OutputStreamBuilder::open_default_stream() {
+            Ok(mut stream) => {
+                stream.log_on_drop(false);
+                Self(Some(stream))
+            }
+            Err(e) => {
+                eprintln!("couldn't open a sound device to beep on: {}", e);
+                Self(None)
+            }
+        }
+    }
+
+    fn beep(&self) {
+        if let Some(stream) = &self.0 {
+            stream.mixer().add(SineWave::new(BEEP_HZ).take_duration(BEEP_LENGTH).amplify(0.2));
+        }
+    }
+}
+
+/// The demo's hotkeys, from the config
+struct Hotkeys {
+    snapshot: Key,
+    filter: Key,
+    binarizer: Key,
+    pause: Key,
+    step: Key,
+    step_back: Key,
+    exposure_up: Key,
+    exposure_down: Key,
+    gain_up: Key,
+    gain_down: Key,
+    auto_exposure: Key,
+    copy: Key,
+    #[cfg(feature = "urls")]
+    open_url: Key,
+    #[cfg(feature = "urls")]
+    dismiss_url: Key,
+}
+
+impl Hotkeys {
+    fn from_config(keys: &KeyConfig) -> Result<Self, String> {
+        let key = |name: &str| key_from_name(name).ok_or_else(|| format!("unknown key in config: {:?}", name));
+        Ok(Hotkeys {
+            snapshot: key(&keys.snapshot)?,
+            filter: key(&keys.filter)?,
+            binarizer: key(&keys.binarizer)?,
+            pause: key(&keys.pause)?,
+            step: key(&keys.step)?,
+            step_back: key(&keys.step_back)?,
+            exposure_up: key(&keys.exposure_up)?,
+            exposure_down: key(&keys.exposure_down)?,
+            gain_up: key(&keys.gain_up)?,
+            gain_down: key(&keys.gain_down)?,
+            auto_exposure: key(&keys.auto_exposure)?,
+            copy: key(&keys.copy)?,
+            #[cfg(feature = "urls")]
+            open_url: key(&keys.open_url)?,
+            #[cfg(feature = "urls")]
+            dismiss_url: key(&keys.dismiss_url)?,
+        })
+    }
+}
+
+/// Looks up a key by the names described in `KeyConfig`
+fn key_from_name(name: &str) -> Option<Key> {
+    let key = match name {
+        "Space" => Key::Space,
+        "Tab" => Key::Tab,
+        "Return" => Key::Return,
+        "Left" => Key::Left,
+        "Right" => Key::Right,
+        "Up" => Key::Up,
+        "Down" => Key::Down,
+        // Letters and digits have their ASCII codes (lower case, for
+        // letters)
+        _ if name.len() == 1 && name.as_bytes()[0].is_ascii_alphanumeric() => {
+            Key::from(name.as_bytes()[0].to_ascii_lowercase() as u32)
+        }
+        _ => match name.strip_prefix('F')?.parse::<u32>().ok()? {
+            n @ 1..=12 => Key::from(u32::from(Key::F1) + n - 1),
+            _ => return None,
+        },
+    };
+    Some(key)
+}
+
+/// A source being scanned with no window to show it in
+struct HeadlessFeed {
+    label: String,
+    fps: f64,
+    scan_interval: u32,
+    /// Only used to tell the scanner where to look next
+    tracker: Tracker,
+    pipeline: Pipeline,
+}
+
+/// Scans every source without opening a window, printing each code found.
+/// Runs until every source runs out of frames (or forever, for cameras).
+/// Where the code `result` found is, its version, and what it says or why
+/// it couldn't be read, on one line. None if no code was found.
+fn describe_code(result: &ScanResult) -> Option<String> {
+    let corners: Vec<String> = result.quad()?.iter()
+        .map(|p| format!("({:.1}, {:.1})", p.x, p.y))
+        .collect();
+    let mut line = format!("code at {}", corners.join(" "));
+    if let Some(version) = result.version {
+        line.push_str(&format!(", version {}", version));
+    }
+    match (&result.payload, result.decode_error) {
+        (Some(payload), _) => line.push_str(&format!(", reads {:?}", String::from_utf8_lossy(payload))),
+        (None, Some(e)) => line.push_str(&format!(", but {}", e)),
+        (None, None) => {}
+    }
+    Some(line)
+}
+
+fn run_headless(mut args: Args) {
+    let (result_tx, result_rx) = mpsc::channel();
+    let recorders: Vec<_> = (0..args.sources.len()).map(|id| args.recorder(id)).collect();
+    let corpora: Vec<_> = (0..args.sources.len()).map(|id| args.corpus(id)).collect();
+    let mut feeds: Vec<HeadlessFeed> = args.sources.drain(..).zip(recorders.into_iter().zip(corpora)).enumerate()
+        .map(|(id, (source, (recorder, corpus)))| HeadlessFeed {
+            label: source.label(),
+            fps: source.frame_rate(),
+            scan_interval: args.config.scanner.scan_interval.max(1),
+            tracker: Tracker::new(),
+            pipeline: Pipeline::start(id, source, result_tx.clone(), false, recorder, corpus, args.config.scanner),
+        })
+        .collect();
+    drop(result_tx);
+
+    for (id, result) in result_rx {
+        let feed = &mut feeds[id];
+        feed.tracker.update(&result);
+        let next_scan = Instant::now() + Duration::from_secs_f64(feed.scan_interval as f64 / feed.fps);
+        feed.pipeline.region_tx.send(feed.tracker.predict_regions(next_scan, REGION_MARGIN)).ok();
+
+        if let Some(code) = describe_code(&result) {
+            println!("{} frame {}: {}", feed.label, result.meta.sequence, code);
+        }
+    }
+
+    for thread in feeds.into_iter().flat_map(|feed| feed.pipeline.threads) {
+        thread.join().unwrap();
+    }
+}
+
+/// Works out how big the terminal is, in characters. Falls back to 80x24.
+fn terminal_size() -> (u32, u32) {
+    let from_env = |name| std::env::var(name).ok().and_then(|v| v.parse().ok());
+    if let (Some(cols), Some(rows)) = (from_env("COLUMNS"), from_env("LINES")) {
+        return (cols, rows);
+    }
+    // Shells don't usually export the size, so ask the terminal
+    let stty = std::fs::File::open("/dev/tty").ok().and_then(|tty| {
+        std::process::Command::new("stty").arg("size").stdin(tty).output().ok()
+    });
+    let size = stty.and_then(|out| {
+        let out = String::from_utf8(out.stdout).ok()?;
+        let mut parts = out.split_whitespace().map(|n| n.parse().ok());
+        let rows = parts.next()??;
+        Some((parts.next()??, rows))
     });
+    size.unwrap_or((80, 24))
+}
+
+/// Lines under the picture in the TUI
+const TUI_PANE_LINES: u32 = 3;
+
+/// Shows the first source in the terminal, as its binarized bitmap drawn in
+/// braille, with found codes outlined and a line or two about each scan.
+/// Runs until the source runs out of frames (or forever, for cameras).
+fn run_tui(mut args: Args) {
+    if args.sources.len() > 1 {
+        eprintln!("--tui shows one source at a time");
+        std::process::exit(2);
+    }
+    let recorder = args.recorder(0);
+    let corpus = args.corpus(0);
+    let source = args.sources.remove(0);
+    let label = source.label();
+    let fps = source.frame_rate();
+    let settings = args.config.scanner;
+    let scan_interval = settings.scan_interval.max(1);
+    let scan_config = ScanConfig::from_settings(&settings);
+    let [r, g, b, _] = args.config.overlay.line.map(|c| (c * 255.0) as u8);
+    let mark_color = [r, g, b];
 
-    // SCAN THREAD hands frames to the scanner and passes back the results
+    let (cols, rows) = terminal_size();
+    let mut canvas = BrailleCanvas::new(cols, rows.saturating_sub(TUI_PANE_LINES + 1).max(1));
     let (result_tx, result_rx) = mpsc::channel();
-    let scan_thread = thread::spawn(move || {
-        let mut send_result = Ok(());
-        while send_result.is_ok() {
-            let frame = scan_rx.recv();
-            if frame.is_err() { break; }
-            let result = scan(&frame.unwrap());
-            send_result = result_tx.send(result);
+    let mut pipeline = Pipeline::start(0, source, result_tx, true, recorder, corpus, settings);
+    // Nothing's rescanned here, and holding on to this would keep the scan
+    // thread waiting after the source runs out
+    pipeline.rescan_tx = None;
+    // There's nowhere to show comparisons
+    #[cfg(feature = "compare")]
+    drop(pipeline.compare_rx);
+    let mut tracker = Tracker::new();
+    let mut bmp = Bitmap::new(0, 0);
+
+    // Clear the screen once, then redraw over the top
+    print!("\x1b[2J");
+    for (_, result) in result_rx {
+        tracker.update(&result);
+        let next_scan = Instant::now() + Duration::from_secs_f64(scan_interval as f64 / fps);
+        pipeline.region_tx.send(tracker.predict_regions(next_scan, REGION_MARGIN)).ok();
+
+        // Draw the newest frame the way the scanner sees it
+        if let Some(img) = pipeline.frame_rx.try_iter().last() {
+            let mut gray = imageops::grayscale(&img);
+            scan_config.preprocess.apply(&mut gray);
+            bmp.set_from_u8_img(&gray, scan_config.binarizer);
         }
+        if bmp.width() == 0 {
+            continue;
+        }
+        let scale = canvas.draw_bitmap(&bmp);
+        let dots = |p: Point<f64>| Point::new(p.x * scale, p.y * scale);
+        for t in result.targets.iter() {
+            canvas.mark_line(dots(Point::new(t.min.x, t.mid.y)), dots(Point::new(t.max.x, t.mid.y)));
+            canvas.mark_line(dots(Point::new(t.mid.x, t.min.y)), dots(Point::new(t.mid.x, t.max.y)));
+        }
+        for track in tracker.confirmed() {
+            let quad = complete_quad(track.corners);
+            for i in 0..4 {
+                canvas.mark_line(dots(quad[i]), dots(quad[(i + 1) % 4]));
+            }
+        }
+
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let code = describe_code(&result).unwrap_or_else(|| "no code".to_owned());
+        let mut out = String::from("\x1b[H");
+        for line in canvas.to_lines(mark_color) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        // Clear to the end of each pane line, since they change length
+        out.push_str(&format!("{} ({})\x1b[K\n", label, scan_config.describe()));
+        out.push_str(&format!(
+            "frame {}: {:?}, {} targets, scan {:.1} ms\x1b[K\n",
+            result.meta.sequence, result.stage, result.targets.len(), ms(result.timings.total()),
+        ));
+        out.push_str(&format!("{}\x1b[K\n", code));
+        print!("{}", out);
+    }
+
+    for thread in pipeline.threads {
+        thread.join().unwrap();
+    }
+}
+
+fn main() {
+    let mut args = parse_args();
+    if args.headless {
+        run_headless(args);
+        return;
+    }
+    if args.tui {
+        run_tui(args);
+        return;
+    }
+    let recorders: Vec<_> = (0..args.sources.len()).map(|id| args.recorder(id)).collect();
+    let corpora: Vec<_> = (0..args.sources.len()).map(|id| args.corpus(id)).collect();
+    let sources = std::mem::take(&mut args.sources);
+    let mut keys = Hotkeys::from_config(&args.config.keys).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
     });
+    let mut watcher = ConfigWatcher::new(&args.config_path);
+
+    // Feeds are tiled in a grid that's as close to square as possible, each
+    // tile big enough for the largest camera and its panels
+    let tile_w = sources.iter().map(|source| tile_size(source.resolution()).0).max().unwrap();
+    let tile_h = sources.iter().map(|source| tile_size(source.resolution()).1).max().unwrap();
+    let cols = (sources.len() as f64).sqrt().ceil() as u32;
+    let rows = (sources.len() as u32).div_ceil(cols);
 
-    // meanwhile, main thread draws the camera feed and scan results
     let mut window: PistonWindow =
-        WindowSettings::new("QR", [width, height])
+        WindowSettings::new("QR", [tile_w * cols, tile_h * rows])
         .exit_on_esc(true)
         .build()
         .unwrap();
 
-    let font = Path::new("./assets/Roboto-Regular.ttf");
     let font_ctx = window.create_texture_context();
-    let mut glyphs = Glyphs::new(
-        &font,
+    let mut glyphs = Glyphs::from_bytes(
+        FONT,
         font_ctx,
         TextureSettings::new()
     ).unwrap();
 
-    let mut cam_ctx = window.create_texture_context();
-    let img = cam_rx.recv().unwrap();
-    let mut cam_tex = Texture::from_image(
-        &mut cam_ctx,
-        &img,
-        &TextureSettings::new()
-    ).unwrap();
-
-    let mut code_ctx = window.create_texture_context();
-    let code_dim = width / 2;
-    let empty_img_data = vec![0; (code_dim * code_dim * 4) as usize];
-    let empty_img = ImageBuffer::from_raw(code_dim, code_dim, empty_img_data).unwrap();
-    let mut code_tex = Texture::from_image(
-        &mut code_ctx,
-        &empty_img,
-        &TextureSettings::new()
-    ).unwrap();
-
-    let mut scan_result = ScanResult::new();
+    // Every scan thread sends to the same channel, tagging results with the
+    // feed they came from
+    let (result_tx, result_rx) = mpsc::channel();
+    let mut tex_ctx = window.create_texture_context();
+    let mut feeds: Vec<Feed> = sources.into_iter().zip(recorders.into_iter().zip(corpora)).enumerate()
+        .map(|(id, (source, (recorder, corpus)))| {
+            Feed::start(id, source, result_tx.clone(), recorder, corpus, &args.config, &mut tex_ctx)
+        })
+        .collect();
+    drop(result_tx);
 
+    let mut config = ScanConfig::from_settings(&args.config.scanner);
+    // The last payload any feed read, for the copy hotkey
+    #[cfg(feature = "clipboard")]
+    let (mut clipboard, mut last_payload) = (Clipboard::new(), None::<Vec<u8>>);
+    #[cfg(feature = "urls")]
+    let mut urls = UrlHandler::new(args.config.urls.clone());
+    #[cfg(feature = "beep")]
+    let beeper = Beeper::new();
     while let Some(e) = window.next() {
-        if let Ok(img) = cam_rx.try_recv() {
-            // filter::binarize_u8_in_place(&mut img);
-            cam_tex.update(&mut cam_ctx, &img).unwrap();
+        for feed in feeds.iter_mut() {
+            feed.update_frame(&mut tex_ctx);
         }
-
-        if let Ok(result) = result_rx.try_recv() {
-            scan_result = result;
-            if let Some(img) = scan_result.code_img {
-                code_tex.update(&mut code_ctx, &img).unwrap();
-            } else {
-                code_tex.update(&mut code_ctx, &empty_img).unwrap();
+        // Pick up edits to the config file. Camera settings only apply at
+        // startup, and flags on the command line don't apply again.
+        match watcher.poll() {
+            Some(Ok(reloaded)) => match Hotkeys::from_config(&reloaded.keys) {
+                Ok(new_keys) => {
+                    keys = new_keys;
+                    feeds.iter_mut().for_each(|feed| feed.reload(&reloaded));
+                    config = ScanConfig::from_settings(&reloaded.scanner);
+                    #[cfg(feature = "urls")]
+                    {
+                        urls.settings = reloaded.urls.clone();
+                    }
+                    args.config = reloaded;
+                    eprintln!("reloaded {}", watcher.path().display());
+                }
+                Err(e) => eprintln!("couldn't reload {}: {}", watcher.path().display(), e),
+            },
+            Some(Err(e)) => eprintln!("couldn't reload {}: {}", watcher.path().display(), e),
+            None => {}
+        }
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            // Saves a snapshot of every feed, for collecting frames that
+            // don't scan properly
+            if key == keys.snapshot {
+                let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+                for (id, feed) in feeds.iter().enumerate() {
+                    let prefix = format!("arqr-{}-{}", stamp, id);
+                    match feed.save_snapshot(&prefix) {
+                        Ok(()) => eprintln!("saved {}-*.png", prefix),
+                        Err(e) => eprintln!("couldn't save snapshot: {}", e),
+                    }
+                }
+            } else if key == keys.filter {
+                config.preprocess = config.preprocess.next();
+                feeds.iter_mut().for_each(|feed| feed.set_config(config));
+            } else if key == keys.binarizer {
+                config.binarizer = match config.binarizer {
+                    Binarizer::Global => args.config.scanner.adaptive(),
+                    Binarizer::Adaptive { .. } => Binarizer::Global,
+                };
+                feeds.iter_mut().for_each(|feed| feed.set_config(config));
+            } else if key == keys.pause {
+                // Stepping pauses too. Replays can step back as well as
+                // forward.
+                feeds.iter_mut().for_each(|feed| feed.control(Control::TogglePause));
+            } else if key == keys.step {
+                feeds.iter_mut().for_each(|feed| feed.control(Control::Step(1)));
+            } else if key == keys.step_back {
+                feeds.iter_mut().for_each(|feed| feed.control(Control::Step(-1)));
+            } else if key == keys.exposure_up {
+                // Camera settings only change where the backend allows it.
+                // Other sources ignore them.
+                feeds.iter_mut().for_each(|feed| feed.control(Control::Camera(CameraAdjust::Exposure(1))));
+            } else if key == keys.exposure_down {
+                feeds.iter_mut().for_each(|feed| feed.control(Control::Camera(CameraAdjust::Exposure(-1))));
+            } else if key == keys.gain_up {
+                feeds.iter_mut().for_each(|feed| feed.control(Control::Camera(CameraAdjust::Gain(1))));
+            } else if key == keys.gain_down {
+                feeds.iter_mut().for_each(|feed| feed.control(Control::Camera(CameraAdjust::Gain(-1))));
+            } else if key == keys.auto_exposure {
+                feeds.iter_mut().for_each(|feed| feed.control(Control::Camera(CameraAdjust::ToggleAuto)));
+            } else if key == keys.copy {
+                #[cfg(feature = "clipboard")]
+                match &last_payload {
+                    Some(payload) => match clipboard.copy_payload(payload) {
+                        Ok(()) => eprintln!("copied {:?}", String::from_utf8_lossy(payload)),
+                        Err(e) => eprintln!("couldn't copy: {}", e),
+                    },
+                    None => eprintln!("nothing's been read to copy yet"),
+                }
+                #[cfg(not(feature = "clipboard"))]
+                eprintln!("copying needs the clipboard feature");
+            }
+            #[cfg(feature = "urls")]
+            if key == keys.open_url {
+                urls.accept();
+            } else if key == keys.dismiss_url {
+                urls.dismiss();
+            }
+        }
+        for (id, result) in result_rx.try_iter() {
+            #[cfg(feature = "clipboard")]
+            if let Some(payload) = &result.payload {
+                last_payload = Some(payload.clone());
+            }
+            if feeds[id].update_result(result, &mut tex_ctx) {
+                #[cfg(feature = "beep")]
+                beeper.beep();
             }
+            #[cfg(feature = "urls")]
+            urls.check(id, &feeds[id].tracker);
         }
 
         window.draw_2d(&e, |c, g, d| {
             piston_window::clear([1.0; 4], g);
-            piston_window::image(&cam_tex, c.transform, g);
-            for (n, &t) in scan_result.targets.iter().enumerate() {
-                let h_line = [t.min.x, t.mid.y, t.max.x, t.mid.y];
-                let v_line = [t.mid.x, t.min.y, t.mid.x, t.max.y];
-                piston_window::line(LINE_COLOR, 1.0, h_line, c.transform, g);
-                piston_window::line(LINE_COLOR, 1.0, v_line, c.transform, g);
-                Text::new_color(LINE_COLOR, 12).draw(
-                    &n.to_string(),
-                    &mut glyphs,
-                    &c.draw_state,
-                    c.transform.trans(t.min.x, t.min.y),
-                    g
-                ).unwrap();
-            }
-    
-            if let Some(points) = scan_result.bbox {
-                for win in points.windows(2) {
-                    let line = [win[0].x, win[0].y, win[1].x, win[1].y];
-                    piston_window::line(LINE_COLOR, 1.0, line, c.transform, g);
-                }
-                let line = [points[2].x, points[2].y, points[0].x, points[0].y];
-                piston_window::line(LINE_COLOR, 1.0, line, c.transform, g);
-
-                // let rect = c.viewport.unwrap().rect;
-                // let rect = [rect[0] as f64, rect[1] as f64, rect[2] as f64, rect[3] as f64];
-                // for pt in points.iter() {
-                //     CircleArc::new(LINE_COLOR, 5.0, 0.0, PI*2.0).draw(
-                //         [0.0, 0.0, 5.0, 5.0],
-                //         &c.draw_state,
-                //         c.transform.trans(pt.x, pt.y),
-                //         g
-                //     );
-                // }
+            for (id, feed) in feeds.iter().enumerate() {
+                let (col, row) = (id as u32 % cols, id as u32 / cols);
+                let transform = c.transform.trans((col * tile_w) as f64, (row * tile_h) as f64);
+                feed.draw(transform, &c, g, &mut glyphs);
             }
-
-            piston_window::image(&code_tex, c.transform, g);
-
-            if let Some(vs) = scan_result.vectors {
-                piston_window::line(LINE_COLOR, 1.0, [0.0, 0.0, vs[0].x, vs[0].y], c.transform, g);
-                piston_window::line(LINE_COLOR, 1.0, [0.0, 0.0, vs[1].x, vs[1].y], c.transform, g);
+            #[cfg(feature = "urls")]
+            if let Some(url) = &urls.pending {
+                let prompt = format!(
+                    "Open {}? {} to open, {} to dismiss",
+                    url, args.config.keys.open_url, args.config.keys.dismiss_url,
+                );
+                piston_window::rectangle([1.0, 1.0, 0.8, 1.0], [0.0, 0.0, (tile_w * cols) as f64, 28.0], c.transform, g);
+                Text::new_color([0.0, 0.0, 0.0, 1.0], 16)
+                    .draw(&prompt, &mut glyphs, &c.draw_state, c.transform.trans(8.0, 20.0), g)
+                    .unwrap();
             }
 
-            cam_ctx.encoder.flush(d);
-            code_ctx.encoder.flush(d);
+            tex_ctx.encoder.flush(d);
             glyphs.factory.encoder.flush(d);
         });
     }
 
     drop(result_rx);
-    drop(cam_rx);
-    scan_thread.join().unwrap();
-    cam_thread.join().unwrap();
+    let threads: Vec<_> = feeds.into_iter().flat_map(|feed| feed.pipeline.threads).collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
 }
@@ -0,0 +1,87 @@
+//! The eight mask patterns QR codes are drawn with. Every module outside the
+//! function patterns (finders, timing, alignment, format and version
+//! information) is flipped wherever the mask says, to break up patterns in the
+//! data that would be hard to scan. Flipping the same modules again takes the
+//! mask back off, so the data can be read once the format information says
+//! which mask was used.
+
+use crate::{
+    decode::ModuleGrid,
+    encode::alignment_positions,
+    list::{List, MAX_MODULES},
+};
+
+/// Whether the module at (`x`, `y`) is flipped by mask `mask`, from 0 to 7
+pub fn is_masked(mask: u8, x: u32, y: u32) -> bool {
+    match mask {
+        0 => (x + y).is_multiple_of(2),
+        1 => y.is_multiple_of(2),
+        2 => x.is_multiple_of(3),
+        3 => (x + y).is_multiple_of(3),
+        4 => (x / 3 + y / 2).is_multiple_of(2),
+        5 => x * y % 2 + x * y % 3 == 0,
+        6 => (x * y % 2 + x * y % 3).is_multiple_of(2),
+        _ => ((x + y) % 2 + x * y % 3).is_multiple_of(2),
+    }
+}
+
+/// Which modules of a version `version` QR code belong to function patterns,
+/// row-major. These are never masked, and don't hold data. Only versions up
+/// to `MAX_VERSION` fit.
+pub fn function_modules(version: u32) -> List<bool, MAX_MODULES> {
+    let size = version * 4 + 17;
+    let mut function: List<bool, MAX_MODULES> = std::iter::repeat_n(false, (size * size) as usize).collect();
+    let mut mark = |x0: u32, y0: u32, w: u32, h: u32| {
+        for y in y0..y0 + h {
+            for x in x0..x0 + w {
+                function[(y * size + x) as usize] = true;
+            }
+        }
+    };
+    // Finders with their separators, and the format information (and the
+    // dark module) next to them
+    mark(0, 0, 9, 9);
+    mark(size - 8, 0, 8, 9);
+    mark(0, size - 8, 9, 8);
+    // Timing
+    mark(6, 0, 1, size);
+    mark(0, 6, size, 1);
+    let align = alignment_positions(version);
+    let last = align.len().saturating_sub(1);
+    for (i, &cy) in align.iter().enumerate() {
+        for (j, &cx) in align.iter().enumerate() {
+            if (i == 0 && (j == 0 || j == last)) || (i == last && j == 0) {
+                continue;
+            }
+            mark(cx - 2, cy - 2, 5, 5);
+        }
+    }
+    if version >= 7 {
+        mark(size - 11, 0, 3, 6);
+        mark(0, size - 11, 6, 3);
+    }
+    function
+}
+
+/// Flips every data module of `grid` that mask `mask` covers. Returns false,
+/// leaving the grid alone, if it isn't a size QR codes come in.
+pub fn apply(grid: &mut ModuleGrid, mask: u8) -> bool {
+    let Some(version) = grid.version() else { return false };
+    let function = function_modules(version);
+    let size = grid.size;
+    for y in 0..size {
+        for x in 0..size {
+            let i = (y * size + x) as usize;
+            if !function[i] && is_masked(mask, x, y) {
+                grid.modules[i] ^= true;
+            }
+        }
+    }
+    true
+}
+
+/// Takes mask `mask` back off `grid`. Masks undo themselves, so this is the
+/// same as applying it again.
+pub fn remove(grid: &mut ModuleGrid, mask: u8) -> bool {
+    apply(grid, mask)
+}
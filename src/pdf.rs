@@ -0,0 +1,137 @@
+//! Rasterizes the pages of PDFs, for pulling codes out of scanned documents.
+//!
+//! As with `video`, the work is left to an executable on the `PATH`, here
+//! poppler's `pdftoppm`, rather than linking a PDF renderer in. Pages are
+//! rendered in greyscale, one at a time, as they're iterated over.
+
+use std::{
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    process::{Child, ChildStdout, Command, Stdio},
+};
+use image::GrayImage;
+
+/// Resolution pages are rendered at by `PdfPages::open`. Scanned archives
+/// are usually 200 to 300 DPI, and codes printed on them need a few pixels
+/// per module.
+pub const DEFAULT_DPI: u32 = 200;
+
+/// Whether `path` looks like a PDF, going by its extension
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+pub struct PdfPage {
+    /// Page number, counting from 0
+    pub index: usize,
+    pub image: GrayImage,
+}
+
+/// Iterator over the rendered pages of a PDF
+pub struct PdfPages {
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+    index: usize,
+    done: bool,
+}
+
+fn other_err(msg: String) -> io::Error {
+    io::Error::other(msg)
+}
+
+/// Reads the next whitespace-separated field of a PGM header, skipping
+/// comments. Returns None at the end of the stream.
+fn header_field(r: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut field = String::new();
+    let mut byte = [0];
+    loop {
+        if r.read(&mut byte)? == 0 {
+            return Ok((!field.is_empty()).then_some(field));
+        }
+        match byte[0] {
+            b'#' if field.is_empty() => {
+                r.read_until(b'\n', &mut Vec::new())?;
+            }
+            b if b.is_ascii_whitespace() => {
+                // The whitespace after the last field is the only byte
+                // between the header and the pixels, so stop right on it
+                if !field.is_empty() {
+                    return Ok(Some(field));
+                }
+            }
+            b => field.push(b as char),
+        }
+    }
+}
+
+impl PdfPages {
+    /// Starts rendering a PDF at `DEFAULT_DPI`. Fails if `pdftoppm` can't be
+    /// run.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::open_at(path, DEFAULT_DPI)
+    }
+
+    /// Like `open`, at `dpi` dots per inch
+    pub fn open_at<P: AsRef<Path>>(path: P, dpi: u32) -> io::Result<Self> {
+        // With no output file name, every page goes to stdout as a PGM,
+        // one after another
+        let mut child = Command::new("pdftoppm")
+            .args(["-gray", "-r", &dpi.to_string()])
+            .arg(path.as_ref())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| other_err(format!("couldn't run pdftoppm: {}", e)))?;
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self { child, stdout, index: 0, done: false })
+    }
+
+    /// Reads one page, or returns None after the last
+    fn read_page(&mut self) -> io::Result<Option<PdfPage>> {
+        let Some(magic) = header_field(&mut self.stdout)? else {
+            // Either a clean end, or pdftoppm gave up partway through
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(other_err(format!("pdftoppm exited with {}", status)));
+            }
+            return Ok(None);
+        };
+        let bad_header = || other_err("unexpected output from pdftoppm".to_owned());
+        let mut number = || -> io::Result<u32> {
+            header_field(&mut self.stdout)?.and_then(|f| f.parse().ok()).ok_or_else(bad_header)
+        };
+        let (width, height, max) = (number()?, number()?, number()?);
+        if magic != "P5" || max > 255 {
+            return Err(bad_header());
+        }
+
+        let mut buf = vec![0; width as usize * height as usize];
+        self.stdout.read_exact(&mut buf)?;
+        let index = self.index;
+        self.index += 1;
+        Ok(Some(PdfPage { index, image: GrayImage::from_raw(width, height, buf).unwrap() }))
+    }
+}
+
+impl Iterator for PdfPages {
+    type Item = io::Result<PdfPage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let page = self.read_page().transpose();
+        if !matches!(page, Some(Ok(_))) {
+            self.done = true;
+        }
+        page
+    }
+}
+
+impl Drop for PdfPages {
+    fn drop(&mut self) {
+        // Stop rendering if the iterator's dropped early
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
@@ -0,0 +1,252 @@
+//! Estimates the 3D position and orientation of a code relative to the
+//! camera, from the homography between the code's plane and the image.
+
+use crate::{
+    Point,
+    calib::CameraIntrinsics,
+    homography::{Homography, solve},
+    target::complete_quad,
+};
+
+/// Position and orientation of a code in camera coordinates (x right, y down,
+/// z forward, in whatever units the code size was given in).
+///
+/// A point `p` in the code's own frame - origin at its top-left corner, x
+/// along its top edge, y down its left edge, z into the code - is at
+/// `r * p + t` in camera coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+    /// Rotation matrix, row-major
+    pub r: [[f64; 3]; 3],
+    /// Translation
+    pub t: [f64; 3],
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn scale(a: [f64; 3], s: f64) -> [f64; 3] {
+    a.map(|v| v * s)
+}
+
+impl Pose {
+    /// Decomposes a homography which maps points on the code's plane (in
+    /// physical units) to *normalized* camera coordinates, i.e. with the
+    /// intrinsics already divided out.
+    pub fn from_normalized_homography(h: &Homography) -> Option<Self> {
+        let m = &h.0;
+        let h1 = [m[0][0], m[1][0], m[2][0]];
+        let h2 = [m[0][1], m[1][1], m[2][1]];
+        let h3 = [m[0][2], m[1][2], m[2][2]];
+
+        // The first two columns are the first two rotation axes, up to a
+        // common scale factor
+        let lambda = 2.0 / (norm(h1) + norm(h2));
+        if !lambda.is_finite() {
+            return None;
+        }
+        // The code has to be in front of the camera
+        let lambda = if h3[2] < 0.0 { -lambda } else { lambda };
+
+        // Noise means the axes won't be quite orthonormal, so fix them up
+        let r1 = scale(h1, lambda);
+        let r1 = scale(r1, 1.0 / norm(r1));
+        let r2 = scale(h2, lambda);
+        let r2 = [0, 1, 2].map(|i| r2[i] - dot(r1, r2) * r1[i]);
+        let r2 = scale(r2, 1.0 / norm(r2));
+        let r3 = cross(r1, r2);
+        let t = scale(h3, lambda);
+
+        let r = [
+            [r1[0], r2[0], r3[0]],
+            [r1[1], r2[1], r3[1]],
+            [r1[2], r2[2], r3[2]],
+        ];
+        Some(Self { r, t })
+    }
+
+    /// Estimates the pose of a square code of side length `code_size` from its
+    /// four corners in the image (top-left, top-right, bottom-right,
+    /// bottom-left)
+    pub fn from_quad(quad: [Point<f64>; 4], code_size: f64, intrinsics: &CameraIntrinsics) -> Option<Self> {
+        let object = [
+            Point::new(0.0, 0.0),
+            Point::new(code_size, 0.0),
+            Point::new(code_size, code_size),
+            Point::new(0.0, code_size),
+        ];
+        let image = quad.map(|p| intrinsics.normalize(p));
+        let h = Homography::from_points(&object, &image)?;
+        Self::from_normalized_homography(&h)
+    }
+
+    /// Estimates the pose of a code from the three corners found by the
+    /// scanner (see `ScanResult::bbox`). The fourth corner is assumed to
+    /// complete a parallelogram, which is only exact when the code is viewed
+    /// head-on, so expect some error in the rotation at steep angles.
+    pub fn from_bbox(bbox: [Point<f64>; 3], code_size: f64, intrinsics: &CameraIntrinsics) -> Option<Self> {
+        Self::from_quad(complete_quad(bbox), code_size, intrinsics)
+    }
+
+    /// Nudges the pose to better fit a set of correspondences, by
+    /// Gauss-Newton on the reprojection error. `object` holds points in the
+    /// code's (or board's) frame, and `image` where each was seen, in
+    /// *normalized* camera coordinates. Useful after `from_quad` when more
+    /// than four points are known.
+    pub fn refine(&self, object: &[[f64; 3]], image: &[Point<f64>], iterations: u32) -> Self {
+        let residuals = |pose: &Pose| -> Option<Vec<f64>> {
+            let mut out = Vec::with_capacity(object.len() * 2);
+            for (&p, q) in object.iter().zip(image) {
+                let c = pose.transform(p);
+                if c[2] <= 0.0 {
+                    return None;
+                }
+                out.push(c[0] / c[2] - q.x);
+                out.push(c[1] / c[2] - q.y);
+            }
+            Some(out)
+        };
+        let cost = |r: &[f64]| r.iter().map(|v| v * v).sum::<f64>();
+
+        let mut pose = *self;
+        let Some(mut r0) = residuals(&pose) else { return pose };
+        for _ in 0..iterations {
+            // Numerical Jacobian over a small rotation (axis-angle) and a
+            // translation
+            const EPS: f64 = 1e-6;
+            let mut jac = Vec::with_capacity(6);
+            for k in 0..6 {
+                let mut delta = [0.0; 6];
+                delta[k] = EPS;
+                let Some(r) = residuals(&pose.perturb(delta)) else { return pose };
+                jac.push(r.iter().zip(&r0).map(|(a, b)| (a - b) / EPS).collect::<Vec<_>>());
+            }
+            let mut jtj = [[0.0; 6]; 6];
+            let mut jtr = [0.0; 6];
+            for i in 0..6 {
+                for j in 0..6 {
+                    jtj[i][j] = jac[i].iter().zip(&jac[j]).map(|(a, b)| a * b).sum();
+                }
+                jtr[i] = -jac[i].iter().zip(&r0).map(|(a, b)| a * b).sum::<f64>();
+            }
+            let Some(step) = solve(jtj, jtr) else { break };
+            let next = pose.perturb(step);
+            match residuals(&next) {
+                Some(r) if cost(&r) < cost(&r0) => {
+                    pose = next;
+                    r0 = r;
+                }
+                _ => break,
+            }
+        }
+        pose
+    }
+
+    /// Rotates by the axis-angle vector `delta[0..3]` (applied on the camera
+    /// side) and translates by `delta[3..6]`
+    fn perturb(&self, delta: [f64; 6]) -> Self {
+        let w = [delta[0], delta[1], delta[2]];
+        let angle = norm(w);
+        let rot = if angle < 1e-12 {
+            [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+        } else {
+            // Rodrigues' formula
+            let k = scale(w, 1.0 / angle);
+            let (sin, cos) = angle.sin_cos();
+            let kx = [[0.0, -k[2], k[1]], [k[2], 0.0, -k[0]], [-k[1], k[0], 0.0]];
+            let mut m = [[0.0; 3]; 3];
+            for (i, row) in m.iter_mut().enumerate() {
+                for (j, val) in row.iter_mut().enumerate() {
+                    let kx2: f64 = (0..3).map(|n| kx[i][n] * kx[n][j]).sum();
+                    *val = if i == j { 1.0 } else { 0.0 } + sin * kx[i][j] + (1.0 - cos) * kx2;
+                }
+            }
+            m
+        };
+        let r = [0, 1, 2].map(|i| [0, 1, 2].map(|j| (0..3).map(|n| rot[i][n] * self.r[n][j]).sum()));
+        let t = [0, 1, 2].map(|i| self.t[i] + delta[3 + i]);
+        Self { r, t }
+    }
+
+    /// Transforms a point from the code's frame to camera coordinates
+    pub fn transform(&self, p: [f64; 3]) -> [f64; 3] {
+        [0, 1, 2].map(|i| dot(self.r[i], p) + self.t[i])
+    }
+
+    /// Distance from the camera to the code's origin (its top-left corner)
+    pub fn distance(&self) -> f64 {
+        norm(self.t)
+    }
+
+    /// Column-major 4x4 model-view matrix placing the code's frame in an
+    /// OpenGL-style eye space (x right, y up, looking down -z). Combine with
+    /// `CameraIntrinsics::gl_projection` to draw content anchored to the code.
+    pub fn model_view(&self) -> [f32; 16] {
+        // Eye space flips y and z relative to camera coordinates
+        let flip = [1.0, -1.0, -1.0];
+        let mut m = [0.0; 16];
+        for row in 0..3 {
+            for col in 0..3 {
+                m[col * 4 + row] = (flip[row] * self.r[row][col]) as f32;
+            }
+            m[12 + row] = (flip[row] * self.t[row]) as f32;
+        }
+        m[15] = 1.0;
+        m
+    }
+}
+
+impl CameraIntrinsics {
+    /// Column-major 4x4 OpenGL-style projection matrix matching these
+    /// intrinsics, for an image of the given size. Distortion is ignored.
+    pub fn gl_projection(&self, width: u32, height: u32, near: f64, far: f64) -> [f32; 16] {
+        let (w, h) = (width as f64, height as f64);
+        let mut m = [0.0; 16];
+        m[0] = 2.0 * self.fx / w;
+        m[5] = 2.0 * self.fy / h;
+        m[8] = 1.0 - 2.0 * self.cx / w;
+        m[9] = 2.0 * self.cy / h - 1.0;
+        m[10] = -(far + near) / (far - near);
+        m[11] = -1.0;
+        m[14] = -2.0 * far * near / (far - near);
+        m.map(|v| v as f32)
+    }
+}
+
+/// Multiplies two column-major 4x4 matrices (`a * b`), e.g. a projection by a
+/// model-view matrix
+pub fn mul_mat4(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
+    let mut out = [0.0; 16];
+    for col in 0..4 {
+        for row in 0..4 {
+            out[col * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[col * 4 + k]).sum();
+        }
+    }
+    out
+}
+
+/// Projects a point through a column-major model-view-projection matrix to
+/// pixel coordinates in an image of the given size. Returns `None` for points
+/// behind the camera.
+pub fn project_mvp(mvp: &[f32; 16], p: [f64; 3], width: u32, height: u32) -> Option<Point<f64>> {
+    let clip = [0, 1, 3].map(|row| {
+        (0..3).map(|k| mvp[k * 4 + row] as f64 * p[k]).sum::<f64>() + mvp[12 + row] as f64
+    });
+    if clip[2] <= 0.0 {
+        return None;
+    }
+    let (ndc_x, ndc_y) = (clip[0] / clip[2], clip[1] / clip[2]);
+    Some(Point::new(
+        (ndc_x + 1.0) / 2.0 * width as f64,
+        (1.0 - ndc_y) / 2.0 * height as f64,
+    ))
+}
@@ -0,0 +1,165 @@
+//! Scan results in protobuf's wire format, following `proto/arqr.proto`, for
+//! streaming them somewhere with a fixed binary layout. Encoding is done by
+//! hand, like the JSON in `json`, so there's nothing to generate and no
+//! protobuf runtime to pull in; readers can generate their own types from
+//! the `.proto`.
+
+use std::{io::{self, Write}, time::Duration};
+use crate::{
+    Point, ScanResult, Stage, Timings,
+    homography::Homography,
+    pose::Pose,
+};
+
+const VARINT: u32 = 0;
+const FIXED64: u32 = 1;
+const LEN: u32 = 2;
+const FIXED32: u32 = 5;
+
+fn varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn tag(out: &mut Vec<u8>, field: u32, wire_type: u32) {
+    varint(out, (field << 3 | wire_type) as u64);
+}
+
+/// A varint field, left out when it's zero, as proto3 does
+fn uint(out: &mut Vec<u8>, field: u32, n: u64) {
+    if n != 0 {
+        tag(out, field, VARINT);
+        varint(out, n);
+    }
+}
+
+/// A double field, left out when it's zero
+fn double(out: &mut Vec<u8>, field: u32, v: f64) {
+    if v != 0.0 {
+        tag(out, field, FIXED64);
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+}
+
+/// An optional float field, written even when it's zero
+fn float(out: &mut Vec<u8>, field: u32, v: f32) {
+    tag(out, field, FIXED32);
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+/// A repeated double field, packed
+fn doubles(out: &mut Vec<u8>, field: u32, vs: &[f64]) {
+    if !vs.is_empty() {
+        tag(out, field, LEN);
+        varint(out, vs.len() as u64 * 8);
+        for v in vs {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+}
+
+/// An embedded message, always written, even if it's empty
+fn message(out: &mut Vec<u8>, field: u32, msg: &[u8]) {
+    tag(out, field, LEN);
+    varint(out, msg.len() as u64);
+    out.extend_from_slice(msg);
+}
+
+fn point(p: Point<f64>) -> Vec<u8> {
+    let mut out = Vec::new();
+    double(&mut out, 1, p.x);
+    double(&mut out, 2, p.y);
+    out
+}
+
+fn pose(pose: &Pose) -> Vec<u8> {
+    let mut out = Vec::new();
+    doubles(&mut out, 1, pose.r.concat().as_slice());
+    doubles(&mut out, 2, &pose.t);
+    out
+}
+
+fn timings(t: &Timings) -> Vec<u8> {
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let mut out = Vec::new();
+    double(&mut out, 1, ms(t.binarize));
+    double(&mut out, 2, ms(t.targets));
+    double(&mut out, 3, ms(t.corners));
+    double(&mut out, 4, ms(t.extract));
+    double(&mut out, 5, ms(t.fiducials));
+    double(&mut out, 6, ms(t.total()));
+    double(&mut out, 7, ms(t.decode));
+    out
+}
+
+fn stage_number(stage: Stage) -> u64 {
+    match stage {
+        Stage::Binarized => 1,
+        Stage::Targets => 2,
+        Stage::Corners => 3,
+        Stage::Extracted => 4,
+        Stage::Complete => 5,
+        Stage::Decoded => 6,
+    }
+}
+
+/// A `ScanResult` message. `code_pose` goes in the code's `pose`, if a code
+/// was found; see `Pose::from_quad`.
+pub fn encode(result: &ScanResult, code_pose: Option<&Pose>) -> Vec<u8> {
+    let mut out = Vec::new();
+    uint(&mut out, 1, result.meta.sequence);
+    uint(&mut out, 2, stage_number(result.stage));
+    uint(&mut out, 3, result.targets.len() as u64);
+    if let Some(quad) = result.quad() {
+        let mut code = Vec::new();
+        for p in quad {
+            message(&mut code, 1, &point(p));
+        }
+        let unit = [Point::new(0.0, 0.0), Point::new(1.0, 0.0), Point::new(1.0, 1.0), Point::new(0.0, 1.0)];
+        if let Some(h) = Homography::from_points(&unit, &quad) {
+            doubles(&mut code, 2, h.0.concat().as_slice());
+        }
+        if let Some(version) = result.version {
+            uint(&mut code, 3, version as u64);
+        }
+        if let Some(format) = result.format {
+            message(&mut code, 4, format!("{:?}", format.ec_level).as_bytes());
+        }
+        if let Some(payload) = &result.payload {
+            message(&mut code, 5, payload);
+        }
+        if let Some(confidence) = result.confidence() {
+            float(&mut code, 6, confidence as f32);
+        }
+        if let Some(e) = result.decode_error {
+            message(&mut code, 8, e.name().as_bytes());
+        }
+        if let Some(p) = code_pose {
+            message(&mut code, 7, &pose(p));
+        }
+        message(&mut out, 4, &code);
+    }
+    message(&mut out, 5, &timings(&result.timings));
+    out
+}
+
+/// Writes an encoded message preceded by its length, so a reader can pick
+/// messages back out of a stream (as protobuf's `parseDelimitedFrom` and
+/// friends do)
+pub fn write_delimited<W: Write>(w: &mut W, msg: &[u8]) -> io::Result<()> {
+    let mut len = Vec::new();
+    varint(&mut len, msg.len() as u64);
+    w.write_all(&len)?;
+    w.write_all(msg)
+}
+
+impl ScanResult {
+    /// The result as a `ScanResult` message from `proto/arqr.proto`, without
+    /// a pose. See `proto::encode`.
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        encode(self, None)
+    }
+}
@@ -0,0 +1,177 @@
+//! Records sessions to disk so problems seen live can be reproduced exactly
+//! later: every scanned frame is saved as a greyscale PNG, and what the
+//! scanner made of it goes in `results.jsonl`, one JSON object per line.
+//!
+//! Each line looks like
+//!
+//! ```text
+//! {"frame":"00000012.png","sequence":12,"time_ms":400.000,"stage":"complete",
+//!  "targets":[[x_min,y_min,x_mid,y_mid,x_max,y_max],...],
+//!  "bbox":[[x,y],[x,y],[x,y]],"markers":[{"dictionary":0,"id":3,"corners":[...],"errors":0}]}
+//! ```
+//!
+//! (all on one line). `time_ms` is counted from the first recorded frame, and
+//! is null for frames without a timestamp. `bbox` is null when no code was
+//! found, and so is any coordinate that isn't a finite number.
+//!
+//! `Recording` reads the frames back, for replaying a session.
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use image::{GrayImage, ImageResult};
+use crate::{
+    ScanResult,
+    json::{number, point, stage_name},
+};
+
+/// Name of the results log in a recording directory
+pub const RESULTS_FILE: &str = "results.jsonl";
+
+/// Writes a recording into a directory
+#[derive(Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+    log: BufWriter<File>,
+    start: Option<Instant>,
+}
+
+impl Recorder {
+    /// Starts a recording in `dir`, creating it if needed. An existing
+    /// results log there is overwritten.
+    pub fn create<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+        let log = BufWriter::new(File::create(dir.join(RESULTS_FILE))?);
+        Ok(Self { dir, log, start: None })
+    }
+
+    /// Saves a frame and what was found in it. The frame is named after
+    /// `result.meta.sequence`.
+    pub fn record(&mut self, img: &GrayImage, result: &ScanResult) -> ImageResult<()> {
+        let name = format!("{:08}.png", result.meta.sequence);
+        img.save(self.dir.join(&name))?;
+
+        let time_ms = result.meta.timestamp.map(|t| {
+            let start = *self.start.get_or_insert(t);
+            t.saturating_duration_since(start).as_secs_f64() * 1000.0
+        });
+        writeln!(self.log, "{}", result_json(result, &name, time_ms))?;
+        // Flush every frame, so a crash doesn't lose the end of the log
+        self.log.flush()?;
+        Ok(())
+    }
+}
+
+/// A frame in a recording
+#[derive(Clone, Debug)]
+pub struct RecordedFrame {
+    pub path: PathBuf,
+    pub sequence: u64,
+    /// When the frame was captured, relative to the first frame recorded, if
+    /// it had a timestamp
+    pub time: Option<Duration>,
+}
+
+impl RecordedFrame {
+    pub fn load(&self) -> ImageResult<GrayImage> {
+        Ok(image::open(&self.path)?.into_luma8())
+    }
+}
+
+/// The frames of a recording made by `Recorder`, in the order they were
+/// recorded
+#[derive(Clone, Debug)]
+pub struct Recording {
+    frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    /// Reads the results log in `dir`. Frames are only loaded when asked for.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let log = BufReader::new(File::open(dir.join(RESULTS_FILE))?);
+        let mut frames = Vec::new();
+        for (n, line) in log.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let bad_line = || io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} line {}: not a recorded frame", RESULTS_FILE, n + 1),
+            );
+            let name = field(&line, "frame")
+                .and_then(|f| f.strip_prefix('"')?.strip_suffix('"'))
+                .ok_or_else(bad_line)?;
+            let sequence = field(&line, "sequence").and_then(|f| f.parse().ok()).ok_or_else(bad_line)?;
+            let time = field(&line, "time_ms")
+                .and_then(|f| f.parse::<f64>().ok())
+                .map(|ms| Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+            frames.push(RecordedFrame { path: dir.join(name), sequence, time });
+        }
+        Ok(Self { frames })
+    }
+
+    pub fn frames(&self) -> &[RecordedFrame] {
+        &self.frames
+    }
+
+    /// Average frame rate, if the frames have timestamps
+    pub fn fps(&self) -> Option<f64> {
+        let first = self.frames.first()?.time?;
+        let last = self.frames.last()?.time?;
+        let span = last.saturating_sub(first).as_secs_f64();
+        (span > 0.0).then(|| (self.frames.len() - 1) as f64 / span)
+    }
+}
+
+/// Finds the raw value of a top-level `"key":value` in one line of the
+/// results log. Only good for the leading fields, which come before anything
+/// nested and never contain commas.
+fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let start = line.find(&format!("\"{}\":", key))? + key.len() + 3;
+    let rest = &line[start..];
+    let end = rest.find([',', '}']).unwrap_or(rest.len());
+    Some(&rest[..end])
+}
+
+/// Formats one line of the results log. `frame` has to be a plain file name:
+/// it isn't escaped.
+pub(crate) fn result_json(result: &ScanResult, frame: &str, time_ms: Option<f64>) -> String {
+    let mut out = format!(
+        "{{\"frame\":\"{}\",\"sequence\":{},\"time_ms\":{},\"stage\":\"{}\",\"targets\":[",
+        frame,
+        result.meta.sequence,
+        time_ms.map_or("null".to_owned(), number),
+        stage_name(result.stage),
+    );
+    for (i, t) in result.targets.iter().enumerate() {
+        let sep = if i > 0 { "," } else { "" };
+        let _ = write!(
+            out, "{}[{},{},{},{},{},{}]", sep,
+            number(t.min.x), number(t.min.y), number(t.mid.x), number(t.mid.y), number(t.max.x), number(t.max.y),
+        );
+    }
+    out.push_str("],\"bbox\":");
+    match result.bbox {
+        Some(bbox) => {
+            let _ = write!(out, "[{}]", bbox.map(point).join(","));
+        }
+        None => out.push_str("null"),
+    }
+    out.push_str(",\"markers\":[");
+    for (i, m) in result.markers.iter().enumerate() {
+        let sep = if i > 0 { "," } else { "" };
+        let _ = write!(
+            out, "{}{{\"dictionary\":{},\"id\":{},\"corners\":[{}],\"errors\":{}}}", sep,
+            m.dictionary, m.id, m.corners.map(point).join(","), m.errors,
+        );
+    }
+    out.push_str("]}");
+    out
+}
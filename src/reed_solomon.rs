@@ -0,0 +1,330 @@
+//! Reed-Solomon error correction over the small fields barcodes use: binary
+//! ones from GF(16) for Aztec mode messages up to GF(4096) for the biggest
+//! Aztec symbols, QR codes' GF(256), and PDF417's GF(929). Codewords are
+//! `u16`s, highest degree first, with the check words at the end, the way
+//! they're laid out in a symbol.
+//!
+//! Decoding finds the syndromes, then the error locator by Berlekamp-Massey,
+//! the error positions by Chien search, and their values by Forney's formula.
+
+/// GF(2^m) built from a primitive polynomial, or GF(p) for a prime p
+#[derive(Clone, Debug)]
+pub struct Field {
+    /// `exp[i]` is alpha^i, doubled up so products don't need a `% order`
+    exp: Vec<u16>,
+    log: Vec<u16>,
+    /// For GF(p), p; elements of GF(2^m) add by xor instead
+    prime: Option<u16>,
+}
+
+impl Field {
+    /// The field of `2^bits` elements generated by `poly`, which includes
+    /// its x^bits term, e.g. 0x13 for x^4 + x + 1
+    pub fn new(bits: u32, poly: u32) -> Self {
+        let size = 1u32 << bits;
+        Self::build(size, None, |x| {
+            let x = x << 1;
+            if x >= size { x ^ poly } else { x }
+        })
+    }
+
+    /// GF(256) modulo x^8 + x^4 + x^3 + x^2 + 1, which QR codes use. Their
+    /// generators' roots start at alpha^0.
+    pub fn qr() -> Self {
+        Self::new(8, 0x11d)
+    }
+
+    /// The integers mod `prime`, with `generator` as alpha, e.g. 929 and 3
+    /// for PDF417
+    pub fn prime(prime: u16, generator: u16) -> Self {
+        Self::build(prime as u32, Some(prime), |x| x * generator as u32 % prime as u32)
+    }
+
+    /// Tables for a field of `size` elements, where `next` multiplies by alpha
+    fn build(size: u32, prime: Option<u16>, next: impl Fn(u32) -> u32) -> Self {
+        let size = size as usize;
+        let mut exp = vec![0; 2 * size];
+        let mut log = vec![0; size];
+        let mut x = 1u32;
+        for (i, e) in exp.iter_mut().enumerate().take(size - 1) {
+            *e = x as u16;
+            log[x as usize] = i as u16;
+            x = next(x);
+        }
+        for i in size - 1..2 * size {
+            exp[i] = exp[i - (size - 1)];
+        }
+        Self { exp, log, prime }
+    }
+
+    fn add(&self, a: u16, b: u16) -> u16 {
+        match self.prime {
+            None => a ^ b,
+            Some(p) => ((a as u32 + b as u32) % p as u32) as u16,
+        }
+    }
+
+    fn sub(&self, a: u16, b: u16) -> u16 {
+        match self.prime {
+            None => a ^ b,
+            Some(p) => ((a as u32 + p as u32 - b as u32) % p as u32) as u16,
+        }
+    }
+
+    /// `n` times `a`, by repeated addition
+    fn times(&self, n: usize, a: u16) -> u16 {
+        match self.prime {
+            None if n.is_multiple_of(2) => 0,
+            None => a,
+            Some(p) => ((n % p as usize) as u32 * a as u32 % p as u32) as u16,
+        }
+    }
+
+    /// Number of non-zero elements
+    fn order(&self) -> usize {
+        self.log.len() - 1
+    }
+
+    fn mul(&self, a: u16, b: u16) -> u16 {
+        if a == 0 || b == 0 {
+            0
+        } else {
+            self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+        }
+    }
+
+    fn div(&self, a: u16, b: u16) -> u16 {
+        if a == 0 {
+            0
+        } else {
+            self.exp[self.log[a as usize] as usize + self.order() - self.log[b as usize] as usize]
+        }
+    }
+
+    /// alpha^n
+    fn pow(&self, n: usize) -> u16 {
+        self.exp[n % self.order()]
+    }
+
+    /// `poly` (highest degree first) at `x`
+    fn eval(&self, poly: &[u16], x: u16) -> u16 {
+        poly.iter().fold(0, |acc, &c| self.add(self.mul(acc, x), c))
+    }
+
+    /// The check words for `data`, with a generator polynomial whose roots
+    /// run from alpha^`first_root` up
+    pub fn encode(&self, data: &[u16], ecc_len: usize, first_root: usize) -> Vec<u16> {
+        // Generator, highest degree first, leading 1 included
+        let mut generator = vec![1u16];
+        for i in 0..ecc_len {
+            let root = self.pow(first_root + i);
+            let mut next = generator.clone();
+            next.push(0);
+            for (j, &g) in generator.iter().enumerate() {
+                next[j + 1] = self.sub(next[j + 1], self.mul(g, root));
+            }
+            generator = next;
+        }
+
+        let mut rem = vec![0; ecc_len];
+        for &d in data {
+            let factor = self.add(d, rem[0]);
+            rem.rotate_left(1);
+            rem[ecc_len - 1] = 0;
+            for (r, &g) in rem.iter_mut().zip(&generator[1..]) {
+                *r = self.sub(*r, self.mul(g, factor));
+            }
+        }
+        // The check words make the whole thing a multiple of the generator
+        rem.iter().map(|&r| self.sub(0, r)).collect()
+    }
+
+    /// Corrects a block of bytes, as QR codes store their codewords, the
+    /// same way as `correct`. Returns None without touching `words` if any
+    /// of them are too big for the field.
+    pub fn correct_bytes(&self, words: &mut [u8], ecc_len: usize, first_root: usize) -> Option<usize> {
+        let mut wide: Vec<u16> = words.iter().map(|&w| w as u16).collect();
+        if wide.iter().any(|&w| w as usize >= self.log.len()) {
+            return None;
+        }
+        let errors = self.correct(&mut wide, ecc_len, first_root)?;
+        for (w, &fixed) in words.iter_mut().zip(&wide) {
+            *w = fixed as u8;
+        }
+        Some(errors)
+    }
+
+    /// Corrects `words`, the last `ecc_len` of which are check words for a
+    /// generator with roots from alpha^`first_root` up. Returns the number of
+    /// words that were wrong, or None if there are too many to correct (up to
+    /// `ecc_len / 2` can be).
+    pub fn correct(&self, words: &mut [u16], ecc_len: usize, first_root: usize) -> Option<usize> {
+        let n = words.len();
+        if ecc_len == 0 || ecc_len > n || n > self.order() {
+            return (ecc_len == 0 && n <= self.order()).then_some(0);
+        }
+        // Syndromes S_j = r(alpha^(first_root + j)), lowest j first
+        let syndromes: Vec<u16> = (0..ecc_len).map(|j| self.eval(words, self.pow(first_root + j))).collect();
+        if syndromes.iter().all(|&s| s == 0) {
+            return Some(0);
+        }
+
+        // Berlekamp-Massey for the error locator, lowest degree first
+        let mut locator = vec![1u16];
+        let mut prev = vec![1u16];
+        let (mut len, mut shift, mut prev_discrepancy) = (0, 1, 1u16);
+        for i in 0..ecc_len {
+            let discrepancy = (0..=len.min(locator.len() - 1))
+                .fold(0, |acc, j| self.add(acc, self.mul(locator[j], syndromes[i - j])));
+            if discrepancy == 0 {
+                shift += 1;
+                continue;
+            }
+            let scale = self.div(discrepancy, prev_discrepancy);
+            let mut next = locator.clone();
+            if next.len() < prev.len() + shift {
+                next.resize(prev.len() + shift, 0);
+            }
+            for (j, &p) in prev.iter().enumerate() {
+                next[j + shift] = self.sub(next[j + shift], self.mul(scale, p));
+            }
+            if 2 * len <= i {
+                len = i + 1 - len;
+                prev = locator;
+                prev_discrepancy = discrepancy;
+                shift = 1;
+            } else {
+                shift += 1;
+            }
+            locator = next;
+        }
+        while locator.last() == Some(&0) {
+            locator.pop();
+        }
+        let errors = locator.len() - 1;
+        if errors == 0 || 2 * errors > ecc_len {
+            return None;
+        }
+
+        // Error evaluator: S(x) * locator(x) mod x^ecc_len
+        let mut evaluator = vec![0u16; ecc_len];
+        for (i, &l) in locator.iter().enumerate() {
+            for (j, &s) in syndromes.iter().enumerate().take(ecc_len - i) {
+                evaluator[i + j] = self.add(evaluator[i + j], self.mul(l, s));
+            }
+        }
+        let eval_low = |poly: &[u16], x: u16| poly.iter().rev().fold(0, |acc, &c| self.add(self.mul(acc, x), c));
+
+        // The formal derivative of the locator; in GF(2^m) that just keeps
+        // its odd terms
+        let derivative: Vec<u16> = locator.iter().enumerate().skip(1).map(|(j, &l)| self.times(j, l)).collect();
+
+        // Chien search over every position, then Forney for the magnitudes.
+        // Position i holds the coefficient of x^(n - 1 - i). Nothing's
+        // changed until every error has been found.
+        let mut fixes = Vec::with_capacity(errors);
+        for i in 0..n {
+            let degree = n - 1 - i;
+            let x_inv = self.pow(self.order() - degree % self.order());
+            if eval_low(&locator, x_inv) != 0 {
+                continue;
+            }
+            let denominator = eval_low(&derivative, x_inv);
+            if denominator == 0 {
+                return None;
+            }
+            // -X^(1 - first_root) * evaluator(X^-1) / locator'(X^-1), where
+            // X is alpha^degree
+            let scale = self.pow(degree * (self.order() + 1 - first_root % self.order()));
+            fixes.push((i, self.sub(0, self.mul(scale, self.div(eval_low(&evaluator, x_inv), denominator)))));
+        }
+        if fixes.len() != errors {
+            return None;
+        }
+        for (i, fix) in fixes {
+            words[i] = self.sub(words[i], fix);
+        }
+        Some(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pseudo-random words below `below`, the same every run
+    fn words(n: usize, below: u16, seed: u32) -> Vec<u16> {
+        let mut state = seed;
+        (0..n)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                ((state >> 16) % below as u32) as u16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encodes_known_check_words() {
+        // "HELLO WORLD" as a version 1-M QR code
+        let data = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
+        assert_eq!(Field::qr().encode(&data, 10, 0), [196, 35, 39, 119, 235, 215, 231, 226, 93, 23]);
+    }
+
+    /// Corrects up to `ecc_len / 2` errors, and not one more
+    fn corrects_up_to_half(field: &Field, size: u16, len: usize, ecc_len: usize, first_root: usize) {
+        let t = ecc_len / 2;
+        for seed in 0..20 {
+            let data = words(len - ecc_len, size, seed);
+            let mut clean = data.clone();
+            clean.extend(field.encode(&data, ecc_len, first_root));
+            assert_eq!(field.correct(&mut clean.clone(), ecc_len, first_root), Some(0));
+
+            for errors in 1..=t + 1 {
+                let mut damaged = clean.clone();
+                // Spread the errors out over the block, check words included
+                let offsets = words(errors, size - 1, seed + 100);
+                for (k, &offset) in offsets.iter().enumerate() {
+                    let i = (k * len / errors + seed as usize) % len;
+                    damaged[i] = field.add(damaged[i], offset + 1);
+                }
+                let result = field.correct(&mut damaged, ecc_len, first_root);
+                if errors <= t {
+                    assert_eq!(result, Some(errors), "seed {seed}, {errors} errors");
+                    assert_eq!(damaged, clean);
+                } else {
+                    assert_eq!(result, None, "seed {seed}, {errors} errors");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corrects_in_gf256() {
+        corrects_up_to_half(&Field::qr(), 256, 26, 10, 0);
+        corrects_up_to_half(&Field::qr(), 256, 255, 30, 0);
+    }
+
+    #[test]
+    fn corrects_in_gf929() {
+        let field = Field::prime(929, 3);
+        corrects_up_to_half(&field, 929, 20, 8, 1);
+        corrects_up_to_half(&field, 929, 300, 64, 1);
+    }
+
+    #[test]
+    fn corrects_in_gf16() {
+        // Aztec mode messages
+        corrects_up_to_half(&Field::new(4, 0x13), 16, 7, 5, 1);
+    }
+
+    #[test]
+    fn rejects_words_the_field_cant_hold() {
+        let field = Field::new(4, 0x13);
+        // Longer than the field has non-zero elements
+        assert_eq!(field.correct(&mut [1; 16], 4, 1), None);
+        // A byte too big for GF(16)
+        let mut bytes = [1, 2, 3, 16, 5, 6, 7];
+        assert_eq!(field.correct_bytes(&mut bytes, 5, 1), None);
+        assert_eq!(bytes, [1, 2, 3, 16, 5, 6, 7]);
+    }
+}
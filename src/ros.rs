@@ -0,0 +1,440 @@
+//! ROS 2 messages, behind the `ros` feature, for robots using codes as
+//! landmarks: the standard ones arqr reads and writes, laid out field for
+//! field as ROS defines them, plus `Detection` from the `arqr_msgs` package
+//! in `ros/arqr_msgs/`. Build that package in your ROS workspace for other
+//! nodes to read detections.
+//!
+//! They go over DDS with `ros2-client`, which needs no ROS install; see the
+//! `arqr-ros` binary for a node that scans `sensor_msgs/Image` frames and
+//! publishes what it finds. Poses are in the image's frame, which for a
+//! camera is its optical frame (x right, y down, z forward), the same axes
+//! as `pose::Pose`.
+
+use image::{Pixel, Rgb};
+use ros2_client::{Message, MessageTypeName, builtin_interfaces::Time};
+use serde::{Deserialize, Serialize};
+use crate::{
+    Scanner, ScanResult,
+    calib::{CameraIntrinsics, Distortion},
+    pose,
+};
+
+/// A message type ROS knows by name, for `Node::create_topic`
+pub trait RosType: Message {
+    /// Package and type, e.g. `("geometry_msgs", "PoseStamped")`
+    const TYPE_NAME: (&'static str, &'static str);
+
+    fn message_type_name() -> MessageTypeName {
+        MessageTypeName::new(Self::TYPE_NAME.0, Self::TYPE_NAME.1)
+    }
+}
+
+/// `std_msgs/Header`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub stamp: Time,
+    pub frame_id: String,
+}
+
+/// `geometry_msgs/Point`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+/// `geometry_msgs/Quaternion`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Quaternion {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self { x: 0.0, y: 0.0, z: 0.0, w: 1.0 }
+    }
+}
+
+impl Quaternion {
+    /// The rotation a row-major rotation matrix makes, with `w` kept
+    /// non-negative
+    pub fn from_matrix(r: &[[f64; 3]; 3]) -> Self {
+        let [[m00, m01, m02], [m10, m11, m12], [m20, m21, m22]] = *r;
+        // Worked out from whichever of w, x, y and z is biggest, so the
+        // square root never gets near zero
+        let trace = m00 + m11 + m22;
+        let (x, y, z, w) = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            ((m21 - m12) / s, (m02 - m20) / s, (m10 - m01) / s, s / 4.0)
+        } else if m00 > m11 && m00 > m22 {
+            let s = (1.0 + m00 - m11 - m22).sqrt() * 2.0;
+            (s / 4.0, (m01 + m10) / s, (m02 + m20) / s, (m21 - m12) / s)
+        } else if m11 > m22 {
+            let s = (1.0 + m11 - m00 - m22).sqrt() * 2.0;
+            ((m01 + m10) / s, s / 4.0, (m12 + m21) / s, (m02 - m20) / s)
+        } else {
+            let s = (1.0 + m22 - m00 - m11).sqrt() * 2.0;
+            ((m02 + m20) / s, (m12 + m21) / s, s / 4.0, (m10 - m01) / s)
+        };
+        let norm = (x * x + y * y + z * z + w * w).sqrt().copysign(w);
+        Self { x: x / norm, y: y / norm, z: z / norm, w: w / norm }
+    }
+}
+
+/// `geometry_msgs/Pose`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pose {
+    pub position: Point,
+    pub orientation: Quaternion,
+}
+
+impl From<&pose::Pose> for Pose {
+    fn from(pose: &pose::Pose) -> Self {
+        let [x, y, z] = pose.t;
+        Self { position: Point { x, y, z }, orientation: Quaternion::from_matrix(&pose.r) }
+    }
+}
+
+/// `geometry_msgs/PoseStamped`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PoseStamped {
+    pub header: Header,
+    pub pose: Pose,
+}
+
+impl PoseStamped {
+    /// A code's pose, stamped with the header of the image it was found in
+    pub fn new(header: Header, pose: &pose::Pose) -> Self {
+        Self { header, pose: pose.into() }
+    }
+}
+
+/// `sensor_msgs/Image`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Image {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    /// E.g. "mono8" or "rgb8"; see `Scanner::scan_ros_image` for the ones
+    /// arqr can scan
+    pub encoding: String,
+    pub is_bigendian: u8,
+    /// Bytes from one row to the next
+    pub step: u32,
+    pub data: Vec<u8>,
+}
+
+/// `sensor_msgs/RegionOfInterest`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RegionOfInterest {
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub height: u32,
+    pub width: u32,
+    pub do_rectify: bool,
+}
+
+/// `sensor_msgs/CameraInfo`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraInfo {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub distortion_model: String,
+    pub d: Vec<f64>,
+    /// Row-major 3x3 camera matrix
+    pub k: [f64; 9],
+    pub r: [f64; 9],
+    pub p: [f64; 12],
+    pub binning_x: u32,
+    pub binning_y: u32,
+    pub roi: RegionOfInterest,
+}
+
+impl CameraInfo {
+    /// The camera's intrinsics, or None if it's uncalibrated (a zero focal
+    /// length) or its distortion model isn't one arqr has: "plumb_bob" and
+    /// "rational_polynomial" without its extra terms are Brown-Conrady, and
+    /// "equidistant" is fisheye.
+    pub fn intrinsics(&self) -> Option<CameraIntrinsics> {
+        let [fx, _, cx, _, fy, cy, ..] = self.k;
+        if fx == 0.0 || fy == 0.0 {
+            return None;
+        }
+        let d = |i: usize| self.d.get(i).copied().unwrap_or(0.0);
+        let distortion = match self.distortion_model.as_str() {
+            _ if self.d.iter().all(|&c| c == 0.0) => Distortion::default(),
+            "plumb_bob" | "rational_polynomial" if self.d.iter().skip(5).all(|&c| c == 0.0) => {
+                Distortion::BrownConrady { k1: d(0), k2: d(1), p1: d(2), p2: d(3), k3: d(4) }
+            }
+            "equidistant" => Distortion::Fisheye { k1: d(0), k2: d(1), k3: d(2), k4: d(3) },
+            _ => return None,
+        };
+        Some(CameraIntrinsics { fx, fy, cx, cy, distortion })
+    }
+}
+
+/// `arqr_msgs/Detection`, a code found in an image. See
+/// `ros/arqr_msgs/msg/Detection.msg` for what each field means; the fields
+/// here have to stay in the same order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Detection {
+    pub header: Header,
+    pub corners: [f64; 8],
+    pub version: u8,
+    pub ec_level: String,
+    pub decoded: bool,
+    pub payload: Vec<u8>,
+    pub confidence: f32,
+    pub decode_error: String,
+    pub has_pose: bool,
+    pub pose: Pose,
+}
+
+impl Detection {
+    /// The code in `result`, stamped with the header of the image it was
+    /// found in, or None if no code was found. `code_pose` goes in the
+    /// detection's pose; see `ScanResult::pose`.
+    pub fn new(header: Header, result: &ScanResult, code_pose: Option<&pose::Pose>) -> Option<Self> {
+        let quad = result.quad()?;
+        let mut corners = [0.0; 8];
+        for (i, p) in quad.iter().enumerate() {
+            corners[i * 2] = p.x;
+            corners[i * 2 + 1] = p.y;
+        }
+        Some(Self {
+            header,
+            corners,
+            version: result.version.unwrap_or(0) as u8,
+            ec_level: result.format.map(|format| format!("{:?}", format.ec_level)).unwrap_or_default(),
+            decoded: result.payload.is_some(),
+            payload: result.payload.clone().unwrap_or_default(),
+            confidence: result.confidence().unwrap_or(0.0) as f32,
+            decode_error: result.decode_error.map(|e| e.name().to_owned()).unwrap_or_default(),
+            has_pose: code_pose.is_some(),
+            pose: code_pose.map(Pose::from).unwrap_or_default(),
+        })
+    }
+}
+
+impl Message for Header {}
+impl Message for PoseStamped {}
+impl Message for Image {}
+impl Message for CameraInfo {}
+impl Message for Detection {}
+
+impl RosType for PoseStamped {
+    const TYPE_NAME: (&'static str, &'static str) = ("geometry_msgs", "PoseStamped");
+}
+
+impl RosType for Image {
+    const TYPE_NAME: (&'static str, &'static str) = ("sensor_msgs", "Image");
+}
+
+impl RosType for CameraInfo {
+    const TYPE_NAME: (&'static str, &'static str) = ("sensor_msgs", "CameraInfo");
+}
+
+impl RosType for Detection {
+    const TYPE_NAME: (&'static str, &'static str) = ("arqr_msgs", "Detection");
+}
+
+impl Scanner {
+    /// Binarizes and scans a `sensor_msgs/Image`, in place. Its encoding has
+    /// to be "mono8" (or "8UC1"), "rgb8", "bgr8", "rgba8" or "bgra8".
+    ///
+    /// Returns None if it isn't, or the image's data is too short for its
+    /// size and step.
+    pub fn scan_ros_image(&mut self, image: &Image) -> Option<ScanResult> {
+        // Bytes a pixel, and which of them are red and blue
+        let (bytes, red, blue) = match image.encoding.as_str() {
+            "mono8" | "8UC1" => (1, 0, 0),
+            "rgb8" => (3, 0, 2),
+            "bgr8" => (3, 2, 0),
+            "rgba8" => (4, 0, 2),
+            "bgra8" => (4, 2, 0),
+            _ => return None,
+        };
+        let (w, h, step) = (image.width as usize, image.height as usize, image.step as usize);
+        let row_len = w * bytes;
+        if step < row_len || (h > 0 && image.data.len() < step * (h - 1) + row_len) {
+            return None;
+        }
+        let lumas = (0..h)
+            .flat_map(|row| image.data[row * step..][..row_len].chunks_exact(bytes))
+            .map(move |px| match bytes {
+                1 => px[0],
+                _ => Rgb([px[red], px[1], px[blue]]).to_luma().0[0],
+            });
+        Some(self.scan_lumas(lumas, image.width, image.height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Point as ImagePoint,
+        encode::{EcLevel, QrCode, Version},
+        testgen::{Distortion as Warp, generate},
+    };
+
+    fn header() -> Header {
+        Header { stamp: Time::from_nanos(1_500_000_000), frame_id: "camera_optical".to_owned() }
+    }
+
+    fn rotation(q: &Quaternion) -> [[f64; 3]; 3] {
+        let Quaternion { x, y, z, w } = *q;
+        [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w)],
+            [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w)],
+            [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y)],
+        ]
+    }
+
+    #[test]
+    fn converts_rotations_to_quaternions() {
+        let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        assert_eq!(Quaternion::from_matrix(&identity), Quaternion::default());
+        // A quarter turn about z, taking x to y
+        let q = Quaternion::from_matrix(&[[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+        let half = 0.5f64.sqrt();
+        assert!((q.z - half).abs() < 1e-12 && (q.w - half).abs() < 1e-12 && q.x == 0.0 && q.y == 0.0);
+        // Half turns leave w at zero, which takes the other branches
+        for axis in 0..3 {
+            let mut r = [[-1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]];
+            r[axis][axis] = 1.0;
+            let q = Quaternion::from_matrix(&r);
+            assert_eq!(rotation(&q), r);
+        }
+        // And back again, from a rotation about every axis at once
+        let (a, b, c) = (0.3f64, -1.2f64, 2.5f64);
+        let rx = [[1.0, 0.0, 0.0], [0.0, a.cos(), -a.sin()], [0.0, a.sin(), a.cos()]];
+        let ry = [[b.cos(), 0.0, b.sin()], [0.0, 1.0, 0.0], [-b.sin(), 0.0, b.cos()]];
+        let rz = [[c.cos(), -c.sin(), 0.0], [c.sin(), c.cos(), 0.0], [0.0, 0.0, 1.0]];
+        let mul = |p: [[f64; 3]; 3], q: [[f64; 3]; 3]| {
+            [0, 1, 2].map(|i| [0, 1, 2].map(|j| (0..3).map(|k| p[i][k] * q[k][j]).sum::<f64>()))
+        };
+        let r = mul(rz, mul(ry, rx));
+        let q = Quaternion::from_matrix(&r);
+        assert!(q.w >= 0.0);
+        for (row, expected) in rotation(&q).iter().zip(&r) {
+            for (v, e) in row.iter().zip(expected) {
+                assert!((v - e).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn stamps_poses() {
+        let pose = pose::Pose { r: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], t: [0.1, -0.2, 1.5] };
+        let stamped = PoseStamped::new(header(), &pose);
+        assert_eq!(stamped.header, header());
+        assert_eq!(stamped.pose.position, Point { x: 0.1, y: -0.2, z: 1.5 });
+        assert_eq!(stamped.pose.orientation, Quaternion::default());
+    }
+
+    #[test]
+    fn reads_camera_info() {
+        let mut info = CameraInfo {
+            header: header(),
+            height: 480,
+            width: 640,
+            distortion_model: "plumb_bob".to_owned(),
+            d: vec![0.1, -0.05, 0.001, 0.002, 0.01],
+            k: [500.0, 0.0, 320.0, 0.0, 510.0, 240.0, 0.0, 0.0, 1.0],
+            r: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            p: [0.0; 12],
+            binning_x: 0,
+            binning_y: 0,
+            roi: RegionOfInterest::default(),
+        };
+        let intrinsics = info.intrinsics().unwrap();
+        assert_eq!((intrinsics.fx, intrinsics.fy, intrinsics.cx, intrinsics.cy), (500.0, 510.0, 320.0, 240.0));
+        assert_eq!(intrinsics.distortion, Distortion::BrownConrady { k1: 0.1, k2: -0.05, p1: 0.001, p2: 0.002, k3: 0.01 });
+
+        info.distortion_model = "equidistant".to_owned();
+        info.d.truncate(4);
+        assert_eq!(info.intrinsics().unwrap().distortion, Distortion::Fisheye { k1: 0.1, k2: -0.05, k3: 0.001, k4: 0.002 });
+        // Models arqr doesn't have are fine as long as there's no distortion
+        info.distortion_model = "unknown".to_owned();
+        assert_eq!(info.intrinsics(), None);
+        info.d = vec![0.0; 8];
+        assert_eq!(info.intrinsics().unwrap().distortion, Distortion::default());
+        info.distortion_model = "rational_polynomial".to_owned();
+        info.d = vec![0.1, 0.0, 0.0, 0.0, 0.0, 0.3, 0.0, 0.0];
+        assert_eq!(info.intrinsics(), None);
+        // Uncalibrated
+        info.k = [0.0; 9];
+        assert_eq!(info.intrinsics(), None);
+    }
+
+    fn sample(encoding: &str) -> Image {
+        let code = QrCode::with_version(b"ros", Version::Normal(2), EcLevel::M).unwrap();
+        let gray = generate(&code, &Warp { rotation: 0.2, ..Warp::default() }).image;
+        let (width, height) = gray.dimensions();
+        let bytes = match encoding {
+            "mono8" => 1,
+            "rgb8" | "bgr8" => 3,
+            _ => 4,
+        };
+        // Rows padded out, to check the step is used
+        let step = width as usize * bytes + 3;
+        let mut data = vec![0; step * height as usize];
+        for (x, y, luma) in gray.enumerate_pixels() {
+            let px = &mut data[y as usize * step + x as usize * bytes..][..bytes];
+            px.fill(luma.0[0]);
+        }
+        Image { header: header(), height, width, encoding: encoding.to_owned(), is_bigendian: 0, step: step as u32, data }
+    }
+
+    #[test]
+    fn scans_images() {
+        let mut scanner = Scanner::new();
+        for encoding in ["mono8", "rgb8", "bgr8", "rgba8", "bgra8"] {
+            let result = scanner.scan_ros_image(&sample(encoding)).unwrap();
+            assert_eq!(result.payload.as_deref(), Some(&b"ros"[..]), "{}", encoding);
+        }
+
+        let mut image = sample("mono8");
+        image.encoding = "yuv422".to_owned();
+        assert!(scanner.scan_ros_image(&image).is_none());
+        image.encoding = "mono8".to_owned();
+        image.data.truncate(image.data.len() - 4);
+        assert!(scanner.scan_ros_image(&image).is_none());
+        image.step = image.width - 1;
+        assert!(scanner.scan_ros_image(&image).is_none());
+    }
+
+    #[test]
+    fn builds_detections() {
+        let mut scanner = Scanner::new();
+        let result = scanner.scan_ros_image(&sample("mono8")).unwrap();
+        let detection = Detection::new(header(), &result, None).unwrap();
+        assert_eq!(detection.header, header());
+        let quad = result.quad().unwrap();
+        assert_eq!(detection.corners[..2], [quad[0].x, quad[0].y]);
+        assert_eq!(detection.corners[6..], [quad[3].x, quad[3].y]);
+        assert_eq!((detection.version, detection.ec_level.as_str()), (2, "M"));
+        assert!(detection.decoded && detection.payload == b"ros" && detection.decode_error.is_empty());
+        assert!(detection.confidence > 0.0);
+        assert!(!detection.has_pose);
+
+        let intrinsics = CameraIntrinsics::from_focal(640, 480, 600.0);
+        let code_pose = pose::Pose::from_quad(quad, 0.05, &intrinsics).unwrap();
+        let detection = Detection::new(header(), &result, Some(&code_pose)).unwrap();
+        assert!(detection.has_pose);
+        assert_eq!(detection.pose, Pose::from(&code_pose));
+
+        // Nothing to detect in a blank frame
+        let blank = ScanResult { bbox: None, ..ScanResult::new() };
+        assert_eq!(Detection::new(header(), &blank, None), None);
+        let nan = ImagePoint::new(f64::NAN, 0.0);
+        let degenerate = ScanResult { bbox: Some([nan; 3]), ..ScanResult::new() };
+        assert_eq!(Detection::new(header(), &degenerate, None), None);
+    }
+}
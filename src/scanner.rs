@@ -0,0 +1,412 @@
+//! Contains `Scanner`, which runs the whole scanning pipeline and keeps its
+//! working memory around between frames.
+
+use std::{ops::Deref, time::{Duration, Instant}};
+use image::{GenericImageView, ImageBuffer, Pixel, buffer::ConvertBuffer};
+use crate::{
+    FrameMeta,
+    Point,
+    Rect,
+    ScanResult,
+    Stage,
+    best_frame::Candidate,
+    bitmap::{Binarizer, Bitmap, affine_transform_chunk},
+    decode,
+    fiducial::FiducialDetector,
+    list::{List, MAX_TARGETS},
+    target::{
+        Target,
+        find_pos_targets_in_regions,
+        pick_corners,
+        to_side_len,
+        to_affine_transform,
+    },
+};
+
+/// Times scans and their stages. On the web there's no clock without going
+/// through JS, so there every stage takes no time and budgets never run out.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Stopwatch(#[cfg(not(target_arch = "wasm32"))] Instant);
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        return Self(Instant::now());
+        #[cfg(target_arch = "wasm32")]
+        return Self();
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.0.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        return Duration::ZERO;
+    }
+}
+
+/// When a scan started, and how long it's allowed
+type Deadline = (Stopwatch, Duration);
+
+/// Per-frame temporaries. Everything in here is overwritten on every scan, so
+/// after the first few frames scanning stops making small heap allocations.
+#[derive(Debug, Default)]
+struct Scratch {
+    targets: List<Target<u32>, MAX_TARGETS>,
+    active_targets: List<usize, MAX_TARGETS>,
+    /// The frame with light and dark swapped, for `Scanner::try_inverted`
+    inverted: Bitmap,
+    /// The crop being read by `Scanner::decode_candidate`
+    candidate: Bitmap,
+}
+
+impl Scratch {
+    fn reset(&mut self) {
+        self.targets.clear();
+        self.active_targets.clear();
+    }
+}
+
+/// Scans images for codes. Scanning many frames with the same `Scanner` avoids
+/// reallocating the binarized image and intermediate lists every frame.
+///
+/// When following codes through video, the scanner can be told where codes
+/// are likely to be in the next frame (see `set_regions` and
+/// `Tracker::predict_regions`) and will only search there, apart from a full
+/// sweep of the frame every `full_sweep_interval` frames to pick up new codes.
+///
+/// Loops that can't afford to wait can give the scanner a `budget`, and get
+/// back whatever it managed in that time (see `ScanResult::stage`).
+#[derive(Debug)]
+pub struct Scanner {
+    bmp: Bitmap,
+    scratch: Scratch,
+    regions: Vec<Rect<u32>>,
+    frames_since_sweep: u32,
+    meta: Option<FrameMeta>,
+    next_sequence: u64,
+    /// Maximum number of frames in a row to search only the regions given to
+    /// `set_regions`
+    pub full_sweep_interval: u32,
+    /// Also looks for fiducial markers in every frame, reusing the same
+    /// binarized image. Markers are always searched for over the whole frame.
+    pub fiducials: Option<FiducialDetector>,
+    /// Time allowed for each scan, counted from when `scan` (or
+    /// `scan_bitmap`, `scan_own_bitmap`) is called. Stages aren't stopped
+    /// partway: the clock is checked between them, so a scan can overrun by
+    /// up to one stage. `None` always runs every stage.
+    pub budget: Option<Duration>,
+    /// How `scan` binarizes images
+    pub binarizer: Binarizer,
+    /// When no code turns up, scans the frame again with light and dark
+    /// swapped, for light-on-dark codes like the ones dark-mode apps show.
+    /// Costs a second search of any frame without a code in it.
+    pub try_inverted: bool,
+    /// Samples and reads the message of every code found. Turned off, scans
+    /// stop once the code's been cut out of the frame, leaving no `modules`,
+    /// `format` or `payload`, for when a `BestFrameSelector` picks which
+    /// frames to read with `decode_candidate`.
+    pub decode: bool,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self {
+            bmp: Bitmap::default(),
+            scratch: Scratch::default(),
+            regions: Vec::new(),
+            frames_since_sweep: 0,
+            meta: None,
+            next_sequence: 0,
+            full_sweep_interval: 10,
+            fiducials: None,
+            budget: None,
+            binarizer: Binarizer::Global,
+            try_inverted: false,
+            decode: true,
+        }
+    }
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts the *next* scan to the given regions of the frame. Regions
+    /// only apply to one scan, so this needs calling before every frame. If
+    /// no regions are set, or a full sweep is due, the whole frame is
+    /// searched.
+    pub fn set_regions<I: IntoIterator<Item = Rect<f64>>>(&mut self, regions: I) {
+        self.regions.clear();
+        self.regions.extend(regions.into_iter().map(|r| r.to_pixels()));
+    }
+
+    /// Tags the *next* scan's result with `meta`. Without this, results are
+    /// numbered in the order they're scanned, and have no timestamp.
+    pub fn set_frame_meta(&mut self, meta: FrameMeta) {
+        self.meta = Some(meta);
+    }
+
+    fn take_meta(&mut self) -> FrameMeta {
+        let meta = self.meta.take().unwrap_or(FrameMeta {
+            sequence: self.next_sequence,
+            timestamp: None,
+        });
+        self.next_sequence = meta.sequence.wrapping_add(1);
+        meta
+    }
+
+    /// Picks the regions to search this frame, and takes them out of the
+    /// scanner so it can be borrowed again
+    fn take_regions(&mut self, width: u32, height: u32) -> Vec<Rect<u32>> {
+        let mut regions = std::mem::take(&mut self.regions);
+        if regions.is_empty() || self.frames_since_sweep >= self.full_sweep_interval {
+            regions.clear();
+            regions.push(Rect { min: Point::new(0, 0), max: Point::new(width, height) });
+            self.frames_since_sweep = 0;
+        } else {
+            self.frames_since_sweep += 1;
+        }
+        regions
+    }
+
+    /// Binarizes an image and scans it
+    pub fn scan<Px, C>(&mut self, img: &ImageBuffer<Px, C>) -> ScanResult
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        let start = Stopwatch::start();
+        self.bmp.set_from_u8_img(img, self.binarizer);
+        self.scan_own_bitmap_since(start)
+    }
+
+    /// Binarizes and scans anything that can be viewed as an image, like a
+    /// `SubImage` or a `FlatSamples` view, reading its pixels in place rather
+    /// than copying them into an `ImageBuffer` first. Owned `ImageBuffer`s
+    /// are quicker to scan with `scan`.
+    pub fn scan_view<I>(&mut self, img: &I) -> ScanResult
+    where
+        I: GenericImageView,
+        I::Pixel: Pixel<Subpixel = u8>,
+    {
+        let (width, height) = img.dimensions();
+        let lumas = (0..height).flat_map(|y| (0..width).map(move |x| img.get_pixel(x, y).to_luma().0[0]));
+        self.scan_lumas(lumas, width, height)
+    }
+
+    /// Binarizes a frame that's already been turned into lumas, row by row,
+    /// into the scanner's own bitmap, and scans it. For frame sources that
+    /// read pixels out of their own layouts, like views and the C and JNI
+    /// interfaces.
+    pub(crate) fn scan_lumas<I>(&mut self, lumas: I, width: u32, height: u32) -> ScanResult
+    where
+        I: Iterator<Item = u8> + Clone,
+    {
+        let start = Stopwatch::start();
+        self.bmp.set_from_luma(lumas, width, height, self.binarizer);
+        self.scan_own_bitmap_since(start)
+    }
+
+    fn deadline(&self, start: Stopwatch) -> Option<Deadline> {
+        self.budget.map(|budget| (start, budget))
+    }
+
+    /// Scans an already binarized image
+    pub fn scan_bitmap(&mut self, bmp: &Bitmap) -> ScanResult {
+        let deadline = self.deadline(Stopwatch::start());
+        let mut regions = self.take_regions(bmp.width(), bmp.height());
+        let mut result = scan_either_way(bmp, &regions, &mut self.scratch, deadline, self.try_inverted, self.decode);
+        result.meta = self.take_meta();
+        if result.stage == Stage::Decoded && !out_of_time(deadline) {
+            let fiducials_start = Stopwatch::start();
+            if let Some(fiducials) = &self.fiducials {
+                result.markers = fiducials.detect(bmp);
+            }
+            result.timings.fiducials = fiducials_start.elapsed();
+            result.stage = Stage::Complete;
+        }
+        // Hand the allocation back for next time
+        regions.clear();
+        self.regions = regions;
+        result
+    }
+
+    /// Reads the code in a frame a `BestFrameSelector` picked out, whether or
+    /// not `decode` is set. The whole crop is searched, and it doesn't count
+    /// as a frame: the regions set for the next frame, and its sequence
+    /// number, are left alone. Corners are in the crop.
+    pub fn decode_candidate(&mut self, candidate: &Candidate) -> ScanResult {
+        let start = Stopwatch::start();
+        let mut bmp = std::mem::take(&mut self.scratch.candidate);
+        bmp.set_from_u8_img(&candidate.image, self.binarizer);
+        let binarize = start.elapsed();
+        let deadline = self.deadline(start);
+        let whole = [Rect { min: Point::new(0, 0), max: Point::new(bmp.width(), bmp.height()) }];
+        let mut result = scan_either_way(&bmp, &whole, &mut self.scratch, deadline, self.try_inverted, true);
+        self.scratch.candidate = bmp;
+        result.timings.binarize = binarize;
+        result
+    }
+
+    /// Gives mutable access to the scanner's own bitmap, so that frame sources
+    /// can binarize into it without allocating. Follow up with
+    /// `scan_own_bitmap`.
+    pub fn bitmap_mut(&mut self) -> &mut Bitmap {
+        &mut self.bmp
+    }
+
+    /// Scans whatever was last written into the scanner's own bitmap
+    pub fn scan_own_bitmap(&mut self) -> ScanResult {
+        self.scan_own_bitmap_since(Stopwatch::start())
+    }
+
+    /// Scans the scanner's own bitmap, which started being binarized at
+    /// `start`. The time since then is counted as binarizing, and against
+    /// the budget.
+    pub(crate) fn scan_own_bitmap_since(&mut self, start: Stopwatch) -> ScanResult {
+        let binarize = start.elapsed();
+        let deadline = self.deadline(start);
+        let mut regions = self.take_regions(self.bmp.width(), self.bmp.height());
+        let mut result = scan_either_way(&self.bmp, &regions, &mut self.scratch, deadline, self.try_inverted, self.decode);
+        result.meta = self.take_meta();
+        result.timings.binarize = binarize;
+        if result.stage == Stage::Decoded && !out_of_time(deadline) {
+            let fiducials_start = Stopwatch::start();
+            if let Some(fiducials) = &self.fiducials {
+                result.markers = fiducials.detect(&self.bmp);
+            }
+            result.timings.fiducials = fiducials_start.elapsed();
+            result.stage = Stage::Complete;
+        }
+        regions.clear();
+        self.regions = regions;
+        result
+    }
+}
+
+fn out_of_time(deadline: Option<Deadline>) -> bool {
+    deadline.is_some_and(|(start, budget)| start.elapsed() >= budget)
+}
+
+/// Runs the scan up to reading the code's message, and if that turns up no
+/// code and `inverted` is set, runs it again over `bmp` with light and dark
+/// swapped. The time spent on both goes in the result's timings.
+fn scan_either_way(
+    bmp: &Bitmap,
+    regions: &[Rect<u32>],
+    scratch: &mut Scratch,
+    deadline: Option<Deadline>,
+    inverted: bool,
+    decode: bool,
+) -> ScanResult {
+    let result = scan_with_scratch(bmp, regions, scratch, deadline, decode);
+    if !inverted || result.bbox.is_some() || result.stage < Stage::Decoded || out_of_time(deadline) {
+        return result;
+    }
+
+    // Inverting counts as part of the search for targets
+    let invert_start = Stopwatch::start();
+    let mut inverted_bmp = std::mem::take(&mut scratch.inverted);
+    inverted_bmp.set_inverted(bmp);
+    let invert = invert_start.elapsed();
+    let mut retry = scan_with_scratch(&inverted_bmp, regions, scratch, deadline, decode);
+    scratch.inverted = inverted_bmp;
+    retry.timings.targets += invert;
+    retry.inverted = true;
+    // Whichever is kept, it took as long as both
+    let (mut kept, other) = if retry.bbox.is_some() { (retry, result) } else { (result, retry) };
+    kept.timings.targets += other.timings.targets;
+    kept.timings.corners += other.timings.corners;
+    kept.timings.extract += other.timings.extract;
+    kept.timings.decode += other.timings.decode;
+    kept
+}
+
+/// Runs the scan up to reading the code's message (if `decode` is set), or
+/// as far as it gets before `deadline`
+fn scan_with_scratch(
+    bmp: &Bitmap,
+    regions: &[Rect<u32>],
+    scratch: &mut Scratch,
+    deadline: Option<Deadline>,
+    decode: bool,
+) -> ScanResult {
+    scratch.reset();
+    let mut result = ScanResult { stage: Stage::Binarized, ..ScanResult::new() };
+    if out_of_time(deadline) {
+        return result;
+    }
+
+    let mut stage_start = Stopwatch::start();
+    find_pos_targets_in_regions(bmp, regions, &mut scratch.targets, &mut scratch.active_targets);
+    let targets = &scratch.targets;
+    result.targets = targets.iter().map(|t| t.to_f64()).collect();
+    result.stage = Stage::Targets;
+    result.timings.targets = stage_start.elapsed();
+    if out_of_time(deadline) {
+        return result;
+    }
+
+    stage_start = Stopwatch::start();
+    let bbox = pick_corners(targets);
+    result.bbox = bbox;
+    result.stage = Stage::Corners;
+    result.timings.corners = stage_start.elapsed();
+    if out_of_time(deadline) {
+        return result;
+    }
+
+    stage_start = Stopwatch::start();
+    let mut code = None;
+    if let Some(bbox) = bbox {
+        let len = to_side_len(bbox);
+        let trans = to_affine_transform(bbox, len);
+        // println!("{:?}", trans);
+        // Wide enough for the whole code, however big it is in the frame
+        let width = (bmp.width() / 2).max(len.ceil() as u32);
+        let angle_h = bbox[0].angle_to(bbox[1]);
+        let angle_v = bbox[0].angle_to(bbox[1]);
+        let vector_h = Point::new(200.0 * angle_h.cos(), 200.0 * angle_h.sin());
+        let vector_v = Point::new(200.0 * angle_v.cos(), 200.0 * angle_v.sin());
+        result.vectors = Some([vector_h, vector_v]);
+        let code_bmp = affine_transform_chunk(bmp, trans, width, width);
+        result.code_img = Some(code_bmp.convert());
+        code = Some((code_bmp, bbox, len));
+    }
+    result.stage = Stage::Extracted;
+    result.timings.extract = stage_start.elapsed();
+    if out_of_time(deadline) {
+        return result;
+    }
+
+    stage_start = Stopwatch::start();
+    if let Some((code_bmp, bbox, len)) = code.filter(|_| decode) {
+        result.modules = decode::module_size(&result.targets, bbox)
+            .and_then(|module| {
+                decode::timing_size(bmp, &result.targets, bbox, module)
+                    .or_else(|| decode::geometric_size(len, module))
+            })
+            .and_then(|size| decode::sample(&code_bmp, len, size));
+        result.version = result.modules.as_ref().and_then(|grid| grid.version());
+        if let Some((format, mirrored)) = result.modules.as_mut().and_then(|grid| grid.orient()) {
+            result.format = Some(format);
+            result.mirrored = mirrored;
+        }
+        let read = match (&result.modules, result.format) {
+            (Some(grid), Some(format)) => decode::read_corrected(grid, format),
+            (Some(_), None) => Err(decode::DecodeError::FormatUnreadable),
+            (None, _) => Err(decode::DecodeError::Unsampled),
+        };
+        match read {
+            Ok((payload, errors)) => {
+                result.payload = Some(payload);
+                result.codeword_errors = Some(errors);
+            }
+            Err(e) => result.decode_error = Some(e),
+        }
+    }
+    result.stage = Stage::Decoded;
+    result.timings.decode = stage_start.elapsed();
+    result
+}
@@ -0,0 +1,106 @@
+//! Filters for smoothing out jittery point positions, used by the tracker to
+//! steady the corners of tracked codes.
+
+use crate::Point;
+
+/// How to smooth a point that's measured once per frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smoothing {
+    /// Use every measurement as-is
+    None,
+    /// Exponential moving average. `alpha` is the weight given to each new
+    /// measurement, from 0 (never move) to 1 (no smoothing).
+    Ema { alpha: f64 },
+    /// Constant-velocity Kalman filter on each axis. Raising `process_noise`
+    /// (px²/s³) makes the filter more responsive to real motion; raising
+    /// `measurement_noise` (px²) makes it trust each measurement less.
+    Kalman { process_noise: f64, measurement_noise: f64 },
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Smoothing::Ema { alpha: 0.5 }
+    }
+}
+
+/// State of a filter along one axis
+#[derive(Clone, Copy, Debug, Default)]
+struct Axis {
+    pos: f64,
+    vel: f64,
+    // Kalman covariance of (pos, vel)
+    cov: [[f64; 2]; 2],
+}
+
+impl Axis {
+    fn new(pos: f64, measurement_noise: f64) -> Self {
+        // Position is as certain as one measurement; velocity is unknown
+        Self { pos, vel: 0.0, cov: [[measurement_noise, 0.0], [0.0, 1e6]] }
+    }
+
+    fn kalman(&mut self, z: f64, dt: f64, q: f64, r: f64) {
+        // Predict
+        self.pos += self.vel * dt;
+        let [[p00, p01], [p10, p11]] = self.cov;
+        let (dt2, dt3) = (dt * dt, dt * dt * dt);
+        let p00 = p00 + dt * (p10 + p01) + dt2 * p11 + q * dt3 / 3.0;
+        let p01 = p01 + dt * p11 + q * dt2 / 2.0;
+        let p10 = p10 + dt * p11 + q * dt2 / 2.0;
+        let p11 = p11 + q * dt;
+
+        // Correct
+        let s = p00 + r;
+        let (k0, k1) = (p00 / s, p10 / s);
+        let y = z - self.pos;
+        self.pos += k0 * y;
+        self.vel += k1 * y;
+        self.cov = [
+            [(1.0 - k0) * p00, (1.0 - k0) * p01],
+            [p10 - k1 * p00, p11 - k1 * p01],
+        ];
+    }
+}
+
+/// Smooths the position of a single point over time
+#[derive(Clone, Copy, Debug)]
+pub struct PointFilter {
+    smoothing: Smoothing,
+    x: Axis,
+    y: Axis,
+}
+
+impl PointFilter {
+    /// Starts a filter at the given point
+    pub fn new(smoothing: Smoothing, start: Point<f64>) -> Self {
+        let r = match smoothing {
+            Smoothing::Kalman { measurement_noise, .. } => measurement_noise,
+            _ => 0.0,
+        };
+        Self { smoothing, x: Axis::new(start.x, r), y: Axis::new(start.y, r) }
+    }
+
+    /// Current smoothed position
+    pub fn position(&self) -> Point<f64> {
+        Point::new(self.x.pos, self.y.pos)
+    }
+
+    /// Feeds in a new measurement taken `dt` seconds after the last one and
+    /// returns the new smoothed position
+    pub fn update(&mut self, measured: Point<f64>, dt: f64) -> Point<f64> {
+        match self.smoothing {
+            Smoothing::None => {
+                self.x.pos = measured.x;
+                self.y.pos = measured.y;
+            }
+            Smoothing::Ema { alpha } => {
+                self.x.pos += alpha * (measured.x - self.x.pos);
+                self.y.pos += alpha * (measured.y - self.y.pos);
+            }
+            Smoothing::Kalman { process_noise, measurement_noise } => {
+                self.x.kalman(measured.x, dt, process_noise, measurement_noise);
+                self.y.kalman(measured.y, dt, process_noise, measurement_noise);
+            }
+        }
+        self.position()
+    }
+}
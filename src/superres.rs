@@ -0,0 +1,252 @@
+//! Builds a sharper, higher resolution image of a code from several frames,
+//! for codes too small to read in any one of them.
+//!
+//! Every frame sees the code from a slightly different sub-pixel offset, so
+//! mapping each frame's pixels onto a common, finer grid through the code's
+//! homography and averaging them (shift-and-add) fills in detail that no
+//! single frame has. How much this helps depends on how precisely the code's
+//! corners were found: registration error blurs the result.
+
+use std::{collections::HashMap, ops::Deref};
+use image::{GrayImage, ImageBuffer, Luma, Pixel};
+use crate::{
+    Point,
+    Rect,
+    ScanResult,
+    Scanner,
+    homography::Homography,
+    target::complete_quad,
+    tracker::{TrackId, Tracker},
+};
+
+/// Accumulates registered views of one code into a composite image
+#[derive(Clone, Debug)]
+pub struct Composite {
+    size: u32,
+    margin: f64,
+    sum: Vec<f32>,
+    weight: Vec<f32>,
+    frames: u32,
+}
+
+impl Composite {
+    /// Starts an empty composite `size` pixels square. The code's quad is
+    /// mapped to the middle of it, with `margin` times the code's size of
+    /// surroundings on every side.
+    pub fn new(size: u32, margin: f64) -> Self {
+        let len = (size * size) as usize;
+        Self { size, margin, sum: vec![0.0; len], weight: vec![0.0; len], frames: 0 }
+    }
+
+    /// Number of frames added so far
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Side length of the composite, in pixels
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// The composite resampled to `size` pixels square, bilinearly, keeping
+    /// the frames added so far
+    pub fn resized(&self, size: u32) -> Self {
+        let mut resized = Self::new(size, self.margin);
+        let scale = self.size as f64 / size.max(1) as f64;
+        let last = self.size as f64 - 1.0;
+        for y in 0..size {
+            let old_y = ((y as f64 + 0.5) * scale - 0.5).clamp(0.0, last);
+            let (y0, fy) = (old_y as u32, old_y.fract() as f32);
+            let y1 = (y0 + 1).min(self.size - 1);
+            for x in 0..size {
+                let old_x = ((x as f64 + 0.5) * scale - 0.5).clamp(0.0, last);
+                let (x0, fx) = (old_x as u32, old_x.fract() as f32);
+                let x1 = (x0 + 1).min(self.size - 1);
+                let i = (y * size + x) as usize;
+                let taps = [
+                    (x0, y0, (1.0 - fx) * (1.0 - fy)),
+                    (x1, y0, fx * (1.0 - fy)),
+                    (x0, y1, (1.0 - fx) * fy),
+                    (x1, y1, fx * fy),
+                ];
+                for (ox, oy, w) in taps {
+                    let old = (oy * self.size + ox) as usize;
+                    resized.sum[i] += w * self.sum[old];
+                    resized.weight[i] += w * self.weight[old];
+                }
+            }
+        }
+        resized.frames = self.frames;
+        resized
+    }
+
+    /// Maps composite pixel coordinates to image coordinates for a code seen
+    /// at `quad` (top-left, top-right, bottom-right, bottom-left)
+    fn to_image(&self, quad: [Point<f64>; 4]) -> Option<Homography> {
+        let size = self.size as f64;
+        let lo = size * self.margin / (1.0 + 2.0 * self.margin);
+        let hi = size - lo;
+        let square = [Point::new(lo, lo), Point::new(hi, lo), Point::new(hi, hi), Point::new(lo, hi)];
+        Homography::from_points(&square, &quad)
+    }
+
+    /// Adds a frame in which the code was seen at `quad`. Returns false (and
+    /// adds nothing) if the quad is degenerate.
+    pub fn add<Px, C>(&mut self, img: &ImageBuffer<Px, C>, quad: [Point<f64>; 4]) -> bool
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        let Some(to_image) = self.to_image(quad) else { return false };
+        let Some(to_composite) = to_image.inverse() else { return false };
+
+        let size = self.size as f64;
+        let outline = [(0.0, 0.0), (size, 0.0), (size, size), (0.0, size)]
+            .map(|(x, y)| to_image.apply(Point::new(x, y)));
+        let (width, height) = img.dimensions();
+        let bounds = Rect::bounding(&outline).to_pixels();
+        let (x1, y1) = (bounds.max.x.min(width), bounds.max.y.min(height));
+
+        // Each image pixel covers roughly `size / scale` composite pixels.
+        // Spreading it over a bit less than that with a tent kernel leaves
+        // fewer holes than point splatting without blurring away the detail.
+        let scale = outline[0].dist_to(outline[1]).max(1.0);
+        let radius = (size / scale * 0.6).max(1.0);
+        let reach = radius.ceil() as i64;
+
+        for y in bounds.min.y..y1 {
+            for x in bounds.min.x..x1 {
+                let p = to_composite.apply(Point::new(x as f64 + 0.5, y as f64 + 0.5));
+                if !(0.0..size).contains(&p.x) || !(0.0..size).contains(&p.y) {
+                    continue;
+                }
+                let value = img.get_pixel(x, y).to_luma().0[0] as f32;
+                let (cx, cy) = (p.x as i64, p.y as i64);
+                for v in (cy - reach).max(0)..=(cy + reach).min(self.size as i64 - 1) {
+                    let wy = 1.0 - ((v as f64 + 0.5 - p.y).abs() / radius);
+                    if wy <= 0.0 {
+                        continue;
+                    }
+                    for u in (cx - reach).max(0)..=(cx + reach).min(self.size as i64 - 1) {
+                        let wx = 1.0 - ((u as f64 + 0.5 - p.x).abs() / radius);
+                        if wx <= 0.0 {
+                            continue;
+                        }
+                        let i = (v as u32 * self.size + u as u32) as usize;
+                        let w = (wx * wy) as f32;
+                        self.sum[i] += w * value;
+                        self.weight[i] += w;
+                    }
+                }
+            }
+        }
+        self.frames += 1;
+        true
+    }
+
+    /// The composite so far. Pixels no frame reached are white.
+    pub fn image(&self) -> GrayImage {
+        GrayImage::from_fn(self.size, self.size, |x, y| {
+            let i = (y * self.size + x) as usize;
+            let w = self.weight[i];
+            Luma([if w > 0.0 { (self.sum[i] / w).round().clamp(0.0, 255.0) as u8 } else { 255 }])
+        })
+    }
+
+    /// Scans the composite. Corners in the result are in the composite's
+    /// coordinates.
+    pub fn scan(&self, scanner: &mut Scanner) -> ScanResult {
+        scanner.scan(&self.image())
+    }
+}
+
+/// Keeps a `Composite` going for each small code the tracker is following,
+/// and scans it once enough frames have built up. As a code comes closer
+/// or moves away, its composite is resampled to stay `upscale` times the
+/// size it's seen at.
+#[derive(Debug)]
+pub struct SuperResolver {
+    composites: HashMap<TrackId, Composite>,
+    scanner: Scanner,
+    /// Codes with sides shorter than this many pixels are accumulated; bigger
+    /// ones should be readable as they are
+    pub max_side: f64,
+    /// How much bigger than the code as seen the composite is
+    pub upscale: f64,
+    /// Surroundings to keep around each code, as a fraction of its size
+    pub margin: f64,
+    /// Frames to accumulate before scanning a composite
+    pub min_frames: u32,
+    /// Frames after which a composite that still hasn't been read is thrown
+    /// away and restarted, in case it's been spoiled by bad registration
+    pub max_frames: u32,
+    /// How far, as a fraction, the size a composite should be can drift
+    /// from the size it is before it's resampled. Sizes jitter along with
+    /// the corners from frame to frame, and every resampling blurs.
+    pub resize_tolerance: f64,
+}
+
+impl Default for SuperResolver {
+    fn default() -> Self {
+        Self {
+            composites: HashMap::new(),
+            scanner: Scanner::new(),
+            max_side: 60.0,
+            upscale: 4.0,
+            margin: 0.5,
+            min_frames: 4,
+            max_frames: 30,
+            resize_tolerance: 0.2,
+        }
+    }
+}
+
+impl SuperResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The composite being built for a track, if there is one
+    pub fn composite(&self, id: TrackId) -> Option<&Composite> {
+        self.composites.get(&id)
+    }
+
+    /// Adds the current frame to the composite of each small confirmed
+    /// track, and returns the tracks whose composite was read this frame.
+    /// Composites that are read are done with, and dropped, as are those of
+    /// tracks the tracker has forgotten.
+    pub fn update<Px, C>(&mut self, img: &ImageBuffer<Px, C>, tracker: &Tracker) -> Vec<(TrackId, ScanResult)>
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        self.composites.retain(|id, _| tracker.tracks().iter().any(|t| t.id == *id));
+
+        let mut found = Vec::new();
+        for track in tracker.confirmed() {
+            let side = track.side_len();
+            // Only frames the code was actually seen in can be registered
+            if side >= self.max_side || track.misses > 0 {
+                continue;
+            }
+            let size = (side * self.upscale * (1.0 + 2.0 * self.margin)).ceil() as u32;
+            let margin = self.margin;
+            let composite = self.composites.entry(track.id).or_insert_with(|| Composite::new(size, margin));
+            if (composite.size() as f64 - size as f64).abs() > composite.size() as f64 * self.resize_tolerance {
+                *composite = composite.resized(size);
+            }
+            composite.add(img, complete_quad(track.raw_corners));
+
+            if composite.frames() >= self.min_frames {
+                let result = composite.scan(&mut self.scanner);
+                if result.payload.is_some() {
+                    self.composites.remove(&track.id);
+                    found.push((track.id, result));
+                } else if composite.frames() >= self.max_frames {
+                    *composite = Composite::new(size, margin);
+                }
+            }
+        }
+        found
+    }
+}
@@ -2,7 +2,7 @@
 //! locate the code as much as possible based on the positions of those targets.
 
 use std::{iter, slice, f64::consts::{PI, TAU}};
-use crate::{Point, bitmap::Bitmap};
+use crate::{Point, Rect, bitmap::Bitmap, list::{List, MAX_TARGETS}};
 
 /// Represents the location of a single identified position target.
 /// 
@@ -93,7 +93,7 @@ impl<T: Copy + Default, const N: usize> FixedBuffer<T, N> {
         self.data[if self.full { self.head } else { 0 }]
     }
 
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         let start = if self.full { self.head } else { N };
         self.data[start..N].iter().chain(self.data[0..self.head].iter())
     }
@@ -201,28 +201,81 @@ fn confirm_row(img: &Bitmap, x: u32, y: u32, width: u32) -> Option<(u32, u32)> {
 
 /// Locates position targets (the 3 big squares in the corners of a QR code) in
 /// an image.
-pub fn find_pos_targets(img: &Bitmap) -> Vec<Target<u32>> {
+pub fn find_pos_targets(img: &Bitmap) -> List<Target<u32>, MAX_TARGETS> {
+    let mut targets = List::new();
+    find_pos_targets_into(img, &mut targets, &mut List::new());
+    targets
+}
+
+/// Same as `find_pos_targets`, but writes into caller-provided lists so they
+/// can be reused from frame to frame. `active_targets` is scratch space; both
+/// lists are cleared first.
+pub fn find_pos_targets_into(
+    img: &Bitmap,
+    targets: &mut List<Target<u32>, MAX_TARGETS>,
+    active_targets: &mut List<usize, MAX_TARGETS>,
+) {
+    let (width, height) = img.dimensions();
+    let whole = Rect { min: Point::new(0, 0), max: Point::new(width, height) };
+    find_pos_targets_in_regions(img, &[whole], targets, active_targets);
+}
+
+/// Same as `find_pos_targets_into`, but only searches for targets within the
+/// given regions of the image. Targets near a region's edge may still be
+/// picked up if their middle rows fall inside it.
+pub fn find_pos_targets_in_regions(
+    img: &Bitmap,
+    regions: &[Rect<u32>],
+    targets: &mut List<Target<u32>, MAX_TARGETS>,
+    active_targets: &mut List<usize, MAX_TARGETS>,
+) {
+    targets.clear();
+    for region in regions {
+        let region = Rect {
+            min: Point::new(region.min.x.min(img.width()), region.min.y.min(img.height())),
+            max: Point::new(region.max.x.min(img.width()), region.max.y.min(img.height())),
+        };
+        if region.max.x < region.min.x + 2 || region.max.y <= region.min.y {
+            continue;
+        }
+        find_in_region(img, region, targets, active_targets);
+    }
+}
+
+fn find_in_region(
+    img: &Bitmap,
+    region: Rect<u32>,
+    targets: &mut List<Target<u32>, MAX_TARGETS>,
+    active_targets: &mut List<usize, MAX_TARGETS>,
+) {
     // Stores the ratios of sizes of successive chunks of pixels
     let mut ratio_buf = FixedBuffer::<f32, 4>::new();
     // Stores the x-coords of the last few chunk edges
     let mut x_buf = FixedBuffer::<u32, 6>::new();
-    // Holds any targets we find
-    let mut targets = Vec::new();
-    // Tracks targets that are in danger of being re-scanned
-    let mut active_targets = Vec::new();
-
-    for (y, row) in img.rows().enumerate().step_by(4) {
+    // `targets` holds any targets we find, and `active_targets` tracks
+    // targets that are in danger of being re-scanned
+    active_targets.clear();
+
+    let (x0, x1) = (region.min.x as usize, region.max.x as usize);
+    let rows = img.rows()
+        .enumerate()
+        .skip(region.min.y as usize)
+        .take((region.max.y - region.min.y) as usize)
+        .step_by(4);
+    for (y, row) in rows {
         let y = y as u32;
-        let mut enum_row = row.enumerate();
+        let mut enum_row = row.as_slice()[x0..x1].iter()
+            .enumerate()
+            .map(|(x, px)| (x + x0, px));
         let mut chunk_color = !*enum_row.next().unwrap().1;
         let mut last_count = 1;
         // advance through the first chunk and save its size in last_count
-        while let Some((_, &px)) = enum_row.next() {
+        for (_, &px) in enum_row.by_ref() {
             if px == chunk_color { break; }
             last_count += 1;
         }
 
-        x_buf.push(last_count);
+        x_buf.push(region.min.x + last_count);
         // counts size of current chunk of black/white
         let mut count = 1;
 
@@ -272,9 +325,17 @@ pub fn find_pos_targets(img: &Bitmap) -> Vec<Target<u32>> {
                     // This also helps fine-tune the edges of the target
                     let y_mid = y_min + (y_max - y_min) / 2;
                     if let Some((x_min, x_max)) = confirm_row(img, x_mid, y_mid, width) {
-                        active_targets.push(targets.len());
+                        // Overlapping regions can turn up the same target twice
+                        let seen = targets.iter().any(|t| {
+                            t.min.x <= x_mid && x_mid <= t.max.x && t.min.y <= y_mid && y_mid <= t.max.y
+                        });
+                        if seen {
+                            continue;
+                        }
                         let new_target = Target::new(x_min, y_min, x_mid, y_mid, x_max, y_max);
-                        targets.push(new_target);
+                        if targets.push(new_target) {
+                            active_targets.push(targets.len() - 1);
+                        }
                     }
                 }
             } else {
@@ -295,8 +356,6 @@ pub fn find_pos_targets(img: &Bitmap) -> Vec<Target<u32>> {
         ratio_buf.clear();
         x_buf.clear();
     }
-
-    targets
 }
 
 /// Helper function which turns a closure into a collection of 3 elements
@@ -323,7 +382,7 @@ fn max3<T: PartialOrd, F: Fn(usize) -> T>(f: F) -> usize {
 /// 
 /// Corner points are guaranteed to be returned in this order: top-left,
 /// top-right, bottom-left
-pub fn pick_corners<T>(targets: &Vec<Target<T>>) -> Option<[Point<f64>; 3]>
+pub fn pick_corners<T>(targets: &[Target<T>]) -> Option<[Point<f64>; 3]>
 where
     T: Copy + Into<f64>
 {
@@ -334,12 +393,14 @@ where
     // Convert target coordinates to floats
     let t = collect3(|i| targets[i].to_f64());
 
-    // Slopes from one target to the next
-    let slopes = collect3(|i| {
+    // Directions from one target to the next, and their slopes. Slopes are
+    // infinite for edges that run straight down the image.
+    let dirs = collect3(|i| {
         let Target {mid: m1, ..} = t[i];
         let Target {mid: m2, ..} = t[(i + 1) % 3];
-        (m2.y - m1.y) / (m2.x - m1.x)
+        Point::new(m2.x - m1.x, m2.y - m1.y)
     });
+    let slopes = collect3(|i| dirs[i].y / dirs[i].x);
 
     // Convert slopes to angles
     // (we didn't do this conversion in the first place b.c. slopes are used later)
@@ -369,11 +430,13 @@ where
     // Now that we have the top-left, pick top-right and bottom-left, and which
     // slopes correspond to which edges.
     let idx = |i| (tl_index + i) % 3;
-    let (top_right, bot_left, h_slope, v_slope) = if (arcs[tl_index] + TAU) % TAU > PI {
-        (t[idx(2)], t[idx(1)], slopes[idx(2)], slopes[tl_index])
+    let (top_right, bot_left, h_edge, v_edge) = if (arcs[tl_index] + TAU) % TAU > PI {
+        (t[idx(2)], t[idx(1)], idx(2), tl_index)
     } else {
-        (t[idx(1)], t[idx(2)], slopes[tl_index], slopes[idx(2)])
+        (t[idx(1)], t[idx(2)], tl_index, idx(2))
     };
+    let (h_slope, v_slope) = (slopes[h_edge], slopes[v_edge]);
+    let (h_dir, v_dir) = (dirs[h_edge], dirs[v_edge]);
 
     // Choose points on the edges of the code.
     let pick_points = |targ: Target<_>, other: Target<_>, slope: f64| {
@@ -396,15 +459,28 @@ where
     let (in_top, out_top, right) = pick_points(top_right, bot_left, h_slope);
     let (in_left, out_left, bottom) = pick_points(bot_left, top_right, v_slope);
 
-    // Compute intersections of lines on the border of the code to find the code's corners
-    let intersect = |p1: Point<_>, p2: Point<_>| {
-        let x = (h_slope * p1.x - v_slope * p2.x + p2.y - p1.y) / (h_slope - v_slope);
-        let y = h_slope * (x - p1.x) + p1.y;
-        Point::new(x, y)
+    // Compute intersections of lines on the border of the code to find the
+    // code's corners: the line through `p1` along the top edge's direction,
+    // and through `p2` along the left edge's. Going by directions rather than
+    // slopes keeps upright codes, whose left edge has an infinite slope, from
+    // coming out as NaN.
+    let cross = |a: Point<f64>, b: Point<f64>| a.x * b.y - a.y * b.x;
+    let intersect = |p1: Point<f64>, p2: Point<f64>| {
+        let along = cross(Point::new(p2.x - p1.x, p2.y - p1.y), v_dir) / cross(h_dir, v_dir);
+        Point::new(p1.x + along * h_dir.x, p1.y + along * h_dir.y)
     };
     Some([intersect(in_top, in_left), intersect(out_top, right), intersect(bottom, out_left)])
 }
 
+/// Given 3 corner points of the code (from `pick_corners`), guesses the
+/// fourth by completing a parallelogram. Returns corners in the order
+/// top-left, top-right, bottom-right, bottom-left.
+pub fn complete_quad(corners: [Point<f64>; 3]) -> [Point<f64>; 4] {
+    let [tl, tr, bl] = corners;
+    let br = Point::new(tr.x + bl.x - tl.x, tr.y + bl.y - tl.y);
+    [tl, tr, br, bl]
+}
+
 pub fn to_side_len(corners: [Point<f64>; 3]) -> f64 {
     let top_len = corners[0].dist_to(corners[1]);
     let left_len = corners[0].dist_to(corners[2]);
@@ -416,13 +492,141 @@ pub fn to_side_len(corners: [Point<f64>; 3]) -> f64 {
 // This transform is immediately inverted by `bitmap::affine_transform_chunk`,
 // so we sacrifice some miniscule constant performance factor to that.
 pub fn to_affine_transform(corners: [Point<f64>; 3], side_len: f64) -> [[f64; 3]; 2] {    
-    let angle_h = corners[0].angle_to(corners[1]);
-    let angle_v = corners[0].angle_to(corners[2]);
-    let h_len = side_len / corners[0].dist_to(corners[1]);
-    let v_len = side_len / corners[0].dist_to(corners[2]);
-    // println!("{} {}", h_len, v_len);
-    println!("{}", angle_v);
-
-    [[angle_h.cos() * h_len, -angle_v.cos() * v_len, corners[0].x],
-     [-angle_h.sin() * h_len, angle_v.sin() * v_len, corners[0].y]]
+    // Each side is stretched or squashed to `side_len`, along its own
+    // direction, so the top and left edges land exactly on the image's even
+    // when they're different lengths
+    let [tl, tr, bl] = corners;
+    [[(tr.x - tl.x) / side_len, (tr.y - tl.y) / side_len, tl.x],
+     [(bl.x - tl.x) / side_len, (bl.y - tl.y) / side_len, tl.y]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn fixed_buffer_keeps_the_last_n(pushed in prop::collection::vec(any::<u32>(), 0..20)) {
+            let mut buf = FixedBuffer::<u32, 5>::new();
+            for (i, &val) in pushed.iter().enumerate() {
+                buf.push(val);
+                let expected = &pushed[(i + 1).saturating_sub(5)..=i];
+                prop_assert_eq!(buf.iter().copied().collect::<Vec<_>>(), expected);
+                prop_assert_eq!(buf.is_full(), i + 1 >= 5);
+                // The back is the oldest value still held
+                prop_assert_eq!(buf.peek_back(), expected[0]);
+            }
+            buf.clear();
+            prop_assert!(!buf.is_full());
+            prop_assert_eq!(buf.iter().count(), 0);
+        }
+    }
+
+    #[test]
+    fn fixed_buffer_peeks_default_when_empty() {
+        let buf = FixedBuffer::<u32, 3>::new();
+        assert_eq!(buf.peek_back(), 0);
+    }
+
+    /// The targets of a code `modules` across with `module` pixel modules,
+    /// turned by `angle` about its top-left corner and moved to `origin`,
+    /// and where its top-left, top-right and bottom-left corners are
+    fn synthetic_code(modules: f64, module: f64, angle: f64, origin: Point<f64>) -> ([Target<f64>; 3], [Point<f64>; 3]) {
+        let (sin, cos) = angle.sin_cos();
+        let place = |x: f64, y: f64| {
+            let (x, y) = (x * module, y * module);
+            Point::new(origin.x + x * cos - y * sin, origin.y + x * sin + y * cos)
+        };
+        // Lines through a target's middle cross its edges further out the
+        // more it's turned, furthest at 45 degrees
+        let folded = (angle.rem_euclid(PI / 2.0) - PI / 4.0).abs();
+        let reach = 3.5 * module / (PI / 4.0 - folded).cos();
+        let target = |x: f64, y: f64| {
+            let mid = place(x, y);
+            Target::new(mid.x - reach, mid.y - reach, mid.x, mid.y, mid.x + reach, mid.y + reach)
+        };
+        let n = modules;
+        (
+            [target(3.5, 3.5), target(n - 3.5, 3.5), target(3.5, n - 3.5)],
+            [place(0.0, 0.0), place(n, 0.0), place(0.0, n)],
+        )
+    }
+
+    /// Any angle, with whole quarter turns (upright codes, whose edges run
+    /// straight along the image's rows and columns) picked as often as the
+    /// rest put together
+    fn angle() -> impl Strategy<Value = f64> {
+        prop_oneof![(0..4).prop_map(|quarters| quarters as f64 * PI / 2.0), 0.0..TAU]
+    }
+
+    fn point(range: f64) -> impl Strategy<Value = Point<f64>> {
+        (-range..range, -range..range).prop_map(Point::from)
+    }
+
+    fn assert_close(found: [Point<f64>; 3], expected: [Point<f64>; 3], tolerance: f64) -> Result<(), TestCaseError> {
+        for (f, e) in found.iter().zip(&expected) {
+            prop_assert!(f.dist_to(*e) < tolerance, "found {:?}, expected {:?}", found, expected);
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn pick_corners_finds_turned_codes(
+            version in 1..40u32,
+            module in 1.0..10.0,
+            angle in angle(),
+            origin in point(500.0),
+        ) {
+            let modules = (17 + 4 * version) as f64;
+            let (targets, expected) = synthetic_code(modules, module, angle, origin);
+            assert_close(pick_corners(&targets).unwrap(), expected, 1e-6 * modules * module)?;
+        }
+
+        #[test]
+        fn pick_corners_ignores_target_order(angle in angle(), order in Just([0, 1, 2]).prop_shuffle()) {
+            let (targets, _) = synthetic_code(25.0, 4.0, angle, Point::new(100.0, 100.0));
+            let first = pick_corners(&targets).unwrap();
+            let shuffled = order.map(|i| targets[i]);
+            assert_close(pick_corners(&shuffled).unwrap(), first, 1e-6)?;
+        }
+
+        #[test]
+        fn pick_corners_scales_with_the_code(angle in angle(), scale in 0.1..20.0) {
+            let (targets, _) = synthetic_code(29.0, 3.0, angle, Point::new(40.0, -20.0));
+            let scaled = targets.map(|t| Target {
+                min: Point::new(t.min.x * scale, t.min.y * scale),
+                mid: Point::new(t.mid.x * scale, t.mid.y * scale),
+                max: Point::new(t.max.x * scale, t.max.y * scale),
+            });
+            let expected = pick_corners(&targets).unwrap().map(|p| Point::new(p.x * scale, p.y * scale));
+            assert_close(pick_corners(&scaled).unwrap(), expected, 1e-6 * scale * 100.0)?;
+        }
+
+        #[test]
+        fn complete_quad_makes_a_parallelogram(tl in point(100.0), tr in point(100.0), bl in point(100.0)) {
+            let corners = [tl, tr, bl];
+            let [tl, tr, br, bl] = complete_quad(corners);
+            assert_close([tl, tr, bl], corners, 1e-12)?;
+            // The diagonals bisect each other
+            prop_assert!((tl.x + br.x - tr.x - bl.x).abs() < 1e-9);
+            prop_assert!((tl.y + br.y - tr.y - bl.y).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn pick_corners_finds_upright_codes() {
+        for quarters in 0..4 {
+            let (targets, expected) = synthetic_code(21.0, 5.0, quarters as f64 * PI / 2.0, Point::new(50.0, 50.0));
+            assert_close(pick_corners(&targets).unwrap(), expected, 1e-6).unwrap();
+        }
+    }
+
+    #[test]
+    fn pick_corners_needs_three_targets() {
+        let (targets, _) = synthetic_code(21.0, 5.0, 0.3, Point::new(0.0, 0.0));
+        assert!(pick_corners(&targets[..2]).is_none());
+        assert!(pick_corners(&[targets[0], targets[1], targets[2], targets[0]]).is_none());
+    }
 }
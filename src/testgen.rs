@@ -0,0 +1,252 @@
+//! Synthetic test images, behind the dev-only `testgen` feature. An encoded
+//! code is put through the things a real camera does to it (perspective,
+//! rotation, blur, noise, JPEG compression and uneven lighting) by amounts
+//! set in a `Distortion`, and comes out as a greyscale image labelled with
+//! where the code's corners ended up. Sweeping one parameter at a time and
+//! comparing what the scanner finds against the labels (`Sample::corner_error`)
+//! shows how far each one can be pushed.
+
+use std::{f64::consts::PI, fs, path::Path};
+use image::{
+    GrayImage, ImageFormat, ImageResult, Luma,
+    codecs::jpeg::JpegEncoder,
+    imageops,
+};
+use crate::{
+    Point, ScanResult,
+    encode::{EcLevel, QrCode, Version},
+    homography::Homography,
+    json,
+};
+
+/// How to distort a code. The default is a clean, upright code filling half
+/// the image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distortion {
+    /// Size of the output image
+    pub width: u32,
+    pub height: u32,
+    /// Side of the code (quiet zone not included), as a fraction of the
+    /// image's shorter side, before any tilt
+    pub scale: f64,
+    /// Where the code's centre goes, as a fraction of the image's width and
+    /// height
+    pub center: (f64, f64),
+    /// Turn in the image plane, in radians clockwise
+    pub rotation: f64,
+    /// Tilt about the code's horizontal axis, in radians, as seen by a
+    /// camera two code-widths away. Positive tips the top away.
+    pub pitch: f64,
+    /// Tilt about the code's vertical axis, in radians. Positive tips the
+    /// right side away.
+    pub yaw: f64,
+    /// Standard deviation of a gaussian blur, in pixels
+    pub blur: f32,
+    /// Standard deviation of gaussian noise added to every pixel, in grey
+    /// levels
+    pub noise: f64,
+    /// Quality to put the image through JPEG compression at, from 1 to 100
+    pub jpeg_quality: Option<u8>,
+    /// How much darker the image gets from one side to the other, from 0 for
+    /// even lighting to 1 for black
+    pub shading: f64,
+    /// Direction the shading darkens towards, in radians clockwise from the
+    /// right
+    pub shading_angle: f64,
+    /// Seed for the noise, so the same distortion always gives the same
+    /// image
+    pub seed: u64,
+}
+
+impl Default for Distortion {
+    fn default() -> Self {
+        Self {
+            width: 640,
+            height: 480,
+            scale: 0.5,
+            center: (0.5, 0.5),
+            rotation: 0.0,
+            pitch: 0.0,
+            yaw: 0.0,
+            blur: 0.0,
+            noise: 0.0,
+            jpeg_quality: None,
+            shading: 0.0,
+            shading_angle: 0.0,
+            seed: 1,
+        }
+    }
+}
+
+/// A distorted code, with what it is and where it ended up
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub image: GrayImage,
+    /// Outer corners of the code itself (not its quiet zone), in pixels,
+    /// starting at the code's own top-left and going clockwise
+    pub corners: [Point<f64>; 4],
+    pub version: Version,
+    pub ec_level: EcLevel,
+    pub distortion: Distortion,
+}
+
+/// xorshift64
+struct Rng(u64);
+
+impl Rng {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A standard normal sample, by Box-Muller
+    fn gaussian(&mut self) -> f64 {
+        let u = self.next_f64().max(f64::MIN_POSITIVE);
+        let v = self.next_f64();
+        (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+    }
+}
+
+/// Where the corners of a unit square centred on the origin land after the
+/// tilts and turn, seen through a pinhole two units away and scaled so an
+/// untilted square stays a unit across
+fn project_corners(d: &Distortion) -> [Point<f64>; 4] {
+    let (sp, cp) = d.pitch.sin_cos();
+    let (sy, cy) = d.yaw.sin_cos();
+    let (sr, cr) = d.rotation.sin_cos();
+    let distance = 2.0;
+    [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)].map(|(x, y): (f64, f64)| {
+        // Yaw about the vertical axis, then pitch about the horizontal one;
+        // z is away from the camera
+        let (x, z) = (x * cy, x * sy);
+        let (y, z) = (y * cp + z * sp, z * cp - y * sp);
+        let f = distance / (distance + z);
+        let (x, y) = (x * f, y * f);
+        Point::new(x * cr - y * sr, x * sr + y * cr)
+    })
+}
+
+/// Draws `code` distorted by `distortion`
+pub fn generate(code: &QrCode, distortion: &Distortion) -> Sample {
+    let d = distortion;
+    let (w, h) = (d.width, d.height);
+    let side = d.scale * w.min(h) as f64;
+    let (cx, cy) = (d.center.0 * w as f64, d.center.1 * h as f64);
+    let corners = project_corners(d).map(|p| Point::new(cx + p.x * side, cy + p.y * side));
+
+    let n = code.size() as f64;
+    let square = [Point::new(0.0, 0.0), Point::new(n, 0.0), Point::new(n, n), Point::new(0.0, n)];
+    // From image pixels back to modules
+    let to_modules = Homography::from_points(&corners, &square);
+
+    // Average a 3x3 grid of samples per pixel, so module edges come out
+    // grey rather than jagged
+    let mut image = GrayImage::from_fn(w, h, |x, y| {
+        let Some(to_modules) = to_modules else { return Luma([255]) };
+        let mut dark = 0;
+        for sy in 0..3 {
+            for sx in 0..3 {
+                let p = Point::new(x as f64 + (sx as f64 + 0.5) / 3.0, y as f64 + (sy as f64 + 0.5) / 3.0);
+                let m = to_modules.apply(p);
+                if m.x >= 0.0 && m.y >= 0.0 && code.is_dark(m.x as u32, m.y as u32) {
+                    dark += 1;
+                }
+            }
+        }
+        let paper = 255.0 * (1.0 - shade(d, x, y));
+        Luma([(paper * (9 - dark) as f64 / 9.0).round() as u8])
+    });
+
+    if d.blur > 0.0 {
+        image = imageops::blur(&image, d.blur);
+    }
+    if d.noise > 0.0 {
+        let mut rng = Rng(d.seed.max(1));
+        for px in image.pixels_mut() {
+            px.0[0] = (px.0[0] as f64 + rng.gaussian() * d.noise).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    if let Some(quality) = d.jpeg_quality {
+        image = jpeg_round_trip(&image, quality);
+    }
+
+    Sample { image, corners, version: code.version(), ec_level: code.ec_level(), distortion: d.clone() }
+}
+
+/// How much the shading darkens pixel (`x`, `y`), from 0 to `shading`
+fn shade(d: &Distortion, x: u32, y: u32) -> f64 {
+    if d.shading <= 0.0 {
+        return 0.0;
+    }
+    let (s, c) = d.shading_angle.sin_cos();
+    // Position along the shading direction, 0 at the lightest corner and 1
+    // at the darkest
+    let along = |x: f64, y: f64| x * c + y * s;
+    let (w, h) = (d.width as f64, d.height as f64);
+    let ends = [along(0.0, 0.0), along(w, 0.0), along(0.0, h), along(w, h)];
+    let min = ends.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = ends.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    d.shading.min(1.0) * (along(x as f64 + 0.5, y as f64 + 0.5) - min) / (max - min)
+}
+
+fn jpeg_round_trip(image: &GrayImage, quality: u8) -> GrayImage {
+    let mut buf = Vec::new();
+    JpegEncoder::new_with_quality(&mut buf, quality.clamp(1, 100))
+        .encode_image(image)
+        .expect("encoding to memory can't fail");
+    image::load_from_memory_with_format(&buf, ImageFormat::Jpeg)
+        .expect("decoding our own JPEG can't fail")
+        .into_luma8()
+}
+
+impl Sample {
+    /// How far the code `result` found is from where it really is: the
+    /// largest distance between matching corners, in pixels. None if no code
+    /// was found.
+    pub fn corner_error(&self, result: &ScanResult) -> Option<f64> {
+        let found = result.quad()?;
+        Some(found.iter().zip(&self.corners).map(|(&a, &b)| a.dist_to(b)).fold(0.0, f64::max))
+    }
+
+    /// The labels, as one line of JSON:
+    ///
+    /// ```text
+    /// {"version":"3","ec_level":"M","corners":[[x,y],[x,y],[x,y],[x,y]],
+    ///  "distortion":{"width":640,"height":480,"scale":0.500,...,"seed":1}}
+    /// ```
+    ///
+    /// Micro QR versions are written "M1" to "M4".
+    pub fn to_json(&self) -> String {
+        let d = &self.distortion;
+        let version = match self.version {
+            Version::Normal(v) => v.to_string(),
+            Version::Micro(v) => format!("M{}", v),
+        };
+        let corners: Vec<String> = self.corners.iter().map(|&p| json::point(p)).collect();
+        format!(
+            concat!(
+                "{{\"version\":{},\"ec_level\":\"{:?}\",\"corners\":[{}],\"distortion\":{{",
+                "\"width\":{},\"height\":{},\"scale\":{},\"center\":[{},{}],",
+                "\"rotation\":{},\"pitch\":{},\"yaw\":{},\"blur\":{},\"noise\":{},\"jpeg_quality\":{},",
+                "\"shading\":{},\"shading_angle\":{},\"seed\":{}}}}}",
+            ),
+            json::string(&version), self.ec_level, corners.join(","),
+            d.width, d.height, json::number(d.scale), json::number(d.center.0), json::number(d.center.1),
+            json::number(d.rotation), json::number(d.pitch), json::number(d.yaw),
+            json::number(d.blur as f64), json::number(d.noise),
+            d.jpeg_quality.map_or("null".to_owned(), |q| q.to_string()),
+            json::number(d.shading), json::number(d.shading_angle), d.seed,
+        )
+    }
+
+    /// Saves the image as a PNG at `path`, with the labels from `to_json`
+    /// next to it, under the same name with a `.json` extension
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        let path = path.as_ref();
+        self.image.save_with_format(path, ImageFormat::Png)?;
+        fs::write(path.with_extension("json"), self.to_json() + "\n")?;
+        Ok(())
+    }
+}
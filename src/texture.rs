@@ -0,0 +1,110 @@
+//! Textures for showing arqr's input and output inside an egui app (with the
+//! `egui` feature) or a bevy game (with the `bevy` feature).
+//!
+//! Frames (anything with `u8` pixels, like the `GrayImage`s from
+//! `camera::luma_from_nokhwa_frame` or a result's `code_img`), `Bitmap`s and
+//! `Overlay`s each convert to an egui `ColorImage` or a bevy `Image`. Overlays
+//! come out on a transparent background the size of the frame they were
+//! made for, so they can be layered over the frame's own texture; to bake
+//! one into the frame instead, `Overlay::draw_on` an `RgbaImage` copy of it
+//! and convert that.
+
+use std::ops::Deref;
+use image::{ImageBuffer, Pixel, RgbaImage};
+use crate::{bitmap::Bitmap, draw::Overlay};
+
+/// Four bytes per pixel, in order
+fn frame_rgba<Px, C>(frame: &ImageBuffer<Px, C>) -> impl Iterator<Item = [u8; 4]> + '_
+where
+    Px: Pixel<Subpixel = u8>,
+    C: Deref<Target = [u8]>,
+{
+    frame.pixels().map(|px| px.to_rgba().0)
+}
+
+fn bitmap_rgba(bmp: &Bitmap) -> impl Iterator<Item = [u8; 4]> + '_ {
+    bmp.iter().map(|&white| if white { [255; 4] } else { [0, 0, 0, 255] })
+}
+
+/// `overlay` drawn on a transparent `width` by `height` image
+fn overlay_image(overlay: &Overlay, width: u32, height: u32) -> RgbaImage {
+    let mut img = RgbaImage::new(width, height);
+    overlay.draw_on(&mut img);
+    img
+}
+
+#[cfg(feature = "egui")]
+mod egui_textures {
+    use super::*;
+    use egui::{Color32, ColorImage};
+
+    fn color_image(width: u32, height: u32, rgba: impl Iterator<Item = [u8; 4]>) -> ColorImage {
+        let pixels = rgba.map(|[r, g, b, a]| Color32::from_rgba_unmultiplied(r, g, b, a)).collect();
+        ColorImage::new([width as usize, height as usize], pixels)
+    }
+
+    /// A frame as an egui image, ready for `Context::load_texture` or
+    /// `TextureHandle::set`
+    pub fn egui_frame<Px, C>(frame: &ImageBuffer<Px, C>) -> ColorImage
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        let (width, height) = frame.dimensions();
+        color_image(width, height, frame_rgba(frame))
+    }
+
+    /// A binarized frame as a black and white egui image
+    pub fn egui_bitmap(bmp: &Bitmap) -> ColorImage {
+        color_image(bmp.width(), bmp.height(), bitmap_rgba(bmp))
+    }
+
+    /// An overlay as an egui image with a transparent background, for a
+    /// `width` by `height` frame
+    pub fn egui_overlay(overlay: &Overlay, width: u32, height: u32) -> ColorImage {
+        egui_frame(&overlay_image(overlay, width, height))
+    }
+}
+
+#[cfg(feature = "egui")]
+pub use egui_textures::*;
+
+#[cfg(feature = "bevy")]
+mod bevy_textures {
+    use super::*;
+    use bevy_asset::RenderAssetUsages;
+    use bevy_image::Image;
+    use wgpu_types::{Extent3d, TextureDimension, TextureFormat};
+
+    fn image(width: u32, height: u32, rgba: impl Iterator<Item = [u8; 4]>) -> Image {
+        let size = Extent3d { width, height, depth_or_array_layers: 1 };
+        let data = rgba.flatten().collect();
+        Image::new(size, TextureDimension::D2, data, TextureFormat::Rgba8UnormSrgb, RenderAssetUsages::default())
+    }
+
+    /// A frame as a bevy image, for `Assets<Image>`. To show a live feed,
+    /// move each new frame's `data` into the existing asset rather than
+    /// adding a new one.
+    pub fn bevy_frame<Px, C>(frame: &ImageBuffer<Px, C>) -> Image
+    where
+        Px: Pixel<Subpixel = u8>,
+        C: Deref<Target = [u8]>,
+    {
+        let (width, height) = frame.dimensions();
+        image(width, height, frame_rgba(frame))
+    }
+
+    /// A binarized frame as a black and white bevy image
+    pub fn bevy_bitmap(bmp: &Bitmap) -> Image {
+        image(bmp.width(), bmp.height(), bitmap_rgba(bmp))
+    }
+
+    /// An overlay as a bevy image with a transparent background, for a
+    /// `width` by `height` frame
+    pub fn bevy_overlay(overlay: &Overlay, width: u32, height: u32) -> Image {
+        bevy_frame(&overlay_image(overlay, width, height))
+    }
+}
+
+#[cfg(feature = "bevy")]
+pub use bevy_textures::*;
@@ -0,0 +1,325 @@
+//! Follows codes from one frame to the next, so that anything drawn over a
+//! code (e.g. an AR overlay) can stay attached to the same code instead of
+//! whatever happened to be detected this frame.
+
+use std::time::Instant;
+use crate::{
+    Point,
+    Rect,
+    ScanResult,
+    Stage,
+    calib::CameraIntrinsics,
+    encode::EcLevel,
+    pose::Pose,
+    target::complete_quad,
+    smooth::{PointFilter, Smoothing},
+};
+
+/// Identifies one tracked code for as long as the tracker keeps seeing it
+pub type TrackId = u64;
+
+/// A code that's been followed across one or more frames
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub id: TrackId,
+    /// Smoothed corners of the code, in the same order as `ScanResult::bbox`
+    pub corners: [Point<f64>; 3],
+    /// Corners exactly as detected in the last frame the code was seen in
+    pub raw_corners: [Point<f64>; 3],
+    /// Number of updates since this code was first seen
+    pub age: u32,
+    /// Number of updates this code was actually detected in
+    pub hits: u32,
+    /// Number of updates since this code was last detected
+    pub misses: u32,
+    /// Whether the code has been detected in enough consecutive updates to be
+    /// reported (see `Tracker::min_frames_to_confirm`)
+    pub confirmed: bool,
+    /// Whether the code is moving faster than `Tracker::moving_speed`
+    pub moving: bool,
+    pub first_seen: Instant,
+    pub last_seen: Instant,
+    /// The message last read from the code. Kept through frames where the
+    /// code was found but couldn't be read.
+    pub payload: Option<Vec<u8>>,
+    /// Number of reads in a row that have given `payload`, not counting
+    /// frames where the code couldn't be read. More than one means the
+    /// payload can be trusted not to be a misread.
+    pub reads: u32,
+    /// The code's version and error correction level, from the last frame
+    /// they could be read in
+    pub version: Option<u32>,
+    pub ec_level: Option<EcLevel>,
+    filters: [PointFilter; 3],
+    // Smoothed rate of change of the raw corners' centroid, in pixels/second
+    velocity: Point<f64>,
+}
+
+impl Track {
+    /// Length of the longer of the code's two measured sides
+    pub fn side_len(&self) -> f64 {
+        side_len(&self.corners)
+    }
+
+    /// Where the code's corners are expected to be at time `at`, assuming it
+    /// keeps moving at its current velocity
+    pub fn predict_corners(&self, at: Instant) -> [Point<f64>; 3] {
+        let dt = at.saturating_duration_since(self.last_seen).as_secs_f64();
+        self.raw_corners.map(|c| Point::new(c.x + self.velocity.x * dt, c.y + self.velocity.y * dt))
+    }
+
+    /// Smoothed velocity of the code's center, in pixels/second
+    pub fn velocity(&self) -> Point<f64> {
+        self.velocity
+    }
+
+    /// Speed of the code's center, in pixels/second
+    pub fn speed(&self) -> f64 {
+        self.velocity.x.hypot(self.velocity.y)
+    }
+
+    /// Velocity of the code in camera space, in the same units as
+    /// `code_size` per second. Only sideways motion is measured: since it's
+    /// worked out from how fast the code moves across the image, moving
+    /// straight towards or away from the camera won't show up.
+    pub fn metric_velocity(&self, intrinsics: &CameraIntrinsics, code_size: f64) -> Option<[f64; 3]> {
+        // Compare the pose now with the pose a short time later, if the code
+        // kept moving as it is
+        const DT: f64 = 0.1;
+        let later = self.corners.map(|c| Point::new(c.x + self.velocity.x * DT, c.y + self.velocity.y * DT));
+        let now = self.pose(intrinsics, code_size)?;
+        let later = Pose::from_bbox(later, code_size, intrinsics)?;
+        Some([0, 1, 2].map(|i| (later.t[i] - now.t[i]) / DT))
+    }
+
+    /// Pose of the code, estimated from its smoothed corners. See
+    /// `Pose::from_bbox`.
+    pub fn pose(&self, intrinsics: &CameraIntrinsics, code_size: f64) -> Option<Pose> {
+        Pose::from_bbox(self.corners, code_size, intrinsics)
+    }
+
+    /// Estimated distance to the code, from its smoothed corners. See
+    /// `CameraIntrinsics::distance_to_bbox`.
+    pub fn distance(&self, intrinsics: &CameraIntrinsics, code_size: f64) -> f64 {
+        intrinsics.distance_to_bbox(self.corners, code_size)
+    }
+
+    /// Column-major model-view matrix anchoring content to this code. See
+    /// `Pose::model_view`.
+    pub fn model_view(&self, intrinsics: &CameraIntrinsics, code_size: f64) -> Option<[f32; 16]> {
+        Some(self.pose(intrinsics, code_size)?.model_view())
+    }
+}
+
+/// Takes whatever could be read from the code in `result` into `track`
+fn read_into(track: &mut Track, result: &ScanResult) {
+    if let Some(payload) = &result.payload {
+        if track.payload.as_ref() == Some(payload) {
+            track.reads += 1;
+        } else {
+            track.payload = Some(payload.clone());
+            track.reads = 1;
+        }
+    }
+    if result.version.is_some() {
+        track.version = result.version;
+    }
+    if let Some(format) = result.format {
+        track.ec_level = Some(format.ec_level);
+    }
+}
+
+fn side_len(corners: &[Point<f64>; 3]) -> f64 {
+    corners[0].dist_to(corners[1]).max(corners[0].dist_to(corners[2]))
+}
+
+fn centroid(corners: &[Point<f64>; 3]) -> Point<f64> {
+    Point::new(
+        corners.iter().map(|c| c.x).sum::<f64>() / 3.0,
+        corners.iter().map(|c| c.y).sum::<f64>() / 3.0,
+    )
+}
+
+/// How badly a detection matches a track, or `None` if it can't be the same
+/// code at all. Compares corner positions relative to the size of the code,
+/// and rejects detections that are suddenly much bigger or smaller.
+fn match_cost(track: &Track, corners: &[Point<f64>; 3], max_dist: f64) -> Option<f64> {
+    let track_len = track.side_len();
+    let det_len = side_len(corners);
+    let scale = det_len / track_len;
+    if !(0.67..=1.5).contains(&scale) {
+        return None;
+    }
+
+    let mean_dist = track.raw_corners.iter()
+        .zip(corners.iter())
+        .map(|(a, &b)| a.dist_to(b))
+        .sum::<f64>() / 3.0;
+    let cost = mean_dist / track_len.max(det_len);
+    if cost <= max_dist { Some(cost) } else { None }
+}
+
+/// Weight given to each new velocity measurement
+const VELOCITY_ALPHA: f64 = 0.5;
+
+/// Associates detections across frames and gives each code a stable ID
+#[derive(Clone, Debug)]
+pub struct Tracker {
+    tracks: Vec<Track>,
+    next_id: TrackId,
+    /// How far a code's corners may move between updates and still count as
+    /// the same code, as a fraction of the code's side length
+    pub max_match_dist: f64,
+    /// Number of consecutive updates a new code must be detected in before
+    /// it's reported by `confirmed`. Filters out one-off false detections.
+    pub min_frames_to_confirm: u32,
+    /// Number of updates a confirmed code can go undetected before it's
+    /// forgotten. Rides out brief detection dropouts. (Unconfirmed codes are
+    /// forgotten as soon as they're missed.)
+    pub max_frames_to_hold: u32,
+    /// How corner positions are smoothed. Changing this only affects codes
+    /// that start being tracked afterwards.
+    pub smoothing: Smoothing,
+    /// Speed above which a code counts as moving, in code side lengths per
+    /// second, so the threshold doesn't depend on how close the code is
+    pub moving_speed: f64,
+}
+
+impl Default for Tracker {
+    fn default() -> Self {
+        Self {
+            tracks: Vec::new(),
+            next_id: 0,
+            max_match_dist: 0.5,
+            min_frames_to_confirm: 3,
+            max_frames_to_hold: 5,
+            smoothing: Smoothing::default(),
+            moving_speed: 0.25,
+        }
+    }
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every code currently being tracked, including unconfirmed ones
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Codes which have been confirmed and not yet forgotten - the ones that
+    /// should be shown to users
+    pub fn confirmed(&self) -> impl Iterator<Item = &Track> {
+        self.tracks.iter().filter(|t| t.confirmed)
+    }
+
+    /// Predicts the regions of a frame taken at time `at` that will contain
+    /// the codes being tracked, padded on every side by `margin` times each
+    /// code's size to allow for error in the prediction. Pass these to
+    /// `Scanner::set_regions` to speed up the next scan.
+    pub fn predict_regions(&self, at: Instant, margin: f64) -> Vec<Rect<f64>> {
+        self.tracks.iter().map(|track| {
+            let quad = complete_quad(track.predict_corners(at));
+            Rect::bounding(&quad).inflate(track.side_len() * margin)
+        }).collect()
+    }
+
+    /// Feeds the tracker the result of scanning a new frame
+    pub fn update(&mut self, result: &ScanResult) -> &[Track] {
+        self.update_at(result, result.meta.timestamp.unwrap_or_else(Instant::now))
+    }
+
+    /// Same as `update`, but with an explicit time for the frame
+    pub fn update_at(&mut self, result: &ScanResult, now: Instant) -> &[Track] {
+        // A scan that ran out of time before looking for a code says nothing
+        // about whether one's there
+        if result.stage < Stage::Corners {
+            return &self.tracks;
+        }
+        let detections: Vec<[Point<f64>; 3]> = result.bbox.into_iter().collect();
+
+        // Score every plausible (track, detection) pair, then greedily take
+        // the best matches first
+        let mut pairs = Vec::new();
+        for (ti, track) in self.tracks.iter().enumerate() {
+            for (di, corners) in detections.iter().enumerate() {
+                if let Some(cost) = match_cost(track, corners, self.max_match_dist) {
+                    pairs.push((cost, ti, di));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut track_matched = vec![false; self.tracks.len()];
+        let mut det_matched = vec![false; detections.len()];
+        for (_, ti, di) in pairs {
+            if track_matched[ti] || det_matched[di] {
+                continue;
+            }
+            track_matched[ti] = true;
+            det_matched[di] = true;
+
+            let track = &mut self.tracks[ti];
+            let dt = now.saturating_duration_since(track.last_seen).as_secs_f64();
+            for ((corner, filter), &raw) in track.corners.iter_mut()
+                .zip(track.filters.iter_mut())
+                .zip(detections[di].iter())
+            {
+                *corner = filter.update(raw, dt);
+            }
+            if dt > 0.0 {
+                let (old, new) = (centroid(&track.raw_corners), centroid(&detections[di]));
+                let measured = Point::new((new.x - old.x) / dt, (new.y - old.y) / dt);
+                track.velocity.x += VELOCITY_ALPHA * (measured.x - track.velocity.x);
+                track.velocity.y += VELOCITY_ALPHA * (measured.y - track.velocity.y);
+            }
+            track.raw_corners = detections[di];
+            read_into(track, result);
+            track.moving = track.speed() > self.moving_speed * track.side_len();
+            track.hits += 1;
+            track.misses = 0;
+            if track.hits >= self.min_frames_to_confirm {
+                track.confirmed = true;
+            }
+            track.last_seen = now;
+        }
+
+        for (track, &matched) in self.tracks.iter_mut().zip(track_matched.iter()) {
+            track.age += 1;
+            if !matched {
+                track.misses += 1;
+            }
+        }
+        let max_hold = self.max_frames_to_hold;
+        self.tracks.retain(|t| t.misses == 0 || (t.confirmed && t.misses <= max_hold));
+
+        for (corners, _) in detections.iter().zip(det_matched).filter(|(_, m)| !m) {
+            let filters = corners.map(|c| PointFilter::new(self.smoothing, c));
+            let mut track = Track {
+                id: self.next_id,
+                corners: *corners,
+                raw_corners: *corners,
+                age: 1,
+                hits: 1,
+                misses: 0,
+                confirmed: self.min_frames_to_confirm <= 1,
+                moving: false,
+                first_seen: now,
+                last_seen: now,
+                payload: None,
+                reads: 0,
+                version: None,
+                ec_level: None,
+                filters,
+                velocity: Point::new(0.0, 0.0),
+            };
+            read_into(&mut track, result);
+            self.tracks.push(track);
+            self.next_id += 1;
+        }
+
+        &self.tracks
+    }
+}
@@ -0,0 +1,88 @@
+//! Picking web links out of payloads, and checking where they go, for the
+//! demo's link handler (see `config::UrlConfig`).
+//!
+//! This isn't a full URL parser. It finds the host the same way a browser
+//! would for the links it accepts, so an allow list can't be talked past
+//! with tricks like `https://example.com@evil.com` or
+//! `https://evil.com\@example.com`.
+
+/// The payload as a link, if it's an `http` or `https` one with a host, and
+/// nothing else: no spaces or control characters, which a genuine link
+/// wouldn't have
+pub fn web_url(payload: &[u8]) -> Option<&str> {
+    let url = std::str::from_utf8(payload).ok()?;
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return None;
+    }
+    host(url).map(|_| url)
+}
+
+/// The host of an `http` or `https` link, without any user name or port
+pub fn host(url: &str) -> Option<&str> {
+    let (scheme, rest) = url.split_once("://")?;
+    if !(scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")) {
+        return None;
+    }
+    // Browsers take a backslash in a web link as a slash
+    let authority = rest.split(['/', '\\', '?', '#']).next()?;
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host_port)| host_port);
+    let host = if host_port.starts_with('[') {
+        // An IPv6 address, brackets and all
+        &host_port[..=host_port.find(']')?]
+    } else {
+        match host_port.rsplit_once(':') {
+            Some((host, port)) if port.bytes().all(|b| b.is_ascii_digit()) => host,
+            Some(_) => return None,
+            None => host_port,
+        }
+    };
+    (!host.is_empty()).then_some(host)
+}
+
+/// Whether `host` is one of `allowed`, or a subdomain of one. A trailing dot
+/// and case don't matter.
+pub fn host_allowed(host: &str, allowed: &[String]) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host).to_ascii_lowercase();
+    allowed.iter().any(|entry| {
+        let entry = entry.strip_suffix('.').unwrap_or(entry).to_ascii_lowercase();
+        host == entry || host.strip_suffix(&entry).is_some_and(|sub| sub.ends_with('.'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_web_links() {
+        assert_eq!(web_url(b"https://example.com/x?y#z"), Some("https://example.com/x?y#z"));
+        assert_eq!(web_url(b"HTTP://example.com"), Some("HTTP://example.com"));
+        assert_eq!(web_url(b"ftp://example.com"), None);
+        assert_eq!(web_url(b"javascript:alert(1)"), None);
+        assert_eq!(web_url(b"https://"), None);
+        assert_eq!(web_url(b"https://example.com/a b"), None);
+        assert_eq!(web_url(b"https://example.com\n"), None);
+        assert_eq!(web_url(b"https://example.com/\xff"), None);
+    }
+
+    #[test]
+    fn finds_hosts() {
+        assert_eq!(host("https://example.com"), Some("example.com"));
+        assert_eq!(host("https://user:pw@example.com:8080/"), Some("example.com"));
+        assert_eq!(host("https://example.com@evil.com/"), Some("evil.com"));
+        assert_eq!(host("https://evil.com\\@example.com/"), Some("evil.com"));
+        assert_eq!(host("https://evil.com?@example.com/"), Some("evil.com"));
+        assert_eq!(host("http://[::1]:80/"), Some("[::1]"));
+        assert_eq!(host("http://example.com:http/"), None);
+    }
+
+    #[test]
+    fn allows_hosts_and_subdomains() {
+        let allowed = ["Example.com".to_owned()];
+        assert!(host_allowed("example.com", &allowed));
+        assert!(host_allowed("www.EXAMPLE.com.", &allowed));
+        assert!(!host_allowed("badexample.com", &allowed));
+        assert!(!host_allowed("example.com.evil.com", &allowed));
+        assert!(!host_allowed("example.com", &[]));
+    }
+}
@@ -0,0 +1,175 @@
+//! Reads frames out of video files, so recordings can be scanned offline.
+//!
+//! Decoding is left to an `ffmpeg` executable on the `PATH` (and `ffprobe` to
+//! find the stream's size and frame rate) rather than linking against its
+//! libraries, so any container and codec ffmpeg understands will work and
+//! nothing extra is needed at build time.
+
+use std::{
+    io::{self, Read},
+    path::Path,
+    process::{Child, ChildStdout, Command, Stdio},
+    time::Duration,
+};
+use image::GrayImage;
+use crate::{FrameMeta, ScanResult, Scanner};
+
+/// Frame rate assumed when the file doesn't say what it is
+const DEFAULT_FPS: f64 = 30.0;
+
+/// A single decoded frame. Only luma is decoded, since that's all the
+/// scanner looks at. Frames come out as they're stored, without the
+/// rotation phones tag their recordings with applied, so a video shot in
+/// portrait can come out on its side.
+pub struct VideoFrame {
+    /// Position in the video, counting from 0
+    pub index: u64,
+    /// Presentation time, assuming a constant frame rate
+    pub timestamp: Duration,
+    pub image: GrayImage,
+}
+
+/// Iterator over the frames of a video file
+pub struct VideoFrames {
+    child: Child,
+    stdout: ChildStdout,
+    width: u32,
+    height: u32,
+    fps: f64,
+    index: u64,
+    done: bool,
+}
+
+fn other_err(msg: String) -> io::Error {
+    io::Error::other(msg)
+}
+
+/// Asks ffprobe for the first video stream's width, height and frame rate
+fn probe(path: &Path) -> io::Result<(u32, u32, f64)> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "stream=width,height,avg_frame_rate"])
+        .args(["-of", "csv=p=0"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(other_err(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    // e.g. "1920,1080,30000/1001"
+    let text = String::from_utf8_lossy(&output.stdout);
+    let fields: Vec<&str> = text.trim().split(',').collect();
+    let bad_output = || other_err(format!("unexpected ffprobe output: {:?}", text.trim()));
+    if fields.len() < 3 {
+        return Err(bad_output());
+    }
+    let width = fields[0].parse().map_err(|_| bad_output())?;
+    let height = fields[1].parse().map_err(|_| bad_output())?;
+    let fps = match fields[2].split_once('/') {
+        Some((num, den)) => num.parse::<f64>().ok().zip(den.parse::<f64>().ok()).map(|(n, d)| n / d),
+        None => fields[2].parse().ok(),
+    };
+    let fps = fps.filter(|f| f.is_finite() && *f > 0.0).unwrap_or(DEFAULT_FPS);
+    Ok((width, height, fps))
+}
+
+impl VideoFrames {
+    /// Starts decoding a video file. Fails if ffmpeg/ffprobe can't be run or
+    /// the file has no video stream.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let (width, height, fps) = probe(path)?;
+        let mut child = Command::new("ffmpeg")
+            // ffprobe gives the size as stored, so the frames have to come
+            // out that way too, not turned as the file's rotation says
+            .args(["-v", "error", "-nostdin", "-noautorotate", "-i"])
+            .arg(path)
+            .args(["-map", "0:v:0", "-f", "rawvideo", "-pix_fmt", "gray", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().unwrap();
+        Ok(Self { child, stdout, width, height, fps, index: 0, done: false })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    /// Reads one frame, or returns None at the end of the stream
+    fn read_frame(&mut self) -> io::Result<Option<VideoFrame>> {
+        let mut buf = vec![0; (self.width * self.height) as usize];
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.stdout.read(&mut buf[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if filled < buf.len() {
+            // Either a clean end, or ffmpeg gave up partway through
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(other_err(format!("ffmpeg exited with {}", status)));
+            }
+            return Ok(None);
+        }
+
+        let index = self.index;
+        self.index += 1;
+        Ok(Some(VideoFrame {
+            index,
+            timestamp: Duration::from_secs_f64(index as f64 / self.fps),
+            image: GrayImage::from_raw(self.width, self.height, buf).unwrap(),
+        }))
+    }
+}
+
+impl Iterator for VideoFrames {
+    type Item = io::Result<VideoFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let frame = self.read_frame().transpose();
+        if !matches!(frame, Some(Ok(_))) {
+            self.done = true;
+        }
+        frame
+    }
+}
+
+impl Drop for VideoFrames {
+    fn drop(&mut self) {
+        // Stop decoding if the iterator's dropped early
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// Scans every frame of a video file, calling `cb` with each frame and its
+/// result as it goes
+pub fn scan_video<P, F>(path: P, mut cb: F) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(&VideoFrame, ScanResult),
+{
+    let mut scanner = Scanner::new();
+    for frame in VideoFrames::open(path)? {
+        let frame = frame?;
+        scanner.set_frame_meta(FrameMeta { sequence: frame.index, timestamp: None });
+        let result = scanner.scan(&frame.image);
+        cb(&frame, result);
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Exports for the browser demo in `web/`. The module is used bare, with no
+//! JS glue: the page allocates buffers in the module's memory with
+//! `arqr_alloc`, copies a frame's RGBA pixels in, calls `arqr_scan_rgba` and
+//! reads the corners back out from `arqr_corners`, and the payload from
+//! `arqr_payload` and `arqr_payload_len`. `web/arqr.js` wraps all
+//! that in a `Scanner` class taking `ImageData`, for pages to use directly.
+//!
+//! Build it with
+//!
+//! ```text
+//! cargo rustc --lib --release --target wasm32-unknown-unknown \
+//!     --no-default-features --features web --crate-type cdylib
+//! cp target/wasm32-unknown-unknown/release/arqr.wasm web/
+//! ```
+//!
+//! and serve `web/` over HTTP (or HTTPS, away from localhost, for camera
+//! access).
+
+use std::cell::{Cell, RefCell};
+use image::{ImageBuffer, Rgba};
+use crate::Scanner;
+
+thread_local! {
+    // The page only scans from one worker, so one scanner is enough, and it
+    // keeps its buffers between frames
+    static SCANNER: RefCell<Scanner> = RefCell::new(Scanner::new());
+    static CORNERS: Cell<[f64; 8]> = const { Cell::new([0.0; 8]) };
+    static PAYLOAD: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+/// Allocates `len` bytes for the page to write into. Give them back with
+/// `arqr_free`.
+#[no_mangle]
+pub extern "C" fn arqr_alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// Frees memory from `arqr_alloc`.
+///
+/// # Safety
+///
+/// `ptr` and `len` have to be exactly as given to and returned from
+/// `arqr_alloc`, and not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn arqr_free(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// Where the last code found's corners are: 8 `f64`s, holding the x and y of
+/// the top-left, top-right, bottom-right and bottom-left corners
+#[no_mangle]
+pub extern "C" fn arqr_corners() -> *const f64 {
+    CORNERS.with(|corners| corners.as_ptr() as *const f64)
+}
+
+/// Where the last code found's payload is. See `arqr_payload_len`.
+#[no_mangle]
+pub extern "C" fn arqr_payload() -> *const u8 {
+    PAYLOAD.with(|payload| payload.borrow().as_ref().map_or(std::ptr::null(), |p| p.as_ptr()))
+}
+
+/// How many bytes long the last code found's payload is, or -1 if it
+/// couldn't be read
+#[no_mangle]
+pub extern "C" fn arqr_payload_len() -> i32 {
+    PAYLOAD.with(|payload| payload.borrow().as_ref().map_or(-1, |p| p.len() as i32))
+}
+
+/// Scans a `width` by `height` frame of RGBA pixels, as found in an
+/// `ImageData`. Returns 1 if a code was found, and puts its corners in
+/// `arqr_corners` and its payload in `arqr_payload`, or 0 if not.
+///
+/// # Safety
+///
+/// `pixels` has to point to `width * height * 4` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn arqr_scan_rgba(pixels: *const u8, width: u32, height: u32) -> u32 {
+    let len = width as usize * height as usize * 4;
+    let pixels = std::slice::from_raw_parts(pixels, len);
+    let Some(img) = ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, pixels) else { return 0 };
+    let result = SCANNER.with(|scanner| scanner.borrow_mut().scan(&img));
+    match result.quad() {
+        Some(quad) => {
+            let mut out = [0.0; 8];
+            for (i, p) in quad.iter().enumerate() {
+                out[i * 2] = p.x;
+                out[i * 2 + 1] = p.y;
+            }
+            CORNERS.with(|corners| corners.set(out));
+            PAYLOAD.with(|payload| *payload.borrow_mut() = result.payload);
+            1
+        }
+        None => 0,
+    }
+}
@@ -0,0 +1,68 @@
+// A JS interface to arqr.wasm, so pages can scan frames without knowing
+// about the module's memory. See src/web.rs for building arqr.wasm.
+//
+//   import { Scanner } from "./arqr.js";
+//   const scanner = await Scanner.load();
+//   const codes = scanner.scan(ctx.getImageData(0, 0, width, height));
+//
+// `scan` returns an array of codes found, each { corners, payload }, with
+// corners an array of four { x, y } in pixels: top-left, top-right,
+// bottom-right, bottom-left. `payload` is the code's message as a string
+// (decoded as UTF-8), or null if it couldn't be read.
+//
+// Each Scanner has its own instance of the module, so scanners don't share
+// any state.
+
+export class Scanner {
+  // Loads arqr.wasm, from next to this file unless another URL is given
+  static async load(url = new URL("arqr.wasm", import.meta.url)) {
+    const { instance } = await WebAssembly.instantiateStreaming(fetch(url), {});
+    return new Scanner(instance.exports);
+  }
+
+  constructor(exports) {
+    this.arqr = exports;
+    // A buffer in the module's memory, reused while frames stay the same
+    // size
+    this.ptr = 0;
+    this.len = 0;
+  }
+
+  // Scans an ImageData, or RGBA bytes (a Uint8ClampedArray or Uint8Array)
+  // along with their width and height
+  scan(image, width = image.width, height = image.height) {
+    const pixels = image.data ?? image;
+    if (pixels.length !== width * height * 4) {
+      throw new RangeError(`expected ${width * height * 4} bytes of RGBA, got ${pixels.length}`);
+    }
+    const arqr = this.arqr;
+    if (this.len !== pixels.length) {
+      this.free();
+      this.len = pixels.length;
+      this.ptr = arqr.arqr_alloc(this.len);
+    }
+
+    // Views have to be made after allocating, since memory can grow and
+    // detach old ones
+    new Uint8Array(arqr.memory.buffer, this.ptr, this.len).set(pixels);
+    if (!arqr.arqr_scan_rgba(this.ptr, width, height)) {
+      return [];
+    }
+    const xy = new Float64Array(arqr.memory.buffer, arqr.arqr_corners(), 8);
+    const corners = [0, 2, 4, 6].map((i) => ({ x: xy[i], y: xy[i + 1] }));
+    const len = arqr.arqr_payload_len();
+    const payload = len < 0
+      ? null
+      : new TextDecoder().decode(new Uint8Array(arqr.memory.buffer, arqr.arqr_payload(), len));
+    return [{ corners, payload }];
+  }
+
+  // Gives back the frame buffer. The scanner can still be used afterwards.
+  free() {
+    if (this.ptr) {
+      this.arqr.arqr_free(this.ptr, this.len);
+    }
+    this.ptr = 0;
+    this.len = 0;
+  }
+}
@@ -0,0 +1,78 @@
+// Shows the camera on a canvas, and hands frames to worker.js to scan. Only
+// one frame is out with the worker at a time, so frames are dropped rather
+// than queued when scanning can't keep up.
+
+const canvas = document.getElementById("view");
+const ctx = canvas.getContext("2d", { willReadFrequently: true });
+const status = document.getElementById("status");
+const video = document.createElement("video");
+const worker = new Worker("worker.js", { type: "module" });
+
+const LINE_COLOR = "#0000ff";
+
+let busy = false;
+// Corners of the last code found, in frame pixels
+let corners = null;
+let scans = 0;
+let found = 0;
+
+worker.onmessage = (e) => {
+  busy = false;
+  if (e.data.error) {
+    status.textContent = e.data.error;
+    return;
+  }
+  scans++;
+  corners = e.data.corners;
+  if (corners) {
+    found++;
+  }
+  status.textContent = `${found} of ${scans} frames had a code, last scan ${e.data.ms.toFixed(1)} ms`;
+};
+
+function drawOverlay() {
+  if (!corners) {
+    return;
+  }
+  ctx.strokeStyle = LINE_COLOR;
+  ctx.lineWidth = 2;
+  ctx.beginPath();
+  ctx.moveTo(corners[0], corners[1]);
+  for (let i = 2; i < 8; i += 2) {
+    ctx.lineTo(corners[i], corners[i + 1]);
+  }
+  ctx.closePath();
+  ctx.stroke();
+}
+
+function frame() {
+  if (video.videoWidth > 0) {
+    if (canvas.width !== video.videoWidth || canvas.height !== video.videoHeight) {
+      canvas.width = video.videoWidth;
+      canvas.height = video.videoHeight;
+    }
+    ctx.drawImage(video, 0, 0);
+    if (!busy) {
+      busy = true;
+      const img = ctx.getImageData(0, 0, canvas.width, canvas.height);
+      // Hand the pixels over rather than copying them
+      worker.postMessage({ width: img.width, height: img.height, pixels: img.data.buffer }, [img.data.buffer]);
+    }
+    drawOverlay();
+  }
+  requestAnimationFrame(frame);
+}
+
+navigator.mediaDevices.getUserMedia({ video: { facingMode: "environment" }, audio: false })
+  .then((stream) => {
+    video.srcObject = stream;
+    video.playsInline = true;
+    return video.play();
+  })
+  .then(() => {
+    status.textContent = "scanning";
+    requestAnimationFrame(frame);
+  })
+  .catch((e) => {
+    status.textContent = `couldn't open the camera: ${e}`;
+  });
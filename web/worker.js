@@ -0,0 +1,23 @@
+// Scans frames sent from main.js with arqr.js. Each message is
+// { width, height, pixels }, with pixels an ArrayBuffer of RGBA bytes, and
+// gets back { corners, ms }, with corners null if no code was found.
+
+import { Scanner } from "./arqr.js";
+
+const ready = Scanner.load();
+
+onmessage = async (e) => {
+  let scanner;
+  try {
+    scanner = await ready;
+  } catch (err) {
+    postMessage({ error: `couldn't load arqr.wasm: ${err}` });
+    return;
+  }
+  const { width, height, pixels } = e.data;
+  const start = performance.now();
+  const codes = scanner.scan(new Uint8Array(pixels), width, height);
+  const ms = performance.now() - start;
+  const corners = codes.length ? codes[0].corners.flatMap((p) => [p.x, p.y]) : null;
+  postMessage({ corners, ms });
+};